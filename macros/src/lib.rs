@@ -0,0 +1,280 @@
+// Copyright (C) 2020  Sojan James
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0>
+
+/* Generate the Rust types of an IDL file at compile time.
+
+    cyclonedds_idlc_macros::idl!("idl/shape.idl", include = ["idl/common"]);
+
+    #[cyclonedds_idlc_macros::idl_module(file = "idl/shape.idl")]
+    mod types {}
+
+ The attribute has its own name, a function-like and an attribute macro share
+ one namespace.
+
+ The paths are relative to CARGO_MANIFEST_DIR. The options are
+    include = [dir, ...]    search path of the included files
+    include_path = "path"   Rust path of the modules of the included files (default: crate)
+    runtime_crate = "path"  Cyclone DDS runtime crate (default: cyclonedds_sys)
+    descriptors = bool      true implements DDSGenType with descriptors generated in
+                            Rust, false (default) links to the _desc of the C file
+
+ The generated code refers to its own types relative to the expansion, so it
+ may be expanded in any module. IDL errors are reported as compile_error!
+ with the file and the line.
+*/
+
+extern crate proc_macro;
+
+use cyclonedds_idlc::{generate_files, Configuration, Diagnostic, IdlSource, Loader};
+use proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+use std::fmt::Display;
+use std::path::PathBuf;
+
+// placeholders of the paths given by the user, the other `crate` paths are
+// made relative to the expansion
+const INCLUDE_PATH: &str = "__idlc_include_path";
+const RUNTIME_CRATE: &str = "__idlc_runtime_crate";
+
+struct Options {
+    file: Option<String>,
+    include: Vec<String>,
+    include_path: String,
+    runtime_crate: String,
+    descriptors: bool,
+}
+
+/// Expands to the Rust types of an IDL file
+#[proc_macro]
+pub fn idl(input: TokenStream) -> TokenStream {
+    match parse_options(input).and_then(|options| generate(&options)) {
+        Ok(tokens) => tokens,
+        Err(errors) => compile_errors(&errors),
+    }
+}
+
+/// Adds the Rust types of an IDL file to the module
+#[proc_macro_attribute]
+pub fn idl_module(args: TokenStream, item: TokenStream) -> TokenStream {
+    let generated = match parse_options(args).and_then(|options| generate(&options)) {
+        Ok(tokens) => tokens,
+        Err(errors) => compile_errors(&errors),
+    };
+    // mod name { items }, or mod name;
+    let mut tokens: Vec<TokenTree> = item.into_iter().collect();
+    match tokens.pop() {
+        Some(TokenTree::Group(ref body)) if body.delimiter() == Delimiter::Brace => {
+            let mut contents = body.stream();
+            contents.extend(generated);
+            tokens.push(TokenTree::Group(Group::new(Delimiter::Brace, contents)));
+        }
+        Some(TokenTree::Punct(ref semicolon)) if semicolon.as_char() == ';' => {
+            tokens.push(TokenTree::Group(Group::new(Delimiter::Brace, generated)));
+        }
+        _ => {
+            return compile_errors(&[error(Span::call_site(), "#[idl_module] applies to a module")]);
+        }
+    }
+    tokens.into_iter().collect()
+}
+
+fn compile_errors(errors: &[syn::Error]) -> TokenStream {
+    errors.iter().map(|error| TokenStream::from(error.to_compile_error())).collect()
+}
+
+fn error(span: Span, message: impl Display) -> syn::Error {
+    syn::Error::new(span.into(), message)
+}
+
+// The value of a string literal
+fn string_value(literal: &Literal) -> Result<String, String> {
+    let text = literal.to_string();
+    if let Some(raw) = text.strip_prefix('r') {
+        let hashes = raw.len() - raw.trim_start_matches('#').len();
+        return Ok(String::from(&raw[hashes + 1..raw.len() - hashes - 1]));
+    }
+    if text.len() < 2 || !text.starts_with('"') || !text.ends_with('"') {
+        return Err(format!("Expected a string, found {}", text));
+    }
+    let mut value = String::new();
+    let mut chars = text[1..text.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        match (c, c == '\\') {
+            (_, true) => match chars.next() {
+                Some('n') => value.push('\n'),
+                Some('t') => value.push('\t'),
+                Some(c) => value.push(c),
+                None => {}
+            },
+            (c, false) => value.push(c),
+        }
+    }
+    Ok(value)
+}
+
+// "file", name = value, ...
+fn parse_options(input: TokenStream) -> Result<Options, Vec<syn::Error>> {
+    let mut options = Options {
+        file: None,
+        include: Vec::new(),
+        include_path: String::from("crate"),
+        runtime_crate: String::from("cyclonedds_sys"),
+        descriptors: false,
+    };
+    let tokens: Vec<TokenTree> = input.into_iter().collect();
+    for option in tokens.split(|t| matches!(t, TokenTree::Punct(p) if p.as_char() == ',')) {
+        match option {
+            [] => {}
+            [TokenTree::Literal(file)] => {
+                options.file = Some(string_value(file).map_err(|e| vec![error(file.span(), e)])?)
+            }
+            [TokenTree::Ident(name), TokenTree::Punct(eq), value] if eq.as_char() == '=' => {
+                let string = || match value {
+                    TokenTree::Literal(literal) => string_value(literal),
+                    _ => Err(format!("Expected a string for {}", name)),
+                }
+                .map_err(|e| vec![error(value.span(), e)]);
+                match name.to_string().as_str() {
+                    "file" => options.file = Some(string()?),
+                    "include_path" => options.include_path = string()?,
+                    "runtime_crate" => options.runtime_crate = string()?,
+                    "descriptors" => {
+                        let value = syn::parse::<syn::LitBool>(TokenStream::from(value.clone()))
+                            .map_err(|e| vec![syn::Error::new(e.span(), format!("Expected true or false for {}", name))])?;
+                        options.descriptors = value.value;
+                    }
+                    "include" => match value {
+                        TokenTree::Group(dirs) if dirs.delimiter() == Delimiter::Bracket => {
+                            for dir in dirs.stream() {
+                                match dir {
+                                    TokenTree::Literal(ref dir) => {
+                                        options.include.push(string_value(dir).map_err(|e| vec![error(dir.span(), e)])?)
+                                    }
+                                    TokenTree::Punct(ref p) if p.as_char() == ',' => {}
+                                    _ => return Err(vec![error(dir.span(), "Expected include = [\"dir\", ...]")]),
+                                }
+                            }
+                        }
+                        _ => return Err(vec![error(value.span(), "Expected include = [\"dir\", ...]")]),
+                    },
+                    _ => return Err(vec![error(name.span(), format!("Unknown option {}", name))]),
+                }
+            }
+            _ => {
+                let span = option[0].span();
+                let option: TokenStream = option.iter().cloned().collect();
+                return Err(vec![error(span, format!("Unexpected {}", option))]);
+            }
+        }
+    }
+    Ok(options)
+}
+
+fn generate(options: &Options) -> Result<TokenStream, Vec<syn::Error>> {
+    let manifest_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap_or_default());
+    let file = match options.file.as_ref() {
+        Some(file) => manifest_dir.join(file),
+        None => return Err(vec![error(Span::call_site(), "Expected the IDL file")]),
+    };
+    let mut loader = Loader::new(
+        options
+            .include
+            .iter()
+            .map(|dir| manifest_dir.join(dir).to_string_lossy().into_owned())
+            .collect(),
+    );
+    let config = Configuration {
+        generate_descriptors: options.descriptors,
+        include_path: String::from(INCLUDE_PATH),
+        runtime_crate: String::from(RUNTIME_CRATE),
+        ..Default::default()
+    };
+
+    let generated = generate_files(&mut loader, &config, &[IdlSource::File(file.clone())]);
+    let errors: Vec<syn::Error> = generated
+        .diagnostics
+        .iter()
+        .filter_map(|d| match d {
+            Diagnostic::Error(_) => Some(error(Span::call_site(), d)),
+            Diagnostic::Warning(_) => None,
+        })
+        .collect();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let rust = match generated.outputs.first() {
+        Some(output) => &output.contents,
+        None => return Err(vec![error(Span::call_site(), format!("{}: no output", file.display()))]),
+    };
+    let tokens: TokenStream = rust
+        .parse()
+        .map_err(|e| vec![error(Span::call_site(), format!("{}: invalid generated code: {:?}", file.display(), e))])?;
+
+    let paths = (
+        path_tokens(&options.include_path).map_err(|e| vec![error(Span::call_site(), e)])?,
+        path_tokens(&options.runtime_crate).map_err(|e| vec![error(Span::call_site(), e)])?,
+    );
+    let mut tokens = relative_paths(tokens, 0, &paths);
+    // rebuild when one of the IDL files changes
+    for read in std::iter::once(file.to_string_lossy().into_owned()).chain(generated.included) {
+        tokens.extend(
+            format!("const _: &[u8] = include_bytes!({:?});", read)
+                .parse::<TokenStream>()
+                .unwrap(),
+        );
+    }
+    Ok(tokens)
+}
+
+fn path_tokens(path: &str) -> Result<TokenStream, String> {
+    path.parse().map_err(|_| format!("Invalid path {}", path))
+}
+
+// `crate` as seen from a module nested `depth` modules deep in the expansion
+fn expansion_root(depth: usize) -> Vec<TokenTree> {
+    if depth == 0 {
+        return vec![TokenTree::Ident(Ident::new("self", Span::call_site()))];
+    }
+    let mut path = Vec::new();
+    for idx in 0..depth {
+        if idx > 0 {
+            path.push(TokenTree::Punct(Punct::new(':', Spacing::Joint)));
+            path.push(TokenTree::Punct(Punct::new(':', Spacing::Alone)));
+        }
+        path.push(TokenTree::Ident(Ident::new("super", Span::call_site())));
+    }
+    path
+}
+
+// Make the `crate` paths of the generated code relative to the expansion and
+// put back the paths of the user
+fn relative_paths(tokens: TokenStream, depth: usize, paths: &(TokenStream, TokenStream)) -> TokenStream {
+    let tokens: Vec<TokenTree> = tokens.into_iter().collect();
+    let mut result = Vec::new();
+    for (idx, token) in tokens.iter().enumerate() {
+        match token {
+            TokenTree::Ident(ident) if ident.to_string() == "crate" => {
+                result.extend(expansion_root(depth));
+            }
+            TokenTree::Ident(ident) if ident.to_string() == INCLUDE_PATH => {
+                result.extend(paths.0.clone());
+            }
+            TokenTree::Ident(ident) if ident.to_string() == RUNTIME_CRATE => {
+                result.extend(paths.1.clone());
+            }
+            TokenTree::Group(group) => {
+                // the body of `mod name { }` is one module deeper
+                let is_module = idx >= 2
+                    && matches!(&tokens[idx - 2], TokenTree::Ident(m) if m.to_string() == "mod")
+                    && group.delimiter() == Delimiter::Brace;
+                let depth = if is_module { depth + 1 } else { depth };
+                let mut nested = Group::new(group.delimiter(), relative_paths(group.stream(), depth, paths));
+                nested.set_span(group.span());
+                result.push(TokenTree::Group(nested));
+            }
+            _ => result.push(token.clone()),
+        }
+    }
+    result.into_iter().collect()
+}
@@ -0,0 +1,37 @@
+// The types of an IDL file generated at compile time
+
+mod point {
+    cyclonedds_idlc_macros::idl!("tests/idl/common/point.idl", descriptors = false);
+}
+
+mod shape {
+    cyclonedds_idlc_macros::idl!("tests/idl/shape.idl", include_path = "crate", include = ["tests/idl"]);
+}
+
+#[cyclonedds_idlc_macros::idl_module(file = "tests/idl/shape.idl", include_path = "crate", include = ["tests/idl"])]
+mod nested {
+    pub const OTHER: i32 = 1;
+}
+
+#[test]
+fn types_of_nested_modules() {
+    let point = point::P::Point { x: 1, y: 2 };
+    let shape = shape::S::Shape {
+        id: 7,
+        p: [point.clone(), point],
+        name: std::ptr::null_mut(),
+    };
+    assert_eq!(shape.p[1].y, 2);
+    assert_eq!(shape::S::SIDES, 3);
+
+    // the included types are referred to through include_path
+    let tagged = nested::S::Inner::Tagged {
+        shape: nested::S::Shape {
+            id: 8,
+            p: [point::P::Point { x: 3, y: 4 }, point::P::Point { x: 5, y: 6 }],
+            name: std::ptr::null_mut(),
+        },
+    };
+    assert_eq!(tagged.shape.p[0].x, 3);
+    assert_eq!(nested::OTHER, 1);
+}
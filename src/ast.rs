@@ -8,7 +8,6 @@ use linked_hash_map::LinkedHashMap;
 use std::io::Error;
 use std::io::Write;
 
-use crate::cdds::type_trait::Type;
 
 ///
 #[derive(Clone, Debug)]
@@ -18,16 +17,6 @@ pub enum UnaryOp {
     Inverse,
 }
 
-const INDENTION: usize = 4;
-const ATTR_ALLOW_DEADCODE: &str = "pub use super::*;\n#[allow(dead_code)]";
-const ATTR_DERIVE_SERDE: &str = "#[derive(Serialize, Deserialize)]";
-const ATTR_DERIVE_CLONE_DEBUG: &str = "#[derive(Clone, Debug)]";
-const ATTR_ALLOW_NON_CAMEL_CASE_TYPES: &str = "#[allow(non_camel_case_types)]";
-const ATTR_ALLOW_NON_SNAKE_CASE: &str = "#[allow(non_snake_case)]";
-const IMPORT_SERDE: &str = "use serde_derive::{Serialize, Deserialize};";
-const ATTR_ALLOW_UNUSED_IMPORTS: &str = "#[allow(unused_imports)]";
-const ATTR_REPL_C: &str = "#[repr(C)]";
-
 
 impl UnaryOp {
     pub fn write<W: Write>(&self, out: &mut W) -> Result<(), Error> {
@@ -71,6 +60,176 @@ impl BinaryOp {
         };
         Ok(())
     }
+
+    // Lower values bind tighter
+    fn precedence(&self) -> usize {
+        match self {
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => 0,
+            BinaryOp::Add | BinaryOp::Sub => 1,
+            BinaryOp::LShift | BinaryOp::RShift => 2,
+            BinaryOp::And => 3,
+            BinaryOp::Xor => 4,
+            BinaryOp::Or => 5,
+        }
+    }
+
+    fn apply(&self, lhs: i64, rhs: i64) -> Option<i64> {
+        match self {
+            BinaryOp::Add => lhs.checked_add(rhs),
+            BinaryOp::Sub => lhs.checked_sub(rhs),
+            BinaryOp::Mul => lhs.checked_mul(rhs),
+            BinaryOp::Div => lhs.checked_div(rhs),
+            BinaryOp::Mod => lhs.checked_rem(rhs),
+            BinaryOp::LShift => lhs.checked_shl(rhs as u32),
+            BinaryOp::RShift => lhs.checked_shr(rhs as u32),
+            BinaryOp::Or => Some(lhs | rhs),
+            BinaryOp::Xor => Some(lhs ^ rhs),
+            BinaryOp::And => Some(lhs & rhs),
+        }
+    }
+}
+
+/// Default @bit_bound of a bitmask
+pub const BITMASK_DEFAULT_BIT_BOUND: u32 = 32;
+
+/// Maximum @bit_bound of a bitmask and maximum number of bits of a bitset
+pub const BITS_MAX: u32 = 64;
+
+/// The smallest unsigned integer type with at least `bits` bits
+pub fn bit_holder(bits: u32) -> IdlTypeSpec {
+    match bits {
+        0..=8 => IdlTypeSpec::U8Type,
+        9..=16 => IdlTypeSpec::U16Type,
+        17..=32 => IdlTypeSpec::U32Type,
+        _ => IdlTypeSpec::U64Type,
+    }
+}
+
+/// The destination type of a bitfield of `width` bits without explicit type
+pub fn bitfield_default_type(width: u32) -> IdlTypeSpec {
+    match width {
+        1 => IdlTypeSpec::BooleanType,
+        2..=8 => IdlTypeSpec::OctetType,
+        _ => bit_holder(width),
+    }
+}
+
+/// Number of bits of a bitfield destination type, None if it is not one
+pub fn bitfield_type_bits(spec: &IdlTypeSpec) -> Option<u32> {
+    match spec {
+        IdlTypeSpec::BooleanType => Some(1),
+        IdlTypeSpec::OctetType | IdlTypeSpec::I8Type | IdlTypeSpec::U8Type => Some(8),
+        IdlTypeSpec::I16Type | IdlTypeSpec::U16Type => Some(16),
+        IdlTypeSpec::I32Type | IdlTypeSpec::U32Type => Some(32),
+        IdlTypeSpec::I64Type | IdlTypeSpec::U64Type => Some(64),
+        _ => None,
+    }
+}
+
+/// Maximum number of digits of the IDL fixed point type
+pub const FIXED_MAX_DIGITS: u32 = 31;
+
+/// A fixed point constant, the value is `scaled` / 10^`scale`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FixedValue {
+    pub scaled: i128,
+    pub scale: u32,
+}
+
+impl FixedValue {
+    // Number of significant digits, at least the scale
+    pub fn digits(&self) -> u32 {
+        let mut digits = 1;
+        let mut magnitude = self.scaled.unsigned_abs() / 10;
+        while magnitude > 0 {
+            digits += 1;
+            magnitude /= 10;
+        }
+        digits.max(self.scale)
+    }
+
+    // Drop the trailing zeros of the fraction
+    fn normalize(self) -> Self {
+        let mut value = self;
+        while value.scale > 0 && value.scaled % 10 == 0 {
+            value.scaled /= 10;
+            value.scale -= 1;
+        }
+        value
+    }
+
+    /// The value multiplied by 10^`scale`, the fraction beyond `scale` is
+    /// truncated
+    pub fn scaled_to(&self, scale: u32) -> Option<i128> {
+        if scale >= self.scale {
+            self.rescale(scale)
+        } else {
+            Some(self.scaled / 10i128.pow(self.scale - scale))
+        }
+    }
+
+    fn rescale(&self, scale: u32) -> Option<i128> {
+        self.scaled.checked_mul(10i128.checked_pow(scale - self.scale)?)
+    }
+
+    // Truncate the fraction to fit the maximum number of digits
+    fn truncate(self) -> Option<Self> {
+        let mut value = self;
+        while value.digits() > FIXED_MAX_DIGITS && value.scale > 0 {
+            value.scaled /= 10;
+            value.scale -= 1;
+        }
+        if value.digits() > FIXED_MAX_DIGITS {
+            None
+        } else {
+            Some(value)
+        }
+    }
+
+    // Binary operations, with the result scale of the IDL specification
+    fn apply(self, op: &BinaryOp, rhs: Self) -> Option<Self> {
+        let value = match op {
+            BinaryOp::Add | BinaryOp::Sub => {
+                let scale = self.scale.max(rhs.scale);
+                let (lhs, rhs) = (self.rescale(scale)?, rhs.rescale(scale)?);
+                let scaled = match op {
+                    BinaryOp::Add => lhs.checked_add(rhs)?,
+                    _ => lhs.checked_sub(rhs)?,
+                };
+                FixedValue { scaled, scale }
+            }
+            BinaryOp::Mul => FixedValue {
+                scaled: self.scaled.checked_mul(rhs.scaled)?,
+                scale: self.scale + rhs.scale,
+            },
+            BinaryOp::Div => {
+                if rhs.scaled == 0 {
+                    return None;
+                }
+                // extend the dividend as far as possible, the quotient is
+                // truncated to the maximum number of digits
+                let mut lhs = self;
+                while lhs.scaled.unsigned_abs() < i128::MAX as u128 / 10
+                    && lhs.scale < rhs.scale + 2 * FIXED_MAX_DIGITS
+                {
+                    lhs.scaled *= 10;
+                    lhs.scale += 1;
+                }
+                if lhs.scale < rhs.scale {
+                    lhs = FixedValue {
+                        scaled: lhs.rescale(rhs.scale)?,
+                        scale: rhs.scale,
+                    };
+                }
+                FixedValue {
+                    scaled: lhs.scaled / rhs.scaled,
+                    scale: lhs.scale - rhs.scale,
+                }
+            }
+            _ => return None,
+        };
+        value.truncate()
+    }
 }
 
 ///
@@ -122,6 +281,14 @@ pub enum IdlValueExpr {
 
 impl IdlValueExpr {
     pub fn write<W: Write>(&self, out: &mut W) -> Result<(), Error> {
+        self.write_with(out, IdlScopedName::write)
+    }
+
+    fn write_with<W: Write>(
+        &self,
+        out: &mut W,
+        write_name: fn(&IdlScopedName, &mut W) -> Result<(), Error>,
+    ) -> Result<(), Error> {
         let _ = match self {
             IdlValueExpr::None => write!(out, ""),
             IdlValueExpr::DecLiteral(ref val) => write!(out, "{}", val),
@@ -133,13 +300,15 @@ impl IdlValueExpr {
             IdlValueExpr::WideStringLiteral(ref val) => write!(out, "{}", val),
             IdlValueExpr::BooleanLiteral(val) => write!(out, "{}", val),
             //            FloatLiteral(ref integ => write!(out, "{}", val), ref fract, ref expo, ref suffix) => write!(out, "{}", val),
-            IdlValueExpr::UnaryOp(op, ref expr) => op.write(out).and_then(|_| expr.write(out)),
-            IdlValueExpr::BinaryOp(op, ref expr) => op.write(out).and_then(|_| expr.write(out)),
+            IdlValueExpr::UnaryOp(op, ref expr) => op.write(out).and_then(|_| expr.write_with(out, write_name)),
+            IdlValueExpr::BinaryOp(op, ref expr) => op.write(out).and_then(|_| expr.write_with(out, write_name)),
             IdlValueExpr::Expr(ref expr1, ref expr2) => {
-                expr1.write(out).and_then(|_| expr2.write(out))
+                expr1
+                .write_with(out, write_name)
+                .and_then(|_| expr2.write_with(out, write_name))
             }
             IdlValueExpr::Brace(ref expr) => write!(out, "{}", "(")
-                .and_then(|_| expr.write(out))
+                .and_then(|_| expr.write_with(out, write_name))
                 .and_then(|_| write!(out, "{}", ")")),
             IdlValueExpr::FloatLiteral(ref integral, ref fraction, ref exponent, ref suffix) => {
                 integral.as_ref().and_then(|i| write!(out, "{}", i).err());
@@ -148,13 +317,167 @@ impl IdlValueExpr {
                 suffix.as_ref().and_then(|s| write!(out, "{}", s).err());
                 Ok(())
             }
-            IdlValueExpr::ScopedName(ref name) => name.write(out),
+            IdlValueExpr::ScopedName(ref name) => write_name(name, out),
             //_ => unimplemented!(),
         };
         Ok(())
     }
 }
 
+impl IdlValueExpr {
+    /// Evaluate an integer constant expression, resolving constant names
+    /// against `root`. Returns `None` if the expression is not an integer
+    /// constant.
+    pub fn eval_integer(&self, root: &IdlModule) -> Option<i64> {
+        self.eval_chain(&|expr| expr.eval_integer_primary(root), &|op, lhs, rhs| {
+            op.apply(lhs, rhs)
+        })
+    }
+
+    // The parser builds binary expressions as a right-leaning chain
+    // without precedence, flatten it and fold with operator precedence.
+    fn eval_chain<T: Copy>(
+        &self,
+        primary: &dyn Fn(&IdlValueExpr) -> Option<T>,
+        apply: &dyn Fn(&BinaryOp, T, T) -> Option<T>,
+    ) -> Option<T> {
+        let mut operands = Vec::new();
+        let mut operators = Vec::new();
+        let mut expr = self;
+        loop {
+            match expr {
+                IdlValueExpr::Expr(lhs, rhs) => match rhs.as_ref() {
+                    IdlValueExpr::BinaryOp(op, next) => {
+                        operands.push(primary(lhs)?);
+                        operators.push(op.clone());
+                        expr = next;
+                    }
+                    _ => return None,
+                },
+                _ => {
+                    operands.push(primary(expr)?);
+                    break;
+                }
+            }
+        }
+
+        for pass in 0..6 {
+            let mut idx = 0;
+            while idx < operators.len() {
+                if operators[idx].precedence() == pass {
+                    let op = operators.remove(idx);
+                    let rhs = operands.remove(idx + 1);
+                    operands[idx] = apply(&op, operands[idx], rhs)?;
+                } else {
+                    idx += 1;
+                }
+            }
+        }
+        operands.pop()
+    }
+
+    /// Evaluate a fixed point constant expression, integer and floating
+    /// point literals without exponent are fixed point literals.
+    pub fn eval_fixed(&self, root: &IdlModule) -> Option<FixedValue> {
+        self.eval_chain(&|expr| expr.eval_fixed_primary(root), &|op, lhs, rhs| {
+            lhs.apply(op, rhs)
+        })
+        .map(FixedValue::normalize)
+    }
+
+    /// Evaluate a floating point constant expression, integer operands are
+    /// converted.
+    pub fn eval_float(&self, root: &IdlModule) -> Option<f64> {
+        self.eval_chain(&|expr| expr.eval_float_primary(root), &|op, lhs, rhs| match op {
+            BinaryOp::Add => Some(lhs + rhs),
+            BinaryOp::Sub => Some(lhs - rhs),
+            BinaryOp::Mul => Some(lhs * rhs),
+            BinaryOp::Div => Some(lhs / rhs),
+            _ => None,
+        })
+    }
+
+    fn eval_float_primary(&self, root: &IdlModule) -> Option<f64> {
+        match self {
+            IdlValueExpr::FloatLiteral(integral, fraction, exponent, _) => format!(
+                "{}.{}e{}",
+                integral.as_deref().unwrap_or("0"),
+                fraction.as_deref().unwrap_or("0"),
+                exponent.as_deref().unwrap_or("0")
+            )
+            .parse()
+            .ok(),
+            IdlValueExpr::UnaryOp(UnaryOp::Neg, ref expr) => expr.eval_float_primary(root).map(|v| -v),
+            IdlValueExpr::UnaryOp(UnaryOp::Pos, ref expr) => expr.eval_float_primary(root),
+            IdlValueExpr::Brace(ref expr) => expr.eval_float(root),
+            IdlValueExpr::Expr(_, _) => self.eval_float(root),
+            IdlValueExpr::ScopedName(ref name) => root
+                .get_const_decl(name)
+                .and_then(|c| c.value.eval_float(root)),
+            _ => self.eval_integer_primary(root).map(|v| v as f64),
+        }
+    }
+
+    fn eval_fixed_primary(&self, root: &IdlModule) -> Option<FixedValue> {
+        match self {
+            IdlValueExpr::FloatLiteral(integral, fraction, None, suffix) => {
+                if let Some(suffix) = suffix {
+                    if suffix != "d" && suffix != "D" {
+                        return None;
+                    }
+                }
+                let integral = integral.as_deref().unwrap_or("0");
+                let fraction = fraction.as_deref().unwrap_or("");
+                Some(FixedValue {
+                    scaled: format!("{}{}", integral, fraction).parse().ok()?,
+                    scale: fraction.len() as u32,
+                })
+            }
+            IdlValueExpr::FloatLiteral(_, _, Some(_), _) => None,
+            IdlValueExpr::UnaryOp(UnaryOp::Neg, ref expr) => {
+                expr.eval_fixed_primary(root).map(|v| FixedValue {
+                    scaled: -v.scaled,
+                    scale: v.scale,
+                })
+            }
+            IdlValueExpr::UnaryOp(UnaryOp::Pos, ref expr) => expr.eval_fixed_primary(root),
+            IdlValueExpr::Brace(ref expr) => expr.eval_fixed(root),
+            IdlValueExpr::Expr(_, _) => self.eval_fixed(root),
+            IdlValueExpr::ScopedName(ref name) => root
+                .get_const_decl(name)
+                .and_then(|c| c.value.eval_fixed(root)),
+            _ => self.eval_integer_primary(root).map(|v| FixedValue {
+                scaled: v as i128,
+                scale: 0,
+            }),
+        }
+    }
+
+    fn eval_integer_primary(&self, root: &IdlModule) -> Option<i64> {
+        match self {
+            IdlValueExpr::DecLiteral(ref val) => val.parse::<i64>().ok(),
+            IdlValueExpr::HexLiteral(ref val) => {
+                i64::from_str_radix(val.trim_start_matches("0x").trim_start_matches("0X"), 16).ok()
+            }
+            IdlValueExpr::OctLiteral(ref val) => i64::from_str_radix(val, 8).ok(),
+            IdlValueExpr::BooleanLiteral(val) => Some(*val as i64),
+            IdlValueExpr::UnaryOp(UnaryOp::Neg, ref expr) => {
+                expr.eval_integer_primary(root).map(|v| -v)
+            }
+            IdlValueExpr::UnaryOp(UnaryOp::Pos, ref expr) => expr.eval_integer_primary(root),
+            IdlValueExpr::UnaryOp(UnaryOp::Inverse, ref expr) => {
+                expr.eval_integer_primary(root).map(|v| !v)
+            }
+            IdlValueExpr::Brace(ref expr) => expr.eval_integer(root),
+            IdlValueExpr::Expr(_, _) => self.eval_integer(root),
+            IdlValueExpr::ScopedName(ref name) => root
+                .get_const_decl(name)
+                .and_then(|c| c.value.eval_integer(root)),
+            _ => None,
+        }
+    }
+}
+
 ///
 impl Default for IdlValueExpr {
     fn default() -> IdlValueExpr {
@@ -168,33 +491,71 @@ pub struct IdlStructMember {
     pub id: String,
     pub type_spec: Box<IdlTypeSpec>,
     pub is_key: bool,
+    // the member embedding the base of a derived struct, see STRUCT_BASE_MEMBER
+    pub is_base: bool,
+    pub annotations: Vec<IdlAnnotation>,
+    // the line of the declaration in its file, the members added by the
+    // parser have the line of their definition
+    pub line: usize,
 }
 
-///
-impl IdlStructMember {
-    ///
-    pub fn write<W: Write>(&self, out: &mut W, _level: usize) -> Result<(), Error> {
-        write!(out, "{}: ", self.id)
-            .and_then(|_| self.type_spec.write(out))
-            .and_then(|_| write!(out, ","))
-    }
+/// An annotation as applied, @name or @name(params)
+#[derive(Clone, Debug)]
+pub struct IdlAnnotation {
+    // the name as written, without the @
+    pub name: String,
+    // the parameters, without a name for the single value form @name(value)
+    pub params: Vec<(Option<String>, Box<IdlValueExpr>)>,
 }
 
-///
+/// Name of the first member of a derived struct, holding its base struct
+pub const STRUCT_BASE_MEMBER: &str = "parent";
+
+/// A flag of a bitmask and its bit position
 #[derive(Clone, Debug)]
-pub struct IdlSwitchElement {
+pub struct IdlBitValue {
+    pub id: String,
+    pub position: u32,
+}
+
+/// A field of a bitset, the bits of anonymous fields are padding
+#[derive(Clone, Debug)]
+pub struct IdlBitField {
+    pub id: Option<String>,
+    pub width: u32,
+    pub type_spec: Box<IdlTypeSpec>,
+}
+
+/// Direction of an operation parameter
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IdlParamAttribute {
+    In,
+    Out,
+    InOut,
+}
+
+/// A parameter of an interface operation
+#[derive(Clone, Debug)]
+pub struct IdlParameter {
     pub id: String,
+    pub attribute: IdlParamAttribute,
     pub type_spec: Box<IdlTypeSpec>,
 }
 
+/// An operation of an interface, a void operation has no return type
+#[derive(Clone, Debug)]
+pub struct IdlOperation {
+    pub id: String,
+    pub return_type: Option<Box<IdlTypeSpec>>,
+    pub params: Vec<IdlParameter>,
+    pub raises: Vec<IdlScopedName>,
+}
+
 ///
-impl IdlSwitchElement {
-    ///
-    pub fn write<W: Write>(&self, out: &mut W, _level: usize) -> Result<(), Error> {
-        write!(out, "{}: ", self.id)
-            .and_then(|_| self.type_spec.write(out))
-            .and_then(|_| write!(out, ","))
-    }
+#[derive(Clone, Debug)]
+pub struct IdlSwitchElement {
+    pub id: String,
+    pub type_spec: Box<IdlTypeSpec>,
 }
 
 ///
@@ -211,32 +572,10 @@ pub struct IdlSwitchCase {
     pub elem_spec: Box<IdlSwitchElement>,
 }
 
-///
-impl IdlSwitchCase {
-    ///
-    pub fn write<W: Write>(&self, out: &mut W, level: usize) -> Result<(), Error> {
-        for label in &self.labels {
-            match label {
-                IdlSwitchLabel::Label(ref val_expr) => {
-                    write!(out, "{:indent$}", "", indent = level * INDENTION)
-                        .and_then(|_| val_expr.write(out))
-                        .and_then(|_| write!(out, "{}", "{"))
-                        .and_then(|_| self.elem_spec.write(out, level + 1))
-                        .and_then(|_| writeln!(out, "{}", "},"))?
-                }
-                IdlSwitchLabel::Default => write!(
-                    out,
-                    "{:indent$}default{}",
-                    "",
-                    "{",
-                    indent = level * INDENTION
-                )
-                .and_then(|_| self.elem_spec.write(out, level + 1))
-                .and_then(|_| writeln!(out, "{}", "},"))?,
-            }
-        }
-        Ok(())
-    }
+/// Size of the packed BCD representation of fixed<digits, _>: a nibble per
+/// digit and the sign nibble
+pub fn fixed_size(digits: u32) -> u32 {
+    (digits + 2) / 2
 }
 
 ///
@@ -247,16 +586,25 @@ pub enum IdlTypeSpec {
     SequenceType(Box<IdlTypeSpec>, Option<Box<IdlValueExpr>>),
     StringType(Option<Box<IdlValueExpr>>),
     WideStringType(Option<Box<IdlValueExpr>>),
-    // FixedPtType,
+    // map<key, value, bound>, serialized as a sequence of the entry struct
+    MapType(
+        Box<IdlTypeSpec>,
+        Box<IdlTypeSpec>,
+        Option<Box<IdlValueExpr>>,
+        Option<IdlScopedName>,
+    ),
     // EnumDcl,
-    // BitsetDcl,
-    // BitmaskDcl,
     F32Type,
     F64Type,
+    // opaque long double, see LongDoubleRepr
     F128Type,
+    // fixed<digits, scale>
+    FixedPtType(u32, u32),
+    I8Type,
     I16Type,
     I32Type,
     I64Type,
+    U8Type,
     U16Type,
     U32Type,
     U64Type,
@@ -270,56 +618,6 @@ pub enum IdlTypeSpec {
     ScopedName(IdlScopedName),
 }
 
-///
-impl IdlTypeSpec {
-    ///
-    pub fn write<W: Write>(&self, out: &mut W) -> Result<(), Error> {
-        let _ = match self {
-            IdlTypeSpec::F32Type => write!(out, "f32"),
-            IdlTypeSpec::F64Type => write!(out, "f64"),
-            IdlTypeSpec::F128Type => write!(out, "f128"),
-            IdlTypeSpec::I16Type => write!(out, "i16"),
-            IdlTypeSpec::I32Type => write!(out, "i32"),
-            IdlTypeSpec::I64Type => write!(out, "i64"),
-            IdlTypeSpec::U16Type => write!(out, "u16"),
-            IdlTypeSpec::U32Type => write!(out, "u32"),
-            IdlTypeSpec::U64Type => write!(out, "u64"),
-            IdlTypeSpec::CharType => write!(out, "char"),
-            IdlTypeSpec::WideCharType => write!(out, "char"),
-            IdlTypeSpec::BooleanType => write!(out, "bool"),
-            IdlTypeSpec::OctetType => write!(out, "u8"),
-            IdlTypeSpec::StringType(None) => write!(out, "*mut ::std::os::raw::c_char"),
-            IdlTypeSpec::WideStringType(None) => write!(out, "*mut ::std::os::raw::c_char"),
-            // TODO implement String/Sequence bounds
-            IdlTypeSpec::StringType(_) => write!(out, "String"),
-            // TODO implement String/Sequence bounds for serializer and deserialzer
-            IdlTypeSpec::WideStringType(_) => write!(out, "String"),
-            IdlTypeSpec::SequenceType(_, _) => write!(out, "dds_sequence_t"),
-            /*
-            IdlTypeSpec::SequenceType(typ_expr, _) => write!(out, "Vec<")
-                .and_then(|_| typ_expr.as_ref().write(out))
-                .and_then(|_| write!(out, ">")),*/
-            IdlTypeSpec::ArrayType(typ_expr, dim_expr_list) => {
-                for _ in dim_expr_list {
-                    let _ = write!(out, "[");
-                }
-                let _ = typ_expr.as_ref().write(out);
-                for dim_expr in dim_expr_list {
-                    // TODO return result
-                    let _ = write!(out, ";")
-                        .and_then(|_| dim_expr.as_ref().write(out))
-                        .and_then(|_| write!(out, "]"));
-                }
-                Ok(())
-            }
-            IdlTypeSpec::ScopedName(ref name) => name.write(out),
-            _ => unimplemented!(),
-        };
-
-        Ok(())
-    }
-}
-
 ///
 impl Default for IdlTypeSpec {
     fn default() -> IdlTypeSpec {
@@ -335,6 +633,29 @@ pub enum IdlTypeDclKind {
     StructDcl(String, Vec<Box<IdlStructMember>>, bool),
     UnionDcl(String, Box<IdlTypeSpec>, Vec<IdlSwitchCase>),
     EnumDcl(String, Vec<String>),
+    BitmaskDcl(String, u32, Vec<IdlBitValue>),
+    BitsetDcl(String, Vec<IdlBitField>),
+    // a struct or union declared ahead of its definition, e.g. for recursive types
+    ForwardDcl(String),
+    // an interface, served by the DDS-RPC request and reply topics, see cdds::rpc
+    InterfaceDcl(String, Vec<IdlOperation>),
+}
+
+impl IdlTypeDclKind {
+    /// The name of the declared type
+    pub fn id(&self) -> &str {
+        match self {
+            IdlTypeDclKind::None => "",
+            IdlTypeDclKind::TypeDcl(id, _)
+            | IdlTypeDclKind::StructDcl(id, _, _)
+            | IdlTypeDclKind::UnionDcl(id, _, _)
+            | IdlTypeDclKind::EnumDcl(id, _)
+            | IdlTypeDclKind::BitmaskDcl(id, _, _)
+            | IdlTypeDclKind::BitsetDcl(id, _)
+            | IdlTypeDclKind::ForwardDcl(id)
+            | IdlTypeDclKind::InterfaceDcl(id, _) => id,
+        }
+    }
 }
 
 ///
@@ -344,537 +665,10 @@ impl Default for IdlTypeDclKind {
     }
 }
 
-// create typename from scope and name
-fn scope_to_name(scope:&Vec<String>, typename:&str) -> String {
-    let mut name = scope.join("::");
-    if name.len() > 0 {
-        format!("{}::{}",name,typename)
-    } else {
-        String::from(typename)
-    }
-}
-
-fn build_topic_xml_metadata(root:&IdlModule, typedecl : &IdlTypeDcl, scope:&Vec<String>) -> String {
-    
-    String::from("<<METADADATA>>")
-}
-
 ///
 #[derive(Clone, Debug, Default)]
 pub struct IdlTypeDcl(pub IdlTypeDclKind);
 
-///
-impl IdlTypeDcl {
-    ///
-    ///
-    pub fn write<W: Write>(
-        &self,
-        out: &mut W,
-        level: usize,
-        root: &IdlModule,
-        scope :&Vec<String>,
-        gen_descriptor : bool,
-    ) -> Result<(), Error> {
-        match self.0 {
-            IdlTypeDclKind::TypeDcl(ref id, ref type_spec) => {
-                // TODO collect/return result
-                let _ = writeln!(out, "");
-                let _ = writeln!(out, "{:indent$}//", "", indent = level * INDENTION);
-                let _ = writeln!(out, "{:indent$}//", "", indent = level * INDENTION);
-                let _ = writeln!(
-                    out,
-                    "{:indent$}{}",
-                    "",
-                    ATTR_ALLOW_DEADCODE,
-                    indent = level * INDENTION
-                );
-                let _ = writeln!(
-                    out,
-                    "{:indent$}{}",
-                    "",
-                    ATTR_ALLOW_NON_CAMEL_CASE_TYPES,
-                    indent = level * INDENTION
-                );
-                let _ = write!(
-                    out,
-                    "{:indent$}pub type {} = ",
-                    "",
-                    id,
-                    indent = level * INDENTION
-                );
-                let _ = type_spec.as_ref().write(out);
-                let _ = writeln!(out, ";");
-                Ok(())
-            }
-            IdlTypeDclKind::StructDcl(ref id, ref type_spec, is_key) => {
-                // TODO collect/return result
-                let _ = writeln!(out, "");
-                let _ = writeln!(out, "{:indent$}//", "", indent = level * INDENTION);
-                let _ = writeln!(out, "{:indent$}//", "", indent = level * INDENTION);
-                let _ = writeln!(
-                    out,
-                    "{:indent$}{}",
-                    "",
-                    ATTR_ALLOW_DEADCODE,
-                    indent = level * INDENTION
-                );
-                let _ = writeln!(
-                    out,
-                    "{:indent$}{}",
-                    "",
-                    ATTR_ALLOW_NON_CAMEL_CASE_TYPES,
-                    indent = level * INDENTION
-                );
-                let _ = writeln!(
-                    out,
-                    "{:indent$}{}",
-                    "",
-                    ATTR_REPL_C,
-                    indent = level * INDENTION
-                );
-                let _ = writeln!(
-                    out,
-                    "{:indent$}{}",
-                    "",
-                    ATTR_DERIVE_CLONE_DEBUG,
-                    indent = level * INDENTION
-                );
-                let _ = writeln!(
-                    out,
-                    "{:indent$}pub struct {} {}",
-                    "",
-                    id,
-                    "{",
-                    indent = level * INDENTION
-                );
-                for member in type_spec {
-                    let _ = write!(out, "{:indent$}pub ", "", indent = (level + 1) * INDENTION)
-                        .and_then(|_| member.as_ref().write(out, level + 1))
-                        .and_then(|_| writeln!(out));
-                }
-
-                let _ = writeln!(out, "{:indent$}{}", "", "}", indent = level * INDENTION);
-
-                // Implementation needed only for key structures
-                if is_key && gen_descriptor  {
-                    let _ = writeln!(
-                        out,
-                        "{:indent$}pub struct {}_desc {}",
-                        "",
-                        id,
-                        "{",
-                        indent = level * INDENTION
-                    );
-
-                    let num_keys = type_spec.iter().filter(|m| m.is_key).count();
-                    let num_ops = type_spec
-                        .iter()
-                        .fold(0, |acc, x| acc + x.type_spec.get_meta_op_size(root))
-                        + 1;
-
-                    let _ = writeln!(
-                        out,
-                        "{:indent$}key_descriptor : [dds_key_descriptor;{}],",
-                        "",
-                        num_keys,
-                        indent = (level + 1) * INDENTION
-                    );
-                    let _ = writeln!(
-                        out,
-                        "{:indent$}ops : [u32;{}],",
-                        "",
-                        num_ops,
-                        indent = (level + 1) * INDENTION
-                    );
-                    let _ = writeln!(
-                        out,
-                        "{:indent$}{}",
-                        "",
-                        "descriptor : dds_topic_descriptor,",
-                        indent = (level + 1) * INDENTION
-                    );
-                    let _ = writeln!(
-                        out,
-                        "{:indent$}{}",
-                        "",
-                        "_pin: PhantomPinned,",
-                        indent = (level + 1) * INDENTION
-                    );
-                    let _ = writeln!(out, "{:indent$}{}", "", "}", indent = level * INDENTION);
-
-                    let _ = writeln!(
-                        out,
-                        "{:indent$}{}",
-                        "",
-                        "//Implementation",
-                        indent = level * INDENTION
-                    );
-
-                    let _ = writeln!(
-                        out,
-                        "{:indent$}impl {} {}",
-                        "",
-                        id,
-                        "{",
-                        indent = level * INDENTION
-                    );
-                    let _ = writeln!(
-                        out,
-                        "{:indent$}pub fn descriptor() -> Pin<Box<{}_desc>> {}",
-                        "",
-                        id,
-                        "{",
-                        indent = (level + 1) * INDENTION
-                    );
-
-                    let _ = writeln!(
-                        out,
-                        "{:indent$}let mut res = {}_desc {{",
-                        "",
-                        id,
-                        indent = (level + 2) * INDENTION
-                    );
-
-                    let _ = writeln!(
-                        out,
-                        "{:indent$}key_descriptor : [",
-                        "",
-                        indent = (level + 3) * INDENTION
-                    );
-                    let mut offset = 0;
-                    for k in type_spec.iter() {
-                        if k.is_key {
-                            let _ = writeln!(
-                                out,
-                                "{:indent$}dds_key_descriptor {{",
-                                "",
-                                indent = (level + 4) * INDENTION
-                            );
-                            let _ = writeln!(out, "{:indent$}m_name : unsafe {{ std::ffi::CStr::from_bytes_with_nul_unchecked(b\"{}\\0\").as_ptr()}},", "", k.id, indent = (level+5) * INDENTION);
-                            let _ = writeln!(
-                                out,
-                                "{:indent$}m_index : {},",
-                                "",
-                                offset,
-                                indent = (level + 5) * INDENTION
-                            );
-                            let _ = writeln!(
-                                out,
-                                "{:indent$}}},",
-                                "",
-                                indent = (level + 4) * INDENTION
-                            );
-                        }
-                        offset = offset + k.type_spec.get_meta_op_size(root);
-                    }
-                    //end of key_descriptor
-                    let _ = writeln!(
-                        out,
-                        "{:indent$}{}",
-                        "",
-                        "],",
-                        indent = (level + 3) * INDENTION
-                    );
-
-                    //Begin ops
-                    let _ = writeln!(
-                        out,
-                        "{:indent$}ops : [",
-                        "",
-                        indent = (level + 3) * INDENTION
-                    );
-
-                    for op in self.get_meta_op("","",false,root) {
-                        let _ = writeln!(
-                            out,
-                            "{:indent$}{},",
-                            "",
-                            op,
-                            indent = (level + 5) * INDENTION
-                        );
-                    }
-                    //end ops
-                    let _ = writeln!(
-                        out,
-                        "{:indent$}{}",
-                        "",
-                        "],",
-                        indent = (level + 3) * INDENTION
-                    );
-
-                    let _ = writeln!(
-                        out,
-                        "{:indent$}descriptor : dds_topic_descriptor {{",
-                        "",
-                        indent = (level + 3) * INDENTION
-                    );
-
-                    let _ = writeln!(out,"{:indent$}m_size : std::mem::size_of::<{}>() as u32,",
-                        "",
-                        id,
-                        indent = (level + 4) * INDENTION
-                    );
-                    let _ = writeln!(out,"{:indent$}m_align: std::mem::size_of::<*const u8>() as u32,",
-                        "",
-                        indent = (level + 4) * INDENTION
-                    );
-                    //| TBD_DDS_TOPIC_FIXED_KEY
-
-                    let _ = writeln!(out,"{:indent$}m_flagset: DDS_TOPIC_NO_OPTIMIZE{},",
-                    "",
-                    if num_keys > 0 && num_keys <= 16 { " | DDS_TOPIC_FIXED_KEY"} else {""},
-                    indent = (level + 4) * INDENTION
-                    );
-                    let _ = writeln!(out,"{:indent$}m_nkeys: {},",
-                    "",
-                    num_keys,
-                    indent = (level + 4) * INDENTION
-                    );
-
-                    let _ = writeln!(out,"{:indent$}m_typename: unsafe {{std::ffi::CStr::from_bytes_with_nul_unchecked(b\"{}\\0\").as_ptr()}},",
-                    "",
-                    scope_to_name(scope,id),
-                    indent = (level + 4) * INDENTION
-                    );
-                    let _ = writeln!(out,"{:indent$}m_keys: std::ptr::null(),",
-                    "",
-                    indent = (level + 4) * INDENTION
-                    );
-                    let _ = writeln!(out,"{:indent$}m_nops: {},",
-                    "",
-                    num_ops,
-                    indent = (level + 4) * INDENTION
-                    );
-                    let _ = writeln!(out,"{:indent$}m_ops: std::ptr::null(),",
-                    "",
-                    indent = (level + 4) * INDENTION
-                    );
-                    let _ = writeln!(out,"{:indent$}m_meta: unsafe {{std::ffi::CStr::from_bytes_with_nul_unchecked(b\"<MetaData version=\"1.0.0\">{}</MetaData>\\0\").as_ptr()}},",
-                    "",
-                    //build_topic_xml_metadata(root),
-                    indent = (level + 4) * INDENTION
-                    );
-
-
-                      //end descriptor
-                    let _ = writeln!(
-                        out,
-                        "{:indent$}}},",
-                        "",
-                        indent = (level + 3) * INDENTION
-                    );
-
-                    let _ = writeln!(
-                        out,
-                        "{:indent$}_pin : PhantomPinned,",
-                        "",
-                        indent = (level + 3) * INDENTION
-                    );
-
-                    //descriptor
-
-                    let _ = writeln!(
-                        out,
-                        "{:indent$}{}",
-                        "",
-                        "};",
-                        indent = (level + 2) * INDENTION
-                    );
-
-                    /*
-                        let key_desc = res.key_descriptor.as_ptr();
-                        let ops = res.ops.as_ptr();
-
-                        res.descriptor.m_keys = key_desc;
-                        res.descriptor.m_ops = ops;
-
-                        let mut boxed = Box::pin(res);
-                        boxed
-                    */
-
-                    let _ = write!(
-                        out,"{:indent$}{};\n{:indent$}{};\n{:indent$}{}\n",
-                        "",
-                        "res.descriptor.m_keys = res.key_descriptor.as_ptr()",
-                        "",
-                        "res.descriptor.m_ops = res.ops.as_ptr()",
-                        "",
-                        "Box::pin(res)",
-                        indent = (level + 2) * INDENTION
-                    );
-
-                    // end of descriptor()
-                    let _ = writeln!(
-                        out,
-                        "{:indent$}{}",
-                        "",
-                        "}",
-                        indent = (level + 1) * INDENTION
-                    );
-
-                    /*
-      pub fn desc(&self) -> * const dds_topic_descriptor {
-            &self.descriptor
-        }
-                    */
-                    let _ = write!(
-                        out,"{:indent$}{};\n{:indent$}{};\n{:indent$}{}\n",
-                        "",
-                        "pub fn desc(&self) -> * const dds_topic_descriptor {",
-                        "",
-                        "    &self.descriptor",
-                        "",
-                        "}",
-                        indent = (level + 1) * INDENTION
-                    );
-
-
-                    let _ = writeln!(out, "{:indent$}{}", "", "}", indent = level * INDENTION);
-                } else if is_key {
-                    
-                    // don't generate the descriptor. It is generated by the cyclonedds C generator.alloc
-                    /*
-                    extern "C" {
-                        pub static HelloWorldData_Msg_desc: dds_topic_descriptor_t;
-                    }
-
-                    impl DDSGenType for {TYPENAME}{
-                        unsafe fn get_descriptor() -> &'static dds_topic_descriptor_t {
-                            &{TYPENAME}_desc
-                        }
-                    }
-
-                    */
-                    let ddsgentype_trait = crate::cdds::generate_ddsgen_trait_for_type(id,&scope);
-                    write!(out,"{}",ddsgentype_trait)?;
-
-                } else {
-                    // do nothing
-                }
-
-                Ok(())
-            }
-
-            IdlTypeDclKind::EnumDcl(ref id, ref enums) => {
-                // TODO collect/return result
-                let _ = writeln!(out, "");
-                let _ = writeln!(out, "{:indent$}//", "", indent = level * INDENTION);
-                let _ = writeln!(out, "{:indent$}//", "", indent = level * INDENTION);
-                let _ = writeln!(
-                    out,
-                    "{:indent$}{}",
-                    "",
-                    ATTR_ALLOW_DEADCODE,
-                    indent = level * INDENTION
-                );
-                let _ = writeln!(
-                    out,
-                    "{:indent$}{}",
-                    "",
-                    ATTR_ALLOW_NON_CAMEL_CASE_TYPES,
-                    indent = level * INDENTION
-                );
-                let _ = writeln!(
-                    out,
-                    "{:indent$}{}",
-                    "",
-                    ATTR_REPL_C,
-                    indent = level * INDENTION
-                );
-                let _ = writeln!(
-                    out,
-                    "{:indent$}{}",
-                    "",
-                    ATTR_DERIVE_CLONE_DEBUG,
-                    indent = level * INDENTION
-                );
-                let _ = writeln!(
-                    out,
-                    "{:indent$}pub enum {} {}",
-                    "",
-                    id,
-                    "{",
-                    indent = level * INDENTION
-                );
-                for variant in enums {
-                    let _ = writeln!(
-                        out,
-                        "{:indent$}{},",
-                        "",
-                        variant,
-                        indent = (level + 1) * INDENTION
-                    );
-                }
-                let _ = writeln!(out, "{:indent$}{}", "", "}", indent = level * INDENTION);
-                Ok(())
-            }
-
-            IdlTypeDclKind::UnionDcl(ref id, ref _type_spec, ref switch_cases) => {
-                // TODO collect/return result
-                let _ = writeln!(out, "");
-                let _ = writeln!(out, "{:indent$}//", "", indent = level * INDENTION);
-                let _ = writeln!(out, "{:indent$}//", "", indent = level * INDENTION);
-                let _ = writeln!(
-                    out,
-                    "{:indent$}{}",
-                    "",
-                    ATTR_ALLOW_DEADCODE,
-                    indent = level * INDENTION
-                );
-                let _ = writeln!(
-                    out,
-                    "{:indent$}{}",
-                    "",
-                    ATTR_ALLOW_NON_CAMEL_CASE_TYPES,
-                    indent = level * INDENTION
-                );
-                let _ = writeln!(
-                    out,
-                    "{:indent$}{}",
-                    "",
-                    ATTR_REPL_C,
-                    indent = level * INDENTION
-                );
-                let _ = writeln!(
-                    out,
-                    "{:indent$}{}",
-                    "",
-                    ATTR_DERIVE_CLONE_DEBUG,
-                    indent = level * INDENTION
-                );
-                let _ = writeln!(
-                    out,
-                    "{:indent$}pub enum {} {}",
-                    "",
-                    id,
-                    "{",
-                    indent = level * INDENTION
-                );
-                for case in switch_cases {
-                    let _ = case.write(out, level + 1);
-                }
-                let _ = writeln!(out, "{:indent$}{}", "", "}", indent = level * INDENTION);
-
-                let _ = writeln!(out, "{:indent$}//", "", indent = level * INDENTION);
-                let _ = writeln!(
-                    out,
-                    "{:indent$}// TODO custom de-/serializer",
-                    "",
-                    indent = level * INDENTION
-                );
-                let _ = writeln!(out, "{:indent$}//", "", indent = level * INDENTION);
-
-                Ok(())
-            }
-            _ => Ok(()),
-        }
-    }
-
-    pub fn get_num_ops() -> usize {
-        5
-    }
-}
-
 ///
 #[derive(Clone, Default, Debug)]
 pub struct IdlConstDcl {
@@ -883,88 +677,46 @@ pub struct IdlConstDcl {
     pub value: Box<IdlValueExpr>,
 }
 
-///
-impl IdlConstDcl {
-    ///
-    ///
-    pub fn write<W: Write>(
-        &self,
-        out: &mut W,
-        level: usize,
-        root: &IdlModule,
-    ) -> Result<(), Error> {
-        writeln!(
-            out,
-            "{:indent$}{}",
-            "",
-            ATTR_ALLOW_DEADCODE,
-            indent = level * INDENTION
-        )
-        .and_then(|_| {
-            write!(
-                out,
-                "{:indent$}pub const {}",
-                "",
-                self.id,
-                indent = level * INDENTION
-            )
-        })
-        .and_then(|_| write!(out, ": "))
-        .and_then(|_| self.typedcl.write(out))
-        .and_then(|_| write!(out, " = "))
-        .and_then(|_| self.value.write(out))
-        .and_then(|_| writeln!(out, ";"))
-    }
-}
-
 ///
 #[derive(Clone, Default, Debug)]
 pub struct IdlModule {
     pub id: Option<String>,
-    pub level: usize,
     pub modules: LinkedHashMap<String, Box<IdlModule>>,
     pub types: LinkedHashMap<String, Box<IdlTypeDcl>>,
     pub constants: LinkedHashMap<String, Box<IdlConstDcl>>,
-    // vector of (type_name, Vec<Keys>)
-    // TODO: This is not needed anymore.
+    // vector of (type_name, Vec<Keys>) in the order of the keylist pragma
     pub keys: Vec<(String, Vec<String>)>,
-      
+    // the include file of each type and constant declared by an included file
+    pub sources: LinkedHashMap<String, String>,
+    // the line of the declaration of each type and constant in its file
+    pub lines: LinkedHashMap<String, usize>,
+    // the annotations of the annotated type declarations
+    pub annotations: LinkedHashMap<String, Vec<IdlAnnotation>>,
 }
 
 ///
 impl IdlModule {
-    pub fn new(id: Option<String>, level: usize) -> IdlModule {
+    pub fn new(id: Option<String>) -> IdlModule {
         IdlModule {
             id: id,
-            level: level,
             modules: LinkedHashMap::default(),
             types: LinkedHashMap::default(),
             constants: LinkedHashMap::default(),
             keys: Vec::new(),
+            sources: LinkedHashMap::default(),
+            lines: LinkedHashMap::default(),
+            annotations: LinkedHashMap::default(),
         }
     }
 
     fn get_type_decl_rec(&self, scoped_name: &[String]) -> Option<&Box<IdlTypeDcl>> {
-        if scoped_name.len() == 1 {
-            return self
-                .types
-                .iter()
-                .find_map(|(k, v)| if *k == scoped_name[0] { Some(v) } else { None });
-        } else if scoped_name.len() > 1 {
-            let modname = &scoped_name[0];
-            let module = self.modules.iter().find_map(|(k, v)| {
-                if k == modname {
-                    v.get_type_decl_rec(&scoped_name[1..])
-                } else {
-                    println!("Not found module:{} cmp with {}", modname, k);
-                    None
-                }
-            });
-            module
-        } else {
-            println!("Not found:{:?}", scoped_name);
-
-            None
+        match scoped_name.split_first() {
+            Some((name, [])) => self.types.get(name),
+            Some((modname, rest)) => self
+                .modules
+                .get(modname)
+                .and_then(|m| m.get_type_decl_rec(rest)),
+            None => None,
         }
     }
 
@@ -973,6 +725,37 @@ impl IdlModule {
         self.get_type_decl_rec(&scoped_name.0)
     }
 
+    fn get_const_decl_rec(&self, scoped_name: &[String]) -> Option<&IdlConstDcl> {
+        match scoped_name.split_first() {
+            Some((name, [])) => self.constants.get(name).map(|c| c.as_ref()),
+            Some((modname, rest)) => self
+                .modules
+                .get(modname)
+                .and_then(|m| m.get_const_decl_rec(rest)),
+            None => None,
+        }
+    }
+
+    pub fn get_const_decl(&self, scoped_name: &IdlScopedName) -> Option<&IdlConstDcl> {
+        // absolute path
+        self.get_const_decl_rec(&scoped_name.0)
+    }
+
+    /// Find the module for `scope`, a path of module names starting at this module.
+    pub fn get_module(&self, scope: &[String]) -> Option<&IdlModule> {
+        match scope.split_first() {
+            Some((modname, rest)) => self.modules.get(modname).and_then(|m| m.get_module(rest)),
+            None => Some(self),
+        }
+    }
+
+    /// Keys of the topic `struct_name` in `#pragma keylist` order.
+    pub fn get_keys(&self, struct_name: &str) -> Option<&Vec<String>> {
+        self.keys
+            .iter()
+            .find_map(|(name, keys)| if name == struct_name { Some(keys) } else { None })
+    }
+
     pub fn set_topic_and_key_flags(
         &mut self,
         struct_name: &str,
@@ -989,10 +772,15 @@ impl IdlModule {
                         struct_found = true;
                         //println!("{} is a topic",&struct_name);
                         for key in keys {
+                            // nested keys ("member.field") are resolved when generating
+                            let (member_id, is_nested) = match key.find('.') {
+                                Some(pos) => (&key[..pos], true),
+                                None => (key.as_str(), false),
+                            };
                             for ref mut member in members.iter_mut() {
                                 //println!("Member:{}",&member.id);
-                                if key == &member.id {
-                                    member.is_key = true;
+                                if member_id == member.id {
+                                    member.is_key = member.is_key || !is_nested;
                                     keys_found = keys_found + 1;
                                     //println!("{} is a key",&member.id);
                                 }
@@ -1004,81 +792,21 @@ impl IdlModule {
             }
         }
         if struct_found && (keys_found == keys.len()) {
+            // the keylist of a derived struct extends the keys inherited from its base
+            match self.keys.iter_mut().find(|(name, _)| name == struct_name) {
+                Some((_, topic_keys)) => {
+                    for key in keys {
+                        if !topic_keys.contains(key) {
+                            topic_keys.push(key.clone());
+                        }
+                    }
+                }
+                None => self.keys.push((String::from(struct_name), keys.clone())),
+            }
             Ok(())
         } else {
-            println!("Error! : Keys not found: {}", String::from(keys.join(",")));
             Err(IdlError::KeyNotFound(String::from(keys.join(","))))
         }
     }
-
-    pub fn write<W: Write>(
-        &self,
-        out: &mut W,
-        level: usize,
-        root: &IdlModule,
-        scope : &Vec<String>,
-        gen_descriptors : bool,
-    ) -> Result<(), Error> {
-
-        let mut scope = scope.clone();
-
-        let _prolog = match self.id {
-            Some(ref id_str) => { 
-                
-                scope.push(String::from(id_str));
-
-                writeln!(
-                out,
-                "{:indent$}{}",
-                "",
-                ATTR_ALLOW_NON_SNAKE_CASE,
-                indent = level * INDENTION
-            )}
-            .and_then(|_| {
-                writeln!(
-                    out,
-                    "{:indent$}pub mod {} {}",
-                    "",
-                    id_str,
-                    "{",
-                    indent = level * INDENTION
-                )
-            }),
-
-            _ => write!(out, ""),
-        };
-
-        let add: usize = if self.id.is_some() { 1 } else { 0 };
-
-
-        let _ = writeln!(
-            out,
-            "{:indent$}{}",
-            "",
-            ATTR_ALLOW_UNUSED_IMPORTS,
-            indent = (level + add) * INDENTION
-        );
-        //.and_then(|_| writeln!(out, "{:indent$}{}", "",
-        //                       IMPORT_SERDE, indent = (level + add) * INDENTION));
-
-        for (_, typ) in self.types.iter() {
-            typ.write(out, level + add, root, &scope, gen_descriptors)?;
-        }
-
-        for (_, module) in self.modules.iter() {
-            module.write(out, level + add, root,&scope, gen_descriptors)?;
-        }
-
-        for (_, cnst) in self.constants.iter() {
-            cnst.write(out, level + add, root)?;
-        }
-
-        let _epilog = match self.id {
-            Some(_) => writeln!(out, "{:indent$}{}", "", "}", indent = level * INDENTION),
-            _ => write!(out, ""),
-        };
-
-        Ok(())
-    }
 }
 
@@ -0,0 +1,366 @@
+// Copyright (C) 2020  Sojan James
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0>
+
+/* Code generators, selected by name.
+
+ A backend turns the model of a parsed IDL file into output files. The
+ built-in backends are "rust", "c" and "cpp", other generators are registered
+ next to them and selected the same way:
+
+    let mut backends = cyclonedds_idlc::Backends::new();
+    backends.register(MyBackend);
+    let generated = backends.generate_files(&["rust", "my"], &mut loader, &config, &sources);
+
+ All backends generate from the IR of the specification, see ir, third
+ party backends do the same.
+*/
+
+use crate::cdds::Model;
+use crate::cpp::CppBackend;
+use crate::ir::Specification;
+use crate::{parse_ir, Configuration, Diagnostic, Generated, IdlError, IdlLoader, IdlSource, OutputFile, OutputKind};
+
+use std::io::Write;
+
+/// A code generator
+pub trait Backend {
+    /// The name selecting the backend, e.g. with --lang
+    fn name(&self) -> &str;
+
+    /// The outputs of the main file of `spec`. The main output comes last, it
+    /// is the one written by generate_with_backend.
+    fn generate(&self, spec: &Specification, config: &Configuration) -> Result<Vec<OutputFile>, IdlError>;
+}
+
+/// The Rust types, and the layout checks with `layout_checks`
+pub struct RustBackend;
+
+/// The C header, the C file of the descriptors with `generate_descriptors`
+/// and the C layout tables with `layout_checks`
+pub struct CBackend;
+
+impl Backend for RustBackend {
+    fn name(&self) -> &str {
+        "rust"
+    }
+
+    fn generate(&self, spec: &Specification, config: &Configuration) -> Result<Vec<OutputFile>, IdlError> {
+        Ok(vec![output(spec, config, OutputKind::Rust, write_rust)?])
+    }
+}
+
+impl Backend for CBackend {
+    fn name(&self) -> &str {
+        "c"
+    }
+
+    fn generate(&self, spec: &Specification, config: &Configuration) -> Result<Vec<OutputFile>, IdlError> {
+        let mut outputs = vec![output(spec, config, OutputKind::CHeader, write_c_header)?];
+        if config.generate_descriptors {
+            outputs.push(output(spec, config, OutputKind::CSource, write_c_source)?);
+        }
+        if config.layout_checks {
+            outputs.push(output(spec, config, OutputKind::CLayout, write_c_layout)?);
+        }
+        Ok(outputs)
+    }
+}
+
+type Writer = fn(&mut Vec<u8>, &Specification, &Configuration) -> Result<(), IdlError>;
+
+fn output(
+    spec: &Specification,
+    config: &Configuration,
+    kind: OutputKind,
+    write: Writer,
+) -> Result<OutputFile, IdlError> {
+    let mut out = Vec::new();
+    write(&mut out, spec, config)?;
+    Ok(OutputFile {
+        name: kind.file_name(&config.idl_name),
+        kind,
+        contents: String::from_utf8_lossy(&out).into_owned(),
+    })
+}
+
+fn io_error(e: std::io::Error) -> IdlError {
+    IdlError::ErrorMesg(e.to_string())
+}
+
+// The model of the generated C and Rust types
+fn model<'a>(spec: &'a Specification, config: &Configuration) -> Model<'a> {
+    Model {
+        root: &spec.root,
+        wchar_size: config.wchar_size,
+    }
+}
+
+fn write_rust<W: Write>(out: &mut W, spec: &Specification, config: &Configuration) -> Result<(), IdlError> {
+    let root = &spec.root;
+    let model = model(spec, config);
+
+    let descriptors = Some(config.cyclone_version).filter(|_| config.generate_descriptors);
+
+    crate::cdds::imports::write_runtime_imports(out, &config.runtime_crate, model, descriptors).map_err(io_error)?;
+    if crate::cdds::imports::uses_fixed(root) {
+        let _ = out.write(include_str!("templates/fixed.txt").as_bytes());
+    }
+    if crate::cdds::imports::uses_long_double(root) {
+        let _ = out.write(include_str!("templates/long_double.txt").as_bytes());
+    }
+    if crate::cdds::rpc::has_interfaces(root) {
+        let _ = out.write(include_str!("templates/rpc.txt").as_bytes());
+    }
+    if crate::cdds::imports::has_keyed_topics(root) {
+        let _ = out.write(include_str!("templates/key_hash.txt").as_bytes());
+    }
+    if crate::cdds::imports::has_topics(root) && config.generate_descriptors {
+        let _ = out.write(include_str!("templates/descriptor.txt").as_bytes());
+    }
+    // the declarations of included files are generated with their own file
+    crate::cdds::rust::write_module(out, 0, root, model, config).map_err(io_error)?;
+    if config.layout_checks {
+        crate::cdds::layout::write_layout_tests(out, root).map_err(io_error)?;
+    }
+    Ok(())
+}
+
+fn write_c_header<W: Write>(out: &mut W, spec: &Specification, config: &Configuration) -> Result<(), IdlError> {
+    let idlnamestem = String::from(config.idl_name.file_stem().unwrap().to_str().unwrap());
+    let idlname = config.idl_name.file_name().unwrap().to_str().unwrap();
+
+    let mut header_name = idlnamestem.clone();
+    header_name.push_str(".h");
+
+    let file_header = std::include_str!("cdds/templates/file_header.txt")
+        .replace("<FILENAME>", &header_name)
+        .replace("<IDLNAME>", idlname)
+        .replace("<HEADERDEFINE>", &crate::cdds::header_macro_name(&idlnamestem));
+    let _ = out.write(file_header.as_bytes());
+    // the headers of included files include their own includes
+    for (_, file) in spec.main_includes.iter() {
+        let _ = writeln!(out, "#include \"{}\"", crate::cdds::include_header_name(file));
+    }
+
+    let hfile_footer = std::include_str!("cdds/templates/h_file_footer.txt")
+        .replace("<HEADERDEFINE>", &crate::cdds::header_macro_name(&idlnamestem));
+
+    let root = &spec.root;
+    crate::cdds::header::write_h_constants(out, root, root).map_err(io_error)?;
+    let _ = writeln!(out);
+    crate::cdds::header::write_h_types(out, &root.main_file_declarations()).map_err(io_error)?;
+    crate::cdds::header::write_h(out, root).map_err(io_error)?;
+
+    out.write(hfile_footer.as_bytes()).map(|_| ()).map_err(io_error)
+}
+
+fn write_c_source<W: Write>(out: &mut W, spec: &Specification, config: &Configuration) -> Result<(), IdlError> {
+    let idlnamestem = String::from(config.idl_name.file_stem().unwrap().to_str().unwrap());
+    let idlname = config.idl_name.file_name().unwrap().to_str().unwrap();
+
+    let file_header = std::include_str!("cdds/templates/c_file_header.txt")
+        .replace("<FILENAME>", &format!("{}.c", idlnamestem))
+        .replace("<IDLNAME>", idlname)
+        .replace("<HEADERNAME>", &format!("{}.h", idlnamestem));
+    let _ = out.write(file_header.as_bytes());
+
+    crate::cdds::source::write_c(out, &spec.root, config.cyclone_version, model(spec, config)).map_err(io_error)
+}
+
+fn write_c_layout<W: Write>(out: &mut W, spec: &Specification, config: &Configuration) -> Result<(), IdlError> {
+    let idlnamestem = String::from(config.idl_name.file_stem().unwrap().to_str().unwrap());
+    let idlname = config.idl_name.file_name().unwrap().to_str().unwrap();
+
+    let file_header = std::include_str!("cdds/templates/c_file_header.txt")
+        .replace("<FILENAME>", &format!("{}_layout.c", idlnamestem))
+        .replace("<IDLNAME>", idlname)
+        .replace("<HEADERNAME>", &format!("{}.h", idlnamestem));
+    let _ = out.write(file_header.as_bytes());
+
+    crate::cdds::layout::write_layout_c(out, &spec.root).map_err(io_error)
+}
+
+/// The backends, by name
+pub struct Backends {
+    backends: Vec<Box<dyn Backend>>,
+}
+
+impl Default for Backends {
+    fn default() -> Backends {
+        Backends::new()
+    }
+}
+
+impl Backends {
+    /// The built-in backends
+    pub fn new() -> Backends {
+        let mut backends = Backends { backends: Vec::new() };
+        backends.register(RustBackend).register(CBackend).register(CppBackend);
+        backends
+    }
+
+    /// Add `backend`, it replaces a backend of the same name
+    pub fn register<B: Backend + 'static>(&mut self, backend: B) -> &mut Backends {
+        self.backends.retain(|b| b.name() != backend.name());
+        self.backends.push(Box::new(backend));
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn Backend> {
+        self.backends.iter().find(|b| b.name() == name).map(|b| b.as_ref())
+    }
+
+    /// The names of the backends, in registration order
+    pub fn names(&self) -> Vec<&str> {
+        self.backends.iter().map(|b| b.name()).collect()
+    }
+
+    /// Generate the outputs of the backends `langs` for every source from a
+    /// single parse. The `idl_name` of the configuration is replaced by the
+    /// name of each source.
+    pub fn generate_files<L: IdlLoader>(
+        &self,
+        langs: &[&str],
+        loader: &mut L,
+        config: &Configuration,
+        sources: &[IdlSource],
+    ) -> Generated {
+        let mut generated = Generated::default();
+        let mut backends = Vec::new();
+        for lang in langs {
+            match self.get(lang) {
+                Some(backend) => backends.push(backend),
+                None => {
+                    generated.diagnostics.push(Diagnostic::Error(format!(
+                        "Unknown backend {}, expected one of {}",
+                        lang,
+                        self.names().join(", ")
+                    )));
+                    return generated;
+                }
+            }
+        }
+
+        for source in sources {
+            let (idl_name, data) = match source {
+                IdlSource::File(path) => match std::fs::read_to_string(path) {
+                    Ok(data) => (path.clone(), data),
+                    Err(e) => {
+                        generated
+                            .diagnostics
+                            .push(Diagnostic::Error(format!("{}: {}", path.display(), e)));
+                        continue;
+                    }
+                },
+                IdlSource::Memory(name, data) => (name.clone(), data.clone()),
+            };
+            let source_config = Configuration {
+                idl_name,
+                ..config.clone()
+            };
+            let spec = match parse_ir(loader, &source_config, &data) {
+                Ok(spec) => spec,
+                Err(e) => {
+                    generated.diagnostics.push(Diagnostic::Error(format!(
+                        "{}: {:?}",
+                        source_config.idl_name.display(),
+                        e
+                    )));
+                    continue;
+                }
+            };
+            generated.diagnostics.extend(spec.diagnostics.iter().cloned());
+            for path in spec.included.iter() {
+                if !generated.included.contains(path) {
+                    generated.included.push(path.clone());
+                }
+            }
+            for backend in backends.iter() {
+                match backend.generate(&spec, &source_config) {
+                    Ok(outputs) => generated.outputs.extend(outputs),
+                    Err(e) => generated.diagnostics.push(Diagnostic::Error(format!(
+                        "{}: {} backend: {:?}",
+                        source_config.idl_name.display(),
+                        backend.name(),
+                        e
+                    ))),
+                }
+            }
+        }
+        generated
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Backend, Backends};
+    use crate::ir::{visit::Visitor, Specification, TypeDecl};
+    use crate::{Configuration, Diagnostic, IdlError, IdlSource, MemoryLoader, OutputFile, OutputKind};
+
+    // lists the types of the model
+    struct Names;
+
+    impl Backend for Names {
+        fn name(&self) -> &str {
+            "names"
+        }
+
+        fn generate(&self, spec: &Specification, config: &Configuration) -> Result<Vec<OutputFile>, IdlError> {
+            struct Collect(String);
+            impl Visitor for Collect {
+                fn visit_type_decl(&mut self, decl: &TypeDecl) {
+                    self.0.push_str(&format!("{}\n", decl.path.join("::")));
+                }
+            }
+            let mut names = Collect(String::new());
+            names.visit_module(&spec.root);
+            Ok(vec![OutputFile {
+                name: format!("{}.txt", OutputKind::Other.file_name(&config.idl_name)),
+                kind: OutputKind::Other,
+                contents: names.0,
+            }])
+        }
+    }
+
+    #[test]
+    fn registered_backends() {
+        let mut backends = Backends::new();
+        backends.register(Names);
+        assert_eq!(backends.names(), vec!["rust", "c", "cpp", "names"]);
+
+        let sources = [IdlSource::Memory(
+            std::path::PathBuf::from("idl/shape.idl"),
+            String::from("module S { struct Shape { long id; }; module T { typedef long Id; }; };"),
+        )];
+        let config = Configuration::default();
+        let generated = backends.generate_files(&["rust", "names"], &mut MemoryLoader::new(), &config, &sources);
+        assert!(!generated.has_errors());
+        assert_eq!(generated.outputs[0].name, "shape.rs");
+        assert_eq!(generated.outputs[1].name, "shape.txt");
+        assert_eq!(generated.outputs[1].contents, "S::Shape\nS::T::Id\n");
+
+        let generated = backends.generate_files(&["java"], &mut MemoryLoader::new(), &config, &sources);
+        assert!(generated.outputs.is_empty());
+        assert_eq!(
+            generated.diagnostics,
+            vec![Diagnostic::Error(String::from("Unknown backend java, expected one of rust, c, cpp, names"))]
+        );
+    }
+
+    #[test]
+    fn c_header_names() {
+        let sources = [IdlSource::Memory(
+            std::path::PathBuf::from("dims.idl"),
+            String::from("module M { const long N = 4; struct All { long arr[N][2]; long b[::M::N]; }; };"),
+        )];
+        let config = Configuration::default();
+        let generated = Backends::new().generate_files(&["c"], &mut MemoryLoader::new(), &config, &sources);
+        assert!(!generated.has_errors());
+        let header = &generated.outputs.iter().find(|f| f.name == "dims.h").unwrap().contents;
+        assert!(header.contains("int32_t arr[4][2];"));
+        assert!(header.contains("int32_t b[4];"));
+        assert!(!header.contains("crate::"));
+    }
+}
@@ -1,48 +1,14 @@
-use cyclonedds_idlc::{generate_with_loader, Configuration, IdlLoader};
+use cyclonedds_idlc::{
+    generate_with_backend, parse_ir, Backends, Configuration, CycloneVersion, Diagnostic, IdlSource, Loader,
+    LongDoubleRepr, TemplateBackend, WideCharSize,
+};
 use getopts::Options;
 use std::collections::HashMap;
 use std::env;
 use std::fs::File;
-use std::io::{self, Read};
+use std::io;
 use std::io::{Error, ErrorKind};
 
-#[derive(Debug, Clone, Default)]
-struct Loader {
-    search_path: Vec<String>,
-}
-
-fn load_from(prefix: &std::path::Path, filename: &str) -> Result<String, Error> {
-    let fullname = prefix.join(filename);
-
-    let mut file = File::open(fullname)?;
-    let mut data = String::new();
-
-    file.read_to_string(&mut data)?;
-
-    return Ok(data);
-}
-
-impl Loader {
-    pub fn new(search_path: Vec<String>) -> Loader {
-        Loader {
-            search_path: search_path,
-        }
-    }
-}
-
-impl IdlLoader for Loader {
-    fn load(&self, filename: &str) -> Result<String, Error> {
-        for prefix in &self.search_path {
-            let prefix_path = std::path::Path::new(&prefix);
-            match load_from(&prefix_path, filename) {
-                Ok(data) => return Ok(data),
-                _ => continue,
-            }
-        }
-        Err(Error::from(ErrorKind::NotFound))
-    }
-}
-
 //
 fn print_usage(program: &str, opts: Options) -> Result<(), std::io::Error> {
     let brief = format!(
@@ -71,13 +37,77 @@ fn main() -> Result<(), std::io::Error> {
     opts.optopt("o", "", "Write output to 'outfile'.", "outfile");
     opts.optflag("v", "", "Verbose output for debugging'.");
     opts.optflag("c", "cfiles", "Generate C files");
+    opts.optflag(
+        "d",
+        "descriptors",
+        "Generate the topic descriptors, in Rust or as the C file with -c",
+    );
+    opts.optflag(
+        "l",
+        "layout-checks",
+        "Generate the layout checks, as Rust tests or as the C tables with -c",
+    );
+    opts.optopt(
+        "r",
+        "runtime-crate",
+        "Path of the Cyclone DDS runtime crate used by the generated Rust (default: cyclonedds_sys)",
+        "path",
+    );
+    opts.optopt(
+        "",
+        "cyclone-version",
+        "Cyclone DDS release of the topic descriptors, 0.7 (default: 0.7)",
+        "version",
+    );
+    opts.optopt(
+        "",
+        "wchar-size",
+        "Size in bits of wchar_t on the target platform, 16 or 32 (default: 32), the wire wchar is 16 bits",
+        "bits",
+    );
+    opts.optopt(
+        "",
+        "long-double",
+        "Representation of long double, opaque or double (default: opaque)",
+        "repr",
+    );
+    opts.optopt(
+        "",
+        "include-path",
+        "Rust path of the modules generated from included files, the module of a.idl is <path>::a (default: crate)",
+        "path",
+    );
+    opts.optopt(
+        "",
+        "lang",
+        "Generator of the output, rust, c, cpp or template (default: rust, c with -c, template with --template)",
+        "name",
+    );
+    opts.optopt(
+        "",
+        "template",
+        "Render the templates of 'directory' against the IDL file, an output per template",
+        "directory",
+    );
+    opts.optopt(
+        "",
+        "out-dir",
+        "Write every output of the generator to 'directory', named after the IDL file",
+        "directory",
+    );
+    opts.optopt(
+        "",
+        "emit",
+        "Write the resolved model instead of code, ir-json is the versioned JSON document",
+        "format",
+    );
     opts.optflag("h", "help", "print this help menu");
 
     let args: Vec<_> = env::args().collect();
     let program = args[0].clone();
     let matches = match opts.parse(&args[1..]) {
         Ok(m) => m,
-        Err(f) => panic!(f.to_string()),
+        Err(f) => panic!("{}", f),
     };
     if matches.opt_present("h") {
         return print_usage(&program, opts);
@@ -105,28 +135,119 @@ fn main() -> Result<(), std::io::Error> {
         _ => return print_usage(&program, opts),
     };
 
-    let data = load_from(&env::current_dir().unwrap(), &infile)
-        .map_err(|_| Error::new(ErrorKind::NotFound, ""))?;
+    let data = std::fs::read_to_string(&infile).map_err(|_| Error::new(ErrorKind::NotFound, ""))?;
 
     let fullname = &env::current_dir().unwrap().join(&infile);
-    let config = Configuration::new(
+    let mut config = Configuration::new(
         defs,
         matches.opt_present("v"),
         matches.opt_present("c"),
         fullname.to_path_buf(),
-        false,
+        matches.opt_present("d"),
+        matches.opt_present("l"),
+        matches
+            .opt_str("r")
+            .unwrap_or_else(|| String::from("cyclonedds_sys")),
     );
+    if let Some(version) = matches.opt_str("cyclone-version") {
+        config.cyclone_version = version
+            .parse::<CycloneVersion>()
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+    }
+    if let Some(size) = matches.opt_str("wchar-size") {
+        config.wchar_size = size
+            .parse::<WideCharSize>()
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+    }
+    if let Some(repr) = matches.opt_str("long-double") {
+        config.long_double = repr
+            .parse::<LongDoubleRepr>()
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+    }
+    if let Some(path) = matches.opt_str("include-path") {
+        config.include_path = path;
+    }
+
+    if let Some(format) = matches.opt_str("emit") {
+        if format != "ir-json" {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Unknown format {}, expected ir-json", format),
+            ));
+        }
+        let spec = parse_ir(&mut loader, &config, &data).map_err(|err| {
+            eprint!("parse error {:?}", err);
+            Error::new(ErrorKind::InvalidData, "parse error")
+        })?;
+        for diagnostic in spec.diagnostics.iter() {
+            eprintln!("{}", diagnostic);
+        }
+        if spec.has_errors() {
+            return Err(Error::new(ErrorKind::InvalidData, "the IDL file has errors"));
+        }
+        return match (matches.opt_str("out-dir"), matches.opt_str("o")) {
+            (Some(dir), _) => {
+                let name = fullname.with_extension("json");
+                std::fs::write(std::path::Path::new(&dir).join(name.file_name().unwrap()), spec.to_json())
+            }
+            (None, Some(outfile)) => std::fs::write(outfile, spec.to_json()),
+            (None, None) => io::Write::write_all(&mut io::stdout(), spec.to_json().as_bytes()),
+        };
+    }
+
+    let mut backends = Backends::new();
+    if let Some(dir) = matches.opt_str("template") {
+        let templates = TemplateBackend::from_dir("template", dir)
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, format!("{:?}", e)))?;
+        backends.register(templates);
+    }
+    let lang = matches.opt_str("lang").unwrap_or_else(|| {
+        String::from(match () {
+            _ if matches.opt_present("template") => "template",
+            _ if config.generate_c => "c",
+            _ => "rust",
+        })
+    });
+    let backend = backends.get(&lang).ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!("Unknown generator {}, expected one of {}", lang, backends.names().join(", ")),
+        )
+    })?;
+
+    if let Some(dir) = matches.opt_str("out-dir") {
+        let sources = [IdlSource::Memory(fullname.to_path_buf(), data)];
+        let generated = backends.generate_files(&[backend.name()], &mut loader, &config, &sources);
+        for diagnostic in generated.diagnostics.iter() {
+            eprintln!("{}", diagnostic);
+        }
+        if generated.has_errors() {
+            return Err(Error::new(ErrorKind::InvalidData, "the IDL file has errors"));
+        }
+        for output in generated.outputs.iter() {
+            std::fs::write(std::path::Path::new(&dir).join(&output.name), &output.contents)?;
+        }
+        return Ok(());
+    }
 
     let result = match matches.opt_str("o") {
         Some(outfile) => {
             let mut of = File::create(std::path::Path::new(&outfile))?;
-            generate_with_loader(&mut of, &mut loader, &config, &data)
+            generate_with_backend(&mut of, &mut loader, backend, &config, &data)
         }
-        _ => generate_with_loader(&mut io::stdout(), &mut loader, &config, &data),
+        _ => generate_with_backend(&mut io::stdout(), &mut loader, backend, &config, &data),
     };
 
     match result {
-        Ok(_) => Ok(()),
+        Ok(diagnostics) => {
+            for diagnostic in diagnostics.iter() {
+                eprintln!("{}", diagnostic);
+            }
+            if diagnostics.iter().any(|d| matches!(d, Diagnostic::Error(_))) {
+                return Err(Error::new(ErrorKind::InvalidData, "the IDL file has errors"));
+            }
+            Ok(())
+        }
         Err(err) => {
             eprint!("parse error {:?}", err);
             Err(Error::new(ErrorKind::InvalidData, "parse error"))
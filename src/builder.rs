@@ -0,0 +1,220 @@
+// Copyright (C) 2020  Sojan James
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0>
+
+/* Code generation from a build script.
+
+ The outputs are written to OUT_DIR, an output is only rewritten when its
+ contents change so the crates using it are not rebuilt needlessly. Cargo is
+ told to rerun the build script when one of the IDL files read changes.
+
+    cyclonedds_idlc::Builder::new()
+        .file("idl/shape.idl")
+        .include("idl")
+        .compile()
+        .unwrap();
+*/
+
+use crate::{
+    generate_files, Configuration, CycloneVersion, Generated, IdlError, IdlSource, Loader, LongDoubleRepr,
+    WideCharSize,
+};
+
+use std::io::{Error, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+/// Generates the code of IDL files into OUT_DIR
+#[derive(Debug, Clone, Default)]
+pub struct Builder {
+    files: Vec<PathBuf>,
+    include_dirs: Vec<PathBuf>,
+    out_dir: Option<PathBuf>,
+    config: Configuration,
+}
+
+impl Builder {
+    pub fn new() -> Builder {
+        Builder::default()
+    }
+
+    /// Add an IDL file to generate
+    pub fn file<P: AsRef<Path>>(&mut self, path: P) -> &mut Builder {
+        self.files.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Add a directory to the search path of the included files
+    pub fn include<P: AsRef<Path>>(&mut self, dir: P) -> &mut Builder {
+        self.include_dirs.push(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Predefine `name` as a macro, with definition 1 without `value`
+    pub fn define(&mut self, name: &str, value: Option<&str>) -> &mut Builder {
+        self.config
+            .definition
+            .insert(String::from(name), String::from(value.unwrap_or("1")));
+        self
+    }
+
+    /// Directory of the outputs, OUT_DIR by default
+    pub fn out_dir<P: AsRef<Path>>(&mut self, dir: P) -> &mut Builder {
+        self.out_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Generate the C header, next to the Rust file
+    pub fn generate_c(&mut self, enable: bool) -> &mut Builder {
+        self.config.generate_c = enable;
+        self
+    }
+
+    /// Generate the topic descriptors, the C file with generate_c
+    pub fn descriptors(&mut self, enable: bool) -> &mut Builder {
+        self.config.generate_descriptors = enable;
+        self
+    }
+
+    /// Generate the layout checks, and the C layout tables with generate_c
+    pub fn layout_checks(&mut self, enable: bool) -> &mut Builder {
+        self.config.layout_checks = enable;
+        self
+    }
+
+    /// Path of the Cyclone DDS runtime crate used by the generated Rust
+    pub fn runtime_crate(&mut self, path: &str) -> &mut Builder {
+        self.config.runtime_crate = String::from(path);
+        self
+    }
+
+    /// Rust path of the modules generated from the included files
+    pub fn include_path(&mut self, path: &str) -> &mut Builder {
+        self.config.include_path = String::from(path);
+        self
+    }
+
+    /// Cyclone DDS release the topic descriptors are generated for
+    pub fn cyclone_version(&mut self, version: CycloneVersion) -> &mut Builder {
+        self.config.cyclone_version = version;
+        self
+    }
+
+    pub fn wchar_size(&mut self, size: WideCharSize) -> &mut Builder {
+        self.config.wchar_size = size;
+        self
+    }
+
+    pub fn long_double(&mut self, repr: LongDoubleRepr) -> &mut Builder {
+        self.config.long_double = repr;
+        self
+    }
+
+    /// Generate the files, an error in one of them fails the build
+    pub fn compile(&self) -> Result<Generated, IdlError> {
+        self.compile_to(&mut std::io::stdout())
+    }
+
+    // Generate the files, writing the instructions for cargo to `cargo`
+    fn compile_to<W: Write>(&self, cargo: &mut W) -> Result<Generated, IdlError> {
+        let out_dir = match self.out_dir.clone() {
+            Some(dir) => dir,
+            None => std::env::var_os("OUT_DIR")
+                .map(PathBuf::from)
+                .ok_or_else(|| IdlError::ErrorMesg(String::from("OUT_DIR is not set")))?,
+        };
+        let mut loader = Loader::new(
+            self.include_dirs
+                .iter()
+                .map(|dir| dir.to_string_lossy().into_owned())
+                .collect(),
+        );
+        let sources: Vec<IdlSource> = self.files.iter().cloned().map(IdlSource::File).collect();
+        let generated = generate_files(&mut loader, &self.config, &sources);
+
+        let io_error = |e: Error| IdlError::ErrorMesg(e.to_string());
+        for file in self.files.iter() {
+            writeln!(cargo, "cargo:rerun-if-changed={}", file.display()).map_err(io_error)?;
+        }
+        for file in generated.included.iter() {
+            writeln!(cargo, "cargo:rerun-if-changed={}", file).map_err(io_error)?;
+        }
+        for diagnostic in generated.diagnostics.iter() {
+            writeln!(cargo, "cargo:warning={}", diagnostic).map_err(io_error)?;
+        }
+        if generated.has_errors() {
+            return Err(IdlError::ErrorMesg(String::from(
+                "The IDL files have errors, see the warnings",
+            )));
+        }
+
+        std::fs::create_dir_all(&out_dir).map_err(io_error)?;
+        for output in generated.outputs.iter() {
+            write_if_changed(&out_dir.join(&output.name), &output.contents).map_err(io_error)?;
+        }
+        Ok(generated)
+    }
+}
+
+// Write `contents` to `path` unless it holds them already, true if written
+fn write_if_changed(path: &Path, contents: &str) -> Result<bool, Error> {
+    match std::fs::read(path) {
+        Ok(ref current) if current == contents.as_bytes() => Ok(false),
+        Ok(_) => std::fs::write(path, contents).map(|_| true),
+        Err(ref e) if e.kind() == ErrorKind::NotFound => std::fs::write(path, contents).map(|_| true),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{write_if_changed, Builder};
+    use crate::fixture::temp_dir;
+
+    #[test]
+    fn rewrites_changed_outputs_only() {
+        let dir = temp_dir("write");
+        let path = dir.join("a.rs");
+        assert!(write_if_changed(&path, "a").unwrap());
+        assert!(!write_if_changed(&path, "a").unwrap());
+        assert!(write_if_changed(&path, "b").unwrap());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "b");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn compile() {
+        let dir = temp_dir("compile");
+        let idl_dir = dir.join("idl");
+        std::fs::create_dir_all(idl_dir.join("common")).unwrap();
+        std::fs::write(idl_dir.join("common/point.idl"), "module P { struct Point { long x; }; };").unwrap();
+        std::fs::write(
+            idl_dir.join("shape.idl"),
+            "#include \"common/point.idl\"\nmodule S { struct Shape { long id; P::Point p; };\n#pragma keylist Shape id\n};",
+        )
+        .unwrap();
+
+        let out_dir = dir.join("out");
+        let mut builder = Builder::new();
+        builder.file(idl_dir.join("shape.idl")).out_dir(&out_dir).generate_c(true);
+        let mut cargo = Vec::new();
+        builder.compile_to(&mut cargo).unwrap();
+        let cargo = String::from_utf8(cargo).unwrap();
+
+        // cargo reruns the build script when any of the files read changes
+        let point = idl_dir.join("common/point.idl").canonicalize().unwrap();
+        assert!(cargo.contains(&format!("cargo:rerun-if-changed={}\n", idl_dir.join("shape.idl").display())));
+        assert!(cargo.contains(&format!("cargo:rerun-if-changed={}\n", point.display())));
+        assert!(std::fs::read_to_string(out_dir.join("shape.rs")).unwrap().contains("pub struct Shape {"));
+        assert!(std::fs::read_to_string(out_dir.join("shape.h")).unwrap().contains("} S_Shape;"));
+
+        // errors fail the build
+        std::fs::write(idl_dir.join("bad.idl"), "#include \"missing.idl\"").unwrap();
+        let mut cargo = Vec::new();
+        assert!(builder.file(idl_dir.join("bad.idl")).compile_to(&mut cargo).is_err());
+        let cargo = String::from_utf8(cargo).unwrap();
+        assert!(cargo.contains("cargo:warning=Error! : "));
+        assert!(cargo.contains("bad.idl:1: #include \"missing.idl\""));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
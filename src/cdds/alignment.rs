@@ -15,6 +15,7 @@ pub enum AlignmentType {
     Four,
     Ptr,
     Eight,
+    Sixteen,
 }
 #[derive(Clone)]
 pub struct Alignment {
@@ -22,6 +23,7 @@ pub struct Alignment {
     value: u32,
     ordering: u32,
     rendering: &'static str,
+    rust_rendering: &'static str,
 }
 
 impl Alignment {
@@ -32,48 +34,63 @@ impl Alignment {
                 value: 1,
                 ordering: 0,
                 rendering: "1u",
+                rust_rendering: "1",
             },
             AlignmentType::Bool => Alignment {
                 alignment,
                 value: 0,
                 ordering: 0,
                 rendering: "sizeof(bool)",
+                rust_rendering: "::std::mem::size_of::<bool>() as u32",
             },
             AlignmentType::OneOrBool => Alignment {
                 alignment,
                 value: 0,
                 ordering: 1,
-                rendering: "(sizeof(bool)>1u)?sizeof(bool):1u)",
+                rendering: "((sizeof(bool)>1u)?sizeof(bool):1u)",
+                rust_rendering: "if ::std::mem::size_of::<bool>() > 1 { ::std::mem::size_of::<bool>() as u32 } else { 1 }",
             },
             AlignmentType::Two => Alignment {
                 alignment,
                 value: 2,
                 ordering: 2,
                 rendering: "2u",
+                rust_rendering: "2",
             },
             AlignmentType::TwoOrBool => Alignment {
                 alignment,
                 value: 0,
                 ordering: 3,
-                rendering: "(sizeof(bool)>2u)?sizeof(bool):2u",
+                rendering: "((sizeof(bool)>2u)?sizeof(bool):2u)",
+                rust_rendering: "if ::std::mem::size_of::<bool>() > 2 { ::std::mem::size_of::<bool>() as u32 } else { 2 }",
             },
             AlignmentType::Four => Alignment {
                 alignment,
                 value: 4,
                 ordering: 4,
                 rendering: "4u",
+                rust_rendering: "4",
             },
             AlignmentType::Ptr => Alignment {
                 alignment,
                 value: 0,
                 ordering: 6,
                 rendering: "sizeof (char *)",
+                rust_rendering: "::std::mem::size_of::<*const u8>() as u32",
             },
             AlignmentType::Eight => Alignment {
                 alignment,
                 value: 8,
                 ordering: 8,
                 rendering: "8u",
+                rust_rendering: "8",
+            },
+            AlignmentType::Sixteen => Alignment {
+                alignment,
+                value: 16,
+                ordering: 16,
+                rendering: "16u",
+                rust_rendering: "16",
             },
         }
     }
@@ -82,28 +99,36 @@ impl Alignment {
         self.value as i32
     }
 
-    pub fn get_ordering(&self) -> i32 {
-        self.ordering as i32
-    }
-
     pub fn to_str(&self) -> &'static str {
         self.rendering
     }
 
+    /// The alignment as a Rust `u32` expression
+    pub fn to_rust_str(&self) -> &'static str {
+        self.rust_rendering
+    }
+
     pub fn maximum(self, rhs: Self) -> Self {
-        if rhs.alignment == AlignmentType::Bool {
-            if self.alignment == AlignmentType::One {
-                return Alignment::new(AlignmentType::OneOrBool);
-            } else if self.alignment == AlignmentType::Two {
-                return Alignment::new(AlignmentType::TwoOrBool);
-            } else {
-                return self;
+        use AlignmentType::*;
+        // bool has no fixed size, it only matters next to one and two byte types
+        match (&self.alignment, &rhs.alignment) {
+            (Bool, One) | (One, Bool) | (Bool, OneOrBool) | (OneOrBool, Bool) => {
+                Alignment::new(OneOrBool)
             }
-        } else {
-            if rhs.ordering > self.ordering {
-                rhs
-            } else {
-                self
+            (Bool, Two)
+            | (Two, Bool)
+            | (Bool, TwoOrBool)
+            | (TwoOrBool, Bool)
+            | (OneOrBool, Two)
+            | (Two, OneOrBool)
+            | (OneOrBool, TwoOrBool)
+            | (TwoOrBool, OneOrBool) => Alignment::new(TwoOrBool),
+            _ => {
+                if rhs.ordering > self.ordering {
+                    rhs
+                } else {
+                    self
+                }
             }
         }
     }
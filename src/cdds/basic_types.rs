@@ -6,6 +6,8 @@
 /*
 BOOLEAN ("bool", "DDS_OP_TYPE_BOO", "DDS_OP_SUBTYPE_BOO", Alignment.BOOL, "Boolean"),
 OCTET ("uint8_t", "DDS_OP_TYPE_1BY", "DDS_OP_SUBTYPE_1BY", Alignment.ONE, "Octet"),
+INT8 ("int8_t", "DDS_OP_TYPE_1BY | DDS_OP_FLAG_SGN", "DDS_OP_SUBTYPE_1BY | DDS_OP_FLAG_SGN", Alignment.ONE, "Int8"),
+UINT8 ("uint8_t", "DDS_OP_TYPE_1BY", "DDS_OP_SUBTYPE_1BY", Alignment.ONE, "UInt8"),
 CHAR ("char", "DDS_OP_TYPE_1BY | DDS_OP_FLAG_SGN", "DDS_OP_SUBTYPE_1BY | DDS_OP_FLAG_SGN", Alignment.ONE, "Char"),
 SHORT ("int16_t", "DDS_OP_TYPE_2BY | DDS_OP_FLAG_SGN", "DDS_OP_SUBTYPE_2BY | DDS_OP_FLAG_SGN", Alignment.TWO, "Short"),
 USHORT ("uint16_t", "DDS_OP_TYPE_2BY", "DDS_OP_SUBTYPE_2BY", Alignment.TWO, "UShort"),
@@ -16,16 +18,24 @@ ULONGLONG ("uint64_t", "DDS_OP_TYPE_8BY", "DDS_OP_SUBTYPE_8BY", Alignment.EIGHT,
 FLOAT ("float", "DDS_OP_TYPE_4BY | DDS_OP_FLAG_FP", "DDS_OP_SUBTYPE_4BY | DDS_OP_FLAG_FP", Alignment.FOUR, "Float"),
 DOUBLE ("double", "DDS_OP_TYPE_8BY | DDS_OP_FLAG_FP", "DDS_OP_SUBTYPE_8BY | DDS_OP_FLAG_FP", Alignment.EIGHT, "Double"),
 STRING ("char *", "DDS_OP_TYPE_STR", "DDS_OP_SUBTYPE_STR", Alignment.PTR, "String");
+
+The wide char is serialized as a 16 bit unsigned integer, the XTypes wchar.
 */
 
 use crate::cdds::alignment::{Alignment, AlignmentType};
+use crate::cdds::descriptor::{key_flag, MetaOp, OpArg};
 use crate::cdds::type_trait::Type;
-use crate::IdlModule;
+use crate::cdds::Model;
+
+use std::io::Error;
 
 enum BType {
     Boolean,
     Octet,
+    Int8,
+    UInt8,
     Char,
+    WChar,
     Short,
     UShort,
     Long,
@@ -67,6 +77,26 @@ impl BasicType {
             xml: "Octet",
         }
     }
+    pub fn new_int8() -> Self {
+        BasicType {
+            basic_type: BType::Int8,
+            ctype: "int8_t",
+            op: "DDS_OP_TYPE_1BY | DDS_OP_FLAG_SGN",
+            subop: "DDS_OP_SUBTYPE_1BY | DDS_OP_FLAG_SGN",
+            align: Alignment::new(AlignmentType::One),
+            xml: "Int8",
+        }
+    }
+    pub fn new_uint8() -> Self {
+        BasicType {
+            basic_type: BType::UInt8,
+            ctype: "uint8_t",
+            op: "DDS_OP_TYPE_1BY",
+            subop: "DDS_OP_SUBTYPE_1BY",
+            align: Alignment::new(AlignmentType::One),
+            xml: "UInt8",
+        }
+    }
     pub fn new_char() -> Self {
         BasicType {
             basic_type: BType::Char,
@@ -77,6 +107,18 @@ impl BasicType {
             xml: "Char",
         }
     }
+    // the wire type, an XTypes wchar is a UTF-16 code unit whatever the size
+    // of wchar_t in memory
+    pub fn new_wchar() -> Self {
+        BasicType {
+            basic_type: BType::WChar,
+            ctype: "wchar_t",
+            op: "DDS_OP_TYPE_2BY",
+            subop: "DDS_OP_SUBTYPE_2BY",
+            align: Alignment::new(AlignmentType::Two),
+            xml: "UShort",
+        }
+    }
     pub fn new_short() -> Self {
         BasicType {
             basic_type: BType::Short,
@@ -177,42 +219,30 @@ impl BasicType {
 }
 
 impl Type for BasicType {
-    fn get_meta_op(&self, name: &str, struct_name: &str, is_key: bool, root: &IdlModule) -> Vec<String> {
-        vec![String::from(format!(
-            "DDS_OP_ADR | {} {} , offsetof!({},{}) as u32",
-            self.op,
-            if is_key { " | DDS_OP_FLAG_KEY" } else { "" },
-            struct_name,
-            name
-        ))]
-    }
-    /*
-    fn get_meta_op_c(&self, name: &str, struct_name: &str, is_key: bool, root: &IdlModule) -> String {
-        String::from(format!(
-            "DDS_OP_ADR | {} {} , offsetof ({},{})",
-            self.op,
-            if is_key { " | DDS_OP_FLAG_KEY" } else { "" },
-            struct_name,
-            name
-        ))
-    }
-    */
-    fn get_sub_op(&self, root: &IdlModule) -> String {
-        self.subop.into()
-    }
-    fn get_op(&self, root: &IdlModule) -> String {
-        self.op.into()
+    fn get_meta_op(
+        &self,
+        path: &[String],
+        keys: &[Vec<String>],
+        _model: Model,
+    ) -> Result<Vec<MetaOp>, Error> {
+        Ok(vec![MetaOp::new(
+            format!("DDS_OP_ADR | {}{}", self.op, key_flag(path, keys)),
+            vec![OpArg::Offset(path.to_vec())],
+        )])
+    }
+    fn get_sub_op(&self, _path: &[String], _model: Model) -> Result<String, Error> {
+        Ok(self.subop.into())
     }
 
-    fn get_c_type(&self, root: &IdlModule) -> String {
+    fn get_c_type(&self, _model: Model) -> String {
         self.ctype.into()
     }
 
-    fn get_xml(&self, root: &IdlModule) -> String {
+    fn get_xml(&self, _model: Model) -> String {
         String::from(format!("<{}/>", self.xml))
     }
 
-    fn get_key_size(&self, root: &IdlModule) -> i32 {
+    fn get_key_size(&self, _model: Model) -> i32 {
         match self.basic_type {
             BType::Boolean => 1,
             BType::String => -1,
@@ -220,13 +250,10 @@ impl Type for BasicType {
         }
     }
 
-    fn get_meta_op_size(&self, root: &IdlModule) -> i32 {
-        2
-    }
-    fn get_alignment(&self, root: &IdlModule) -> Alignment {
+    fn get_alignment(&self, _model: Model) -> Alignment {
         self.align.clone()
     }
-    fn contains_union(&self, root: &IdlModule) -> bool {
+    fn contains_union(&self, _model: Model) -> bool {
         false
     }
 }
@@ -0,0 +1,402 @@
+// Copyright (C) 2020  Sojan James
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0>
+
+/* Bitmask and bitset types.
+
+ Both are stored and serialized as the smallest unsigned integer holding
+ their bits. Bitmask flags are bits of the holder at their position,
+ bitset fields are packed from the least significant bit in declaration
+ order.
+*/
+
+use crate::cdds::{c_type_name, rust_type, scoped_name, write_lines, INDENTION};
+use crate::ir::{BitField, BitFlag, TypeKind, TypeSpec};
+use crate::WideCharSize;
+
+use std::io::{Error, Write};
+
+/// The unsigned integer holding the bits of a bitmask or bitset, the type
+/// is serialized as this integer
+pub fn bit_holder(kind: &TypeKind) -> Option<TypeSpec> {
+    match kind {
+        TypeKind::Bitmask(bitmask) => Some(holder_of(bitmask.bit_bound)),
+        TypeKind::Bitset(fields) => Some(holder_of(fields.iter().map(|f| f.width).sum())),
+        _ => None,
+    }
+}
+
+// The smallest unsigned integer type with at least `bits` bits
+fn holder_of(bits: u32) -> TypeSpec {
+    match bits {
+        0..=8 => TypeSpec::UInt8,
+        9..=16 => TypeSpec::UInt16,
+        17..=32 => TypeSpec::UInt32,
+        _ => TypeSpec::UInt64,
+    }
+}
+
+// The Rust type of a holder or a bitfield, these are integers or booleans and
+// never wide chars
+fn bits_rust_type(spec: &TypeSpec) -> String {
+    rust_type(spec, WideCharSize::Bits16)
+}
+
+// Mask of the `width` low bits
+fn low_bits(width: u32) -> u64 {
+    if width >= 64 {
+        u64::MAX
+    } else {
+        (1u64 << width) - 1
+    }
+}
+
+fn holder_bits(holder: &TypeSpec) -> u32 {
+    match holder {
+        TypeSpec::UInt8 => 8,
+        TypeSpec::UInt16 => 16,
+        TypeSpec::UInt32 => 32,
+        _ => 64,
+    }
+}
+
+fn is_signed(spec: &TypeSpec) -> bool {
+    matches!(spec, TypeSpec::Int8 | TypeSpec::Int16 | TypeSpec::Int32 | TypeSpec::Int64)
+}
+
+// The named fields of a bitset with their bit offset
+fn field_offsets(fields: &[BitField]) -> Vec<(&str, u32, &BitField)> {
+    let mut offset = 0;
+    let mut named = Vec::new();
+    for field in fields {
+        if let Some(id) = field.id.as_ref() {
+            named.push((id.as_str(), offset, field));
+        }
+        offset += field.width;
+    }
+    named
+}
+
+// Attributes and declaration of the transparent Rust type over the holder
+fn write_rust_holder_type<W: Write>(
+    out: &mut W,
+    level: usize,
+    id: &str,
+    holder: &TypeSpec,
+    derive: &str,
+) -> Result<(), Error> {
+    writeln!(out)?;
+    writeln!(out, "{:indent$}//", "", indent = level * INDENTION)?;
+    writeln!(out, "{:indent$}//", "", indent = level * INDENTION)?;
+    writeln!(out, "{:indent$}#[allow(dead_code)]", "", indent = level * INDENTION)?;
+    writeln!(out, "{:indent$}#[allow(non_camel_case_types)]", "", indent = level * INDENTION)?;
+    writeln!(out, "{:indent$}#[repr(transparent)]", "", indent = level * INDENTION)?;
+    writeln!(out, "{:indent$}{}", "", derive, indent = level * INDENTION)?;
+    writeln!(
+        out,
+        "{:indent$}pub struct {}(pub {});",
+        "",
+        id,
+        bits_rust_type(holder),
+        indent = level * INDENTION
+    )
+}
+
+/// Write the Rust type of the bitmask `id`, a flags type with a constant
+/// per flag
+pub fn write_bitmask<W: Write>(
+    out: &mut W,
+    level: usize,
+    id: &str,
+    bit_bound: u32,
+    values: &[BitFlag],
+) -> Result<(), Error> {
+    let holder = holder_of(bit_bound);
+    write_rust_holder_type(
+        out,
+        level,
+        id,
+        &holder,
+        "#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]",
+    )?;
+    writeln!(
+        out,
+        "{:indent$}#[allow(dead_code, non_upper_case_globals)]",
+        "",
+        indent = level * INDENTION
+    )?;
+    writeln!(out, "{:indent$}impl {} {{", "", id, indent = level * INDENTION)?;
+    for value in values {
+        writeln!(
+            out,
+            "{:indent$}pub const {}: {} = {}(1 << {});",
+            "",
+            value.id,
+            id,
+            id,
+            value.position,
+            indent = (level + 1) * INDENTION
+        )?;
+    }
+    writeln!(out, "{:indent$}}}", "", indent = level * INDENTION)?;
+
+    let all = values.iter().fold(0u64, |all, v| all | 1 << v.position);
+    let ops = include_str!("../templates/bitmask.txt")
+        .replace("{TYPENAME}", id)
+        .replace("{HOLDER}", &bits_rust_type(&holder))
+        .replace("{ALL}", &format!("{:#x}", all));
+    write_lines(out, level, &ops)
+}
+
+/// Write the Rust type of the bitset `id` with a getter and setter per field
+pub fn write_bitset<W: Write>(
+    out: &mut W,
+    level: usize,
+    id: &str,
+    fields: &[BitField],
+) -> Result<(), Error> {
+    let holder = holder_of(fields.iter().map(|f| f.width).sum());
+    let holder_type = bits_rust_type(&holder);
+    let bits = holder_bits(&holder);
+    write_rust_holder_type(
+        out,
+        level,
+        id,
+        &holder,
+        "#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]",
+    )?;
+
+    let named = field_offsets(fields);
+    writeln!(out, "{:indent$}#[allow(dead_code)]", "", indent = level * INDENTION)?;
+    writeln!(out, "{:indent$}impl {} {{", "", id, indent = level * INDENTION)?;
+    for (name, offset, field) in &named {
+        let field_type = bits_rust_type(&field.type_spec);
+        let mask = format!("{:#x}", low_bits(field.width));
+        let shifted = |expr: String| {
+            if *offset == 0 {
+                expr
+            } else {
+                format!("({} >> {})", expr, offset)
+            }
+        };
+        let cast = |expr: String, to: &str| {
+            if to == field_type {
+                expr
+            } else {
+                format!("({} as {})", expr, to)
+            }
+        };
+        let get = match field.type_spec {
+            TypeSpec::Boolean => {
+                format!("self.0 & {:#x} != 0", 1u64 << offset)
+            }
+            // shift the field to the top and back to extend the sign
+            ref spec if is_signed(spec) => {
+                let top = bits - offset - field.width;
+                let raised = if top == 0 {
+                    String::from("self.0")
+                } else {
+                    format!("(self.0 << {})", top)
+                };
+                let signed = format!("i{}", bits);
+                let value = format!("{} as {} >> {}", raised, signed, bits - field.width);
+                if field_type == signed {
+                    value
+                } else {
+                    format!("({}) as {}", value, field_type)
+                }
+            }
+            _ => {
+                let value = format!("{} & {}", shifted(String::from("self.0")), mask);
+                if field_type == holder_type {
+                    value
+                } else {
+                    format!("({}) as {}", value, field_type)
+                }
+            }
+        };
+        let value = match field.type_spec {
+            TypeSpec::Boolean => format!("(value as {})", holder_type),
+            _ => format!("({} & {})", cast(String::from("value"), &holder_type), mask),
+        };
+        let set = if *offset == 0 {
+            format!("self.0 = (self.0 & !{}) | {};", mask, value)
+        } else {
+            format!(
+                "self.0 = (self.0 & !({} << {})) | ({} << {});",
+                mask, offset, value, offset
+            )
+        };
+
+        writeln!(
+            out,
+            "{:indent$}pub fn {}(&self) -> {} {{",
+            "",
+            name,
+            field_type,
+            indent = (level + 1) * INDENTION
+        )?;
+        writeln!(out, "{:indent$}{}", "", get, indent = (level + 2) * INDENTION)?;
+        writeln!(out, "{:indent$}}}", "", indent = (level + 1) * INDENTION)?;
+        writeln!(
+            out,
+            "{:indent$}pub fn set_{}(&mut self, value: {}) {{",
+            "",
+            name,
+            field_type,
+            indent = (level + 1) * INDENTION
+        )?;
+        writeln!(out, "{:indent$}{}", "", set, indent = (level + 2) * INDENTION)?;
+        writeln!(out, "{:indent$}}}", "", indent = (level + 1) * INDENTION)?;
+    }
+    writeln!(out, "{:indent$}}}", "", indent = level * INDENTION)?;
+
+    // the fields instead of the packed bits
+    writeln!(
+        out,
+        "{:indent$}impl ::std::fmt::Debug for {} {{",
+        "",
+        id,
+        indent = level * INDENTION
+    )?;
+    writeln!(
+        out,
+        "{:indent$}fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {{",
+        "",
+        indent = (level + 1) * INDENTION
+    )?;
+    writeln!(
+        out,
+        "{:indent$}f.debug_struct(\"{}\")",
+        "",
+        id,
+        indent = (level + 2) * INDENTION
+    )?;
+    for (name, _, _) in &named {
+        writeln!(
+            out,
+            "{:indent$}.field(\"{}\", &self.{}())",
+            "",
+            name,
+            name,
+            indent = (level + 3) * INDENTION
+        )?;
+    }
+    writeln!(out, "{:indent$}.finish()", "", indent = (level + 3) * INDENTION)?;
+    writeln!(out, "{:indent$}}}", "", indent = (level + 1) * INDENTION)?;
+    writeln!(out, "{:indent$}}}", "", indent = level * INDENTION)
+}
+
+/// Write the C typedef of the bitmask `id` and a define per flag
+pub fn write_bitmask_h<W: Write>(
+    out: &mut W,
+    scope: &[String],
+    id: &str,
+    bit_bound: u32,
+    values: &[BitFlag],
+) -> Result<(), Error> {
+    let c_name = scoped_name(&scope.to_vec(), id);
+    writeln!(out, "typedef {} {};", c_type_name(&holder_of(bit_bound)), c_name)?;
+    for value in values {
+        writeln!(
+            out,
+            "#define {}_{} (0x{:016x}llu)",
+            c_name,
+            value.id,
+            1u64 << value.position
+        )?;
+    }
+    Ok(())
+}
+
+/// Write the C typedef of the bitset `id` and inline accessors per field
+pub fn write_bitset_h<W: Write>(
+    out: &mut W,
+    scope: &[String],
+    id: &str,
+    fields: &[BitField],
+) -> Result<(), Error> {
+    let c_name = scoped_name(&scope.to_vec(), id);
+    let holder = holder_of(fields.iter().map(|f| f.width).sum());
+    writeln!(out, "typedef {} {};", c_type_name(&holder), c_name)?;
+    for (name, offset, field) in field_offsets(fields) {
+        let field_type = c_type_name(&field.type_spec);
+        let mask = format!("0x{:x}llu", low_bits(field.width));
+        let get = match field.type_spec {
+            TypeSpec::Boolean => format!("(((uint64_t) bits >> {}) & 1llu) != 0", offset),
+            // shift the field to the top and back to extend the sign
+            ref spec if is_signed(spec) => format!(
+                "({}) ((int64_t) ((uint64_t) bits << {}) >> {})",
+                field_type,
+                64 - offset - field.width,
+                64 - field.width
+            ),
+            _ => format!("({}) (((uint64_t) bits >> {}) & {})", field_type, offset, mask),
+        };
+        writeln!(
+            out,
+            "static inline {} {}_get_{} ({} bits) {{ return {}; }}",
+            field_type, c_name, name, c_name, get
+        )?;
+        writeln!(
+            out,
+            "static inline void {}_set_{} ({} *bits, {} value) {{ *bits = ({}) (((uint64_t) *bits & ~({} << {})) | (((uint64_t) value & {}) << {})); }}",
+            c_name, name, c_name, field_type, c_name, mask, offset, mask, offset
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::fixture::generate;
+    use crate::Configuration;
+
+    const IDL: &str = "module M {
+        @bit_bound(12) bitmask Flags { A, @position(4) B, C };
+        bitset Status { bitfield<1> power; bitfield<3> level; bitfield<2>; bitfield<4, short> delta; };
+        struct S { long id; Flags flags; Status status; };
+    #pragma keylist S id flags
+    };";
+
+    #[test]
+    fn rust_bit_types() {
+        let generated = generate(&Configuration::default(), IDL);
+        assert!(generated.contains("pub struct Flags(pub u16);"));
+        assert!(generated.contains("pub const B: Flags = Flags(1 << 4);"));
+        assert!(generated.contains("pub const C: Flags = Flags(1 << 5);"));
+        assert!(generated.contains("Flags(!self.0 & 0x31)"));
+        assert!(generated.contains("pub struct Status(pub u16);"));
+        assert!(generated.contains("self.0 & 0x1 != 0"));
+        assert!(generated.contains("((self.0 >> 1) & 0x7) as u8"));
+        assert!(generated.contains("(self.0 << 6) as i16 >> 12"));
+        assert!(generated.contains("self.0 = (self.0 & !(0x7 << 1)) | (((value as u16) & 0x7) << 1);"));
+        assert!(generated.contains("writer.write_u16(self.flags.0);"));
+    }
+
+    #[test]
+    fn c_bit_types() {
+        let config = Configuration {
+            generate_c: true,
+            idl_name: std::path::PathBuf::from("bits.idl"),
+            ..Default::default()
+        };
+        let generated = generate(&config, IDL);
+        assert!(generated.contains("typedef uint16_t M_Flags;"));
+        assert!(generated.contains("#define M_Flags_B (0x0000000000000010llu)"));
+        assert!(generated.contains("typedef uint16_t M_Status;"));
+        assert!(generated.contains(
+            "static inline int16_t M_Status_get_delta (M_Status bits) { return (int16_t) ((int64_t) ((uint64_t) bits << 54) >> 60); }"
+        ));
+
+        let config = Configuration {
+            generate_descriptors: true,
+            ..config
+        };
+        let generated = generate(&config, IDL);
+        assert!(generated.contains("DDS_OP_ADR | DDS_OP_TYPE_2BY | DDS_OP_FLAG_KEY, offsetof (M_S, flags),"));
+        assert!(generated.contains("DDS_OP_ADR | DDS_OP_TYPE_2BY, offsetof (M_S, status),"));
+        assert!(generated.contains("<TypeDef name=\\\"Flags\\\"><UShort/></TypeDef>"));
+    }
+}
@@ -0,0 +1,760 @@
+// Copyright (C) 2020  Sojan James
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0>
+
+/* Topic descriptors (dds_topic_descriptor_t) of keyed topics.
+
+ The ops, keys, flags, alignment and XML metadata are computed once and
+ rendered either as C (the .c file of the C backend) or as Rust statics, so
+ both backends describe a topic identically to Cyclone DDS.
+*/
+
+use crate::cdds::alignment::Alignment;
+use crate::cdds::bits::bit_holder;
+use crate::cdds::key_hash::is_fixed_key;
+use crate::cdds::type_impl::{contains_wide_string, get_members_alignment, get_topic_ops};
+use crate::cdds::type_trait::Type;
+use crate::cdds::{scoped_name, struct_members, Model, INDENTION};
+use crate::ir::{Key, Member, Module, Struct, Topic, TypeDecl, TypeKind, TypeSpec};
+use crate::CycloneVersion;
+
+use std::collections::HashSet;
+use std::io::{Error, ErrorKind, Write};
+
+/// An argument word of an op
+#[derive(Clone, Debug, PartialEq)]
+pub enum OpArg {
+    /// Offset of the member path
+    Offset(Vec<String>),
+    Value(i64),
+    /// Size of the element type, as C type and Rust type
+    Size(String, String),
+    /// Offset of the next op and of the element ops, relative to the op
+    Jump(usize, usize),
+}
+
+/// An op and its argument words
+#[derive(Clone, Debug)]
+pub struct MetaOp {
+    pub op: String,
+    pub args: Vec<OpArg>,
+    /// Type the offsets are relative to as C type and Rust type, the topic
+    /// type if None
+    pub base: Option<(String, String)>,
+    /// Nesting of sequence and array element ops
+    pub depth: usize,
+    /// Element levels up to the ops a DDS_OP_JSR jumps to, the jump is
+    /// resolved by the topic descriptor
+    pub jsr: Option<usize>,
+}
+
+impl MetaOp {
+    pub fn new(op: String, args: Vec<OpArg>) -> Self {
+        MetaOp {
+            op,
+            args,
+            base: None,
+            depth: 0,
+            jsr: None,
+        }
+    }
+
+    /// Jump to the ops of the element or topic `levels` element levels up
+    pub fn jsr(levels: usize) -> Self {
+        MetaOp {
+            jsr: Some(levels),
+            ..MetaOp::new(String::from("DDS_OP_JSR"), Vec::new())
+        }
+    }
+
+    /// Number of words of the op
+    pub fn size(&self) -> usize {
+        1 + self.args.len()
+    }
+}
+
+/// " | DDS_OP_FLAG_KEY" if `path` is one of the `keys`
+pub fn key_flag(path: &[String], keys: &[Vec<String>]) -> &'static str {
+    if keys.iter().any(|key| key.as_slice() == path) {
+        " | DDS_OP_FLAG_KEY"
+    } else {
+        ""
+    }
+}
+
+/// The descriptor of a keyed topic
+pub struct TopicDescriptor {
+    pub id: String,
+    /// Name of the C type, e.g. TestData_Msg
+    pub c_name: String,
+    /// Name registered with DDS, e.g. TestData::Msg
+    pub type_name: String,
+    /// Key name and index of its op word, in keylist order
+    pub keys: Vec<(String, usize)>,
+    pub ops: Vec<MetaOp>,
+    pub flags: Vec<String>,
+    pub alignment: Alignment,
+    pub meta: String,
+}
+
+/// The flagset of the topic with `members` and `keys`
+pub fn topic_flags(members: &[Member], keys: &[Key], model: Model) -> Vec<&'static str> {
+    let mut flags = vec!["DDS_TOPIC_NO_OPTIMIZE"];
+    if is_fixed_key(keys, model) {
+        flags.push("DDS_TOPIC_FIXED_KEY");
+    }
+    if members.iter().any(|m| m.type_spec.contains_union(model)) {
+        flags.push("DDS_TOPIC_CONTAINS_UNION");
+    }
+    flags
+}
+
+/// Fails if the topic `id` holds a wide string and the serializer of `target`
+/// has no op for them
+pub fn check_wide_strings(
+    id: &str,
+    members: &[Member],
+    target: CycloneVersion,
+    model: Model,
+) -> Result<(), Error> {
+    if target.has_wide_string_ops() {
+        return Ok(());
+    }
+    match members.iter().find(|m| contains_wide_string(&m.type_spec, model)) {
+        Some(m) => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Topic {} can't be described for Cyclone DDS {}, member {} holds a wide string and the serializer has no wide string op",
+                id, target, m.id
+            ),
+        )),
+        None => Ok(()),
+    }
+}
+
+impl TopicDescriptor {
+    /// The descriptor of the struct `s` with the absolute name `path`
+    pub fn new(
+        path: &[String],
+        s: &Struct,
+        topic: &Topic,
+        target: CycloneVersion,
+        model: Model,
+    ) -> Result<Self, Error> {
+        let (id, scope) = path.split_last().expect("the path of a topic names it");
+        let members = struct_members(s);
+        check_wide_strings(id, &members, target, model)?;
+        let keys = &topic.keys;
+        let key_paths: Vec<Vec<String>> = keys.iter().map(|key| key.path.clone()).collect();
+
+        let mut ops = get_topic_ops(path, &members, &key_paths, model)?;
+        resolve_jumps(&mut ops);
+
+        let mut key_descriptors = Vec::new();
+        for key in keys {
+            let mut index = 0;
+            let mut found = false;
+            for op in &ops {
+                if op.depth == 0 && op.args.first() == Some(&OpArg::Offset(key.path.clone())) {
+                    found = true;
+                    break;
+                }
+                index += op.size();
+            }
+            if !found {
+                return Err(Error::new(
+                    ErrorKind::NotFound,
+                    format!("No op for key {} of {}", key.path.join("."), id),
+                ));
+            }
+            key_descriptors.push((key.path.join("."), index));
+        }
+
+        let alignment = get_members_alignment(&members, model);
+
+        let mut type_name = scope.join("::");
+        if !type_name.is_empty() {
+            type_name.push_str("::");
+        }
+        type_name.push_str(id);
+
+        Ok(TopicDescriptor {
+            id: String::from(id),
+            c_name: scoped_name(&scope.to_vec(), id),
+            type_name,
+            keys: key_descriptors,
+            ops,
+            flags: topic.flags.clone(),
+            alignment,
+            meta: build_xml_metadata(path, model),
+        })
+    }
+
+    /// Number of words of the ops
+    pub fn ops_size(&self) -> usize {
+        self.ops.iter().map(|op| op.size()).sum()
+    }
+
+    fn flagset(&self) -> String {
+        self.flags.join(" | ")
+    }
+
+    /// Write the descriptor as C
+    pub fn write_c<W: Write>(&self, out: &mut W) -> Result<(), Error> {
+        writeln!(out)?;
+        writeln!(out)?;
+        if !self.keys.is_empty() {
+            writeln!(
+                out,
+                "static const dds_key_descriptor_t {}_keys[{}] =",
+                self.c_name,
+                self.keys.len()
+            )?;
+            writeln!(out, "{{")?;
+            let keys: Vec<String> = self
+                .keys
+                .iter()
+                .map(|(name, index)| format!("  {{ \"{}\", {} }}", name, index))
+                .collect();
+            writeln!(out, "{}", keys.join(",\n"))?;
+            writeln!(out, "}};")?;
+            writeln!(out)?;
+        }
+
+        writeln!(out, "static const uint32_t {}_ops [] =", self.c_name)?;
+        writeln!(out, "{{")?;
+        for (idx, op) in self.ops.iter().enumerate() {
+            let mut words = vec![op.op.clone()];
+            for arg in &op.args {
+                words.push(match arg {
+                    OpArg::Offset(path) => format!(
+                        "offsetof ({}, {})",
+                        op.base.as_ref().map(|b| b.0.as_str()).unwrap_or(&self.c_name),
+                        path.join(".")
+                    ),
+                    OpArg::Value(value) => value.to_string(),
+                    OpArg::Size(c_type, _) => format!("sizeof ({})", c_type),
+                    OpArg::Jump(next, elem) => format!("({}u << 16u) + {}u", next, elem),
+                });
+            }
+            writeln!(
+                out,
+                "{:indent$}{}{}",
+                "",
+                words.join(", "),
+                if idx + 1 < self.ops.len() { "," } else { "" },
+                indent = 2 + 2 * op.depth
+            )?;
+        }
+        writeln!(out, "}};")?;
+        writeln!(out)?;
+
+        writeln!(out, "const dds_topic_descriptor_t {}_desc =", self.c_name)?;
+        writeln!(out, "{{")?;
+        writeln!(out, "  sizeof ({}),", self.c_name)?;
+        writeln!(out, "  {},", self.alignment.to_str())?;
+        writeln!(out, "  {},", self.flagset())?;
+        writeln!(out, "  {}u,", self.keys.len())?;
+        writeln!(out, "  \"{}\",", self.type_name)?;
+        if self.keys.is_empty() {
+            writeln!(out, "  NULL,")?;
+        } else {
+            writeln!(out, "  {}_keys,", self.c_name)?;
+        }
+        writeln!(out, "  {},", self.ops.len())?;
+        writeln!(out, "  {}_ops,", self.c_name)?;
+        writeln!(out, "  \"{}\"", escape(&self.meta))?;
+        writeln!(out, "}};")
+    }
+
+    /// Write the descriptor as Rust statics and the DDSGenType implementation
+    pub fn write_rust<W: Write>(&self, out: &mut W, level: usize) -> Result<(), Error> {
+        let indent = level * INDENTION;
+        let c_str = |s: &str| {
+            format!(
+                "b\"{}\\0\".as_ptr() as *const ::std::os::raw::c_char",
+                escape(s)
+            )
+        };
+
+        if !self.keys.is_empty() {
+            writeln!(out, "{:indent$}#[allow(non_upper_case_globals)]", "", indent = indent)?;
+            writeln!(
+                out,
+                "{:indent$}static {}_keys: DescriptorKeys = DescriptorKeys(&[",
+                "",
+                self.c_name,
+                indent = indent
+            )?;
+            for (name, index) in &self.keys {
+                writeln!(
+                    out,
+                    "{:indent$}dds_key_descriptor {{ m_name: {}, m_index: {} }},",
+                    "",
+                    c_str(name),
+                    index,
+                    indent = indent + INDENTION
+                )?;
+            }
+            writeln!(out, "{:indent$}]);", "", indent = indent)?;
+        }
+
+        writeln!(out, "{:indent$}#[allow(non_upper_case_globals)]", "", indent = indent)?;
+        writeln!(
+            out,
+            "{:indent$}static {}_ops: [u32; {}] = [",
+            "",
+            self.c_name,
+            self.ops_size(),
+            indent = indent
+        )?;
+        for op in &self.ops {
+            let mut words = vec![op.op.clone()];
+            for arg in &op.args {
+                words.push(match arg {
+                    OpArg::Offset(path) => format!(
+                        "::std::mem::offset_of!({}, {}) as u32",
+                        op.base.as_ref().map(|b| b.1.as_str()).unwrap_or(&self.id),
+                        path.join(".")
+                    ),
+                    OpArg::Value(value) => value.to_string(),
+                    OpArg::Size(_, rust_type) => {
+                        format!("::std::mem::size_of::<{}>() as u32", rust_type)
+                    }
+                    OpArg::Jump(next, elem) => format!("({} << 16) + {}", next, elem),
+                });
+            }
+            writeln!(
+                out,
+                "{:indent$}{},",
+                "",
+                words.join(", "),
+                indent = indent + (1 + op.depth) * INDENTION
+            )?;
+        }
+        writeln!(out, "{:indent$}];", "", indent = indent)?;
+
+        let fields = vec![
+            ("m_size", format!("::std::mem::size_of::<{}>() as u32", self.id)),
+            ("m_align", String::from(self.alignment.to_rust_str())),
+            ("m_flagset", self.flagset()),
+            ("m_nkeys", self.keys.len().to_string()),
+            ("m_typename", c_str(&self.type_name)),
+            (
+                "m_keys",
+                if self.keys.is_empty() {
+                    String::from("::std::ptr::null()")
+                } else {
+                    format!("{}_keys.0.as_ptr()", self.c_name)
+                },
+            ),
+            ("m_nops", self.ops.len().to_string()),
+            ("m_ops", format!("{}_ops.as_ptr()", self.c_name)),
+            ("m_meta", c_str(&self.meta)),
+        ];
+        writeln!(out, "{:indent$}#[allow(non_upper_case_globals)]", "", indent = indent)?;
+        writeln!(
+            out,
+            "{:indent$}static {}_desc: DescriptorCell = DescriptorCell(dds_topic_descriptor {{",
+            "",
+            self.c_name,
+            indent = indent
+        )?;
+        for (name, value) in fields {
+            writeln!(
+                out,
+                "{:indent$}{}: {},",
+                "",
+                name,
+                value,
+                indent = indent + INDENTION
+            )?;
+        }
+        writeln!(out, "{:indent$}}});", "", indent = indent)?;
+
+        writeln!(out, "{:indent$}impl DDSGenType for {} {{", "", self.id, indent = indent)?;
+        writeln!(
+            out,
+            "{:indent$}unsafe fn get_descriptor() -> &'static dds_topic_descriptor {{",
+            "",
+            indent = indent + INDENTION
+        )?;
+        writeln!(
+            out,
+            "{:indent$}&{}_desc.0",
+            "",
+            self.c_name,
+            indent = indent + 2 * INDENTION
+        )?;
+        writeln!(out, "{:indent$}}}", "", indent = indent + INDENTION)?;
+        writeln!(out, "{:indent$}}}", "", indent = indent)
+    }
+}
+
+// Resolve the DDS_OP_JSR of recursive types, they jump back to the start of
+// the ops of an enclosing element or of the topic
+fn resolve_jumps(ops: &mut [MetaOp]) {
+    let mut words = Vec::with_capacity(ops.len());
+    let mut word = 0;
+    for op in ops.iter() {
+        words.push(word);
+        word += op.size();
+    }
+    for idx in 0..ops.len() {
+        if let Some(levels) = ops[idx].jsr {
+            let depth = ops[idx].depth - levels;
+            // element ops follow the op of their sequence or array
+            let target = (0..idx)
+                .rev()
+                .find(|&i| ops[i].depth < depth)
+                .map_or(0, |i| i + 1);
+            ops[idx].op = format!("DDS_OP_JSR | (65536 - {})", words[idx] - words[target]);
+        }
+    }
+}
+
+// Escape for C and Rust string literals
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// Absolute names of the types `spec` depends on
+fn collect_dependencies(spec: &TypeSpec, model: Model, deps: &mut HashSet<Vec<String>>) {
+    match spec {
+        TypeSpec::Array(typespec, _) | TypeSpec::Sequence(typespec, _) => {
+            collect_dependencies(typespec, model, deps)
+        }
+        TypeSpec::Map(_, _, _, _) => {
+            if let Some(entries) = spec.as_entry_sequence() {
+                collect_dependencies(&entries, model, deps)
+            }
+        }
+        TypeSpec::Named(name) => {
+            if !deps.insert(name.clone()) {
+                return;
+            }
+            match model.kind(name) {
+                Some(TypeKind::Struct(s)) => {
+                    for m in struct_members(s) {
+                        collect_dependencies(&m.type_spec, model, deps);
+                    }
+                }
+                Some(TypeKind::Typedef(typespec)) => collect_dependencies(typespec, model, deps),
+                _ => {}
+            }
+        }
+        _ => {}
+    }
+}
+
+fn type_xml(spec: &TypeSpec, scope: &[String], model: Model) -> String {
+    match spec {
+        TypeSpec::Array(typespec, dims) => {
+            let mut xml = type_xml(typespec, scope, model);
+            for dim in dims.iter().rev() {
+                xml = format!("<Array size=\"{}\">{}</Array>", dim, xml);
+            }
+            xml
+        }
+        TypeSpec::Sequence(typespec, bound) => match bound {
+            Some(bound) => format!(
+                "<Sequence size=\"{}\">{}</Sequence>",
+                bound,
+                type_xml(typespec, scope, model)
+            ),
+            None => format!("<Sequence>{}</Sequence>", type_xml(typespec, scope, model)),
+        },
+        TypeSpec::Map(_, _, _, Some(_)) => {
+            type_xml(&spec.as_entry_sequence().unwrap(), scope, model)
+        }
+        TypeSpec::String(None) => String::from("<String/>"),
+        TypeSpec::String(Some(bound)) => format!("<String length=\"{}\"/>", bound),
+        TypeSpec::Named(path) => {
+            // types of the same module are referenced by their plain name
+            if path.len() > 1 && path[..path.len() - 1] == *scope {
+                format!("<Type name=\"{}\"/>", path[path.len() - 1])
+            } else {
+                format!("<Type name=\"{}\"/>", path.join("::"))
+            }
+        }
+        _ => spec.get_xml(model),
+    }
+}
+
+fn type_dcl_xml(typ: &TypeDecl, scope: &[String], model: Model) -> String {
+    let id = &typ.id;
+    match &typ.kind {
+        TypeKind::Struct(s) => {
+            let members: String = struct_members(s)
+                .iter()
+                .map(|m| {
+                    format!(
+                        "<Member name=\"{}\">{}</Member>",
+                        m.id,
+                        type_xml(&m.type_spec, scope, model)
+                    )
+                })
+                .collect();
+            format!("<Struct name=\"{}\">{}</Struct>", id, members)
+        }
+        TypeKind::Enum(variants) => {
+            let elements: String = variants
+                .iter()
+                .enumerate()
+                .map(|(value, name)| format!("<Element name=\"{}\" value=\"{}\"/>", name, value))
+                .collect();
+            format!("<Enum name=\"{}\">{}</Enum>", id, elements)
+        }
+        TypeKind::Typedef(typespec) => format!(
+            "<TypeDef name=\"{}\">{}</TypeDef>",
+            id,
+            type_xml(typespec, scope, model)
+        ),
+        // the metadata has no bit types, they are described by their holder
+        TypeKind::Bitmask(_) | TypeKind::Bitset(_) => format!(
+            "<TypeDef name=\"{}\">{}</TypeDef>",
+            id,
+            bit_holder(&typ.kind).map(|holder| holder.get_xml(model)).unwrap_or_default()
+        ),
+        _ => String::new(),
+    }
+}
+
+// Types of `module` and its submodules in `deps`, in declaration order
+fn module_xml(module: &Module, deps: &HashSet<Vec<String>>, model: Model) -> String {
+    let mut xml = String::new();
+    for typ in module.types.iter() {
+        if deps.contains(&typ.path) {
+            xml.push_str(&type_dcl_xml(typ, &module.path, model));
+        }
+    }
+    for submodule in module.modules.iter() {
+        let inner = module_xml(submodule, deps, model);
+        if !inner.is_empty() {
+            xml.push_str(&format!(
+                "<Module name=\"{}\">{}</Module>",
+                submodule.id.as_deref().unwrap_or(""),
+                inner
+            ));
+        }
+    }
+    xml
+}
+
+/// The XML metadata of the topic `path` and the types it depends on
+pub fn build_xml_metadata(path: &[String], model: Model) -> String {
+    let mut deps = HashSet::new();
+    collect_dependencies(&TypeSpec::Named(path.to_vec()), model, &mut deps);
+    format!(
+        "<MetaData version=\"1.0.0\">{}</MetaData>",
+        module_xml(model.root, &deps, model)
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use crate::fixture::{
+        c_config, generate, generate_error, has_tool, run, temp_dir, write_file, DDS_PUBLIC_IMPL_H, RUNTIME_STUB,
+    };
+    use crate::{
+        Backends, Configuration, CycloneVersion, IdlSource, LongDoubleRepr, MemoryLoader, WideCharSize,
+    };
+    use std::process::Command;
+
+    // non-empty lines of a C file after the #include
+    fn descriptor_lines(c_file: &str) -> Vec<&str> {
+        c_file
+            .lines()
+            .skip_while(|line| !line.starts_with("#include"))
+            .skip(1)
+            .filter(|line| !line.trim().is_empty())
+            .collect()
+    }
+
+    #[test]
+    fn c_descriptors_match_idlc() {
+        let expected = include_str!("../../testdata/test.c");
+        let generated = generate(&c_config(), include_str!("../../testdata/test.idl"));
+
+        assert_eq!(
+            descriptor_lines(&generated),
+            descriptor_lines(expected)
+        );
+    }
+
+    #[test]
+    fn bool_alignment() {
+        let generated = generate(
+            &c_config(),
+            "module M { struct Flags { boolean a; octet b; };\n#pragma keylist Flags b\n};",
+        );
+        assert!(generated.contains("  ((sizeof(bool)>1u)?sizeof(bool):1u),\n"));
+
+        let generated = generate(
+            &c_config(),
+            "module M { struct Flags { boolean a; short b; };\n#pragma keylist Flags b\n};",
+        );
+        assert!(generated.contains("  ((sizeof(bool)>2u)?sizeof(bool):2u),\n"));
+    }
+
+    #[test]
+    fn wide_char_and_long_double() {
+        let idl = "module M { struct W { wchar w; long double d; };\n#pragma keylist W w\n};";
+        let config = |wchar_size, long_double| Configuration {
+            generate_c: true,
+            generate_descriptors: true,
+            idl_name: std::path::PathBuf::from("test.idl"),
+            wchar_size,
+            long_double,
+            ..Default::default()
+        };
+
+        // a wchar is 16 bits on the wire, whatever the size of wchar_t
+        let config16 = config(WideCharSize::Bits16, LongDoubleRepr::Opaque);
+        let generated = generate(&config16, idl);
+        assert!(generated.contains("DDS_OP_ADR | DDS_OP_TYPE_2BY | DDS_OP_FLAG_KEY, offsetof (M_W, w),"));
+        assert!(generated.contains("DDS_OP_ADR | DDS_OP_TYPE_ARR | DDS_OP_SUBTYPE_1BY, offsetof (M_W, d), 16,"));
+        assert!(generated.contains("  16u,\n"));
+        assert!(generated.contains("<Member name=\\\"w\\\"><UShort/>"));
+
+        let config16 = config(WideCharSize::Bits16, LongDoubleRepr::Double);
+        let generated = generate(&config16, idl);
+        assert!(generated.contains("DDS_OP_ADR | DDS_OP_TYPE_2BY | DDS_OP_FLAG_KEY, offsetof (M_W, w),"));
+        assert!(generated.contains("DDS_OP_ADR | DDS_OP_TYPE_8BY | DDS_OP_FLAG_FP, offsetof (M_W, d),"));
+
+        // the ops can't narrow a 32 bit wchar_t
+        let config32 = config(WideCharSize::Bits32, LongDoubleRepr::Opaque);
+        let mesg = generate_error(&config32, idl);
+        assert!(mesg.contains("--wchar-size 16"), "{}", mesg);
+    }
+
+    // Serializes a sample with the ops of its descriptor and reads it back,
+    // for topics of primitive members
+    const ROUND_TRIP: &str = r#"
+#[cfg(test)]
+mod round_trip {
+    use crate::sys::*;
+
+    fn size(op: u32) -> usize {
+        1 << (((op >> 16) & 0xff) - 1)
+    }
+
+    // big-endian CDR
+    fn swap(bytes: &mut [u8]) {
+        if cfg!(target_endian = "little") {
+            bytes.reverse();
+        }
+    }
+
+    pub unsafe fn serialize(desc: &dds_topic_descriptor, sample: *const u8) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut op = desc.m_ops;
+        while *op != DDS_OP_RTS {
+            let (n, offset) = (size(*op), *op.add(1) as usize);
+            data.resize((data.len() + n - 1) / n * n, 0);
+            let mut bytes = ::std::slice::from_raw_parts(sample.add(offset), n).to_vec();
+            swap(&mut bytes);
+            data.extend(bytes);
+            op = op.add(2);
+        }
+        data
+    }
+
+    pub unsafe fn deserialize(desc: &dds_topic_descriptor, data: &[u8], sample: *mut u8) {
+        let (mut op, mut pos) = (desc.m_ops, 0);
+        while *op != DDS_OP_RTS {
+            let (n, offset) = (size(*op), *op.add(1) as usize);
+            pos = (pos + n - 1) / n * n;
+            let mut bytes = data[pos..pos + n].to_vec();
+            swap(&mut bytes);
+            ::std::ptr::copy_nonoverlapping(bytes.as_ptr(), sample.add(offset), n);
+            pos += n;
+            op = op.add(2);
+        }
+    }
+
+    #[test]
+    fn wide_char() {
+        let sample = crate::M::W { w: 0xe9, k: -2 };
+        let desc = unsafe { <crate::M::W as DDSGenType>::get_descriptor() };
+        let data = unsafe { serialize(desc, &sample as *const crate::M::W as *const u8) };
+        assert_eq!(data, [0x00, 0xe9, 0, 0, 0xff, 0xff, 0xff, 0xfe]);
+
+        let mut copy = crate::M::W { w: 0, k: 0 };
+        unsafe { deserialize(desc, &data, &mut copy as *mut crate::M::W as *mut u8) };
+        assert_eq!((copy.w, copy.k), (sample.w, sample.k));
+        // the key hash holds the wchar of the wire
+        assert_eq!(sample.key_hash()[..2], data[..2]);
+    }
+}
+"#;
+
+    #[test]
+    fn wide_char_round_trip_16bit() {
+        if !has_tool("cc") || !has_tool("rustc") {
+            return;
+        }
+        let config = Configuration {
+            generate_descriptors: true,
+            layout_checks: true,
+            runtime_crate: String::from("crate::sys"),
+            wchar_size: WideCharSize::Bits16,
+            ..Default::default()
+        };
+        let idl = "module M { struct W { wchar w; long k; };\n#pragma keylist W w\n};";
+        let sources = [IdlSource::Memory(std::path::PathBuf::from("w.idl"), String::from(idl))];
+        let generated = Backends::new().generate_files(&["rust", "c"], &mut MemoryLoader::new(), &config, &sources);
+        assert!(!generated.has_errors());
+
+        let dir = temp_dir("wchar16");
+        write_file(&dir, "dds/ddsc/dds_public_impl.h", DDS_PUBLIC_IMPL_H);
+        for output in generated.outputs.iter() {
+            write_file(&dir, &output.name, &output.contents);
+        }
+        let rust = std::fs::read_to_string(dir.join("w.rs")).unwrap();
+        write_file(&dir, "w.rs", &format!("{}{}{}", RUNTIME_STUB, rust, ROUND_TRIP));
+
+        // a target with a 16 bit wchar_t
+        run(&dir, Command::new("cc").args(["-c", "-fshort-wchar", "-Wall", "-Werror", "-I.", "w_layout.c"]));
+        run(&dir, Command::new("ar").args(["rcs", "libw_layout.a", "w_layout.o"]));
+        run(&dir, Command::new("rustc").args(["--edition", "2018", "--test", "w.rs", "-L.", "-lstatic=w_layout"]));
+        run(&dir, &mut Command::new(dir.join("w")));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn wide_strings_are_rejected() {
+        let config = Configuration {
+            generate_descriptors: true,
+            ..Default::default()
+        };
+        let idl = "module M { struct W { long k; wstring w; };\n#pragma keylist W k\n};";
+        let mesg = generate_error(&config, idl);
+        assert!(mesg.contains("Cyclone DDS 0.7"));
+        // the descriptor linked from the C file has the same limits
+        let mesg = generate_error(&Configuration::default(), idl);
+        assert!(mesg.contains("Cyclone DDS 0.7"), "{}", mesg);
+
+        // also when nested in the types of a member
+        let idl = "module M {
+            union U switch (long) { case 1: wstring<8> w; };
+            struct N { sequence<U> u; };
+            struct W { long k; N n; };
+            #pragma keylist W k
+        };";
+        let mesg = generate_error(&config, idl);
+        assert!(mesg.contains("member n holds a wide string"), "{}", mesg);
+        assert!("0.8".parse::<CycloneVersion>().is_err());
+
+        // the elements without a sub op are errors instead of invalid ops
+        let idl = "module M {
+            union U switch (long) { case 1: long l; };
+            struct S { long k; sequence<U> u; };
+            #pragma keylist S k
+        };";
+        let mesg = generate_error(&config, idl);
+        assert!(mesg.contains("Unsupported type"), "{}", mesg);
+    }
+
+}
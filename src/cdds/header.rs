@@ -0,0 +1,215 @@
+// Copyright (C) 2020  Sojan James
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0>
+
+/* The declarations of the C header, from the IR of the main file. */
+
+use crate::ast::{fixed_size, FixedValue};
+use crate::cdds::{c_escape, c_type_name, scoped_name, struct_members, INDENTION};
+use crate::ir::{ConstValue, Constant, Declaration, Module, TypeDecl, TypeKind, TypeSpec};
+
+use std::io::Write;
+use std::io::{Error, ErrorKind};
+
+// The module of the declaration `path`
+fn scope_of(path: &[String]) -> Vec<String> {
+    path[..path.len() - 1].to_vec()
+}
+
+/// Write the type declarations of the C header in the order of
+/// `declarations`, after the types they use
+pub fn write_h_types<W: Write>(out: &mut W, declarations: &[Declaration]) -> Result<(), Error> {
+    for declaration in declarations.iter() {
+        if let Declaration::Type(decl) = declaration {
+            write_type_h(out, decl)?;
+        }
+    }
+    Ok(())
+}
+
+/// Write the topic descriptor declarations and the allocator macros of the
+/// topics of the main file in `module`
+pub fn write_h<W: Write>(out: &mut W, module: &Module) -> Result<(), Error> {
+    for submodule in module.modules.iter() {
+        write_h(out, submodule)?;
+    }
+
+    writeln!(out)?;
+
+    let topics: Vec<String> = module
+        .types
+        .iter()
+        .filter(|typ| typ.source.is_none())
+        .filter(|typ| matches!(&typ.kind, TypeKind::Struct(s) if s.topic.is_some()))
+        .map(|typ| scoped_name(&module.path, &typ.id))
+        .collect();
+    for c_name in topics.iter() {
+        //extern const dds_topic_descriptor_t HelloWorldData_Msg_desc;
+        writeln!(out, "extern const dds_topic_descriptor_t {}_desc;", c_name)?;
+    }
+
+    writeln!(out)?;
+
+    for c_name in topics.iter() {
+        let alloc_str = std::include_str!("templates/allocator_macro.txt");
+        out.write_all(alloc_str.replace("<SCOPED_NAME>", c_name).as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Write the constants of the main file in `module` as macros, the
+/// declarations may use a constant of any module
+pub fn write_h_constants<W: Write>(out: &mut W, module: &Module, root: &Module) -> Result<(), Error> {
+    for cnst in module.constants.iter().filter(|cnst| cnst.source.is_none()) {
+        write_constant_h(out, cnst, root)?;
+    }
+    for submodule in module.modules.iter() {
+        write_h_constants(out, submodule, root)?;
+    }
+    Ok(())
+}
+
+// Write the constant as a macro of the C header
+fn write_constant_h<W: Write>(out: &mut W, cnst: &Constant, root: &Module) -> Result<(), Error> {
+    write!(out, "#define {} ", scoped_name(&scope_of(&cnst.path), &cnst.id))?;
+    let spec = cnst.type_spec.resolve(root);
+    let prefix = match spec {
+        TypeSpec::WideChar | TypeSpec::WideString(_) => "L",
+        _ => "",
+    };
+    match &cnst.value {
+        // the initializer of the packed BCD array
+        ConstValue::Fixed { scaled, scale } => {
+            let value = FixedValue { scaled: *scaled, scale: *scale };
+            let fixed = match spec {
+                TypeSpec::Fixed { digits, scale } => value.scaled_to(*scale).map(|scaled| (scaled, *digits)),
+                _ => None,
+            };
+            match fixed {
+                Some((scaled, digits)) => {
+                    let bytes: Vec<String> = fixed_bcd(scaled, digits)
+                        .iter()
+                        .map(|byte| format!("0x{:02x}", byte))
+                        .collect();
+                    write!(out, "{{ {} }}", bytes.join(", "))?
+                }
+                None => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("Unable to evaluate fixed point constant {}", cnst.id),
+                    ))
+                }
+            }
+        }
+        ConstValue::Integer(value) if *value < 0 => write!(out, "({})", value)?,
+        ConstValue::Integer(value) => write!(out, "{}", value)?,
+        ConstValue::Float(value) if *value < 0.0 => write!(out, "({:?})", value)?,
+        ConstValue::Float(value) => write!(out, "{:?}", value)?,
+        ConstValue::Boolean(value) => write!(out, "{}", value)?,
+        ConstValue::Char(c) => write!(out, "{}'{}'", prefix, c_escape(&c.to_string()))?,
+        ConstValue::String(s) => write!(out, "{}\"{}\"", prefix, c_escape(s))?,
+        // the enumerators are declared in the scope of the enum
+        ConstValue::Enumerator { enumeration, id, .. } => {
+            write!(out, "{}", scoped_name(&scope_of(enumeration), id))?
+        }
+    }
+    writeln!(out)
+}
+
+// The packed BCD bytes of the fixed<digits, _> value `scaled`, the digits
+// most significant first and the sign nibble
+fn fixed_bcd(scaled: i128, digits: u32) -> Vec<u8> {
+    let size = fixed_size(digits) as usize;
+    let mut bcd = vec![0u8; size];
+    bcd[size - 1] = if scaled < 0 { 0x0d } else { 0x0c };
+    let mut magnitude = scaled.unsigned_abs();
+    let mut nibble = 1;
+    while magnitude > 0 && nibble / 2 < size {
+        let digit = (magnitude % 10) as u8;
+        let idx = size - 1 - nibble / 2;
+        bcd[idx] |= if nibble % 2 == 0 { digit } else { digit << 4 };
+        magnitude /= 10;
+        nibble += 1;
+    }
+    bcd
+}
+
+// Write the C declaration of the type `decl`
+fn write_type_h<W: Write>(out: &mut W, decl: &TypeDecl) -> Result<(), Error> {
+    let scope = scope_of(&decl.path);
+    let id = &decl.id;
+    match &decl.kind {
+        TypeKind::Struct(s) => {
+            //typedef struct HelloWorldData_Msg
+            writeln!(out, "typedef struct {}", &scoped_name(&scope, id))?;
+            writeln!(out, "{{")?;
+
+            for member in struct_members(s) {
+                write!(out, "{:indent$} ", "", indent = INDENTION)?;
+                write_member_h(out, &member.id, &member.type_spec)?;
+                writeln!(out)?;
+            }
+
+            writeln!(out, "}} {};", &scoped_name(&scope, id))
+        }
+        TypeKind::Bitmask(bitmask) => {
+            crate::cdds::bits::write_bitmask_h(out, &scope, id, bitmask.bit_bound, &bitmask.flags)
+        }
+        TypeKind::Bitset(fields) => crate::cdds::bits::write_bitset_h(out, &scope, id, fields),
+        // sequences of forward declared types are untyped dds_sequence_t
+        TypeKind::Forward => Ok(()),
+        TypeKind::Interface(operations) => crate::cdds::rpc::write_interface_h(out, &scope, id, operations),
+        TypeKind::Typedef(type_spec) => {
+            // declared like a member named after the type
+            write!(out, "typedef ")?;
+            write_member_h(out, &scoped_name(&scope, id), type_spec)?;
+            writeln!(out)
+        }
+        TypeKind::Enum(enumerators) => {
+            // the enumerators are scoped by the module, like the type
+            writeln!(out, "typedef enum {}", &scoped_name(&scope, id))?;
+            writeln!(out, "{{")?;
+            for (idx, enumerator) in enumerators.iter().enumerate() {
+                let separator = if idx + 1 < enumerators.len() { "," } else { "" };
+                writeln!(
+                    out,
+                    "{:indent$}{}{}",
+                    "",
+                    &scoped_name(&scope, enumerator),
+                    separator,
+                    indent = INDENTION
+                )?;
+            }
+            writeln!(out, "}} {};", &scoped_name(&scope, id))
+        }
+        TypeKind::Union(_) => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Union {} can't be generated in C", &scoped_name(&scope, id)),
+        )),
+    }
+}
+
+// Write the declaration of the member `id` of type `spec`
+fn write_member_h<W: Write>(out: &mut W, id: &str, spec: &TypeSpec) -> Result<(), Error> {
+    write!(out, "{} {}", c_type_name(spec), id)?;
+    match spec {
+        TypeSpec::Array(elem, dims) => {
+            // Array types in c are different. The array size comes after the id
+            for dim in dims {
+                write!(out, "[{}]", dim)?;
+            }
+            match elem.as_ref() {
+                TypeSpec::Fixed { digits, .. } => write!(out, "[{}]", fixed_size(*digits))?,
+                TypeSpec::String(Some(bound)) => write!(out, "[{}]", bound + 1)?,
+                _ => (),
+            }
+        }
+        // bounded strings are char arrays
+        TypeSpec::String(Some(bound)) => write!(out, "[{}]", bound + 1)?,
+        // packed BCD, like the CDR encoding
+        TypeSpec::Fixed { digits, .. } => write!(out, "[{}]", fixed_size(*digits))?,
+        _ => (),
+    }
+    write!(out, ";")
+}
@@ -0,0 +1,202 @@
+// Copyright (C) 2020  Sojan James
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0>
+
+/* Imports of the generated Rust code from the Cyclone DDS runtime crate.
+
+ Only the items the generated code refers to are imported, so the output
+ compiles without unused import warnings. The opcodes and flags of the topic
+ descriptors are imported as well, they are not redefined by the output. The runtime crate path is
+ configurable, it may be cyclonedds_sys or a crate re-exporting it.
+*/
+
+use crate::cdds::descriptor::TopicDescriptor;
+use crate::cdds::Model;
+use crate::ir::{Module, Topic, TypeKind, TypeSpec};
+use crate::CycloneVersion;
+
+use std::collections::BTreeSet;
+use std::io::{Error, Write};
+
+// True if `pred` holds for the type or its elements, without following scoped names
+fn contains(spec: &TypeSpec, pred: &dyn Fn(&TypeSpec) -> bool) -> bool {
+    match spec {
+        TypeSpec::Sequence(elem, _) | TypeSpec::Array(elem, _) => pred(spec) || contains(elem, pred),
+        TypeSpec::Map(key, value, _, _) => pred(spec) || contains(key, pred) || contains(value, pred),
+        _ => pred(spec),
+    }
+}
+
+// True if `pred` holds for a type used in the module or its submodules
+fn module_uses(module: &Module, pred: &dyn Fn(&TypeSpec) -> bool) -> bool {
+    module.types.iter().any(|typ| match &typ.kind {
+        TypeKind::Typedef(spec) => contains(spec, pred),
+        TypeKind::Struct(s) => s.members.iter().any(|m| contains(&m.type_spec, pred)),
+        TypeKind::Union(u) => u.cases.iter().any(|case| contains(&case.type_spec, pred)),
+        _ => false,
+    }) || module.constants.iter().any(|c| pred(&c.type_spec))
+        || module.modules.iter().any(|m| module_uses(m, pred))
+}
+
+// True if `pred` holds for a topic of the module or its submodules
+fn module_has_topic(module: &Module, pred: &dyn Fn(&Topic) -> bool) -> bool {
+    module.types.iter().any(|typ| match &typ.kind {
+        TypeKind::Struct(s) => s.topic.as_ref().is_some_and(pred),
+        _ => false,
+    }) || module.modules.iter().any(|m| module_has_topic(m, pred))
+}
+
+/// True if the Fixed type must be generated
+pub fn uses_fixed(root: &Module) -> bool {
+    module_uses(root, &|spec| matches!(spec, TypeSpec::Fixed { .. }))
+}
+
+/// True if the LongDouble type must be generated
+pub fn uses_long_double(root: &Module) -> bool {
+    module_uses(root, &|spec| matches!(spec, TypeSpec::LongDouble))
+}
+
+/// True if a struct of the module or its submodules is a topic
+pub fn has_topics(root: &Module) -> bool {
+    module_has_topic(root, &|_| true)
+}
+
+/// True if a topic of the module or its submodules has keys
+pub fn has_keyed_topics(root: &Module) -> bool {
+    module_has_topic(root, &|topic| !topic.keys.is_empty())
+}
+
+// The opcodes and topic flags of the descriptors of the topics of the main
+// file in `module`, the descriptors failing to generate are reported by the
+// generation
+fn descriptor_constants(
+    module: &Module,
+    target: CycloneVersion,
+    model: Model,
+    constants: &mut BTreeSet<String>,
+) {
+    for typ in module.types.iter().filter(|typ| typ.source.is_none()) {
+        if let TypeKind::Struct(s) = &typ.kind {
+            let topic = match &s.topic {
+                Some(topic) => topic,
+                None => continue,
+            };
+            if let Ok(descriptor) = TopicDescriptor::new(&typ.path, s, topic, target, model) {
+                let words = descriptor
+                    .ops
+                    .iter()
+                    .map(|op| op.op.as_str())
+                    .chain(descriptor.flags.iter().map(String::as_str));
+                for word in words.flat_map(|w| w.split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))) {
+                    if word.starts_with("DDS_") {
+                        constants.insert(String::from(word));
+                    }
+                }
+            }
+        }
+    }
+    for submodule in module.modules.iter() {
+        descriptor_constants(submodule, target, model, constants);
+    }
+}
+
+/// The runtime items referenced by the generated Rust code, `descriptors` is
+/// the release of the generated descriptors if any
+pub fn runtime_items(model: Model, descriptors: Option<CycloneVersion>) -> Vec<String> {
+    let root = model.root;
+    let mut items = Vec::new();
+    if has_topics(root) {
+        items.push(String::from("DDSGenType"));
+        items.push(String::from("dds_topic_descriptor"));
+        if descriptors.is_some() && has_keyed_topics(root) {
+            items.push(String::from("dds_key_descriptor"));
+        }
+    }
+    // maps are sequences of their entries
+    if module_uses(root, &|spec| matches!(spec, TypeSpec::Sequence(_, _) | TypeSpec::Map(_, _, _, _))) {
+        items.push(String::from("dds_sequence_t"));
+    }
+    // the descriptors are generated for the topics of the main file
+    if let Some(target) = descriptors {
+        let mut constants = BTreeSet::new();
+        descriptor_constants(root, target, model, &mut constants);
+        items.extend(constants);
+    }
+    items
+}
+
+/// Write the import of the runtime items from `runtime_crate`
+pub fn write_runtime_imports<W: Write>(
+    out: &mut W,
+    runtime_crate: &str,
+    model: Model,
+    descriptors: Option<CycloneVersion>,
+) -> Result<(), Error> {
+    let items = runtime_items(model, descriptors);
+    if items.is_empty() {
+        return Ok(());
+    }
+    writeln!(out, "use {}::{{", runtime_crate.trim_end_matches("::"))?;
+    for item in items {
+        writeln!(out, "    {},", item)?;
+    }
+    writeln!(out, "}};")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::fixture::{generate, has_tool, run, temp_dir, write_file, RUNTIME_STUB};
+    use crate::Configuration;
+    use std::process::Command;
+
+    #[test]
+    fn no_imports_without_runtime_items() {
+        let generated = generate(&Configuration::default(), "struct A { long a; };");
+        assert!(!generated.contains("use cyclonedds_sys"));
+    }
+
+    #[test]
+    fn imports_from_runtime_crate() {
+        let config = Configuration {
+            generate_descriptors: true,
+            runtime_crate: String::from("dds_wrapper::sys"),
+            ..Default::default()
+        };
+        let idl = "module M { struct T { long k; sequence<long> s; };\n#pragma keylist T k\n};";
+        let generated = generate(&config, idl);
+        // the opcodes and flags of the descriptors are those of the runtime
+        assert!(generated.starts_with(
+            "use dds_wrapper::sys::{\n    DDSGenType,\n    dds_topic_descriptor,\n    dds_key_descriptor,\n    dds_sequence_t,\n    \
+             DDS_OP_ADR,\n    DDS_OP_FLAG_KEY,\n    DDS_OP_FLAG_SGN,\n    DDS_OP_RTS,\n    DDS_OP_SUBTYPE_4BY,\n    \
+             DDS_OP_TYPE_4BY,\n    DDS_OP_TYPE_SEQ,\n    DDS_TOPIC_FIXED_KEY,\n    DDS_TOPIC_NO_OPTIMIZE,\n};\n"
+        ));
+        assert!(!generated.contains("const DDS_"));
+    }
+
+    #[test]
+    fn descriptors_compile_with_runtime_imports() {
+        if !has_tool("rustc") {
+            return;
+        }
+        let config = Configuration {
+            generate_descriptors: true,
+            runtime_crate: String::from("crate::sys"),
+            ..Default::default()
+        };
+        let idl = "module M {
+            struct P { double x; boolean b; };
+            struct T { long k; string<4> s; sequence<P> p; short a[2]; };
+            #pragma keylist T k s
+        };";
+        let generated = generate(&config, idl);
+
+        let dir = temp_dir("imports");
+        write_file(&dir, "t.rs", &format!("{}{}", RUNTIME_STUB, generated));
+        run(
+            &dir,
+            Command::new("rustc").args(["--edition", "2018", "--crate-type", "lib", "-Dunused-imports", "t.rs"]),
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,349 @@
+// Copyright (C) 2020  Sojan James
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0>
+
+/* Key hash generation for keyed topics.
+
+ The key fields are serialized in big-endian CDR in keylist order. If the
+ serialized key never exceeds 16 bytes the topic has a fixed key
+ (DDS_TOPIC_FIXED_KEY) and the key hash is the zero padded key, otherwise
+ it is the MD5 digest of the serialized key.
+*/
+
+use crate::cdds::bits::bit_holder;
+use crate::cdds::keys::{key_expr, write_key_fn};
+use crate::cdds::type_trait::Type;
+use crate::cdds::{Model, INDENTION};
+use crate::ir::{Key, TypeKind, TypeSpec};
+use crate::WideCharSize;
+
+use std::io::{Error, ErrorKind, Write};
+
+// CDR alignment of a key field
+fn get_key_alignment(spec: &TypeSpec, model: Model) -> i32 {
+    match spec {
+        TypeSpec::Array(typespec, _) => get_key_alignment(typespec, model),
+        TypeSpec::String(_) => 4,
+        // packed BCD
+        TypeSpec::Fixed { .. } => 1,
+        TypeSpec::Named(name) => match model.kind(name) {
+            Some(TypeKind::Typedef(typespec)) => get_key_alignment(typespec, model),
+            _ => 4,
+        },
+        _ => spec.get_key_size(model).max(1),
+    }
+}
+
+/// Maximum size of the serialized key, -1 if the key is unbounded
+pub fn get_key_size(keys: &[Key], model: Model) -> i32 {
+    let mut size = 0;
+    for key in keys {
+        let key_size = key.type_spec.get_key_size(model);
+        if key_size < 0 {
+            return -1;
+        }
+        let align = get_key_alignment(&key.type_spec, model);
+        size = (size + align - 1) / align * align + key_size;
+    }
+    size
+}
+
+/// The DDS_TOPIC_FIXED_KEY condition, the key fits into the 16 byte key hash
+pub fn is_fixed_key(keys: &[Key], model: Model) -> bool {
+    let size = get_key_size(keys, model);
+    size > 0 && size <= 16
+}
+
+// Statements serializing `expr` of type `spec`, paired with their nesting depth
+fn get_key_write_stmts(
+    spec: &TypeSpec,
+    expr: &str,
+    depth: usize,
+    model: Model,
+    stmts: &mut Vec<(usize, String)>,
+) -> Result<(), Error> {
+    let mut write = |method: &str, value: String| {
+        stmts.push((depth, format!("writer.{}({});", method, value)));
+    };
+    match spec {
+        TypeSpec::Float32 => write("write_f32", String::from(expr)),
+        TypeSpec::Float64 => write("write_f64", String::from(expr)),
+        TypeSpec::Int8 => write("write_i8", String::from(expr)),
+        TypeSpec::Int16 => write("write_i16", String::from(expr)),
+        TypeSpec::Int32 => write("write_i32", String::from(expr)),
+        TypeSpec::Int64 => write("write_i64", String::from(expr)),
+        TypeSpec::UInt16 => write("write_u16", String::from(expr)),
+        TypeSpec::UInt32 => write("write_u32", String::from(expr)),
+        TypeSpec::UInt64 => write("write_u64", String::from(expr)),
+        TypeSpec::Char => write("write_u8", format!("{} as u8", expr)),
+        TypeSpec::WideChar => match model.wchar_size {
+            // a wchar is 16 bits on the wire
+            WideCharSize::Bits16 => write("write_u16", String::from(expr)),
+            WideCharSize::Bits32 => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "Key {} is a 32 bit wide char, the key hash can't narrow it to the 16 bit wchar of the wire, generate with --wchar-size 16",
+                        expr
+                    ),
+                ))
+            }
+        },
+        TypeSpec::Boolean => write("write_bool", String::from(expr)),
+        TypeSpec::Octet | TypeSpec::UInt8 => write("write_u8", String::from(expr)),
+        TypeSpec::Fixed { .. } => stmts.push((
+            depth,
+            format!("for b in {}.bcd().iter() {{ writer.write_u8(*b); }}", expr),
+        )),
+        TypeSpec::String(None) => {
+            stmts.push((depth, format!("unsafe {{ writer.write_string({}) }};", expr)))
+        }
+        TypeSpec::String(Some(_)) => write("write_bstring", format!("&{}", expr)),
+        TypeSpec::WideString(_) => {
+            let method = match model.wchar_size {
+                WideCharSize::Bits16 => "write_wstring16",
+                WideCharSize::Bits32 => "write_wstring32",
+            };
+            stmts.push((depth, format!("unsafe {{ writer.{}({}) }};", method, expr)))
+        }
+        TypeSpec::Array(typespec, dims) => {
+            let mut elem = String::from(expr);
+            for (idx, _) in dims.iter().enumerate() {
+                let var = format!("v{}", depth + idx);
+                stmts.push((depth + idx, format!("for {} in {}.iter() {{", var, elem)));
+                elem = var;
+            }
+            get_key_write_stmts(typespec, &format!("*{}", elem), depth + dims.len(), model, stmts)?;
+            for idx in (0..dims.len()).rev() {
+                stmts.push((depth + idx, String::from("}")));
+            }
+        }
+        TypeSpec::Named(name) => match model.kind(name) {
+            Some(TypeKind::Enum(_)) => write("write_u32", format!("{}.clone() as u32", expr)),
+            Some(kind @ (TypeKind::Bitmask(_) | TypeKind::Bitset(_))) => {
+                let holder = bit_holder(kind).unwrap_or(TypeSpec::UInt64);
+                get_key_write_stmts(&holder, &format!("{}.0", expr), depth, model, stmts)?
+            }
+            Some(TypeKind::Typedef(typespec)) => get_key_write_stmts(typespec, expr, depth, model, stmts)?,
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Unsupported key type {:?} for {}", name, expr),
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Unsupported key type {:?} for {}", spec, expr),
+            ))
+        }
+    }
+    Ok(())
+}
+
+/// Write the `key_hash()` implementation for the keyed topic `id`
+pub fn write_key_hash<W: Write>(
+    out: &mut W,
+    level: usize,
+    id: &str,
+    keys: &[Key],
+    model: Model,
+) -> Result<(), Error> {
+    let mut stmts = Vec::new();
+    for key in keys {
+        get_key_write_stmts(&key.type_spec, &key_expr(key, "self"), 0, model, &mut stmts)?;
+    }
+
+    writeln!(out, "{:indent$}impl {} {{", "", id, indent = level * INDENTION)?;
+    write_key_fn(
+        out,
+        level + 1,
+        "The RTPS key hash of this sample",
+        "key_hash(&self) -> [u8; 16]",
+        keys,
+        model,
+    )?;
+    writeln!(
+        out,
+        "{:indent$}let mut writer = KeyHashWriter::new();",
+        "",
+        indent = (level + 2) * INDENTION
+    )?;
+    for (depth, stmt) in stmts {
+        writeln!(out, "{:indent$}{}", "", stmt, indent = (level + 2 + depth) * INDENTION)?;
+    }
+    writeln!(
+        out,
+        "{:indent$}writer.finish({})",
+        "",
+        is_fixed_key(keys, model),
+        indent = (level + 2) * INDENTION
+    )?;
+    writeln!(out, "{:indent$}}}", "", indent = (level + 1) * INDENTION)?;
+    writeln!(out, "{:indent$}}}", "", indent = level * INDENTION)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::fixture::{c_config, generate, generate_error, has_tool, run, temp_dir, write_file};
+    use crate::{Configuration, WideCharSize};
+    use std::process::Command;
+
+    // The generated support code must compile and produce standard digests
+    include!("../templates/key_hash.txt");
+    include!("../templates/fixed.txt");
+
+    fn hex(digest: [u8; 16]) -> String {
+        digest.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn md5_digest() {
+        assert_eq!(hex(key_hash_md5(b"")), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(hex(key_hash_md5(b"abc")), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(
+            hex(key_hash_md5(
+                b"12345678901234567890123456789012345678901234567890123456789012345678901234567890"
+            )),
+            "57edf4a22be3c955ac49da2e2107b67a"
+        );
+    }
+
+    #[test]
+    fn fixed_key_is_zero_padded() {
+        let mut writer = KeyHashWriter::new();
+        writer.write_i16(1);
+        writer.write_i32(2);
+        assert_eq!(
+            writer.finish(true),
+            [0, 1, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn wide_strings_are_utf16() {
+        // U+1F600 needs a surrogate pair
+        let utf32: Vec<u32> = vec!['a' as u32, 0x1F600, 0];
+        let utf16: Vec<u16> = vec!['a' as u16, 0xD83D, 0xDE00, 0];
+
+        let mut writer = KeyHashWriter::new();
+        unsafe { writer.write_wstring32(utf32.as_ptr()) };
+        let expected = [0, 0, 0, 6, 0, 0x61, 0xD8, 0x3D, 0xDE, 0x00, 0, 0, 0, 0, 0, 0];
+        assert_eq!(writer.finish(true), expected);
+
+        let mut writer = KeyHashWriter::new();
+        unsafe { writer.write_wstring16(utf16.as_ptr()) };
+        assert_eq!(writer.finish(true), expected);
+
+        let mut writer = KeyHashWriter::new();
+        unsafe { writer.write_wstring16(::std::ptr::null()) };
+        assert_eq!(writer.finish(true), [0u8; 16]);
+
+        assert_eq!(unsafe { key_wstring16(utf16.as_ptr()) }, "a\u{1F600}");
+        assert_eq!(unsafe { key_wstring32(utf32.as_ptr()) }, "a\u{1F600}");
+    }
+
+    #[test]
+    fn bounded_strings_are_char_arrays() {
+        // string<4> holding "ab", the characters after the nul are ignored
+        let value: [::std::os::raw::c_char; 5] = [0x61, 0x62, 0, 0x63, 0];
+        let mut writer = KeyHashWriter::new();
+        writer.write_bstring(&value);
+        assert_eq!(writer.finish(true), [0, 0, 0, 3, 0x61, 0x62, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(key_bstring(&value), "ab");
+
+        // a full array without the nul
+        let value: [::std::os::raw::c_char; 2] = [0x61, 0x62];
+        assert_eq!(key_bstring(&value), "ab");
+    }
+
+    #[test]
+    fn key_hash_accessors() {
+        let generated = generate(
+            &Configuration::default(),
+            "module M {
+                struct A { long id; };
+                #pragma keylist A id
+                struct B { string name; };
+                #pragma keylist B name
+            };",
+        );
+        assert!(generated.contains("/// The RTPS key hash of this sample\n        pub fn key_hash(&self) -> [u8; 16] {"));
+        assert!(generated.contains("/// # Safety\n        /// The string fields of the key must be null or point to nul terminated strings.\n        pub unsafe fn key_hash(&self) -> [u8; 16] {"));
+    }
+
+    #[test]
+    fn key_alignment() {
+        // packed BCD is byte aligned, the 16 byte key fits the key hash
+        let idl = "module M { struct A { octet o; fixed<29,0> f; };\n#pragma keylist A o f\n};";
+        let generated = generate(&Configuration::default(), idl);
+        assert!(generated.contains("writer.finish(true)"), "{}", generated);
+
+        let config = Configuration {
+            wchar_size: WideCharSize::Bits32,
+            ..Default::default()
+        };
+        let mesg = generate_error(&config, "module M { struct W { wchar w; };\n#pragma keylist W w\n};");
+        assert!(mesg.contains("--wchar-size 16"), "{}", mesg);
+    }
+
+    #[test]
+    fn fixed_is_packed_bcd() {
+        let value: Fixed<5, 2, 3> = "-123.45".parse().unwrap();
+        assert_eq!(value.bcd(), &[0x12, 0x34, 0x5d]);
+        assert_eq!(value.to_scaled(), -12345);
+        assert_eq!(value.to_string(), "-123.45");
+        assert_eq!(Fixed::<4, 2, 3>::new(150).bcd(), &[0x00, 0x15, 0x0c]);
+        assert_eq!("1.5d".parse::<Fixed<4, 2, 3>>(), Ok(Fixed::new(150)));
+        assert!("1.234".parse::<Fixed<4, 2, 3>>().is_err());
+        assert!("100".parse::<Fixed<4, 2, 3>>().is_err());
+
+        let half = Fixed::<4, 2, 3>::new(50);
+        assert_eq!((half * half).to_string(), "0.25");
+        assert_eq!(Fixed::<4, 2, 3>::new(100) / Fixed::new(300), Fixed::new(33));
+        assert_eq!(half.convert::<3, 0, 2>(), Some(Fixed::new(0)));
+    }
+
+    #[test]
+    fn fixed_point() {
+        let idl = "module M { const long D = 12; struct F { fixed<D, 4> f; fixed<3, 0> a[2]; };\n#pragma keylist F f\n};";
+        let generated = generate(&c_config(), idl);
+        assert!(generated.contains(
+            "DDS_OP_ADR | DDS_OP_TYPE_ARR | DDS_OP_SUBTYPE_1BY | DDS_OP_FLAG_KEY, offsetof (M_F, f), 7,"
+        ));
+        assert!(generated.contains("DDS_OP_ADR | DDS_OP_TYPE_ARR | DDS_OP_SUBTYPE_1BY, offsetof (M_F, a), 4,"));
+        assert!(generated.contains("<Fixed digits=\\\"12\\\" scale=\\\"4\\\"/>"));
+
+        // the struct with more than 31 digits is rejected
+        let idl = "module M { struct F { fixed<32, 0> f; }; };";
+        let mesg = generate_error(&Configuration::default(), idl);
+        assert!(mesg.contains("struct F {"), "{}", mesg);
+
+        let idl = "module M { const fixed P = 12.3400d; const fixed H = P / 2; };";
+        let generated = generate(&Configuration::default(), idl);
+        assert!(generated.contains("pub const P: Fixed<4, 2, 3> = Fixed::new(1234);"));
+        assert!(generated.contains("pub const H: Fixed<3, 2, 2> = Fixed::new(617);"));
+        assert!(generated.contains("pub struct Fixed<const __D: u32"));
+    }
+
+    #[test]
+    fn fixed_point_names_do_not_collide() {
+        if !has_tool("rustc") {
+            return;
+        }
+        // top level modules and constants named like the usual generic parameters
+        let idl = "module D { struct F { fixed<5, 2> f; }; };
+            module S { const fixed P = 1.5d; };
+            module M { const long N = 3; };
+            const long DIGITS = 4;
+            const long SCALE = 1;";
+        let generated = generate(&Configuration::default(), idl);
+
+        let dir = temp_dir("fixed-names");
+        write_file(&dir, "f.rs", &generated);
+        run(&dir, Command::new("rustc").args(["--edition", "2018", "--crate-type", "lib", "f.rs"]));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,233 @@
+// Copyright (C) 2020  Sojan James
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0>
+
+/* Key fields of keyed topics and the generated <Type>Key types.
+
+ Keys are given by "#pragma keylist" and may name members of nested
+ structs with a dotted path, e.g. "#pragma keylist Msg header.id".
+*/
+
+use crate::cdds::{rust_type, Model, INDENTION};
+use crate::ir::{Key, TypeKind, TypeSpec};
+use crate::WideCharSize;
+
+use std::io::{Error, ErrorKind, Write};
+
+/// Name of the field of `key` in the generated key type
+pub fn field_name(key: &Key) -> String {
+    key.path.join("_")
+}
+
+/// Rust expression accessing the field of `key` of `base`
+pub fn key_expr(key: &Key, base: &str) -> String {
+    format!("{}.{}", base, key.path.join("."))
+}
+
+// Whether `spec` is read through a raw pointer, an unbounded or wide string
+fn is_pointer(spec: &TypeSpec, model: Model) -> bool {
+    match spec {
+        TypeSpec::String(None) | TypeSpec::WideString(_) => true,
+        TypeSpec::Array(typespec, _) => is_pointer(typespec, model),
+        TypeSpec::Named(name) => match model.kind(name) {
+            Some(TypeKind::Typedef(typespec)) => is_pointer(typespec, model),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Whether a key field is a string read through its raw pointer, the key
+/// accessors are unsafe functions then
+pub fn has_pointer_keys(keys: &[Key], model: Model) -> bool {
+    keys.iter().any(|key| is_pointer(&key.type_spec, model))
+}
+
+/// Write the doc and signature of the key accessor `signature`, unsafe when
+/// the key holds raw string pointers
+pub fn write_key_fn<W: Write>(
+    out: &mut W,
+    level: usize,
+    doc: &str,
+    signature: &str,
+    keys: &[Key],
+    model: Model,
+) -> Result<(), Error> {
+    let indent = level * INDENTION;
+    writeln!(out, "{:indent$}/// {}", "", doc, indent = indent)?;
+    if !has_pointer_keys(keys, model) {
+        return writeln!(out, "{:indent$}pub fn {} {{", "", signature, indent = indent);
+    }
+    writeln!(out, "{:indent$}///", "", indent = indent)?;
+    writeln!(out, "{:indent$}/// # Safety", "", indent = indent)?;
+    writeln!(
+        out,
+        "{:indent$}/// The string fields of the key must be null or point to nul terminated strings.",
+        "",
+        indent = indent
+    )?;
+    writeln!(out, "{:indent$}pub unsafe fn {} {{", "", signature, indent = indent)
+}
+
+// The key type field type and the expression converting `expr` into it
+fn get_key_field_type(
+    spec: &TypeSpec,
+    expr: &str,
+    depth: usize,
+    model: Model,
+) -> Result<(String, String), Error> {
+    match spec {
+        TypeSpec::Float32 => Ok((String::from("KeyF32"), format!("KeyF32({})", expr))),
+        TypeSpec::Float64 => Ok((String::from("KeyF64"), format!("KeyF64({})", expr))),
+        TypeSpec::Int8
+        | TypeSpec::Int16
+        | TypeSpec::Int32
+        | TypeSpec::Int64
+        | TypeSpec::UInt16
+        | TypeSpec::UInt32
+        | TypeSpec::UInt8
+        | TypeSpec::UInt64
+        | TypeSpec::Char
+        | TypeSpec::WideChar
+        | TypeSpec::Fixed { .. }
+        | TypeSpec::Boolean
+        | TypeSpec::Octet => Ok((rust_type(spec, model.wchar_size), String::from(expr))),
+        TypeSpec::String(None) => Ok((
+            String::from("String"),
+            format!("unsafe {{ key_string({}) }}", expr),
+        )),
+        TypeSpec::WideString(_) => {
+            let convert = match model.wchar_size {
+                WideCharSize::Bits16 => "key_wstring16",
+                WideCharSize::Bits32 => "key_wstring32",
+            };
+            Ok((String::from("String"), format!("unsafe {{ {}({}) }}", convert, expr)))
+        }
+        TypeSpec::String(Some(_)) => {
+            Ok((String::from("String"), format!("key_bstring(&{})", expr)))
+        }
+        TypeSpec::Array(typespec, dims) => {
+            // one closure per dimension, the innermost converts the elements
+            let vars: Vec<String> = (0..dims.len()).map(|idx| format!("v{}", depth + idx)).collect();
+            let elem_var = &vars[vars.len() - 1];
+            let (elem_type, elem_expr) =
+                get_key_field_type(typespec, elem_var, depth + dims.len(), model)?;
+
+            // same nesting as the array type of the topic struct
+            let mut array_type = elem_type;
+            for dim in dims {
+                array_type = format!("[{};{}]", array_type, dim);
+            }
+
+            if elem_expr == *elem_var {
+                return Ok((array_type, String::from(expr)));
+            }
+            let mut array_expr = elem_expr;
+            for idx in (1..vars.len()).rev() {
+                array_expr = format!("{}.map(|{}| {})", vars[idx - 1], vars[idx], array_expr);
+            }
+            Ok((
+                array_type,
+                format!("{}.clone().map(|{}| {})", expr, vars[0], array_expr),
+            ))
+        }
+        TypeSpec::Named(name) => match model.kind(name) {
+            Some(TypeKind::Enum(_)) | Some(TypeKind::Bitmask(_)) | Some(TypeKind::Bitset(_)) => {
+                Ok((rust_type(spec, model.wchar_size), String::from(expr)))
+            }
+            Some(TypeKind::Typedef(typespec)) => get_key_field_type(typespec, expr, depth, model),
+            _ => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Unsupported key type {:?} for {}", name, expr),
+            )),
+        },
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Unsupported key type {:?} for {}", spec, expr),
+        )),
+    }
+}
+
+/// Write the `<id>Key` type and the `key()` accessor of the keyed topic `id`
+pub fn write_key_type<W: Write>(
+    out: &mut W,
+    level: usize,
+    id: &str,
+    keys: &[Key],
+    model: Model,
+) -> Result<(), Error> {
+    let mut fields = Vec::new();
+    for key in keys {
+        let (field_type, field_expr) = get_key_field_type(&key.type_spec, &key_expr(key, "self"), 0, model)?;
+        fields.push((field_name(key), field_type, field_expr));
+    }
+
+    writeln!(out, "{:indent$}/// Key fields of {}", "", id, indent = level * INDENTION)?;
+    writeln!(
+        out,
+        "{:indent$}#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]",
+        "",
+        indent = level * INDENTION
+    )?;
+    writeln!(out, "{:indent$}pub struct {}Key {{", "", id, indent = level * INDENTION)?;
+    for (name, field_type, _) in &fields {
+        writeln!(
+            out,
+            "{:indent$}pub {}: {},",
+            "",
+            name,
+            field_type,
+            indent = (level + 1) * INDENTION
+        )?;
+    }
+    writeln!(out, "{:indent$}}}", "", indent = level * INDENTION)?;
+
+    writeln!(out, "{:indent$}impl {} {{", "", id, indent = level * INDENTION)?;
+    write_key_fn(
+        out,
+        level + 1,
+        "The key fields of this sample",
+        &format!("key(&self) -> {}Key", id),
+        keys,
+        model,
+    )?;
+    writeln!(out, "{:indent$}{}Key {{", "", id, indent = (level + 2) * INDENTION)?;
+    for (name, _, field_expr) in &fields {
+        writeln!(
+            out,
+            "{:indent$}{}: {},",
+            "",
+            name,
+            field_expr,
+            indent = (level + 3) * INDENTION
+        )?;
+    }
+    writeln!(out, "{:indent$}}}", "", indent = (level + 2) * INDENTION)?;
+    writeln!(out, "{:indent$}}}", "", indent = (level + 1) * INDENTION)?;
+    writeln!(out, "{:indent$}}}", "", indent = level * INDENTION)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::fixture::generate;
+    use crate::Configuration;
+
+    #[test]
+    fn key_accessors() {
+        let generated = generate(
+            &Configuration::default(),
+            "module M {
+                struct A { long id; };
+                #pragma keylist A id
+                struct B { long id; string name; };
+                #pragma keylist B id name
+            };",
+        );
+        assert!(generated.contains("/// The key fields of this sample\n        pub fn key(&self) -> AKey {"));
+        // the strings are read through the raw pointers of the sample
+        assert!(generated.contains(
+            "/// # Safety\n        /// The string fields of the key must be null or point to nul terminated strings.\n        pub unsafe fn key(&self) -> BKey {"
+        ), "{}", generated);
+    }
+}
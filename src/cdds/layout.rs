@@ -0,0 +1,272 @@
+// Copyright (C) 2020  Sojan James
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0>
+
+/* Layout equivalence checks between the generated Rust and C types.
+
+ The C translation unit exports, for every struct, a table with the size,
+ the alignment and the offset of each member as computed by the C compiler.
+ The Rust test module links against these tables and compares them with the
+ layout of the #[repr(C)] Rust structs.
+*/
+
+use crate::cdds::{scoped_name, struct_members, INDENTION};
+use crate::ir::{Module, TypeKind};
+
+use std::io::{Error, Write};
+
+struct StructLayout {
+    scope: Vec<String>,
+    id: String,
+    members: Vec<String>,
+}
+
+impl StructLayout {
+    fn c_name(&self) -> String {
+        scoped_name(&self.scope, &self.id)
+    }
+
+    fn rust_path(&self) -> String {
+        let mut path = vec![String::from("crate")];
+        path.extend(self.scope.iter().cloned());
+        path.push(self.id.clone());
+        path.join("::")
+    }
+
+    // Name of the exported C table
+    fn table_name(&self) -> String {
+        format!("idlc_layout_{}", self.c_name())
+    }
+}
+
+// The structs of the main file in `module` and its submodules
+fn collect_structs(module: &Module, structs: &mut Vec<StructLayout>) {
+    for typ in module.types.iter().filter(|typ| typ.source.is_none()) {
+        if let TypeKind::Struct(s) = &typ.kind {
+            structs.push(StructLayout {
+                scope: module.path.clone(),
+                id: typ.id.clone(),
+                members: struct_members(s).into_iter().map(|m| m.id).collect(),
+            });
+        }
+    }
+    for submodule in module.modules.iter() {
+        collect_structs(submodule, structs);
+    }
+}
+
+/// Write the layout tables of the C translation unit, the generated header
+/// must already be included
+pub fn write_layout_c<W: Write>(out: &mut W, module: &Module) -> Result<(), Error> {
+    let mut structs = Vec::new();
+    collect_structs(module, &mut structs);
+
+    writeln!(out, "#include <stddef.h>")?;
+    for layout in &structs {
+        let c_name = layout.c_name();
+        writeln!(out)?;
+        writeln!(out, "/* size, alignment and member offsets of {} */", c_name)?;
+        writeln!(
+            out,
+            "const size_t {}[{}] =",
+            layout.table_name(),
+            2 + layout.members.len()
+        )?;
+        writeln!(out, "{{")?;
+        writeln!(out, "  sizeof ({}),", c_name)?;
+        write!(out, "  _Alignof ({})", c_name)?;
+        for member in &layout.members {
+            write!(out, ",\n  offsetof ({}, {})", c_name, member)?;
+        }
+        writeln!(out)?;
+        writeln!(out, "}};")?;
+    }
+    Ok(())
+}
+
+/// Write the Rust test module comparing the layout with the C tables
+pub fn write_layout_tests<W: Write>(
+    out: &mut W,
+    module: &Module,
+) -> Result<(), Error> {
+    let mut structs = Vec::new();
+    collect_structs(module, &mut structs);
+    if structs.is_empty() {
+        return Ok(());
+    }
+
+    writeln!(out)?;
+    writeln!(out, "// Compares the layout of the Rust types with the C types, the")?;
+    writeln!(out, "// generated C layout tables must be linked into the test")?;
+    writeln!(out, "#[cfg(test)]")?;
+    writeln!(out, "#[allow(non_snake_case, non_upper_case_globals)]")?;
+    writeln!(out, "mod idlc_layout_tests {{")?;
+    writeln!(out, "{:indent$}extern \"C\" {{", "", indent = INDENTION)?;
+    for layout in &structs {
+        writeln!(
+            out,
+            "{:indent$}static {}: [usize; {}];",
+            "",
+            layout.table_name(),
+            2 + layout.members.len(),
+            indent = 2 * INDENTION
+        )?;
+    }
+    writeln!(out, "{:indent$}}}", "", indent = INDENTION)?;
+
+    for layout in &structs {
+        let rust_path = layout.rust_path();
+        let checks = vec![
+            (
+                format!("::std::mem::size_of::<{}>()", rust_path),
+                format!("size of {}", rust_path),
+            ),
+            (
+                format!("::std::mem::align_of::<{}>()", rust_path),
+                format!("alignment of {}", rust_path),
+            ),
+        ]
+        .into_iter()
+        .chain(layout.members.iter().map(|member| {
+            (
+                format!("::std::mem::offset_of!({}, {})", rust_path, member),
+                format!("offset of {}.{}", rust_path, member),
+            )
+        }));
+
+        writeln!(out)?;
+        writeln!(out, "{:indent$}#[test]", "", indent = INDENTION)?;
+        writeln!(
+            out,
+            "{:indent$}fn layout_{}() {{",
+            "",
+            layout.c_name(),
+            indent = INDENTION
+        )?;
+        writeln!(
+            out,
+            "{:indent$}let c_layout = unsafe {{ &{} }};",
+            "",
+            layout.table_name(),
+            indent = 2 * INDENTION
+        )?;
+        for (idx, (rust_expr, what)) in checks.enumerate() {
+            writeln!(
+                out,
+                "{:indent$}assert_eq!({}, c_layout[{}], \"{}\");",
+                "",
+                rust_expr,
+                idx,
+                what,
+                indent = 2 * INDENTION
+            )?;
+        }
+        writeln!(out, "{:indent$}}}", "", indent = INDENTION)?;
+    }
+    writeln!(out, "}}")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::fixture::{generate, has_tool, run, temp_dir, write_file, DDS_PUBLIC_IMPL_H, RUNTIME_STUB};
+    use crate::{Backends, Configuration, IdlSource, MemoryLoader};
+    use std::process::Command;
+
+    const IDL: &str = "module A { struct Inner { octet o; }; module B { struct Outer { long l; A::Inner i; }; }; };";
+
+    fn config(generate_c: bool) -> Configuration {
+        Configuration {
+            generate_c,
+            layout_checks: true,
+            idl_name: std::path::PathBuf::from("layout.idl"),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn c_layout_tables() {
+        let generated = generate(&config(true), IDL);
+        assert!(generated.contains("#include \"layout.h\""));
+        assert!(generated.contains("const size_t idlc_layout_A_Inner[3] ="));
+        assert!(generated.contains("const size_t idlc_layout_A_B_Outer[4] ="));
+        assert!(generated.contains("  offsetof (A_B_Outer, i)\n};"));
+    }
+
+    #[test]
+    fn rust_layout_tests() {
+        let generated = generate(&config(false), IDL);
+        assert!(generated.contains("static idlc_layout_A_B_Outer: [usize; 4];"));
+        assert!(generated.contains(
+            "assert_eq!(::std::mem::offset_of!(crate::A::B::Outer, i), c_layout[3], \"offset of crate::A::B::Outer.i\");"
+        ));
+    }
+
+    #[test]
+    fn layouts_match_compiled() {
+        if !has_tool("cc") || !has_tool("rustc") {
+            return;
+        }
+        let idl = "const long LEN = 4;
+        module M {
+            const long SIZE = LEN * 2;
+            const string GREETING = \"hi\";
+            const char SEP = ',';
+            const double RATIO = 1.5;
+            const boolean ON = TRUE;
+            enum Color { RED, GREEN };
+            const Color DEFAULT_COLOR = GREEN;
+            typedef fixed<4, 2> Money;
+            const Money PRICE = 12.3d;
+            typedef string<LEN> Name;
+            struct Msg {
+                long id;
+                string<SIZE> name;
+                char c;
+                string<3> tags[2];
+                Name alias;
+                string s;
+                double d;
+                map<string, Name, 2> m;
+            };
+        };";
+        let config = Configuration {
+            layout_checks: true,
+            runtime_crate: String::from("crate::sys"),
+            ..Default::default()
+        };
+        let sources = [IdlSource::Memory(std::path::PathBuf::from("msg.idl"), String::from(idl))];
+        let generated = Backends::new().generate_files(&["rust", "c"], &mut MemoryLoader::new(), &config, &sources);
+        assert!(!generated.has_errors());
+
+        let dir = temp_dir("layout");
+        write_file(&dir, "dds/ddsc/dds_public_impl.h", DDS_PUBLIC_IMPL_H);
+        for output in generated.outputs.iter() {
+            write_file(&dir, &output.name, &output.contents);
+        }
+        write_file(&dir, "msg.rs", &format!("{}{}", RUNTIME_STUB, generated.outputs[0].contents));
+        // the constants are C macros
+        assert!(generated.outputs[1].contents.contains("#define M_PRICE { 0x01, 0x23, 0x0c }\n"));
+        write_file(
+            &dir,
+            "consts.c",
+            "#include \"msg.h\"
+             _Static_assert(M_SIZE == 8, \"SIZE\");
+             const char *greeting = M_GREETING;
+             const char sep = M_SEP;
+             const double ratio = M_RATIO;
+             const bool on = M_ON;
+             const M_Color color = M_DEFAULT_COLOR;
+             const M_Money price = M_PRICE;",
+        );
+        run(&dir, Command::new("cc").args(["-c", "-Wall", "-Werror", "-I.", "consts.c"]));
+        run(&dir, Command::new("cc").args(["-c", "-Wall", "-Werror", "-I.", "msg_layout.c"]));
+        run(&dir, Command::new("ar").args(["rcs", "libmsg_layout.a", "msg_layout.o"]));
+        run(
+            &dir,
+            Command::new("rustc").args(["--edition", "2018", "--test", "msg.rs", "-L.", "-lstatic=msg_layout"]),
+        );
+        run(&dir, &mut Command::new(dir.join("msg")));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,17 +1,67 @@
 mod alignment;
-mod ast_c;
-mod ast_h;
 mod basic_types;
-mod type_impl;
+pub mod bits;
+pub mod descriptor;
+pub(crate) mod header;
+pub mod imports;
+pub mod key_hash;
+pub mod keys;
+pub mod layout;
+pub mod rpc;
+pub(crate) mod rust;
+pub(crate) mod source;
+pub(crate) mod type_impl;
 pub mod type_trait;
 
+use crate::ast::{fixed_size, STRUCT_BASE_MEMBER};
+use crate::ir::{Member, Module, Struct, TypeKind, TypeSpec};
+use crate::WideCharSize;
+
+use std::io::{Error, Write};
+
 pub const INDENTION: usize = 4;
 
+/// The model the C and Rust types are generated from, the size of wide chars
+/// is a choice of the generation
+#[derive(Clone, Copy)]
+pub struct Model<'a> {
+    /// The root module of the IR
+    pub root: &'a Module,
+    pub wchar_size: WideCharSize,
+}
+
+impl<'a> Model<'a> {
+    /// The kind of the declared type `name`
+    pub fn kind(&self, name: &[String]) -> Option<&'a TypeKind> {
+        self.root.get_type(name).map(|t| &t.kind)
+    }
+}
+
 pub fn header_macro_name(stem: &str) -> String {
     String::from(format!("_DDSL_{}_H_", stem.to_uppercase()))
 }
 
+/// The C header generated for the included IDL file `file`, next to it
+pub fn include_header_name(file: &str) -> String {
+    std::path::Path::new(file)
+        .with_extension("h")
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// The Rust module of the code generated for the included IDL file `file`
+pub fn include_module_name(file: &str) -> String {
+    std::path::Path::new(file)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().replace(|c: char| !c.is_alphanumeric(), "_"))
+        .unwrap_or_default()
+}
+
 pub fn scoped_name(scope: &Vec<String>, id: &str) -> String {
+    // the declarations of the root module keep their name
+    if scope.is_empty() {
+        return String::from(id);
+    }
     let mut name = scope.join("_");
     name.push_str("_");
     name.push_str(id);
@@ -37,3 +87,121 @@ pub fn generate_ddsgen_trait_for_type(typename: &str, scope:&Vec<String>) -> Str
     template.replace("{SCOPEDNAME}", &scoped_name(scope, typename))
 }
 
+
+/// The Rust type generated for `spec`, wide chars are `wchar_size` bits
+pub fn rust_type(spec: &TypeSpec, wchar_size: WideCharSize) -> String {
+    String::from(match spec {
+        TypeSpec::Boolean => "bool",
+        TypeSpec::Octet | TypeSpec::UInt8 => "u8",
+        TypeSpec::Char => "::std::os::raw::c_char",
+        TypeSpec::WideChar | TypeSpec::WideString(_) => {
+            let wchar = match wchar_size {
+                WideCharSize::Bits16 => "u16",
+                WideCharSize::Bits32 => "u32",
+            };
+            return match spec {
+                TypeSpec::WideChar => String::from(wchar),
+                // wchar_t * for bounded and unbounded wide strings
+                _ => format!("*mut {}", wchar),
+            };
+        }
+        TypeSpec::Int8 => "i8",
+        TypeSpec::Int16 => "i16",
+        TypeSpec::Int32 => "i32",
+        TypeSpec::Int64 => "i64",
+        TypeSpec::UInt16 => "u16",
+        TypeSpec::UInt32 => "u32",
+        TypeSpec::UInt64 => "u64",
+        TypeSpec::Float32 => "f32",
+        TypeSpec::Float64 => "f64",
+        TypeSpec::LongDouble => "LongDouble",
+        TypeSpec::Fixed { digits, scale } => {
+            return format!("Fixed<{}, {}, {}>", digits, scale, fixed_size(*digits))
+        }
+        TypeSpec::String(None) => "*mut ::std::os::raw::c_char",
+        // bounded strings are char arrays, like in C
+        TypeSpec::String(Some(bound)) => return format!("[::std::os::raw::c_char;{}]", bound + 1),
+        // a map is a sequence of its key/value entry structs, like in C
+        TypeSpec::Sequence(_, _) | TypeSpec::Map(_, _, _, _) => "dds_sequence_t",
+        TypeSpec::Array(elem, dims) => {
+            let closing = dims.iter().map(|dim| format!(";{}]", dim)).collect::<String>();
+            return format!("{}{}{}", "[".repeat(dims.len()), rust_type(elem, wchar_size), closing);
+        }
+        TypeSpec::Named(path) => return format!("crate::{}", path.join("::")),
+    })
+}
+
+/// The members of the C struct of `s`, the base of a derived struct is
+/// embedded as its first member, see STRUCT_BASE_MEMBER
+pub fn struct_members(s: &Struct) -> Vec<Member> {
+    let mut members = Vec::new();
+    if let Some(base) = s.base.as_ref() {
+        members.push(Member {
+            id: String::from(STRUCT_BASE_MEMBER),
+            type_spec: TypeSpec::Named(base.clone()),
+            is_key: false,
+            annotations: Vec::new(),
+            line: s.members.first().map(|m| m.line).unwrap_or_default(),
+        });
+    }
+    members.extend(s.members.iter().cloned());
+    members
+}
+
+/// The C type of the header for the IR type `spec`, the dimensions of arrays
+/// and the length of bounded strings follow the member name
+pub fn c_type_name(spec: &TypeSpec) -> String {
+    String::from(match spec {
+        TypeSpec::Boolean => "bool",
+        TypeSpec::Octet | TypeSpec::UInt8 => "uint8_t",
+        TypeSpec::Char => "char",
+        TypeSpec::WideChar => "wchar_t",
+        TypeSpec::Int8 => "int8_t",
+        TypeSpec::Int16 => "int16_t",
+        TypeSpec::Int32 => "int32_t",
+        TypeSpec::Int64 => "int64_t",
+        TypeSpec::UInt16 => "uint16_t",
+        TypeSpec::UInt32 => "uint32_t",
+        TypeSpec::UInt64 => "uint64_t",
+        TypeSpec::Float32 => "float",
+        TypeSpec::Float64 => "double",
+        TypeSpec::LongDouble => "long double",
+        TypeSpec::Fixed { .. } => "uint8_t",
+        TypeSpec::String(None) => "char *",
+        TypeSpec::String(Some(_)) => "char",
+        TypeSpec::WideString(_) => "wchar_t *",
+        TypeSpec::Sequence(_, _) | TypeSpec::Map(_, _, _, _) => "dds_sequence_t",
+        TypeSpec::Array(elem, _) => return c_type_name(elem),
+        TypeSpec::Named(path) => return path.join("_"),
+    })
+}
+
+/// The text of a C or C++ character or string literal, without the quotes
+pub fn c_escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\'' => escaped.push_str("\\'"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\{:03o}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Write the lines of `text` indented by `level`
+pub fn write_lines<W: Write>(out: &mut W, level: usize, text: &str) -> Result<(), Error> {
+    for line in text.lines() {
+        if line.is_empty() {
+            writeln!(out)?;
+        } else {
+            writeln!(out, "{:indent$}{}", "", line, indent = level * INDENTION)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,464 @@
+// Copyright (C) 2020  Sojan James
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0>
+
+/* DDS-RPC services of IDL interfaces.
+
+ An interface is served by a request and a reply topic, both keyless. The
+ request carries the id of the client, the id of the call and the number of
+ the operation, followed by a member per operation holding its in and inout
+ parameters. The reply correlates by the same ids and holds a member per
+ operation with the return value, inout and out parameters, and a member
+ per exception the operations raise. Members of operations without
+ parameters or results are left out, the C types would be empty.
+
+ The Rust client trait builds the requests and unpacks the replies, the
+ transport is left to its `call` method. The service trait dispatches a
+ request to the operation it names.
+*/
+
+use crate::cdds::{rust_type, scoped_name, write_lines};
+use crate::ir::{Direction, Module, Operation, ScopedName, TypeKind, TypeSpec};
+use crate::WideCharSize;
+
+use std::io::{Error, Write};
+
+/// Names of the members and methods of the generated types and traits,
+/// operations and parameters can't use them
+pub const RPC_RESERVED: &[&str] = &[
+    "client_id",
+    "call_id",
+    "operation",
+    "remote_ex",
+    "exception",
+    "return_",
+    "call",
+    "next_call_id",
+    "dispatch",
+    "set_exception",
+];
+
+/// Member of the reply holding the return value of an operation
+pub const RPC_RETURN_MEMBER: &str = "return_";
+
+/// The request topic type of interface `id`
+pub fn request_name(id: &str) -> String {
+    format!("{}_Request", id)
+}
+
+/// The reply topic type of interface `id`
+pub fn reply_name(id: &str) -> String {
+    format!("{}_Reply", id)
+}
+
+/// The struct of the in and inout parameters of the operation `op_id`
+pub fn in_name(id: &str, op_id: &str) -> String {
+    format!("{}_{}_In", id, op_id)
+}
+
+/// The struct of the results of the operation `op_id`
+pub fn out_name(id: &str, op_id: &str) -> String {
+    format!("{}_{}_Out", id, op_id)
+}
+
+/// The member of the reply holding the exception `name`
+pub fn exception_member(name: &[String]) -> String {
+    format!("{}_ex", exception_id(name))
+}
+
+fn exception_id(name: &[String]) -> &str {
+    name.last().map(|id| id.as_str()).unwrap_or("")
+}
+
+/// The exceptions raised, each once in order of appearance
+pub fn exceptions<'a, I: IntoIterator<Item = &'a ScopedName>>(raises: I) -> Vec<&'a ScopedName> {
+    let mut exceptions: Vec<&ScopedName> = Vec::new();
+    for name in raises {
+        if !exceptions.contains(&name) {
+            exceptions.push(name);
+        }
+    }
+    exceptions
+}
+
+// The exceptions raised by the operations
+fn raised(operations: &[Operation]) -> Vec<&ScopedName> {
+    exceptions(operations.iter().flat_map(|op| op.raises.iter()))
+}
+
+/// True if the module or its submodules declare an interface
+pub fn has_interfaces(module: &Module) -> bool {
+    module.types.iter().any(|typ| matches!(typ.kind, TypeKind::Interface(_)))
+        || module.modules.iter().any(has_interfaces)
+}
+
+// The members of the results of `op` in order of the returned tuple
+fn results(op: &Operation) -> Vec<String> {
+    let mut results = Vec::new();
+    if op.return_type.is_some() {
+        results.push(String::from(RPC_RETURN_MEMBER));
+    }
+    for param in op.params.iter().filter(|p| p.direction == Direction::Out) {
+        results.push(param.id.clone());
+    }
+    results
+}
+
+// The Rust type of the results of `op`, a tuple for more than one
+fn results_type(op: &Operation, wchar_size: WideCharSize) -> String {
+    let mut types = Vec::new();
+    if let Some(return_type) = op.return_type.as_ref() {
+        types.push(rust_type(return_type, wchar_size));
+    }
+    for param in op.params.iter().filter(|p| p.direction == Direction::Out) {
+        types.push(rust_type(&param.type_spec, wchar_size));
+    }
+    match types.len() {
+        1 => types.remove(0),
+        _ => format!("({})", types.join(", ")),
+    }
+}
+
+// Pattern or expression of the results, matching results_type
+fn results_tuple(names: &[String]) -> String {
+    match names.len() {
+        1 => names[0].clone(),
+        _ => format!("({})", names.join(", ")),
+    }
+}
+
+// The parameters of the Rust method of `op`, out parameters are results
+fn method_params(op: &Operation, wchar_size: WideCharSize) -> String {
+    let mut params = vec![String::from("&mut self")];
+    for param in op.params.iter() {
+        match param.direction {
+            Direction::In => {
+                params.push(format!("{}: {}", param.id, rust_type(&param.type_spec, wchar_size)))
+            }
+            Direction::InOut => {
+                params.push(format!("{}: &mut {}", param.id, rust_type(&param.type_spec, wchar_size)))
+            }
+            Direction::Out => {}
+        }
+    }
+    params.join(", ")
+}
+
+fn write_exception_type(text: &mut String, id: &str, operations: &[Operation], wchar_size: WideCharSize) {
+    text.push_str(&format!(
+        "\n/// Exceptions raised by the operations of {}\n\
+         #[allow(dead_code)]\n\
+         #[derive(Clone, Debug)]\n\
+         pub enum {}Exception {{\n",
+        id, id
+    ));
+    for name in raised(operations) {
+        let spec = TypeSpec::Named(name.clone());
+        text.push_str(&format!("    {}({}),\n", exception_id(name), rust_type(&spec, wchar_size)));
+    }
+    text.push_str("    /// DDS-RPC remote exception code of a failed call\n");
+    text.push_str("    Remote(i32),\n}\n");
+}
+
+fn write_request_impl(text: &mut String, id: &str, operations: &[Operation]) {
+    text.push_str(&format!(
+        "\n/// Numbers of the operations of a request\n\
+         #[allow(dead_code)]\n\
+         impl {} {{\n",
+        request_name(id)
+    ));
+    for (idx, op) in operations.iter().enumerate() {
+        text.push_str(&format!(
+            "    pub const {}: u32 = {};\n",
+            op.id.to_uppercase(),
+            idx + 1
+        ));
+    }
+    text.push_str("}\n");
+}
+
+fn write_reply_impl(text: &mut String, id: &str, operations: &[Operation]) {
+    let exception = format!("{}Exception", id);
+    text.push_str(&format!(
+        "\n#[allow(dead_code)]\n\
+         impl {} {{\n\
+         \x20   /// The exception raised by the call, if any\n\
+         \x20   pub fn exception(&self) -> Option<{}> {{\n\
+         \x20       match (self.remote_ex, self.exception) {{\n\
+         \x20           (RPC_REMOTE_EX_OK, 0) => None,\n",
+        reply_name(id),
+        exception
+    ));
+    let exceptions = raised(operations);
+    for (idx, name) in exceptions.iter().enumerate() {
+        text.push_str(&format!(
+            "            (RPC_REMOTE_EX_OK, {}) => Some({}::{}(self.{}.clone())),\n",
+            idx + 1,
+            exception,
+            exception_id(name),
+            exception_member(name)
+        ));
+    }
+    text.push_str(&format!(
+        "            (RPC_REMOTE_EX_OK, _) => Some({}::Remote(RPC_REMOTE_EX_UNKNOWN_EXCEPTION)),\n\
+         \x20           (code, _) => Some({}::Remote(code)),\n\
+         \x20       }}\n\
+         \x20   }}\n\n\
+         \x20   /// Set the exception raised by the call\n\
+         \x20   pub fn set_exception(&mut self, exception: {}) {{\n\
+         \x20       match exception {{\n",
+        exception, exception, exception
+    ));
+    for (idx, name) in exceptions.iter().enumerate() {
+        text.push_str(&format!(
+            "            {}::{}(ex) => {{\n\
+             \x20               self.exception = {};\n\
+             \x20               self.{} = ex;\n\
+             \x20           }}\n",
+            exception,
+            exception_id(name),
+            idx + 1,
+            exception_member(name)
+        ));
+    }
+    text.push_str(&format!(
+        "            {}::Remote(code) => self.remote_ex = code,\n\
+         \x20       }}\n\
+         \x20   }}\n\
+         }}\n",
+        exception
+    ));
+}
+
+fn write_client_trait(text: &mut String, id: &str, operations: &[Operation], wchar_size: WideCharSize) {
+    text.push_str(&format!(
+        "\n/// Client of the interface {}. The operations send their request with\n\
+         /// `call` and return the results of the reply.\n\
+         #[allow(dead_code)]\n\
+         pub trait {}Client {{\n\
+         \x20   type Error: From<{}Exception>;\n\n\
+         \x20   /// Id of this client, sent with every request\n\
+         \x20   fn client_id(&self) -> u64;\n\
+         \x20   /// Id of the next call of this client\n\
+         \x20   fn next_call_id(&mut self) -> i64;\n\
+         \x20   /// Write the request and wait for the reply with its client and call id\n\
+         \x20   fn call(&mut self, request: {}) -> Result<{}, Self::Error>;\n",
+        id,
+        id,
+        id,
+        request_name(id),
+        reply_name(id)
+    ));
+    for op in operations {
+        text.push_str(&format!(
+            "\n    fn {}({}) -> Result<{}, Self::Error> {{\n\
+             \x20       // the generated types are plain C data, all zero is a valid value\n\
+             \x20       let mut request: {} = unsafe {{ ::std::mem::zeroed() }};\n\
+             \x20       request.client_id = self.client_id();\n\
+             \x20       request.call_id = self.next_call_id();\n\
+             \x20       request.operation = {}::{};\n",
+            op.id,
+            method_params(op, wchar_size),
+            results_type(op, wchar_size),
+            request_name(id),
+            request_name(id),
+            op.id.to_uppercase()
+        ));
+        for param in op.params.iter() {
+            match param.direction {
+                Direction::In => text.push_str(&format!(
+                    "        request.{}.{} = {};\n",
+                    op.id, param.id, param.id
+                )),
+                Direction::InOut => text.push_str(&format!(
+                    "        request.{}.{} = {}.clone();\n",
+                    op.id, param.id, param.id
+                )),
+                Direction::Out => {}
+            }
+        }
+        text.push_str(
+            "        let reply = self.call(request)?;\n\
+             \x20       if let Some(exception) = reply.exception() {\n\
+             \x20           return Err(exception.into());\n\
+             \x20       }\n",
+        );
+        for param in op.params.iter().filter(|p| p.direction == Direction::InOut) {
+            text.push_str(&format!("        *{} = reply.{}.{};\n", param.id, op.id, param.id));
+        }
+        let values: Vec<String> = results(op)
+            .iter()
+            .map(|r| format!("reply.{}.{}", op.id, r))
+            .collect();
+        text.push_str(&format!("        Ok({})\n    }}\n", results_tuple(&values)));
+    }
+    text.push_str("}\n");
+}
+
+fn write_service_trait(text: &mut String, id: &str, operations: &[Operation], wchar_size: WideCharSize) {
+    text.push_str(&format!(
+        "\n/// Service of the interface {}, `dispatch` calls the operation of a request\n\
+         #[allow(dead_code)]\n\
+         pub trait {}Service {{\n",
+        id, id
+    ));
+    for op in operations {
+        text.push_str(&format!(
+            "    fn {}({}) -> Result<{}, {}Exception>;\n",
+            op.id,
+            method_params(op, wchar_size),
+            results_type(op, wchar_size),
+            id
+        ));
+    }
+    text.push_str(&format!(
+        "\n    /// The reply to `request` with the results of its operation\n\
+         \x20   fn dispatch(&mut self, request: &{}) -> {} {{\n\
+         \x20       // the generated types are plain C data, all zero is a valid value\n\
+         \x20       let mut reply: {} = unsafe {{ ::std::mem::zeroed() }};\n\
+         \x20       reply.client_id = request.client_id;\n\
+         \x20       reply.call_id = request.call_id;\n\
+         \x20       reply.operation = request.operation;\n\
+         \x20       let result = match request.operation {{\n",
+        request_name(id),
+        reply_name(id),
+        reply_name(id)
+    ));
+    for op in operations {
+        text.push_str(&format!(
+            "            {}::{} => {{\n",
+            request_name(id),
+            op.id.to_uppercase()
+        ));
+        let mut args = Vec::new();
+        for param in op.params.iter() {
+            match param.direction {
+                Direction::In => {
+                    args.push(format!("request.{}.{}.clone()", op.id, param.id))
+                }
+                Direction::InOut => {
+                    text.push_str(&format!(
+                        "                let mut {} = request.{}.{}.clone();\n",
+                        param.id, op.id, param.id
+                    ));
+                    args.push(format!("&mut {}", param.id));
+                }
+                Direction::Out => {}
+            }
+        }
+        let results = results(op);
+        text.push_str(&format!(
+            "                self.{}({}).map(|{}| {{\n",
+            op.id,
+            args.join(", "),
+            results_tuple(&results)
+        ));
+        let inouts = op
+            .params
+            .iter()
+            .filter(|p| p.direction == Direction::InOut)
+            .map(|p| p.id.clone());
+        for result in results.iter().cloned().chain(inouts) {
+            text.push_str(&format!(
+                "                    reply.{}.{} = {};\n",
+                op.id, result, result
+            ));
+        }
+        text.push_str("                })\n            }\n");
+    }
+    text.push_str(&format!(
+        "            _ => Err({}Exception::Remote(RPC_REMOTE_EX_UNKNOWN_OPERATION)),\n\
+         \x20       }};\n\
+         \x20       if let Err(exception) = result {{\n\
+         \x20           reply.set_exception(exception);\n\
+         \x20       }}\n\
+         \x20       reply\n\
+         \x20   }}\n\
+         }}\n",
+        id
+    ));
+}
+
+/// Write the Rust exception type, the operation numbers, and the client
+/// and service traits of the interface `id`
+pub fn write_interface<W: Write>(
+    out: &mut W,
+    level: usize,
+    id: &str,
+    operations: &[Operation],
+    wchar_size: WideCharSize,
+) -> Result<(), Error> {
+    let mut text = String::new();
+    write_exception_type(&mut text, id, operations, wchar_size);
+    write_request_impl(&mut text, id, operations);
+    write_reply_impl(&mut text, id, operations);
+    write_client_trait(&mut text, id, operations, wchar_size);
+    write_service_trait(&mut text, id, operations, wchar_size);
+    write_lines(out, level, &text)
+}
+
+/// Write the C defines of the operation numbers and the remote exception
+/// codes of the interface `id`
+pub fn write_interface_h<W: Write>(
+    out: &mut W,
+    scope: &Vec<String>,
+    id: &str,
+    operations: &[Operation],
+) -> Result<(), Error> {
+    let request = scoped_name(scope, &request_name(id));
+    writeln!(out, "/* operations of {}, the operation of a request */", scoped_name(scope, id))?;
+    for (idx, op) in operations.iter().enumerate() {
+        writeln!(out, "#define {}_{} {}u", request, op.id.to_uppercase(), idx + 1)?;
+    }
+    writeln!(out, "{}", include_str!("templates/rpc_h.txt"))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::fixture::{c_config, generate, generate_error};
+    use crate::Configuration;
+
+    #[test]
+    fn interfaces() {
+        let idl = "module M { exception Overflow { long value; };
+                   interface Calculator {
+                       long add(in long a, in long b) raises (Overflow);
+                       void reset();
+                       double swap(inout string s, out long n);
+                   };
+};";
+        // the request and reply are keyless topics
+        let generated = generate(&c_config(), idl);
+        assert!(generated.contains(
+            "  DDS_OP_ADR | DDS_OP_TYPE_4BY | DDS_OP_FLAG_SGN, offsetof (M_Calculator_Request, add.b),\n\
+             \x20 DDS_OP_ADR | DDS_OP_TYPE_STR, offsetof (M_Calculator_Request, swap.s),\n"
+        ));
+        assert!(generated.contains("  \"M::Calculator_Reply\",\n  NULL,\n"));
+        assert!(generated
+            .contains("DDS_OP_ADR | DDS_OP_TYPE_4BY | DDS_OP_FLAG_SGN, offsetof (M_Calculator_Reply, Overflow_ex.value),"));
+        // reset has no in or out values
+        assert!(!generated.contains("M_Calculator_reset_"));
+
+        let config = Configuration::default();
+        let generated = generate(&config, idl);
+        assert!(generated.contains("pub const SWAP: u32 = 3;"));
+        assert!(generated.contains("fn add(&mut self, a: i32, b: i32) -> Result<i32, Self::Error> {"));
+        assert!(generated.contains(
+            "fn swap(&mut self, s: &mut *mut ::std::os::raw::c_char) -> Result<(f64, i32), CalculatorException>;"
+        ));
+
+        // raises names an exception and operations don't use the reserved names
+        for (idl, error) in &[
+            (
+                "module M { struct E { long a; }; interface I { void f() raises (E, X); }; };",
+                "X raised by interface I is not an exception",
+            ),
+            ("module M { interface I { void call(); }; };", "call"),
+            ("module M { interface I { void f(in long client_id); }; };", "client_id"),
+        ] {
+            let errors = generate_error(&c_config(), idl);
+            assert!(errors.contains(error), "{}", errors);
+        }
+    }
+}
@@ -0,0 +1,288 @@
+// Copyright (C) 2020  Sojan James
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0>
+
+/* The Rust types, from the IR of the main file.
+
+ Every IDL module is a Rust module seeing the items of the enclosing one.
+ The declarations of included files are generated with their own file, the
+ modules re-export them from the module of the included file.
+*/
+
+use crate::ast::FixedValue;
+use crate::cdds::descriptor::{check_wide_strings, TopicDescriptor};
+use crate::cdds::{generate_ddsgen_trait_for_type, include_module_name, rust_type, struct_members, Model, INDENTION};
+use crate::ir::{CaseLabel, ConstValue, Constant, Module, TypeDecl, TypeKind, TypeSpec};
+use crate::Configuration;
+
+use std::io::{Error, ErrorKind, Write};
+
+const ATTR_ALLOW_DEADCODE: &str = "#[allow(dead_code)]";
+const IMPORT_SUPER: &str = "pub use super::*;";
+const ATTR_DERIVE_CLONE_DEBUG: &str = "#[derive(Clone, Debug)]";
+const ATTR_DERIVE_ENUM: &str = "#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]";
+const ATTR_ALLOW_NON_CAMEL_CASE_TYPES: &str = "#[allow(non_camel_case_types)]";
+const ATTR_ALLOW_NON_SNAKE_CASE: &str = "#[allow(non_snake_case)]";
+const ATTR_ALLOW_UNUSED_IMPORTS: &str = "#[allow(unused_imports)]";
+const ATTR_REPL_C: &str = "#[repr(C)]";
+
+// Write `lines` indented by `level`
+fn write_indented<W: Write>(out: &mut W, level: usize, lines: &[&str]) -> Result<(), Error> {
+    for line in lines {
+        writeln!(out, "{:indent$}{}", "", line, indent = level * INDENTION)?;
+    }
+    Ok(())
+}
+
+// The Rust expression of `value`
+fn rust_value(value: &ConstValue) -> String {
+    match value {
+        ConstValue::Integer(value) => value.to_string(),
+        ConstValue::Float(value) => format!("{:?}", value),
+        ConstValue::Fixed { scaled, scale } => crate::ir::fixed_decimal(*scaled, *scale),
+        ConstValue::Boolean(value) => value.to_string(),
+        ConstValue::Char(c) => format!("{:?}", c),
+        ConstValue::String(s) => format!("{:?}", s),
+        // the enumerators are variants of the enum
+        ConstValue::Enumerator { enumeration, id, .. } => format!("crate::{}::{}", enumeration.join("::"), id),
+    }
+}
+
+/// The Rust items generated for the type `decl`
+pub fn rust_items(decl: &TypeDecl) -> Vec<String> {
+    let id = &decl.id;
+    match &decl.kind {
+        TypeKind::Struct(s) => {
+            let mut items = vec![id.clone()];
+            if s.topic.as_ref().is_some_and(|topic| !topic.keys.is_empty()) {
+                items.push(format!("{}Key", id));
+            }
+            items
+        }
+        TypeKind::Interface(_) => vec![
+            format!("{}Exception", id),
+            format!("{}Client", id),
+            format!("{}Service", id),
+        ],
+        TypeKind::Forward => Vec::new(),
+        _ => vec![id.clone()],
+    }
+}
+
+/// Write the Rust module of `module` and its submodules at `level`, the
+/// declarations of included files are re-exported
+pub fn write_module<W: Write>(
+    out: &mut W,
+    level: usize,
+    module: &Module,
+    model: Model,
+    config: &Configuration,
+) -> Result<(), Error> {
+    if let Some(id) = module.id.as_ref() {
+        write_indented(out, level, &[ATTR_ALLOW_NON_SNAKE_CASE, &format!("pub mod {} {{", id)])?;
+    }
+
+    let add: usize = if module.id.is_some() { 1 } else { 0 };
+
+    // nested modules see the types and imports of the enclosing module
+    if module.id.is_some() {
+        write_indented(out, level + add, &[ATTR_ALLOW_UNUSED_IMPORTS, IMPORT_SUPER])?;
+    }
+    // the declarations of included files are generated with their own file
+    let included = module
+        .types
+        .iter()
+        .filter_map(|typ| typ.source.as_ref().map(|file| (file, rust_items(typ))))
+        .chain(
+            module
+                .constants
+                .iter()
+                .filter_map(|cnst| cnst.source.as_ref().map(|file| (file, vec![cnst.id.clone()]))),
+        );
+    for (file, items) in included {
+        for item in items {
+            let mut path = module.path.clone();
+            path.push(item);
+            writeln!(
+                out,
+                "{:indent$}pub use {}::{}::{};",
+                "",
+                config.include_path,
+                include_module_name(file),
+                path.join("::"),
+                indent = (level + add) * INDENTION
+            )?;
+        }
+    }
+
+    for typ in module.types.iter().filter(|typ| typ.source.is_none()) {
+        write_type(out, level + add, typ, model, config)?;
+    }
+
+    for submodule in module.modules.iter() {
+        write_module(out, level + add, submodule, model, config)?;
+    }
+
+    for cnst in module.constants.iter().filter(|cnst| cnst.source.is_none()) {
+        write_constant(out, level + add, cnst, model)?;
+    }
+
+    if module.id.is_some() {
+        writeln!(out, "{:indent$}}}", "", indent = level * INDENTION)?;
+    }
+    Ok(())
+}
+
+// Write the comment and the attributes preceding the item of a type
+fn write_type_prolog<W: Write>(out: &mut W, level: usize) -> Result<(), Error> {
+    writeln!(out)?;
+    write_indented(out, level, &["//", "//", ATTR_ALLOW_DEADCODE, ATTR_ALLOW_NON_CAMEL_CASE_TYPES])
+}
+
+// Write the Rust items of the type `decl`
+fn write_type<W: Write>(
+    out: &mut W,
+    level: usize,
+    decl: &TypeDecl,
+    model: Model,
+    config: &Configuration,
+) -> Result<(), Error> {
+    let id = &decl.id;
+    let scope = decl.path[..decl.path.len() - 1].to_vec();
+    let inner = (level + 1) * INDENTION;
+    match &decl.kind {
+        TypeKind::Typedef(type_spec) => {
+            write_type_prolog(out, level)?;
+            write_indented(
+                out,
+                level,
+                &[&format!("pub type {} = {};", id, rust_type(type_spec, model.wchar_size))],
+            )
+        }
+        TypeKind::Struct(s) => {
+            write_type_prolog(out, level)?;
+            write_indented(out, level, &[ATTR_REPL_C, ATTR_DERIVE_CLONE_DEBUG, &format!("pub struct {} {{", id)])?;
+            let members = struct_members(s);
+            for member in members.iter() {
+                writeln!(
+                    out,
+                    "{:indent$}pub {}: {},",
+                    "",
+                    member.id,
+                    rust_type(&member.type_spec, model.wchar_size),
+                    indent = inner
+                )?;
+            }
+            write_indented(out, level, &["}"])?;
+
+            // the members of the base struct are reachable through the derived struct
+            if let Some(base) = s.base.as_ref() {
+                let base_type = rust_type(&TypeSpec::Named(base.clone()), model.wchar_size);
+                let base_id = &members[0].id;
+                write_indented(
+                    out,
+                    level,
+                    &[
+                        &format!("impl ::std::ops::Deref for {} {{", id),
+                        &format!("    type Target = {};", base_type),
+                        &format!("    fn deref(&self) -> &{} {{", base_type),
+                        &format!("        &self.{}", base_id),
+                        "    }",
+                        "}",
+                        &format!("impl ::std::ops::DerefMut for {} {{", id),
+                        &format!("    fn deref_mut(&mut self) -> &mut {} {{", base_type),
+                        &format!("        &mut self.{}", base_id),
+                        "    }",
+                        "}",
+                    ],
+                )?;
+            }
+
+            let topic = match s.topic.as_ref() {
+                Some(topic) => topic,
+                None => return Ok(()),
+            };
+            let target = config.cyclone_version;
+            if config.generate_descriptors {
+                TopicDescriptor::new(&decl.path, s, topic, target, model)?.write_rust(out, level)?;
+            } else {
+                // the descriptor of the C file has the limits of the release
+                check_wide_strings(id, &members, target, model)?;
+                // the descriptor is generated by the C generator
+                write!(out, "{}", generate_ddsgen_trait_for_type(id, &scope))?;
+            }
+
+            if !topic.keys.is_empty() {
+                crate::cdds::keys::write_key_type(out, level, id, &topic.keys, model)?;
+                crate::cdds::key_hash::write_key_hash(out, level, id, &topic.keys, model)?;
+            }
+            Ok(())
+        }
+        TypeKind::Enum(variants) => {
+            write_type_prolog(out, level)?;
+            write_indented(out, level, &[ATTR_REPL_C, ATTR_DERIVE_ENUM, &format!("pub enum {} {{", id)])?;
+            for variant in variants {
+                writeln!(out, "{:indent$}{},", "", variant, indent = inner)?;
+            }
+            write_indented(out, level, &["}"])
+        }
+        TypeKind::Bitmask(bitmask) => {
+            crate::cdds::bits::write_bitmask(out, level, id, bitmask.bit_bound, &bitmask.flags)
+        }
+        TypeKind::Bitset(fields) => crate::cdds::bits::write_bitset(out, level, id, fields),
+        TypeKind::Interface(operations) => {
+            crate::cdds::rpc::write_interface(out, level, id, operations, model.wchar_size)
+        }
+        TypeKind::Union(union) => {
+            write_type_prolog(out, level)?;
+            write_indented(out, level, &[ATTR_REPL_C, ATTR_DERIVE_CLONE_DEBUG, &format!("pub enum {} {{", id)])?;
+            for case in union.cases.iter() {
+                let element = format!("{}: {},", case.id, rust_type(&case.type_spec, model.wchar_size));
+                for label in case.labels.iter() {
+                    let label = match label {
+                        CaseLabel::Value(value) => rust_value(value),
+                        CaseLabel::Default => String::from("default"),
+                    };
+                    writeln!(out, "{:indent$}{}{{{}}},", "", label, element, indent = inner)?;
+                }
+            }
+            write_indented(out, level, &["}", "//", "// TODO custom de-/serializer", "//"])
+        }
+        TypeKind::Forward => Ok(()),
+    }
+}
+
+// Write the constant `cnst`, string constants are string slices
+fn write_constant<W: Write>(out: &mut W, level: usize, cnst: &Constant, model: Model) -> Result<(), Error> {
+    let spec = cnst.type_spec.resolve(model.root);
+    let type_name = match spec {
+        // string members are C strings, the constants string literals
+        TypeSpec::String(_) | TypeSpec::WideString(_) => String::from("&str"),
+        _ => rust_type(&cnst.type_spec, model.wchar_size),
+    };
+    let value = match (spec, &cnst.value) {
+        (TypeSpec::Fixed { scale, .. }, ConstValue::Fixed { scaled, scale: value_scale }) => {
+            match (FixedValue { scaled: *scaled, scale: *value_scale }).scaled_to(*scale) {
+                Some(scaled) => format!("Fixed::new({})", scaled),
+                None => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("Unable to evaluate fixed point constant {}", cnst.id),
+                    ))
+                }
+            }
+        }
+        (_, value) => rust_value(value),
+    };
+    // char literals are converted to the integer type of the C char
+    let cast = match cnst.type_spec {
+        TypeSpec::Char | TypeSpec::WideChar => format!(" as {}", type_name),
+        _ => String::new(),
+    };
+    write_indented(
+        out,
+        level,
+        &[ATTR_ALLOW_DEADCODE, &format!("pub const {}: {} = {}{};", cnst.id, type_name, value, cast)],
+    )
+}
@@ -0,0 +1,30 @@
+// Copyright (C) 2020  Sojan James
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0>
+
+/* C File generator */
+use crate::cdds::descriptor::TopicDescriptor;
+use crate::cdds::Model;
+use crate::ir::{Module, TypeKind};
+use crate::CycloneVersion;
+
+use std::io::Error;
+use std::io::Write;
+
+/// Write the topic descriptors of the topics of the main file in `module`
+pub fn write_c<W: Write>(out: &mut W, module: &Module, target: CycloneVersion, model: Model) -> Result<(), Error> {
+    for typ in module.types.iter().filter(|typ| typ.source.is_none()) {
+        if let TypeKind::Struct(s) = &typ.kind {
+            if let Some(topic) = s.topic.as_ref() {
+                TopicDescriptor::new(&typ.path, s, topic, target, model)?.write_c(out)?;
+            }
+        }
+    }
+
+    for submodule in module.modules.iter() {
+        write_c(out, submodule, target, model)?;
+    }
+
+    Ok(())
+}
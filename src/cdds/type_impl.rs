@@ -5,63 +5,22 @@
 
 use crate::cdds::alignment::{Alignment, AlignmentType};
 use crate::cdds::basic_types::BasicType;
+use crate::cdds::bits::bit_holder;
+use crate::cdds::descriptor::{key_flag, MetaOp, OpArg};
 use crate::cdds::type_trait::Type;
-use crate::{IdlModule, IdlScopedName, IdlTypeDcl,IdlTypeDclKind, IdlTypeSpec};
+use crate::cdds::{c_type_name, rust_type, struct_members, Model};
+use crate::ir::{Member, TypeKind, TypeSpec};
+use crate::{fixed_size, WideCharSize};
 
-use std::io::Error;
-use std::io::Write;
-use std::ops::Deref;
+use std::io::{Error, ErrorKind};
 
 use lazy_static::lazy_static;
 
-/*
-  None,
-    ArrayType(Box<IdlTypeSpec>, Vec<Box<IdlValueExpr>>),
-    SequenceType(Box<IdlTypeSpec>, Option<Box<IdlValueExpr>>),
-    StringType(Option<Box<IdlValueExpr>>),
-    WideStringType(Option<Box<IdlValueExpr>>),
-    // FixedPtType,
-    // EnumDcl,
-    // BitsetDcl,
-    // BitmaskDcl,
-    F32Type,
-    F64Type,
-    F128Type,
-    I16Type,
-    I32Type,
-    I64Type,
-    U16Type,
-    U32Type,
-    U64Type,
-    CharType,
-    WideCharType,
-    BooleanType,
-    OctetType,
-    // AnyType,
-    // ObjectType,
-    // ValueBaseType,
-    ScopedName(IdlScopedName),
-*/
-
-/*
- pub fn new_boolean() -> Self {
- pub fn new_octet() -> Self {
- pub fn new_char() -> Self {
- pub fn new_short() -> Self {
- pub fn new_ushort() -> Self {
- pub fn new_long() -> Self {
- pub fn new_ulong() -> Self {
- pub fn new_longlong() -> Self {
- pub fn new_ulonglong() -> Self {
- pub fn new_float() -> Self {
- pub fn new_double() -> Self {
- pub fn new_string() -> Self {
-
-*/
-
 lazy_static! {
     static ref BOOLEAN: BasicType = BasicType::new_boolean();
     static ref OCTET: BasicType = BasicType::new_octet();
+    static ref INT8: BasicType = BasicType::new_int8();
+    static ref UINT8: BasicType = BasicType::new_uint8();
     static ref CHAR: BasicType = BasicType::new_char();
     static ref SHORT: BasicType = BasicType::new_short();
     static ref USHORT: BasicType = BasicType::new_ushort();
@@ -72,372 +31,574 @@ lazy_static! {
     static ref FLOAT: BasicType = BasicType::new_float();
     static ref DOUBLE: BasicType = BasicType::new_double();
     static ref STRING: BasicType = BasicType::new_string();
+    static ref WCHAR: BasicType = BasicType::new_wchar();
 }
 
-fn get_meta_op_complex(
-    spec: &IdlTypeSpec,
-    name: &str,
-    struct_name: &str,
-    is_key: bool,
-    root: &IdlModule,
-) -> Vec<String> {
+// The opaque long double is serialized as its bytes
+const LONG_DOUBLE_SIZE: i64 = 16;
+
+fn unsupported(spec: &TypeSpec, path: &[String]) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("Unsupported type {:?} for {}", spec, path.join(".")),
+    )
+}
+
+// The topic descriptors of the supported releases can't describe wide strings,
+// TopicDescriptor::new rejects them before with the target release
+fn no_wide_strings(path: &[String]) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("Wide string {} has no op in the topic descriptor", path.join(".")),
+    )
+}
+
+// The ops copy a wchar as is, a 32 bit wchar_t can't be written as the 16 bit
+// wchar of the wire
+fn no_wide_char32(path: &[String]) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!(
+            "Wide char {} is 32 bits in memory, the serializer can't write it as the 16 bit wchar of the wire, generate with --wchar-size 16",
+            path.join(".")
+        ),
+    )
+}
+
+// Forward declared types must be defined before the ops of a topic use them
+fn check_defined(spec: &TypeSpec, path: &[String], model: Model) -> Result<(), Error> {
     match spec {
-        IdlTypeSpec::SequenceType(typespec, values) => vec![String::from(format!(
-            "DDS_OP_ADR | DDS_OP_TYPE_SEQ | {}{} ,offsetof!({},{}) as u32",
-            typespec.get_sub_op(root),
-            if is_key { " | DDS_OP_FLAG_KEY" } else { "" },
-            struct_name,
-            name
-        ))],
-        IdlTypeSpec::ArrayType(typespec, values) => vec![String::from(format!(
-            "DDS_OP_ADR | DDS_OP_TYPE_ARR | {}{} ,offsetof!({},{}) as u32",
-            typespec.get_sub_op(root),
-            if is_key { " | DDS_OP_FLAG_KEY" } else { "" },
-            struct_name,
-            name
-        ))],
-        _ => vec![String::from("UNIMPLEMENTED")],
+        TypeSpec::Named(name) => match model.kind(name) {
+            Some(TypeKind::Forward) => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "{} of {} is declared but never defined",
+                    name.last().map(String::as_str).unwrap_or(""),
+                    path.join(".")
+                ),
+            )),
+            _ => Ok(()),
+        },
+        _ => Ok(()),
     }
 }
 
-impl Type for IdlTypeSpec {
-    fn get_meta_op(
-        &self,
-        name: &str,
-        struct_name: &str,
-        is_key_field: bool,
-        root: &IdlModule,
-    ) -> Vec<String> {
-        match self {
-            IdlTypeSpec::ArrayType(typespec, _values) => {
-                get_meta_op_complex(self, name, struct_name, is_key_field, root)
-            }
-            IdlTypeSpec::SequenceType(typespec, _value) => {
-                get_meta_op_complex(self, name, struct_name, is_key_field, root)
-            }
-            IdlTypeSpec::StringType(_value) => {
-                STRING.get_meta_op(name, struct_name, is_key_field, root)
-            }
-            IdlTypeSpec::WideStringType(_value) => vec![String::from("NOT IMPLEMENTED")],
-            IdlTypeSpec::F32Type => FLOAT.get_meta_op(name, struct_name, is_key_field, root),
-            IdlTypeSpec::F64Type => DOUBLE.get_meta_op(name, struct_name, is_key_field, root),
-            IdlTypeSpec::F128Type => vec![String::from("NOT IMPLEMENTED")],
-            IdlTypeSpec::I16Type => SHORT.get_meta_op(name, struct_name, is_key_field, root),
-            IdlTypeSpec::I32Type => LONG.get_meta_op(name, struct_name, is_key_field, root),
-            IdlTypeSpec::I64Type => LONGLONG.get_meta_op(name, struct_name, is_key_field, root),
-            IdlTypeSpec::U16Type => USHORT.get_meta_op(name, struct_name, is_key_field, root),
-            IdlTypeSpec::U32Type => ULONG.get_meta_op(name, struct_name, is_key_field, root),
-            IdlTypeSpec::U64Type => ULONGLONG.get_meta_op(name, struct_name, is_key_field, root),
-            IdlTypeSpec::CharType => CHAR.get_meta_op(name, struct_name, is_key_field, root),
-            IdlTypeSpec::WideCharType => vec![String::from("NOT IMPLEMENTED")],
-            IdlTypeSpec::BooleanType => BOOLEAN.get_meta_op(name, struct_name, is_key_field, root),
-            IdlTypeSpec::OctetType => OCTET.get_meta_op(name, struct_name, is_key_field, root),
-            IdlTypeSpec::ScopedName(scoped_name) => {
-                
-                if let Some(scoped_name_type) = root.get_type_decl(&scoped_name) {
-                    let mut meta_ops = Vec::new();
-                    match &scoped_name_type.0 {
-                        IdlTypeDclKind::StructDcl(id, members, is_key) => {
-                            for m in members {
-                                let field_name = format!("{}.{}",name,m.id);
-                                meta_ops.append(&mut m.type_spec.get_meta_op(&field_name,struct_name,*is_key,root))
-                            }
-                        }
-                        _ => panic!("Only structs supported in ScopedName")
+// Bitmasks and bitsets are serialized as the unsigned integer holding their bits
+fn bit_holder_of(spec: &TypeSpec, model: Model) -> Option<TypeSpec> {
+    match spec {
+        TypeSpec::Named(name) => model.kind(name).and_then(bit_holder),
+        _ => None,
+    }
+}
+
+// Ops of a sequence or array element struct, offsets are relative to the
+// element. `blocks` are the structs of the enclosing element ops and the
+// topic, outermost first; a recursive element jumps back to their ops.
+fn get_element_ops(
+    spec: &TypeSpec,
+    path: &[String],
+    model: Model,
+    blocks: &[Vec<String>],
+) -> Result<Vec<MetaOp>, Error> {
+    match spec {
+        TypeSpec::Named(name) => match model.kind(name) {
+            Some(TypeKind::Struct(s)) => {
+                let mut ops = match blocks.iter().position(|block| block == name) {
+                    Some(level) => vec![MetaOp::jsr(blocks.len() - level)],
+                    None => {
+                        let mut blocks = blocks.to_vec();
+                        blocks.push(name.clone());
+                        get_struct_ops(&struct_members(s), &[], &[], model, &blocks)?
+                    }
+                };
+                ops.push(MetaOp::new(String::from("DDS_OP_RTS"), Vec::new()));
+                for op in ops.iter_mut() {
+                    if op.base.is_none() {
+                        op.base = Some((spec.get_c_type(model), rust_type(spec, model.wchar_size)));
                     }
-                    meta_ops
-                } else {
-                    panic!("Unable to determine type of scoped name:{:?}",name);
+                    op.depth += 1;
                 }
-            },
-            IdlTypeSpec::None => panic!("Unexpected get_meta_op for IdlTypeSpec::None"),
-        }
+                Ok(ops)
+            }
+            _ => Err(unsupported(spec, path)),
+        },
+        _ => Err(unsupported(spec, path)),
     }
-    fn get_sub_op(&self, root: &IdlModule) -> String {
-        match self {
-            IdlTypeSpec::ArrayType(_typespec, _values) => String::from("NOT IMPLEMENTED"),
-            IdlTypeSpec::SequenceType(_typespec, _value) => String::from("NOT IMPLEMENTED"),
-            IdlTypeSpec::StringType(_value) => STRING.get_sub_op(root),
-            IdlTypeSpec::WideStringType(_value) => String::from("NOT IMPLEMENTED"),
-            IdlTypeSpec::F32Type => FLOAT.get_sub_op(root),
-            IdlTypeSpec::F64Type => DOUBLE.get_sub_op(root),
-            IdlTypeSpec::F128Type => String::from("NOT IMPLEMENTED"),
-            IdlTypeSpec::I16Type => SHORT.get_sub_op(root),
-            IdlTypeSpec::I32Type => LONG.get_sub_op(root),
-            IdlTypeSpec::I64Type => LONGLONG.get_sub_op(root),
-            IdlTypeSpec::U16Type => USHORT.get_sub_op(root),
-            IdlTypeSpec::U32Type => ULONG.get_sub_op(root),
-            IdlTypeSpec::U64Type => ULONGLONG.get_sub_op(root),
-            IdlTypeSpec::CharType => CHAR.get_sub_op(root),
-            IdlTypeSpec::WideCharType => String::from("NOT IMPLEMENTED"),
-            IdlTypeSpec::BooleanType => BOOLEAN.get_sub_op(root),
-            IdlTypeSpec::OctetType => OCTET.get_sub_op(root),
-            IdlTypeSpec::ScopedName(_name) => String::from("NOT IMPLEMENTED"),
-            IdlTypeSpec::None => panic!("Unexpected get_meta_op for IdlTypeSpec::None"),
-        }
+}
+
+// Ops of the struct `members` at `path`, nested structs are flattened
+fn get_struct_ops(
+    members: &[Member],
+    path: &[String],
+    keys: &[Vec<String>],
+    model: Model,
+    blocks: &[Vec<String>],
+) -> Result<Vec<MetaOp>, Error> {
+    let mut meta_ops = Vec::new();
+    for m in members {
+        let mut member_path = path.to_vec();
+        member_path.push(m.id.clone());
+        meta_ops.append(&mut m.type_spec.get_nested_meta_op(&member_path, keys, model, blocks)?);
     }
+    Ok(meta_ops)
+}
 
-    //DDS_OP_ADR | DDS_OP_TYPE_SEQ | DDS_OP_SUBTYPE_STR, offsetof (TestData_Msg, sequence_field),
-    fn get_op(&self, root: &IdlModule) -> String {
-        match self {
-            IdlTypeSpec::ArrayType(_typespec, _values) => String::from("NOT IMPLEMENTED"),
-            IdlTypeSpec::SequenceType(_typespec, _value) => String::from("NOT IMPLEMENTED"),
-            IdlTypeSpec::StringType(_value) => String::from("NOT IMPLEMENTED"),
-            IdlTypeSpec::WideStringType(_value) => String::from("NOT IMPLEMENTED"),
-            IdlTypeSpec::F32Type => FLOAT.get_op(root),
-            IdlTypeSpec::F64Type => DOUBLE.get_op(root),
-            IdlTypeSpec::F128Type => String::from("NOT IMPLEMENTED"),
-            IdlTypeSpec::I16Type => SHORT.get_op(root),
-            IdlTypeSpec::I32Type => LONG.get_op(root),
-            IdlTypeSpec::I64Type => LONGLONG.get_op(root),
-            IdlTypeSpec::U16Type => USHORT.get_op(root),
-            IdlTypeSpec::U32Type => ULONG.get_op(root),
-            IdlTypeSpec::U64Type => ULONGLONG.get_op(root),
-            IdlTypeSpec::CharType => CHAR.get_op(root),
-            IdlTypeSpec::WideCharType => String::from("NOT IMPLEMENTED"),
-            IdlTypeSpec::BooleanType => BOOLEAN.get_op(root),
-            IdlTypeSpec::OctetType => OCTET.get_op(root),
-            IdlTypeSpec::ScopedName(_name) => String::from("NOT IMPLEMENTED"),
-            IdlTypeSpec::None => panic!("Unexpected get_meta_op for IdlTypeSpec::None"),
+/// Ops of the topic `name` with `members`, closed by DDS_OP_RTS
+pub fn get_topic_ops(
+    name: &[String],
+    members: &[Member],
+    keys: &[Vec<String>],
+    model: Model,
+) -> Result<Vec<MetaOp>, Error> {
+    let mut ops = get_struct_ops(members, &[], keys, model, &[name.to_vec()])?;
+    ops.push(MetaOp::new(String::from("DDS_OP_RTS"), Vec::new()));
+    Ok(ops)
+}
+
+// The words of the element ops, including the closing DDS_OP_RTS
+fn get_ops_size(ops: &[MetaOp]) -> usize {
+    ops.iter().map(|op| op.size()).sum()
+}
+
+fn get_meta_op_complex(
+    spec: &TypeSpec,
+    path: &[String],
+    keys: &[Vec<String>],
+    model: Model,
+    blocks: &[Vec<String>],
+) -> Result<Vec<MetaOp>, Error> {
+    let offset = OpArg::Offset(path.to_vec());
+    match spec {
+        TypeSpec::Sequence(typespec, _bound) => {
+            let elem = typespec.resolve(model.root);
+            check_defined(elem, path, model)?;
+            let op = || -> Result<String, Error> {
+                Ok(format!(
+                    "DDS_OP_ADR | DDS_OP_TYPE_SEQ | {}{}",
+                    elem.get_sub_op(path, model)?,
+                    key_flag(path, keys)
+                ))
+            };
+            match elem {
+                TypeSpec::String(Some(bound)) => {
+                    Ok(vec![MetaOp::new(op()?, vec![offset, OpArg::Value(*bound as i64 + 1)])])
+                }
+                TypeSpec::Named(_) if elem.is_struct(model) => {
+                    let elem_ops = get_element_ops(elem, path, model, blocks)?;
+                    let mut ops = vec![MetaOp::new(
+                        op()?,
+                        vec![
+                            offset,
+                            OpArg::Size(elem.get_c_type(model), rust_type(elem, model.wchar_size)),
+                            OpArg::Jump(4 + get_ops_size(&elem_ops), 4),
+                        ],
+                    )];
+                    ops.extend(elem_ops);
+                    Ok(ops)
+                }
+                TypeSpec::Sequence(_, _)
+                | TypeSpec::Array(_, _)
+                | TypeSpec::Map(_, _, _, _)
+                | TypeSpec::LongDouble
+                | TypeSpec::Fixed { .. } => Err(unsupported(spec, path)),
+                _ => Ok(vec![MetaOp::new(op()?, vec![offset])]),
+            }
         }
-    }
-    fn get_c_type(&self, root: &IdlModule) -> String {
-        match self {
-            IdlTypeSpec::ArrayType(typespec, _values) => {
-                // The array sizes are handled at the StructMember.
-                typespec.get_c_type(root)
+        TypeSpec::Array(typespec, dims) => {
+            let elem = typespec.resolve(model.root);
+            check_defined(elem, path, model)?;
+            let count = dims.iter().product::<u64>() as i64;
+            // fixed point arrays have their own op
+            let op = || -> Result<String, Error> {
+                Ok(format!(
+                    "DDS_OP_ADR | DDS_OP_TYPE_ARR | {}{}",
+                    elem.get_sub_op(path, model)?,
+                    key_flag(path, keys)
+                ))
+            };
+            match elem {
+                // the BCD bytes of all elements are a single octet array
+                TypeSpec::Fixed { digits, .. } => Ok(vec![MetaOp::new(
+                    format!(
+                        "DDS_OP_ADR | DDS_OP_TYPE_ARR | DDS_OP_SUBTYPE_1BY{}",
+                        key_flag(path, keys)
+                    ),
+                    vec![offset, OpArg::Value(count * fixed_size(*digits) as i64)],
+                )]),
+                TypeSpec::String(Some(bound)) => Ok(vec![MetaOp::new(
+                    op()?,
+                    vec![
+                        offset,
+                        OpArg::Value(count),
+                        OpArg::Value(0),
+                        OpArg::Value(*bound as i64 + 1),
+                    ],
+                )]),
+                TypeSpec::Named(_) if elem.is_struct(model) => {
+                    let elem_ops = get_element_ops(elem, path, model, blocks)?;
+                    let mut ops = vec![MetaOp::new(
+                        op()?,
+                        vec![
+                            offset,
+                            OpArg::Value(count),
+                            OpArg::Jump(5 + get_ops_size(&elem_ops), 5),
+                            OpArg::Size(elem.get_c_type(model), rust_type(elem, model.wchar_size)),
+                        ],
+                    )];
+                    ops.extend(elem_ops);
+                    Ok(ops)
+                }
+                TypeSpec::Sequence(_, _)
+                | TypeSpec::Array(_, _)
+                | TypeSpec::Map(_, _, _, _)
+                | TypeSpec::LongDouble => Err(unsupported(spec, path)),
+                _ => Ok(vec![MetaOp::new(op()?, vec![offset, OpArg::Value(count)])]),
             }
-            IdlTypeSpec::SequenceType(_typespec, _value) => String::from("dds_sequence_t"),
-            IdlTypeSpec::StringType(_value) => STRING.get_c_type(root),
-            IdlTypeSpec::WideStringType(_value) => String::from("NOT IMPLEMENTED"),
-            IdlTypeSpec::F32Type => FLOAT.get_c_type(root),
-            IdlTypeSpec::F64Type => DOUBLE.get_c_type(root),
-            IdlTypeSpec::F128Type => String::from("NOT IMPLEMENTED"),
-            IdlTypeSpec::I16Type => SHORT.get_c_type(root),
-            IdlTypeSpec::I32Type => LONG.get_c_type(root),
-            IdlTypeSpec::I64Type => LONGLONG.get_c_type(root),
-            IdlTypeSpec::U16Type => USHORT.get_c_type(root),
-            IdlTypeSpec::U32Type => ULONG.get_c_type(root),
-            IdlTypeSpec::U64Type => ULONGLONG.get_c_type(root),
-            IdlTypeSpec::CharType => CHAR.get_c_type(root),
-            IdlTypeSpec::WideCharType => String::from("NOT IMPLEMENTED"),
-            IdlTypeSpec::BooleanType => BOOLEAN.get_c_type(root),
-            IdlTypeSpec::OctetType => OCTET.get_c_type(root),
-            IdlTypeSpec::ScopedName(name) => name.get_c_type(root),
-            IdlTypeSpec::None => panic!("Unexpected get_meta_op for IdlTypeSpec::None"),
         }
+        _ => Err(unsupported(spec, path)),
     }
-    fn get_xml(&self, root: &IdlModule) -> String {
-        match self {
-            IdlTypeSpec::ArrayType(_typespec, _values) => String::from("NOT IMPLEMENTED"),
-            IdlTypeSpec::SequenceType(_typespec, _value) => String::from("NOT IMPLEMENTED"),
-            IdlTypeSpec::StringType(_value) => String::from("NOT IMPLEMENTED"),
-            IdlTypeSpec::WideStringType(_value) => String::from("NOT IMPLEMENTED"),
-            IdlTypeSpec::F32Type => FLOAT.get_xml(root),
-            IdlTypeSpec::F64Type => DOUBLE.get_xml(root),
-            IdlTypeSpec::F128Type => String::from("NOT IMPLEMENTED"),
-            IdlTypeSpec::I16Type => SHORT.get_xml(root),
-            IdlTypeSpec::I32Type => LONG.get_xml(root),
-            IdlTypeSpec::I64Type => LONGLONG.get_xml(root),
-            IdlTypeSpec::U16Type => USHORT.get_xml(root),
-            IdlTypeSpec::U32Type => ULONG.get_xml(root),
-            IdlTypeSpec::U64Type => ULONGLONG.get_xml(root),
-            IdlTypeSpec::CharType => CHAR.get_xml(root),
-            IdlTypeSpec::WideCharType => String::from("NOT IMPLEMENTED"),
-            IdlTypeSpec::BooleanType => BOOLEAN.get_xml(root),
-            IdlTypeSpec::OctetType => OCTET.get_xml(root),
-            IdlTypeSpec::ScopedName(_name) => String::from("NOT IMPLEMENTED"),
-            IdlTypeSpec::None => panic!("Unexpected get_meta_op for IdlTypeSpec::None"),
+}
+
+/// Whether `spec` holds a wide string, the serializer of the target release
+/// may have no op for it
+pub fn contains_wide_string(spec: &TypeSpec, model: Model) -> bool {
+    contains_in(spec, model, &|spec| matches!(spec, TypeSpec::WideString(_)), &mut Vec::new())
+}
+
+// Whether `spec` or the types it is made of match `found`
+fn contains_in(
+    spec: &TypeSpec,
+    model: Model,
+    found: &dyn Fn(&TypeSpec) -> bool,
+    visited: &mut Vec<Vec<String>>,
+) -> bool {
+    if found(spec) {
+        return true;
+    }
+    match spec {
+        TypeSpec::Array(typespec, _) | TypeSpec::Sequence(typespec, _) => {
+            contains_in(typespec, model, found, visited)
         }
+        TypeSpec::Map(key, value, _, _) => {
+            contains_in(key, model, found, visited) || contains_in(value, model, found, visited)
+        }
+        TypeSpec::Named(name) => {
+            // recursive types refer to themselves through sequences
+            if visited.contains(name) {
+                return false;
+            }
+            visited.push(name.clone());
+            match model.kind(name) {
+                Some(TypeKind::Struct(s)) => struct_members(s)
+                    .iter()
+                    .any(|m| contains_in(&m.type_spec, model, found, visited)),
+                Some(TypeKind::Union(u)) => u
+                    .cases
+                    .iter()
+                    .any(|case| contains_in(&case.type_spec, model, found, visited)),
+                Some(TypeKind::Typedef(typespec)) => contains_in(typespec, model, found, visited),
+                _ => false,
+            }
+        }
+        _ => false,
     }
+}
 
-    fn get_key_size(&self, root: &IdlModule) -> i32 {
-        0
+/// Alignment of a struct with `members`, the maximum of the member alignments
+pub fn get_members_alignment(members: &[Member], model: Model) -> Alignment {
+    members
+        .iter()
+        .fold(Alignment::new(AlignmentType::One), |align, m| {
+            align.maximum(m.type_spec.get_alignment(model))
+        })
+}
+
+// Alignment of the declared type `kind`
+fn get_kind_alignment(kind: &TypeKind, model: Model) -> Alignment {
+    match kind {
+        TypeKind::Struct(s) => get_members_alignment(&struct_members(s), model),
+        TypeKind::Union(u) => u
+            .cases
+            .iter()
+            .fold(u.discriminator.get_alignment(model), |align, case| {
+                align.maximum(case.type_spec.get_alignment(model))
+            }),
+        TypeKind::Enum(_) => Alignment::new(AlignmentType::Four),
+        TypeKind::Bitmask(_) | TypeKind::Bitset(_) => bit_holder(kind)
+            .map_or(Alignment::new(AlignmentType::One), |holder| holder.get_alignment(model)),
+        TypeKind::Typedef(typespec) => typespec.get_alignment(model),
+        TypeKind::Forward | TypeKind::Interface(_) => Alignment::new(AlignmentType::One),
     }
+}
 
-    fn get_meta_op_size(&self, root: &IdlModule) -> i32 {
+impl TypeSpec {
+    /// The sequence of entry structs that serializes a map, XCDR encodes a
+    /// map as a sequence of key/value pairs
+    pub(crate) fn as_entry_sequence(&self) -> Option<TypeSpec> {
         match self {
-            IdlTypeSpec::ArrayType(typespec, _values) => {
-                match (*typespec).deref() {
-                    IdlTypeSpec::StringType(_) => 5,
-                    IdlTypeSpec::ScopedName(name) => {
-                        if let Some(t) = root.get_type_decl(name) {
-                            match &t.0 {
-                                IdlTypeDclKind::StructDcl(_id, members, _is_key) => {
-                                    let sum = members
-                                        .iter()
-                                        .fold(0, |sum, x| sum + x.type_spec.get_meta_op_size(root));
-                                    6 + sum
-                                }
-                                _ => panic!("Unsupported Scoped name:{:?}", name),
-                            }
-                        } else {
-                            panic!("Unable to find type decl for scoped name:{:?}", name);
-                        }
-                    }
-                    _ => 1 + typespec.get_meta_op_size(root), // basic types
-                }
-            }
-            IdlTypeSpec::SequenceType(typespec, _value) => match (*typespec).deref() {
-                IdlTypeSpec::StringType(_) => 2,
-                IdlTypeSpec::ScopedName(name) => {
-                    if let Some(t) = root.get_type_decl(name) {
-                        match &t.0 {
-                            IdlTypeDclKind::StructDcl(_id, members, _is_key) => {
-                                let sum = members
-                                    .iter()
-                                    .fold(0, |sum, x| sum + x.type_spec.get_meta_op_size(root));
-                                5 + sum
-                            }
-                            _ => panic!("Unsupported Scoped name:{:?}", name),
-                        }
-                    } else {
-                        panic!("Unable to find type decl for scoped name:{:?}", name);
-                    }
-                }
-                _ => typespec.get_meta_op_size(root),
-            },
-            IdlTypeSpec::StringType(_value) => STRING.get_meta_op_size(root),
-            IdlTypeSpec::WideStringType(_value) => 0,
-            IdlTypeSpec::F32Type => FLOAT.get_meta_op_size(root),
-            IdlTypeSpec::F64Type => DOUBLE.get_meta_op_size(root),
-            IdlTypeSpec::F128Type => panic!("Unimplemented F128Type"),
-            IdlTypeSpec::I16Type => SHORT.get_meta_op_size(root),
-            IdlTypeSpec::I32Type => LONG.get_meta_op_size(root),
-            IdlTypeSpec::I64Type => LONGLONG.get_meta_op_size(root),
-            IdlTypeSpec::U16Type => USHORT.get_meta_op_size(root),
-            IdlTypeSpec::U32Type => ULONG.get_meta_op_size(root),
-            IdlTypeSpec::U64Type => ULONGLONG.get_meta_op_size(root),
-            IdlTypeSpec::CharType => CHAR.get_meta_op_size(root),
-            IdlTypeSpec::WideCharType => panic!("Not implemented WideChar"),
-            IdlTypeSpec::BooleanType => BOOLEAN.get_meta_op_size(root),
-            IdlTypeSpec::OctetType => OCTET.get_meta_op_size(root),
-            IdlTypeSpec::ScopedName(name) => {
-                if let Some(t) = root.get_type_decl(name) {
-                    match &t.0 {
-                        IdlTypeDclKind::StructDcl(id, members, is_key) => {
-                            let sum = members
-                                .iter()
-                                .fold(0, |sum, x| sum + x.type_spec.get_meta_op_size(root));
-                            sum
-                        }
-                        _ => panic!("Unsupported Scoped name:{:?}", name),
-                    }
-                } else {
-                    panic!("Unable to find type decl for scoped name:{:?}", name);
-                }
+            TypeSpec::Map(_, _, bound, Some(entry)) => {
+                Some(TypeSpec::Sequence(Box::new(TypeSpec::Named(entry.clone())), *bound))
             }
-            IdlTypeSpec::None => panic!("Unexpected get_meta_op for IdlTypeSpec::None"),
+            _ => None,
         }
     }
-    fn get_alignment(&self, root: &IdlModule) -> Alignment {
-        Alignment::new(AlignmentType::Ptr)
-    }
 
-    fn contains_union(&self, root: &IdlModule) -> bool {
-        false
+    // True if the type is a struct, following typedefs
+    fn is_struct(&self, model: Model) -> bool {
+        match self.resolve(model.root) {
+            TypeSpec::Named(name) => matches!(model.kind(name), Some(TypeKind::Struct(_))),
+            _ => false,
+        }
     }
-}
-
-impl IdlTypeSpec {
-    pub fn write_h<W: Write>(&self, out: &mut W, root: &IdlModule) -> Result<(), Error> {
-        out.write_all(self.get_c_type(root).as_bytes())?;
 
-        Ok(())
+    // The ops of get_meta_op inside the element ops or topic of `blocks`
+    fn get_nested_meta_op(
+        &self,
+        path: &[String],
+        keys: &[Vec<String>],
+        model: Model,
+        blocks: &[Vec<String>],
+    ) -> Result<Vec<MetaOp>, Error> {
+        if let Some(holder) = bit_holder_of(self, model) {
+            return holder.get_meta_op(path, keys, model);
+        }
+        match self {
+            TypeSpec::Array(_, _) | TypeSpec::Sequence(_, _) => {
+                get_meta_op_complex(self, path, keys, model, blocks)
+            }
+            TypeSpec::Map(_, _, _, _) => match self.as_entry_sequence() {
+                Some(entries) => get_meta_op_complex(&entries, path, keys, model, blocks),
+                None => Err(unsupported(self, path)),
+            },
+            TypeSpec::String(None) => STRING.get_meta_op(path, keys, model),
+            TypeSpec::String(Some(bound)) => Ok(vec![MetaOp::new(
+                format!("DDS_OP_ADR | DDS_OP_TYPE_BST{}", key_flag(path, keys)),
+                vec![OpArg::Offset(path.to_vec()), OpArg::Value(*bound as i64 + 1)],
+            )]),
+            TypeSpec::WideString(_) => Err(no_wide_strings(path)),
+            TypeSpec::Float32 => FLOAT.get_meta_op(path, keys, model),
+            TypeSpec::Float64 => DOUBLE.get_meta_op(path, keys, model),
+            TypeSpec::LongDouble => Ok(vec![MetaOp::new(
+                format!(
+                    "DDS_OP_ADR | DDS_OP_TYPE_ARR | DDS_OP_SUBTYPE_1BY{}",
+                    key_flag(path, keys)
+                ),
+                vec![OpArg::Offset(path.to_vec()), OpArg::Value(LONG_DOUBLE_SIZE)],
+            )]),
+            // the packed BCD bytes are the CDR encoding
+            TypeSpec::Fixed { digits, .. } => Ok(vec![MetaOp::new(
+                format!(
+                    "DDS_OP_ADR | DDS_OP_TYPE_ARR | DDS_OP_SUBTYPE_1BY{}",
+                    key_flag(path, keys)
+                ),
+                vec![OpArg::Offset(path.to_vec()), OpArg::Value(fixed_size(*digits) as i64)],
+            )]),
+            TypeSpec::Int8 => INT8.get_meta_op(path, keys, model),
+            TypeSpec::Int16 => SHORT.get_meta_op(path, keys, model),
+            TypeSpec::Int32 => LONG.get_meta_op(path, keys, model),
+            TypeSpec::Int64 => LONGLONG.get_meta_op(path, keys, model),
+            TypeSpec::UInt8 => UINT8.get_meta_op(path, keys, model),
+            TypeSpec::UInt16 => USHORT.get_meta_op(path, keys, model),
+            TypeSpec::UInt32 => ULONG.get_meta_op(path, keys, model),
+            TypeSpec::UInt64 => ULONGLONG.get_meta_op(path, keys, model),
+            TypeSpec::Char => CHAR.get_meta_op(path, keys, model),
+            TypeSpec::WideChar => match model.wchar_size {
+                WideCharSize::Bits16 => WCHAR.get_meta_op(path, keys, model),
+                WideCharSize::Bits32 => Err(no_wide_char32(path)),
+            },
+            TypeSpec::Boolean => BOOLEAN.get_meta_op(path, keys, model),
+            TypeSpec::Octet => OCTET.get_meta_op(path, keys, model),
+            TypeSpec::Named(name) => match model.kind(name) {
+                // nested structs are flattened into the ops of the topic
+                Some(TypeKind::Struct(s)) => get_struct_ops(&struct_members(s), path, keys, model, blocks),
+                Some(TypeKind::Enum(_)) => Ok(vec![MetaOp::new(
+                    format!("DDS_OP_ADR | DDS_OP_TYPE_4BY{}", key_flag(path, keys)),
+                    vec![OpArg::Offset(path.to_vec())],
+                )]),
+                Some(TypeKind::Typedef(typespec)) => typespec.get_nested_meta_op(path, keys, model, blocks),
+                Some(TypeKind::Forward) => check_defined(self, path, model).map(|_| Vec::new()),
+                _ => Err(unsupported(self, path)),
+            },
+        }
     }
 }
 
-impl Type for IdlScopedName {
+impl Type for TypeSpec {
     fn get_meta_op(
         &self,
-        name: &str,
-        struct_name: &str,
-        is_key_field: bool,
-        root: &IdlModule,
-    ) -> Vec<String> {
-        panic!("Unimplemented");
-    }
-    fn get_sub_op(&self, root: &IdlModule) -> String {
-        panic!("Unimplemented");
+        path: &[String],
+        keys: &[Vec<String>],
+        model: Model,
+    ) -> Result<Vec<MetaOp>, Error> {
+        self.get_nested_meta_op(path, keys, model, &[])
     }
-    fn get_op(&self, root: &IdlModule) -> String {
-        panic!("Unimplemented");
-    }
-    fn get_c_type(&self, root: &IdlModule) -> String {
-        let is_absolute_path = self.1;
-        let components = &self.0;
-        if is_absolute_path {
-            components.join("_")
-        } else {
-            String::from(components.iter().last().as_deref().unwrap())
+    fn get_sub_op(&self, path: &[String], model: Model) -> Result<String, Error> {
+        if let Some(holder) = bit_holder_of(self, model) {
+            return holder.get_sub_op(path, model);
+        }
+        match self {
+            TypeSpec::String(None) => STRING.get_sub_op(path, model),
+            TypeSpec::String(Some(_)) => Ok(String::from("DDS_OP_SUBTYPE_BST")),
+            TypeSpec::WideString(_) => Err(no_wide_strings(path)),
+            TypeSpec::Float32 => FLOAT.get_sub_op(path, model),
+            TypeSpec::Float64 => DOUBLE.get_sub_op(path, model),
+            TypeSpec::Int8 => INT8.get_sub_op(path, model),
+            TypeSpec::Int16 => SHORT.get_sub_op(path, model),
+            TypeSpec::Int32 => LONG.get_sub_op(path, model),
+            TypeSpec::Int64 => LONGLONG.get_sub_op(path, model),
+            TypeSpec::UInt8 => UINT8.get_sub_op(path, model),
+            TypeSpec::UInt16 => USHORT.get_sub_op(path, model),
+            TypeSpec::UInt32 => ULONG.get_sub_op(path, model),
+            TypeSpec::UInt64 => ULONGLONG.get_sub_op(path, model),
+            TypeSpec::Char => CHAR.get_sub_op(path, model),
+            TypeSpec::WideChar => match model.wchar_size {
+                WideCharSize::Bits16 => WCHAR.get_sub_op(path, model),
+                WideCharSize::Bits32 => Err(no_wide_char32(path)),
+            },
+            TypeSpec::Boolean => BOOLEAN.get_sub_op(path, model),
+            TypeSpec::Octet => OCTET.get_sub_op(path, model),
+            TypeSpec::Named(name) => match model.kind(name) {
+                Some(TypeKind::Struct(_)) => Ok(String::from("DDS_OP_SUBTYPE_STU")),
+                Some(TypeKind::Enum(_)) => Ok(String::from("DDS_OP_SUBTYPE_4BY")),
+                Some(TypeKind::Typedef(typespec)) => typespec.get_sub_op(path, model),
+                _ => Err(unsupported(self, path)),
+            },
+            // the elements of sequences and arrays are not nested in place
+            TypeSpec::Array(_, _)
+            | TypeSpec::Sequence(_, _)
+            | TypeSpec::Map(_, _, _, _)
+            | TypeSpec::LongDouble
+            | TypeSpec::Fixed { .. } => Err(unsupported(self, path)),
         }
     }
-    fn get_xml(&self, root: &IdlModule) -> String {
-        panic!("Unimplemented");
-    }
-    fn get_key_size(&self, root: &IdlModule) -> i32 {
-        panic!("Unimplemented");
-    }
-    fn get_meta_op_size(&self, root: &IdlModule) -> i32 {
-        panic!("Unimplemented");
-    }
-    fn get_alignment(&self, root: &IdlModule) -> Alignment {
-        panic!("Unimplemented");
-    }
-    fn contains_union(&self, root: &IdlModule) -> bool {
-        panic!("Unimplemented");
-    }
-}
 
+    // The array sizes, the bounds of strings and the BCD size are handled at
+    // the struct member
+    fn get_c_type(&self, _model: Model) -> String {
+        c_type_name(self)
+    }
 
-impl Type for IdlTypeDcl {
-    fn get_meta_op(
-        &self,
-        name: &str,
-        struct_name: &str,
-        is_key_field: bool,
-        root: &IdlModule,
-    ) -> Vec<String> {
+    fn get_xml(&self, model: Model) -> String {
+        match self {
+            TypeSpec::Array(_, _) => String::from("NOT IMPLEMENTED"),
+            TypeSpec::Sequence(_, _) => String::from("NOT IMPLEMENTED"),
+            TypeSpec::Map(_, _, _, _) => String::from("NOT IMPLEMENTED"),
+            TypeSpec::String(_) => String::from("NOT IMPLEMENTED"),
+            TypeSpec::WideString(None) => String::from("<WString/>"),
+            TypeSpec::WideString(Some(bound)) => format!("<WString length=\"{}\"/>", bound),
+            TypeSpec::Float32 => FLOAT.get_xml(model),
+            TypeSpec::Float64 => DOUBLE.get_xml(model),
+            TypeSpec::LongDouble => {
+                format!("<Array size=\"{}\"><Octet/></Array>", LONG_DOUBLE_SIZE)
+            }
+            TypeSpec::Fixed { digits, scale } => {
+                format!("<Fixed digits=\"{}\" scale=\"{}\"/>", digits, scale)
+            }
+            TypeSpec::Int8 => INT8.get_xml(model),
+            TypeSpec::Int16 => SHORT.get_xml(model),
+            TypeSpec::Int32 => LONG.get_xml(model),
+            TypeSpec::Int64 => LONGLONG.get_xml(model),
+            TypeSpec::UInt8 => UINT8.get_xml(model),
+            TypeSpec::UInt16 => USHORT.get_xml(model),
+            TypeSpec::UInt32 => ULONG.get_xml(model),
+            TypeSpec::UInt64 => ULONGLONG.get_xml(model),
+            TypeSpec::Char => CHAR.get_xml(model),
+            TypeSpec::WideChar => WCHAR.get_xml(model),
+            TypeSpec::Boolean => BOOLEAN.get_xml(model),
+            TypeSpec::Octet => OCTET.get_xml(model),
+            TypeSpec::Named(_) => String::from("NOT IMPLEMENTED"),
+        }
+    }
 
-        let mut meta_ops = Vec::new();
-        match &self.0 {
-            IdlTypeDclKind::StructDcl(id,members,is_key) => {
-                for m in members {
-                    match *m.type_spec {
-                        IdlTypeSpec::ScopedName(ref name) => meta_ops.append( &mut m.type_spec.get_meta_op(&m.id,&id,m.is_key,root)),
-                        _ => meta_ops.append( &mut m.type_spec.get_meta_op(&m.id,&id,m.is_key,root))
-                    }
+    // Maximum size of this type in a serialized key, -1 if unbounded
+    fn get_key_size(&self, model: Model) -> i32 {
+        if let Some(holder) = bit_holder_of(self, model) {
+            return holder.get_key_size(model);
+        }
+        match self {
+            TypeSpec::Array(typespec, dims) => {
+                let elem_size = typespec.get_key_size(model);
+                match elem_size {
+                    size if size >= 0 => size * dims.iter().product::<u64>() as i32,
+                    _ => -1,
                 }
-                meta_ops.append(&mut vec![String::from("DDS_OP_RTS")]);
             }
-            _ => panic!("Unsupported IdlTypeDeclKind")
+            TypeSpec::Sequence(_, _) => -1,
+            TypeSpec::Map(_, _, _, _) => -1,
+            TypeSpec::String(None) => STRING.get_key_size(model),
+            // length, characters and the terminating nul
+            TypeSpec::String(Some(bound)) => 4 + *bound as i32 + 1,
+            TypeSpec::WideString(None) => -1,
+            // length and the UTF-16 code units, a 32 bit wchar may need two units
+            TypeSpec::WideString(Some(bound)) => {
+                let unit_size = match model.wchar_size {
+                    WideCharSize::Bits16 => 2,
+                    WideCharSize::Bits32 => 4,
+                };
+                4 + unit_size * *bound as i32
+            }
+            TypeSpec::Float32 => FLOAT.get_key_size(model),
+            TypeSpec::Float64 => DOUBLE.get_key_size(model),
+            TypeSpec::LongDouble => -1,
+            TypeSpec::Fixed { digits, .. } => fixed_size(*digits) as i32,
+            TypeSpec::Int8 => INT8.get_key_size(model),
+            TypeSpec::Int16 => SHORT.get_key_size(model),
+            TypeSpec::Int32 => LONG.get_key_size(model),
+            TypeSpec::Int64 => LONGLONG.get_key_size(model),
+            TypeSpec::UInt8 => UINT8.get_key_size(model),
+            TypeSpec::UInt16 => USHORT.get_key_size(model),
+            TypeSpec::UInt32 => ULONG.get_key_size(model),
+            TypeSpec::UInt64 => ULONGLONG.get_key_size(model),
+            TypeSpec::Char => CHAR.get_key_size(model),
+            TypeSpec::WideChar => WCHAR.get_key_size(model),
+            TypeSpec::Boolean => BOOLEAN.get_key_size(model),
+            TypeSpec::Octet => OCTET.get_key_size(model),
+            TypeSpec::Named(name) => match model.kind(name) {
+                Some(TypeKind::Enum(_)) => 4,
+                Some(TypeKind::Typedef(typespec)) => typespec.get_key_size(model),
+                _ => -1,
+            },
         }
-
-        
-        meta_ops
-    }
-    fn get_sub_op(&self, root: &IdlModule) -> String {
-        panic!("Unimplemented");
-    }
-    fn get_op(&self, root: &IdlModule) -> String {
-        panic!("Unimplemented");
-    }
-    fn get_c_type(&self, root: &IdlModule) -> String {
-        String::from("UNIMPLEMENTED")
     }
-    fn get_xml(&self, root: &IdlModule) -> String {
-        panic!("Unimplemented");
-    }
-    fn get_key_size(&self, root: &IdlModule) -> i32 {
-        panic!("Unimplemented");
-    }
-    fn get_meta_op_size(&self, root: &IdlModule) -> i32 {
-        panic!("Unimplemented");
-    }
-    fn get_alignment(&self, root: &IdlModule) -> Alignment {
-        panic!("Unimplemented");
+
+    fn get_alignment(&self, model: Model) -> Alignment {
+        match self {
+            TypeSpec::Array(typespec, _) => typespec.get_alignment(model),
+            // dds_sequence_t holds the buffer pointer
+            TypeSpec::Sequence(_, _) => Alignment::new(AlignmentType::Ptr),
+            TypeSpec::Map(_, _, _, _) => Alignment::new(AlignmentType::Ptr),
+            TypeSpec::String(None) => STRING.get_alignment(model),
+            // bounded strings are char arrays
+            TypeSpec::String(Some(_)) => Alignment::new(AlignmentType::One),
+            TypeSpec::WideString(_) => Alignment::new(AlignmentType::Ptr),
+            TypeSpec::Float32 => FLOAT.get_alignment(model),
+            TypeSpec::Float64 => DOUBLE.get_alignment(model),
+            TypeSpec::LongDouble => Alignment::new(AlignmentType::Sixteen),
+            TypeSpec::Fixed { .. } => Alignment::new(AlignmentType::One),
+            TypeSpec::Int8 => INT8.get_alignment(model),
+            TypeSpec::Int16 => SHORT.get_alignment(model),
+            TypeSpec::Int32 => LONG.get_alignment(model),
+            TypeSpec::Int64 => LONGLONG.get_alignment(model),
+            TypeSpec::UInt8 => UINT8.get_alignment(model),
+            TypeSpec::UInt16 => USHORT.get_alignment(model),
+            TypeSpec::UInt32 => ULONG.get_alignment(model),
+            TypeSpec::UInt64 => ULONGLONG.get_alignment(model),
+            TypeSpec::Char => CHAR.get_alignment(model),
+            // the alignment in memory is the one of wchar_t
+            TypeSpec::WideChar => match model.wchar_size {
+                WideCharSize::Bits16 => WCHAR.get_alignment(model),
+                WideCharSize::Bits32 => Alignment::new(AlignmentType::Four),
+            },
+            TypeSpec::Boolean => BOOLEAN.get_alignment(model),
+            TypeSpec::Octet => OCTET.get_alignment(model),
+            TypeSpec::Named(name) => match model.kind(name) {
+                Some(kind) => get_kind_alignment(kind, model),
+                None => panic!("Unable to find type decl for scoped name:{:?}", name),
+            },
+        }
     }
-    fn contains_union(&self, root: &IdlModule) -> bool {
-        panic!("Unimplemented");
+
+    fn contains_union(&self, _model: Model) -> bool {
+        false
     }
-}
\ No newline at end of file
+}
@@ -23,22 +23,24 @@ public interface Type
 }
 */
 use crate::cdds::alignment::Alignment;
-use crate::IdlModule;
+use crate::cdds::descriptor::MetaOp;
+use crate::cdds::Model;
+
+use std::io::Error;
 
 pub trait Type {
+    // `path` is the member path from the topic struct, `keys` the key paths
     fn get_meta_op(
         &self,
-        name: &str,
-        struct_name: &str,
-        is_key_field: bool,
-        root: &IdlModule,
-    ) -> Vec<String>;
-    fn get_sub_op(&self, root: &IdlModule) -> String;
-    fn get_op(&self, root: &IdlModule) -> String;
-    fn get_c_type(&self, root: &IdlModule) -> String;
-    fn get_xml(&self, root: &IdlModule) -> String;
-    fn get_key_size(&self, root: &IdlModule) -> i32;
-    fn get_meta_op_size(&self, root: &IdlModule) -> i32;
-    fn get_alignment(&self, root: &IdlModule) -> Alignment;
-    fn contains_union(&self, root: &IdlModule) -> bool;
+        path: &[String],
+        keys: &[Vec<String>],
+        model: Model,
+    ) -> Result<Vec<MetaOp>, Error>;
+    // the subtype of a sequence or array element at `path`
+    fn get_sub_op(&self, path: &[String], model: Model) -> Result<String, Error>;
+    fn get_c_type(&self, model: Model) -> String;
+    fn get_xml(&self, model: Model) -> String;
+    fn get_key_size(&self, model: Model) -> i32;
+    fn get_alignment(&self, model: Model) -> Alignment;
+    fn contains_union(&self, model: Model) -> bool;
 }
@@ -0,0 +1,944 @@
+// Copyright (C) 2020  Sojan James
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0>
+
+/* The ISO C++17 backend, for the Cyclone C++ binding.
+
+ The header <idl>.hpp declares a namespace per module and a class per struct
+ and union, with private members and the accessors of the language mapping:
+
+    int32_t x() const;
+    int32_t& x();
+    void x(int32_t _val_);
+
+ Strings, sequences, arrays and maps are std::string, std::vector,
+ std::array and std::map, the branches of a union are held in a
+ std::variant next to the discriminator _d().
+
+ A topic gets the TopicTraits specialisation of the 0.7 binding. The structs
+ are copied to and from the C types of the header of the c backend by the
+ overloads idlc::copyIn and idlc::copyOut, the traits hand them to the binding
+ untyped, the descriptor is the one of the C file generated with -d.
+
+ The limits, each an error of the file:
+ - the C header holds no unions, so a file with unions has no copies and
+   can't declare a topic, a topic holding a union elsewhere can't be copied
+   either, nor one holding a wide string, a fixed point value, a map or a
+   bitset
+ - the C header declares the global declarations with their C++ names, so the
+   declarations of a file with topics must all be in modules
+ - interfaces have no C++ mapping
+ The header of an included file is generated with its own file and included
+ as <file>.hpp.
+*/
+
+use crate::ast::{fixed_size, STRUCT_BASE_MEMBER};
+use crate::backend::Backend;
+use crate::cdds::{c_escape, c_type_name};
+use crate::ir::{
+    fixed_decimal, CaseLabel, ConstValue, Constant, Declaration, Module, ScopedName, Specification, Struct,
+    TypeDecl, TypeKind, TypeSpec, Union,
+};
+use crate::{Configuration, IdlError, OutputFile, OutputKind};
+
+const INDENTION: usize = 4;
+
+/// The C++ classes, and the topic traits of the Cyclone C++ binding
+pub struct CppBackend;
+
+impl Backend for CppBackend {
+    fn name(&self) -> &str {
+        "cpp"
+    }
+
+    fn generate(&self, spec: &Specification, config: &Configuration) -> Result<Vec<OutputFile>, IdlError> {
+        Ok(vec![OutputFile {
+            name: format!("{}.hpp", OutputKind::Other.file_name(&config.idl_name)),
+            kind: OutputKind::Other,
+            contents: write_hpp(spec, config)?,
+        }])
+    }
+}
+
+fn line(out: &mut String, level: usize, text: &str) {
+    if !text.is_empty() {
+        out.push_str(&" ".repeat(level * INDENTION));
+        out.push_str(text);
+    }
+    out.push('\n');
+}
+
+// the C++ type of `spec`
+fn cpp_type(spec: &TypeSpec) -> String {
+    String::from(match spec {
+        TypeSpec::Boolean => "bool",
+        TypeSpec::Octet | TypeSpec::UInt8 => "uint8_t",
+        TypeSpec::Char => "char",
+        TypeSpec::WideChar => "wchar_t",
+        TypeSpec::Int8 => "int8_t",
+        TypeSpec::Int16 => "int16_t",
+        TypeSpec::Int32 => "int32_t",
+        TypeSpec::Int64 => "int64_t",
+        TypeSpec::UInt16 => "uint16_t",
+        TypeSpec::UInt32 => "uint32_t",
+        TypeSpec::UInt64 => "uint64_t",
+        TypeSpec::Float32 => "float",
+        TypeSpec::Float64 => "double",
+        TypeSpec::LongDouble => "long double",
+        // the packed decimal digits, as in C
+        TypeSpec::Fixed { digits, .. } => return format!("std::array<uint8_t, {}>", fixed_size(*digits)),
+        TypeSpec::String(_) => "std::string",
+        TypeSpec::WideString(_) => "std::wstring",
+        TypeSpec::Sequence(elem, _) => return format!("std::vector<{}>", cpp_type(elem)),
+        TypeSpec::Array(elem, dims) => {
+            return dims
+                .iter()
+                .rev()
+                .fold(cpp_type(elem), |inner, dim| format!("std::array<{}, {}>", inner, dim))
+        }
+        TypeSpec::Map(key, value, _, _) => return format!("std::map<{}, {}>", cpp_type(key), cpp_type(value)),
+        TypeSpec::Named(path) => return format!("::{}", path.join("::")),
+    })
+}
+
+// passed and returned by value by the accessors
+fn is_value_type(spec: &TypeSpec, root: &Module) -> bool {
+    match spec.resolve(root) {
+        TypeSpec::Fixed { .. }
+        | TypeSpec::String(_)
+        | TypeSpec::WideString(_)
+        | TypeSpec::Sequence(_, _)
+        | TypeSpec::Array(_, _)
+        | TypeSpec::Map(_, _, _, _) => false,
+        TypeSpec::Named(path) => matches!(
+            root.get_type(path).map(|t| &t.kind),
+            Some(TypeKind::Enum(_)) | Some(TypeKind::Bitmask(_))
+        ),
+        _ => true,
+    }
+}
+
+// the C++ literal of `value`, of type `spec`
+fn literal(value: &ConstValue, spec: &TypeSpec, root: &Module) -> String {
+    let wide = matches!(spec.resolve(root), TypeSpec::WideChar | TypeSpec::WideString(_));
+    let prefix = if wide { "L" } else { "" };
+    match value {
+        ConstValue::Integer(n) => n.to_string(),
+        ConstValue::Float(f) => match spec.resolve(root) {
+            TypeSpec::Float32 => format!("{:?}f", f),
+            TypeSpec::LongDouble => format!("{:?}L", f),
+            _ => format!("{:?}", f),
+        },
+        ConstValue::Fixed { scaled, scale } => format!("{}L", fixed_decimal(*scaled, *scale)),
+        ConstValue::Boolean(b) => b.to_string(),
+        ConstValue::Char(c) => format!("{}'{}'", prefix, c_escape(&c.to_string())),
+        ConstValue::String(s) => format!("{}\"{}\"", prefix, c_escape(s)),
+        ConstValue::Enumerator { enumeration, id, .. } => format!("::{}::{}", enumeration.join("::"), id),
+    }
+}
+
+fn write_hpp(spec: &Specification, config: &Configuration) -> Result<String, IdlError> {
+    let idlnamestem = String::from(config.idl_name.file_stem().unwrap().to_str().unwrap());
+    let idlname = config.idl_name.file_name().unwrap().to_str().unwrap();
+    let root = &spec.root;
+
+    let items = root.main_file_declarations();
+    let topics: Vec<(&TypeDecl, &Struct)> = items
+        .iter()
+        .filter_map(|item| match item {
+            Declaration::Type(decl) => match decl.kind {
+                TypeKind::Struct(ref s) if s.topic.is_some() => Some((*decl, s)),
+                _ => None,
+            },
+            Declaration::Constant(_) => None,
+        })
+        .collect();
+
+    let structs = copied_structs(&items, &topics, root)?;
+
+    let mut out = std::include_str!("templates/file_header.txt")
+        .replace("<FILENAME>", &format!("{}.hpp", idlnamestem))
+        .replace("<IDLNAME>", idlname)
+        .replace("<HEADERDEFINE>", &format!("_DDSL_{}_HPP_", idlnamestem.to_uppercase()));
+    if !topics.is_empty() || !structs.is_empty() {
+        line(&mut out, 0, "");
+    }
+    if !topics.is_empty() {
+        line(&mut out, 0, "#include \"dds/dds.hpp\"");
+    }
+    if !structs.is_empty() {
+        line(&mut out, 0, &format!("#include \"{}.h\"", idlnamestem));
+    }
+    // the headers of included files include their own includes
+    for (_, file) in spec.main_includes.iter() {
+        let header = std::path::Path::new(file).with_extension("hpp");
+        line(&mut out, 0, &format!("#include \"{}\"", header.to_string_lossy()));
+    }
+
+    let mut scope: Vec<String> = Vec::new();
+    for item in items.iter() {
+        let path = item.path();
+        let module = &path[..path.len() - 1];
+        let common = scope.iter().zip(module.iter()).take_while(|(a, b)| a == b).count();
+        while scope.len() > common {
+            let id = scope.pop().unwrap();
+            line(&mut out, scope.len(), &format!("}} // namespace {}", id));
+        }
+        for id in module[common..].iter() {
+            line(&mut out, 0, "");
+            line(&mut out, scope.len(), &format!("namespace {}", id));
+            line(&mut out, scope.len(), "{");
+            scope.push(id.clone());
+        }
+        line(&mut out, 0, "");
+        match item {
+            Declaration::Type(decl) => write_type(&mut out, scope.len(), decl, root)?,
+            Declaration::Constant(constant) => write_constant(&mut out, scope.len(), constant, root),
+        }
+    }
+    while let Some(id) = scope.pop() {
+        line(&mut out, scope.len(), &format!("}} // namespace {}", id));
+    }
+
+    if !structs.is_empty() {
+        write_copies(&mut out, &structs, root);
+    }
+    for (decl, s) in topics.iter() {
+        let keyless = s.topic.as_ref().is_none_or(|t| t.keys.is_empty());
+        out.push_str(
+            &std::include_str!("templates/topic_traits.txt")
+                .replace("<TYPE>", &format!("::{}", decl.path.join("::")))
+                .replace("<TYPENAME>", &decl.path.join("::"))
+                .replace("<CTYPE>", &decl.path.join("_"))
+                .replace("<KEYLESS>", if keyless { "true" } else { "false" }),
+        );
+    }
+
+    line(&mut out, 0, "");
+    line(&mut out, 0, &format!("#endif /* _DDSL_{}_HPP_ */", idlnamestem.to_uppercase()));
+    Ok(out)
+}
+
+fn write_constant(out: &mut String, level: usize, constant: &Constant, root: &Module) {
+    let value = literal(&constant.value, &constant.type_spec, root);
+    let declaration = match constant.type_spec.resolve(root) {
+        TypeSpec::String(_) | TypeSpec::WideString(_) => {
+            format!("inline const {} {} = {};", cpp_type(&constant.type_spec), constant.id, value)
+        }
+        // no C++ type holds the decimal value
+        TypeSpec::Fixed { .. } => format!("constexpr long double {} = {};", constant.id, value),
+        _ => format!("constexpr {} {} = {};", cpp_type(&constant.type_spec), constant.id, value),
+    };
+    line(out, level, &declaration);
+}
+
+fn write_type(out: &mut String, level: usize, decl: &TypeDecl, root: &Module) -> Result<(), IdlError> {
+    let id = decl.path.last().unwrap();
+    match decl.kind {
+        TypeKind::Typedef(ref spec) => line(out, level, &format!("using {} = {};", id, cpp_type(spec))),
+        TypeKind::Enum(ref enumerators) => {
+            line(out, level, &format!("enum class {}", id));
+            line(out, level, "{");
+            for (idx, enumerator) in enumerators.iter().enumerate() {
+                let separator = if idx + 1 < enumerators.len() { "," } else { "" };
+                line(out, level + 1, &format!("{}{}", enumerator, separator));
+            }
+            line(out, level, "};");
+        }
+        TypeKind::Bitmask(ref bitmask) => {
+            let holder = match bitmask.bit_bound {
+                0..=8 => "uint8_t",
+                9..=16 => "uint16_t",
+                17..=32 => "uint32_t",
+                _ => "uint64_t",
+            };
+            line(out, level, &format!("using {} = {};", id, holder));
+            for flag in bitmask.flags.iter() {
+                line(
+                    out,
+                    level,
+                    &format!("constexpr {} {} = static_cast<{}>(1ull << {});", id, flag.id, id, flag.position),
+                );
+            }
+        }
+        TypeKind::Bitset(ref fields) => {
+            line(out, level, &format!("struct {}", id));
+            line(out, level, "{");
+            for field in fields.iter() {
+                let name = field.id.as_ref().map(|id| format!(" {}", id)).unwrap_or_default();
+                line(
+                    out,
+                    level + 1,
+                    &format!("{}{} : {};", cpp_type(&field.type_spec), name, field.width),
+                );
+            }
+            line(out, level, "};");
+        }
+        TypeKind::Forward => line(out, level, &format!("class {};", id)),
+        TypeKind::Struct(ref s) => write_struct(out, level, id, s, root),
+        TypeKind::Union(ref u) => write_union(out, level, id, u, root),
+        TypeKind::Interface(_) => {
+            return Err(IdlError::ErrorMesg(format!(
+                "Interface {} can't be generated in C++",
+                decl.path.join("::")
+            )))
+        }
+    }
+    Ok(())
+}
+
+// the getters and setters of the language mapping for the member `id` held
+// by `field`, the setters take `param` after the value and run `set` with the
+// value in place of {}
+fn write_accessors(
+    out: &mut String,
+    level: usize,
+    id: &str,
+    spec: &TypeSpec,
+    field: &str,
+    (param, set): (&str, &str),
+    root: &Module,
+) {
+    let ty = cpp_type(spec);
+    if is_value_type(spec, root) {
+        line(out, level, &format!("{} {}() const {{ return {}; }}", ty, id, field));
+        line(out, level, &format!("{}& {}() {{ return {}; }}", ty, id, field));
+        let body = set.replace("{}", "_val_");
+        line(out, level, &format!("void {}({} _val_{}) {{ {} }}", id, ty, param, body));
+    } else {
+        line(out, level, &format!("const {}& {}() const {{ return {}; }}", ty, id, field));
+        line(out, level, &format!("{}& {}() {{ return {}; }}", ty, id, field));
+        let body = set.replace("{}", "_val_");
+        line(out, level, &format!("void {}(const {}& _val_{}) {{ {} }}", id, ty, param, body));
+        let body = set.replace("{}", "std::move(_val_)");
+        line(out, level, &format!("void {}({}&& _val_{}) {{ {} }}", id, ty, param, body));
+    }
+}
+
+fn write_struct(out: &mut String, level: usize, id: &str, s: &Struct, root: &Module) {
+    match s.base {
+        Some(ref base) => line(out, level, &format!("class {} : public ::{}", id, base.join("::"))),
+        None => line(out, level, &format!("class {}", id)),
+    }
+    line(out, level, "{");
+    if !s.members.is_empty() {
+        line(out, level, "private:");
+        for member in s.members.iter() {
+            line(out, level + 1, &format!("{} {}_{{}};", cpp_type(&member.type_spec), member.id));
+        }
+        line(out, 0, "");
+    }
+    line(out, level, "public:");
+    line(out, level + 1, &format!("{}() = default;", id));
+    if !s.members.is_empty() {
+        line(out, 0, "");
+        line(out, level + 1, &format!("explicit {}(", id));
+        for (idx, member) in s.members.iter().enumerate() {
+            let separator = if idx + 1 < s.members.len() { "," } else { ") :" };
+            let param = if is_value_type(&member.type_spec, root) {
+                format!("{} {}", cpp_type(&member.type_spec), member.id)
+            } else {
+                format!("const {}& {}", cpp_type(&member.type_spec), member.id)
+            };
+            line(out, level + 2, &format!("{}{}", param, separator));
+        }
+        for (idx, member) in s.members.iter().enumerate() {
+            let separator = if idx + 1 < s.members.len() { "," } else { " {}" };
+            line(out, level + 2, &format!("{}_({}){}", member.id, member.id, separator));
+        }
+    }
+    for member in s.members.iter() {
+        line(out, 0, "");
+        let field = format!("this->{}_", member.id);
+        let set = format!("{} = {{}};", field);
+        write_accessors(out, level + 1, &member.id, &member.type_spec, &field, ("", &set), root);
+    }
+
+    let mut equal: Vec<String> = Vec::new();
+    if let Some(ref base) = s.base {
+        equal.push(format!("::{}::operator==(_other)", base.join("::")));
+    }
+    equal.extend(s.members.iter().map(|m| format!("{}_ == _other.{}_", m.id, m.id)));
+    write_comparison(out, level + 1, id, &equal);
+    line(out, level, "};");
+}
+
+fn write_comparison(out: &mut String, level: usize, id: &str, equal: &[String]) {
+    line(out, 0, "");
+    line(out, level, &format!("bool operator==(const {}& _other) const", id));
+    line(out, level, "{");
+    if equal.is_empty() {
+        line(out, level + 1, "(void) _other;");
+        line(out, level + 1, "return true;");
+    } else {
+        for (idx, term) in equal.iter().enumerate() {
+            match (idx, idx + 1 < equal.len()) {
+                (0, true) => line(out, level + 1, &format!("return {} &&", term)),
+                (0, false) => line(out, level + 1, &format!("return {};", term)),
+                (_, true) => line(out, level + 2, &format!("{} &&", term)),
+                (_, false) => line(out, level + 2, &format!("{};", term)),
+            }
+        }
+    }
+    line(out, level, "}");
+    line(out, 0, "");
+    line(
+        out,
+        level,
+        &format!("bool operator!=(const {}& _other) const {{ return !(*this == _other); }}", id),
+    );
+}
+
+// the value of a label, as an integer
+fn label_ordinal(value: &ConstValue) -> Option<i64> {
+    match value {
+        ConstValue::Integer(n) => Some(*n),
+        ConstValue::Boolean(b) => Some(*b as i64),
+        ConstValue::Char(c) => Some(*c as i64),
+        ConstValue::Enumerator { ordinal, .. } => Some(*ordinal as i64),
+        _ => None,
+    }
+}
+
+// the discriminator selecting the default case, a value of no label
+fn default_discriminator(u: &Union, root: &Module) -> String {
+    let used: Vec<i64> = u
+        .cases
+        .iter()
+        .flat_map(|c| c.labels.iter())
+        .filter_map(|label| match label {
+            CaseLabel::Value(value) => label_ordinal(value),
+            CaseLabel::Default => None,
+        })
+        .collect();
+    let unused = (0..).find(|n| !used.contains(n)).unwrap();
+    match u.discriminator.resolve(root) {
+        TypeSpec::Boolean => (unused != 0).to_string(),
+        TypeSpec::Char => format!("'{}'", c_escape(&char::from(unused as u8).to_string())),
+        TypeSpec::Named(path) => match root.get_type(path).map(|t| &t.kind) {
+            Some(TypeKind::Enum(enumerators)) => match enumerators.get(unused as usize) {
+                Some(enumerator) => format!("::{}::{}", path.join("::"), enumerator),
+                None => format!("static_cast<::{}>({})", path.join("::"), unused),
+            },
+            _ => unused.to_string(),
+        },
+        _ => unused.to_string(),
+    }
+}
+
+// the discriminator selecting the case `idx`
+fn case_discriminator(u: &Union, idx: usize, root: &Module) -> String {
+    match u.cases[idx].labels.iter().find_map(|label| match label {
+        CaseLabel::Value(value) => Some(value),
+        CaseLabel::Default => None,
+    }) {
+        Some(value) if !u.cases[idx].labels.contains(&CaseLabel::Default) => {
+            literal(value, &u.discriminator, root)
+        }
+        _ => default_discriminator(u, root),
+    }
+}
+
+fn write_union(out: &mut String, level: usize, id: &str, u: &Union, root: &Module) {
+    let discriminator = cpp_type(&u.discriminator);
+    let branches = match u.cases.is_empty() {
+        true => String::from("std::monostate"),
+        false => u.cases.iter().map(|c| cpp_type(&c.type_spec)).collect::<Vec<_>>().join(", "),
+    };
+    line(out, level, &format!("class {}", id));
+    line(out, level, "{");
+    line(out, level, "private:");
+    line(out, level + 1, &format!("{} m__d;", discriminator));
+    line(out, level + 1, &format!("std::variant<{}> m__u;", branches));
+    line(out, 0, "");
+    line(out, level, "public:");
+    // the first branch, held by a default constructed variant
+    let initial = match u.cases.is_empty() {
+        true => default_discriminator(u, root),
+        false => case_discriminator(u, 0, root),
+    };
+    line(out, level + 1, &format!("{}() : m__d({}), m__u() {{}}", id, initial));
+    line(out, 0, "");
+    line(out, level + 1, &format!("{} _d() const {{ return this->m__d; }}", discriminator));
+    line(out, level + 1, &format!("void _d({} _val_) {{ this->m__d = _val_; }}", discriminator));
+    for (idx, case) in u.cases.iter().enumerate() {
+        line(out, 0, "");
+        let field = format!("std::get<{}>(this->m__u)", idx);
+        let param = format!(", {} _disc_ = {}", discriminator, case_discriminator(u, idx, root));
+        let set = format!("this->m__d = _disc_; this->m__u.emplace<{}>({{}});", idx);
+        write_accessors(out, level + 1, &case.id, &case.type_spec, &field, (&param, &set), root);
+    }
+    let equal = [String::from("m__d == _other.m__d"), String::from("m__u == _other.m__u")];
+    write_comparison(out, level + 1, id, &equal);
+    line(out, level, "};");
+}
+
+// the reason the values of `spec` can't be copied to and from C
+fn not_copyable(spec: &TypeSpec, root: &Module, visiting: &mut Vec<ScopedName>) -> Option<&'static str> {
+    match spec.resolve(root) {
+        TypeSpec::Fixed { .. } => Some("a fixed point value"),
+        TypeSpec::WideString(_) => Some("a wide string"),
+        TypeSpec::Map(_, _, _, _) => Some("a map"),
+        TypeSpec::Sequence(elem, _) | TypeSpec::Array(elem, _) => not_copyable(elem, root, visiting),
+        TypeSpec::Named(path) => {
+            if visiting.contains(path) {
+                return None;
+            }
+            visiting.push(path.clone());
+            let reason = match root.get_type(path).map(|t| &t.kind) {
+                Some(TypeKind::Struct(s)) => s
+                    .base
+                    .iter()
+                    .map(|base| TypeSpec::Named(base.clone()))
+                    .chain(s.members.iter().map(|m| m.type_spec.clone()))
+                    .find_map(|spec| not_copyable(&spec, root, visiting)),
+                Some(TypeKind::Enum(_)) | Some(TypeKind::Bitmask(_)) => None,
+                Some(TypeKind::Union(_)) => Some("a union"),
+                Some(TypeKind::Bitset(_)) => Some("a bitset"),
+                _ => Some("an undefined type"),
+            };
+            visiting.pop();
+            reason
+        }
+        _ => None,
+    }
+}
+
+// the structs of the main file copied to and from C by the overloads
+// idlc::copyIn and idlc::copyOut, every topic must be. The copies use the C
+// header of the file, which holds no unions.
+fn copied_structs<'a>(
+    items: &[Declaration<'a>],
+    topics: &[(&'a TypeDecl, &'a Struct)],
+    root: &Module,
+) -> Result<Vec<(&'a TypeDecl, &'a Struct)>, IdlError> {
+    let union = items.iter().find_map(|item| match item {
+        Declaration::Type(decl) if matches!(decl.kind, TypeKind::Union(_)) => Some(decl),
+        _ => None,
+    });
+    // the C names of the global declarations are their C++ names
+    let global = items.iter().find(|item| item.path().len() == 1);
+    for (decl, s) in topics.iter() {
+        if let Some(global) = global {
+            return Err(IdlError::ErrorMesg(format!(
+                "Topic {} can't be generated in C++, the C header of the file declares {} in the global namespace as well",
+                decl.path.join("::"),
+                global.path().join("::")
+            )));
+        }
+        if let Some(union) = union {
+            return Err(IdlError::ErrorMesg(format!(
+                "Topic {} can't be generated in C++, the C header of the file can't hold the union {}",
+                decl.path.join("::"),
+                union.path.join("::")
+            )));
+        }
+        for member in s.members.iter() {
+            if let Some(reason) = not_copyable(&member.type_spec, root, &mut vec![decl.path.clone()]) {
+                return Err(IdlError::ErrorMesg(format!(
+                    "Topic {} can't be generated in C++, member {} holds {}, which can't be copied to C",
+                    decl.path.join("::"),
+                    member.id,
+                    reason
+                )));
+            }
+        }
+    }
+    if union.is_some() {
+        return Ok(Vec::new());
+    }
+
+    Ok(items
+        .iter()
+        .filter_map(|item| match item {
+            Declaration::Type(decl) => match decl.kind {
+                TypeKind::Struct(ref s) => Some((*decl, s)),
+                _ => None,
+            },
+            Declaration::Constant(_) => None,
+        })
+        .filter(|(decl, _)| not_copyable(&TypeSpec::Named(decl.path.clone()), root, &mut Vec::new()).is_none())
+        .collect())
+}
+
+fn write_copies(out: &mut String, structs: &[(&TypeDecl, &Struct)], root: &Module) {
+    line(out, 0, "");
+    line(out, 0, "namespace idlc");
+    line(out, 0, "{");
+    line(out, 0, "");
+    for (decl, _) in structs.iter() {
+        let (cpp, c) = (format!("::{}", decl.path.join("::")), decl.path.join("_"));
+        line(out, 1, &format!("inline void copyIn(const {} &from, {} &to);", cpp, c));
+        line(out, 1, &format!("inline void copyOut(const {} &from, {} &to);", c, cpp));
+    }
+    for (decl, s) in structs.iter() {
+        let (cpp, c) = (format!("::{}", decl.path.join("::")), decl.path.join("_"));
+        line(out, 0, "");
+        line(out, 1, &format!("inline void copyIn(const {} &from, {} &to)", cpp, c));
+        line(out, 1, "{");
+        if s.base.is_none() && s.members.is_empty() {
+            line(out, 2, "(void) from;");
+            line(out, 2, "(void) to;");
+        }
+        if let Some(ref base) = s.base {
+            line(
+                out,
+                2,
+                &format!("copyIn(static_cast<const ::{} &>(from), to.{});", base.join("::"), STRUCT_BASE_MEMBER),
+            );
+        }
+        for member in s.members.iter() {
+            let (from, to) = (format!("from.{}()", member.id), format!("to.{}", member.id));
+            copy_in(out, 2, &member.type_spec, &from, &to, root, 0);
+        }
+        line(out, 1, "}");
+        line(out, 0, "");
+        line(out, 1, &format!("inline void copyOut(const {} &from, {} &to)", c, cpp));
+        line(out, 1, "{");
+        if s.base.is_none() && s.members.is_empty() {
+            line(out, 2, "(void) from;");
+            line(out, 2, "(void) to;");
+        }
+        if let Some(ref base) = s.base {
+            line(
+                out,
+                2,
+                &format!("copyOut(from.{}, static_cast<::{} &>(to));", STRUCT_BASE_MEMBER, base.join("::")),
+            );
+        }
+        for member in s.members.iter() {
+            let (from, to) = (format!("from.{}", member.id), format!("to.{}()", member.id));
+            copy_out(out, 2, &member.type_spec, &from, &to, root, 0);
+        }
+        line(out, 1, "}");
+    }
+    // the copies of the topic traits, untyped as the binding calls them
+    line(out, 0, "");
+    line(out, 1, "template <typename T, typename C> void topicCopyIn(const void *from, void *to)");
+    line(out, 1, "{");
+    line(out, 2, "copyIn(*static_cast<const T *>(from), *static_cast<C *>(to));");
+    line(out, 1, "}");
+    line(out, 0, "");
+    line(out, 1, "template <typename C, typename T> void topicCopyOut(const void *from, void *to)");
+    line(out, 1, "{");
+    line(out, 2, "copyOut(*static_cast<const C *>(from), *static_cast<T *>(to));");
+    line(out, 1, "}");
+    line(out, 0, "");
+    line(out, 0, "} // namespace idlc");
+}
+
+// the innermost array of `dims` as its own type, for the loop over the
+// outermost dimension
+fn inner_array(elem: &TypeSpec, dims: &[u64]) -> TypeSpec {
+    match dims.len() {
+        1 => elem.clone(),
+        _ => TypeSpec::Array(Box::new(elem.clone()), dims[1..].to_vec()),
+    }
+}
+
+// the C type of the elements of a sequence of `elem`, and the pointer to them
+fn c_elem_types(elem: &TypeSpec) -> (String, String) {
+    match elem {
+        TypeSpec::String(Some(bound)) => (format!("char[{}]", bound + 1), format!("char (*)[{}]", bound + 1)),
+        _ => (c_type_name(elem), format!("{} *", c_type_name(elem))),
+    }
+}
+
+// copy the C++ value `from` to the C value `to`
+fn copy_in(out: &mut String, level: usize, spec: &TypeSpec, from: &str, to: &str, root: &Module, depth: usize) {
+    match spec.resolve(root) {
+        TypeSpec::String(None) => line(out, level, &format!("{} = dds_string_dup({}.c_str());", to, from)),
+        // truncated to the bound, the char array holds the terminating nul
+        TypeSpec::String(Some(bound)) => line(out, level, &format!("{}[{}.copy({}, {})] = '\\0';", to, from, to, bound)),
+        TypeSpec::Array(elem, dims) => {
+            line(out, level, &format!("for (size_t i{d} = 0; i{d} < {}; i{d}++) {{", dims[0], d = depth));
+            let (from, to) = (format!("{}[i{}]", from, depth), format!("{}[i{}]", to, depth));
+            copy_in(out, level + 1, &inner_array(elem, dims), &from, &to, root, depth + 1);
+            line(out, level, "}");
+        }
+        TypeSpec::Sequence(elem, _) => {
+            let (c_elem, c_ptr) = c_elem_types(elem);
+            line(out, level, &format!("{to}._length = {to}._maximum = static_cast<uint32_t>({}.size());", from, to = to));
+            line(out, level, &format!("{}._release = true;", to));
+            line(
+                out,
+                level,
+                &format!(
+                    "{to}._buffer = {to}._maximum ? static_cast<uint8_t *>(dds_alloc(sizeof({}) * {to}._maximum)) : nullptr;",
+                    c_elem,
+                    to = to
+                ),
+            );
+            line(out, level, &format!("for (uint32_t i{d} = 0; i{d} < {}._length; i{d}++) {{", to, d = depth));
+            let (from, to) = (
+                format!("{}[i{}]", from, depth),
+                format!("reinterpret_cast<{}>({}._buffer)[i{}]", c_ptr, to, depth),
+            );
+            copy_in(out, level + 1, elem, &from, &to, root, depth + 1);
+            line(out, level, "}");
+        }
+        TypeSpec::Named(path) => match root.get_type(path).map(|t| &t.kind) {
+            Some(TypeKind::Enum(_)) => line(out, level, &format!("{} = static_cast<{}>({});", to, path.join("_"), from)),
+            Some(TypeKind::Struct(_)) => line(out, level, &format!("copyIn({}, {});", from, to)),
+            _ => line(out, level, &format!("{} = {};", to, from)),
+        },
+        _ => line(out, level, &format!("{} = {};", to, from)),
+    }
+}
+
+// copy the C value `from` to the C++ value `to`
+fn copy_out(out: &mut String, level: usize, spec: &TypeSpec, from: &str, to: &str, root: &Module, depth: usize) {
+    match spec.resolve(root) {
+        TypeSpec::String(None) => line(out, level, &format!("{} = {from} ? {from} : \"\";", to, from = from)),
+        TypeSpec::String(Some(_)) => line(out, level, &format!("{} = {};", to, from)),
+        TypeSpec::Array(elem, dims) => {
+            line(out, level, &format!("for (size_t i{d} = 0; i{d} < {}; i{d}++) {{", dims[0], d = depth));
+            let (from, to) = (format!("{}[i{}]", from, depth), format!("{}[i{}]", to, depth));
+            copy_out(out, level + 1, &inner_array(elem, dims), &from, &to, root, depth + 1);
+            line(out, level, "}");
+        }
+        TypeSpec::Sequence(elem, _) => {
+            line(out, level, &format!("{}.resize({}._length);", to, from));
+            line(out, level, &format!("for (uint32_t i{d} = 0; i{d} < {}._length; i{d}++) {{", from, d = depth));
+            let (from, to) = (
+                format!("reinterpret_cast<const {}>({}._buffer)[i{}]", c_elem_types(elem).1, from, depth),
+                format!("{}[i{}]", to, depth),
+            );
+            copy_out(out, level + 1, elem, &from, &to, root, depth + 1);
+            line(out, level, "}");
+        }
+        TypeSpec::Named(path) => match root.get_type(path).map(|t| &t.kind) {
+            Some(TypeKind::Enum(_)) => {
+                line(out, level, &format!("{} = static_cast<::{}>({});", to, path.join("::"), from))
+            }
+            Some(TypeKind::Struct(_)) => line(out, level, &format!("copyOut({}, {});", from, to)),
+            _ => line(out, level, &format!("{} = {};", to, from)),
+        },
+        _ => line(out, level, &format!("{} = {};", to, from)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CppBackend;
+    use crate::backend::Backend;
+    use crate::fixture::{has_tool, run, temp_dir, write_file, DDS_PUBLIC_IMPL_H};
+    use crate::{parse_ir, Backends, Configuration, IdlSource, MemoryLoader};
+    use std::process::Command;
+
+    // Stub of the Cyclone C++ binding header, with the TopicTraits and
+    // REGISTER_TOPIC_TYPE of the 0.7 binding
+    const DDS_HPP: &str = "#include <cstdlib>
+#include <cstring>
+typedef struct dds_topic_descriptor dds_topic_descriptor_t;
+inline void *dds_alloc(size_t size) { return calloc(1, size); }
+inline char *dds_string_dup(const char *str) { return strdup(str); }
+namespace org { namespace eclipse { namespace cyclonedds { namespace topic {
+typedef void (*copyInFunction)(const void *from, void *to);
+typedef void (*copyOutFunction)(const void *from, void *to);
+template <class TOPIC> struct TopicTraits
+{
+    static bool isKeyless() { return false; }
+    static const char *getTypeName() { return \"\"; }
+    static const dds_topic_descriptor_t *getDescriptor() { return NULL; }
+    static copyInFunction getCopyIn() { return NULL; }
+    static copyOutFunction getCopyOut() { return NULL; }
+    static size_t getSampleSize() { return 0; }
+};
+} } } }
+namespace dds { namespace topic {
+template <typename T> struct is_topic_type { enum { value = 0 }; };
+} }
+#define REGISTER_TOPIC_TYPE(TOPIC_TYPE) \\
+    namespace dds { namespace topic { \\
+    template <> struct is_topic_type<TOPIC_TYPE> { enum { value = 1 }; }; \\
+    } }
+";
+
+    fn hpp(idl: &str) -> Result<String, String> {
+        let config = Configuration {
+            idl_name: std::path::PathBuf::from("shapes.idl"),
+            ..Configuration::default()
+        };
+        let spec = parse_ir(&mut MemoryLoader::new(), &config, idl).unwrap();
+        CppBackend
+            .generate(&spec, &config)
+            .map(|mut outputs| outputs.pop().unwrap().contents)
+            .map_err(|e| format!("{:?}", e))
+    }
+
+    #[test]
+    fn classes() {
+        let out = hpp(r#"
+            module M {
+                module N {
+                    enum Color { RED, GREEN };
+                    struct Inner { Color color; };
+                };
+                struct Shape {
+                    N::Inner inner;
+                    string name;
+                    long pos[2][3];
+                    sequence<string> tags;
+                };
+                #pragma keylist Shape name
+            };
+        "#)
+        .unwrap();
+        let lines: Vec<&str> = out.lines().map(|l| l.trim()).collect();
+        let position = |text: &str| lines.iter().position(|l| *l == text).expect(text);
+
+        // the IR lists the types of M before its modules
+        assert!(position("namespace N") < position("class Shape"));
+        assert!(lines.contains(&"::M::N::Color color() const { return this->color_; }"));
+        assert!(lines.contains(&"std::array<std::array<int32_t, 3>, 2> pos_{};"));
+        assert!(lines.contains(&"void name(std::string&& _val_) { this->name_ = std::move(_val_); }"));
+        assert!(lines.contains(&"#include \"shapes.h\""));
+
+        assert!(lines.contains(&"to.color = static_cast<M_N_Color>(from.color());"));
+        assert!(lines.contains(&"copyIn(from.inner(), to.inner);"));
+        assert!(lines.contains(&"reinterpret_cast<char * *>(to.tags._buffer)[i0] = dds_string_dup(from.tags()[i0].c_str());"));
+        assert!(lines.contains(&"to.name() = from.name ? from.name : \"\";"));
+        assert!(lines.contains(&"struct TopicTraits<::M::Shape>"));
+        assert!(lines.contains(&"static bool isKeyless() { return false; }"));
+        assert!(lines.contains(&"static copyInFunction getCopyIn() { return &::idlc::topicCopyIn<::M::Shape, M_Shape>; }"));
+        assert!(lines.contains(&"static const dds_topic_descriptor_t *getDescriptor() { return &M_Shape_desc; }"));
+        assert!(lines.contains(&"REGISTER_TOPIC_TYPE(::M::Shape)"));
+    }
+
+    #[test]
+    fn unions() {
+        let out = hpp(r#"
+            module M {
+                enum Color { RED, GREEN, BLUE };
+                union U switch (Color) {
+                    case RED: long r;
+                    default: string s;
+                };
+                const string NAME = "u\"1";
+            };
+        "#)
+        .unwrap();
+        let lines: Vec<&str> = out.lines().map(|l| l.trim()).collect();
+
+        assert!(lines.contains(&"std::variant<int32_t, std::string> m__u;"));
+        assert!(lines.contains(&"U() : m__d(::M::Color::RED), m__u() {}"));
+        // the default case is selected by a value of no label
+        assert!(lines.contains(
+            &"void s(const std::string& _val_, ::M::Color _disc_ = ::M::Color::GREEN) { this->m__d = _disc_; this->m__u.emplace<1>(_val_); }"
+        ));
+        assert!(lines.contains(&"inline const std::string NAME = \"u\\\"1\";"));
+        // no C header, no copies
+        assert!(!out.contains("namespace idlc"));
+        assert!(!out.contains("#include \"shapes.h\""));
+
+        assert_eq!(
+            hpp("module M { union U switch (long) { case 1: long a; }; struct S { long a; }; \n#pragma keylist S a\n};"),
+            Err(String::from(
+                "ErrorMesg(\"Topic M::S can't be generated in C++, the C header of the file can't hold the union M::U\")"
+            ))
+        );
+        assert_eq!(
+            hpp("const long N = 1; module M { struct S { long a; }; \n#pragma keylist S a\n};"),
+            Err(String::from(
+                "ErrorMesg(\"Topic M::S can't be generated in C++, the C header of the file declares N in the global namespace as well\")"
+            ))
+        );
+        assert_eq!(
+            hpp("module M { struct S { map<long, long> m; long a; }; \n#pragma keylist S a\n};"),
+            Err(String::from(
+                "ErrorMesg(\"Topic M::S can't be generated in C++, member m holds a map, which can't be copied to C\")"
+            ))
+        );
+    }
+
+    #[test]
+    fn compiles() {
+        if !has_tool("g++") {
+            return;
+        }
+        let idl = "module M {
+            const long LEN = 2;
+            const string NAME = \"n\";
+            const double RATIO = 1.5;
+            const boolean ON = TRUE;
+            module N {
+                enum Color { RED, GREEN };
+                struct Inner { Color color; };
+            };
+            const N::Color DEFAULT_COLOR = N::GREEN;
+            struct Base { long id; };
+            struct Shape : Base {
+                N::Inner inner;
+                string name;
+                string<8> label;
+                long pos[LEN][3];
+                sequence<string> tags;
+                sequence<N::Inner, 4> inners;
+                double d;
+                boolean b;
+                char c;
+            };
+            #pragma keylist Shape id
+        };";
+        let unions = "module V {
+            enum Kind { A, B };
+            union U switch (Kind) { case A: long a; default: string s; };
+            struct Holder { U u; sequence<U> us; };
+        };";
+        let config = Configuration {
+            generate_descriptors: true,
+            ..Configuration::default()
+        };
+        let dir = temp_dir("cpp");
+        write_file(&dir, "dds/ddsc/dds_public_impl.h", DDS_PUBLIC_IMPL_H);
+        write_file(&dir, "dds/dds.hpp", DDS_HPP);
+        // the C header can't hold the unions, they have no topic
+        for (name, idl, backends) in [("shapes.idl", idl, &["c", "cpp"][..]), ("unions.idl", unions, &["cpp"][..])] {
+            let sources = [IdlSource::Memory(std::path::PathBuf::from(name), String::from(idl))];
+            let generated = Backends::new().generate_files(backends, &mut MemoryLoader::new(), &config, &sources);
+            assert!(!generated.has_errors(), "{:?}", generated.diagnostics);
+            for output in generated.outputs.iter() {
+                write_file(&dir, &output.name, &output.contents);
+            }
+        }
+        write_file(
+            &dir,
+            "main.cpp",
+            "#include \"shapes.hpp\"
+             #include \"unions.hpp\"
+             // as the binding uses the traits of a topic
+             template <typename T> bool roundTrip(const T &sample, T &copy)
+             {
+                 using Traits = org::eclipse::cyclonedds::topic::TopicTraits<T>;
+                 static_assert(dds::topic::is_topic_type<T>::value, \"not a topic\");
+                 void *c_sample = dds_alloc(Traits::getSampleSize());
+                 Traits::getCopyIn()(&sample, c_sample);
+                 Traits::getCopyOut()(c_sample, &copy);
+                 free(c_sample);
+                 return Traits::getDescriptor() != NULL && !Traits::isKeyless() && Traits::getTypeName()[0] != 0;
+             }
+             int main()
+             {
+                 M::Shape shape;
+                 shape.name(\"shape\");
+                 shape.tags({ \"a\", \"b\" });
+                 M_Shape c_shape;
+                 idlc::copyIn(shape, c_shape);
+                 M::Shape copy;
+                 idlc::copyOut(c_shape, copy);
+                 if (!roundTrip(shape, copy))
+                     return 2;
+                 V::U u;
+                 u.s(\"text\");
+                 return copy.name() == M::NAME ? 1 : 0;
+             }",
+        );
+        run(
+            &dir,
+            Command::new("g++").args(["-std=c++17", "-Wall", "-Werror", "-I.", "-fsyntax-only", "main.cpp"]),
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,168 @@
+// Copyright (C) 2020  Sojan James
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0>
+
+/* Helpers shared by the tests.
+
+ The generate functions return the main output of an IDL text. The compile
+ checks build the generated code with the C, C++ and Rust compilers of the
+ host, they are skipped when a compiler is missing.
+*/
+
+use crate::{generate_with_loader, Configuration, IdlError, IdlLoader, Loader};
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The main output of `idl` with `config`, the Rust file or the C output
+/// selected by generate_c
+pub fn generate(config: &Configuration, idl: &str) -> String {
+    generate_with(&mut Loader::new(Vec::new()), config, idl)
+}
+
+/// The main output of `idl` including the files of `loader`
+pub fn generate_with<L: IdlLoader>(loader: &mut L, config: &Configuration, idl: &str) -> String {
+    let mut out = Vec::new();
+    generate_with_loader(&mut out, loader, config, idl).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+/// The message of the error failing the generation of `idl`
+pub fn generate_error(config: &Configuration, idl: &str) -> String {
+    let mut out = Vec::new();
+    match generate_with_loader(&mut out, &mut Loader::new(Vec::new()), config, idl) {
+        Err(IdlError::ErrorMesg(mesg)) | Err(IdlError::IncompleteType(mesg)) => mesg,
+        result => panic!("Unexpected result {:?} for {}", result, idl),
+    }
+}
+
+/// The configuration generating the C descriptors of test.idl
+pub fn c_config() -> Configuration {
+    Configuration {
+        generate_c: true,
+        generate_descriptors: true,
+        idl_name: PathBuf::from("test.idl"),
+        ..Default::default()
+    }
+}
+
+/// An empty directory of the test `name`
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("idlc-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Write `contents` to `name` in `dir`, creating the parent directories
+pub fn write_file(dir: &Path, name: &str, contents: &str) {
+    let path = dir.join(name);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, contents).unwrap();
+}
+
+/// Whether `tool` can be run, the compile checks are skipped otherwise
+pub fn has_tool(tool: &str) -> bool {
+    let available = Command::new(tool).arg("--version").output().is_ok();
+    if !available {
+        eprintln!("{} not found, skipping the compile check", tool);
+    }
+    available
+}
+
+/// Run `command` in `dir`, failing the test with its output on errors
+pub fn run(dir: &Path, command: &mut Command) {
+    let output = command.current_dir(dir).output().unwrap();
+    assert!(
+        output.status.success(),
+        "{:?} failed\n{}{}",
+        command,
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+/// Stub of the Cyclone DDS header included by the generated C headers, it
+/// declares what the type declarations use
+pub const DDS_PUBLIC_IMPL_H: &str = "#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+typedef struct dds_sequence
+{
+  uint32_t _maximum;
+  uint32_t _length;
+  uint8_t *_buffer;
+  bool _release;
+} dds_sequence_t;
+typedef struct dds_topic_descriptor dds_topic_descriptor_t;
+";
+
+/// Stub of the runtime crate for the generated Rust, with the items the
+/// descriptors use and the Cyclone DDS 0.7 values of the opcodes
+pub const RUNTIME_STUB: &str = "
+#[allow(non_camel_case_types, non_upper_case_globals, dead_code)]
+pub mod sys {
+    pub trait DDSGenType {
+        unsafe fn get_descriptor() -> &'static dds_topic_descriptor;
+    }
+    #[repr(C)]
+    pub struct dds_key_descriptor {
+        pub m_name: *const ::std::os::raw::c_char,
+        pub m_index: u32,
+    }
+    #[repr(C)]
+    pub struct dds_topic_descriptor {
+        pub m_size: u32,
+        pub m_align: u32,
+        pub m_flagset: u32,
+        pub m_nkeys: u32,
+        pub m_typename: *const ::std::os::raw::c_char,
+        pub m_keys: *const dds_key_descriptor,
+        pub m_nops: u32,
+        pub m_ops: *const u32,
+        pub m_meta: *const ::std::os::raw::c_char,
+    }
+    #[repr(C)]
+    #[derive(Debug, Copy, Clone)]
+    pub struct dds_sequence_t {
+        pub _maximum: u32,
+        pub _length: u32,
+        pub _buffer: *mut u8,
+        pub _release: bool,
+    }
+    pub const DDS_TOPIC_NO_OPTIMIZE: u32 = 0x0001;
+    pub const DDS_TOPIC_FIXED_KEY: u32 = 0x0002;
+    pub const DDS_TOPIC_CONTAINS_UNION: u32 = 0x0004;
+    pub const DDS_OP_RTS: u32 = 0x00 << 24;
+    pub const DDS_OP_ADR: u32 = 0x01 << 24;
+    pub const DDS_OP_JSR: u32 = 0x02 << 24;
+    pub const DDS_OP_JEQ: u32 = 0x03 << 24;
+    pub const DDS_OP_TYPE_1BY: u32 = 0x01 << 16;
+    pub const DDS_OP_TYPE_2BY: u32 = 0x02 << 16;
+    pub const DDS_OP_TYPE_4BY: u32 = 0x03 << 16;
+    pub const DDS_OP_TYPE_8BY: u32 = 0x04 << 16;
+    pub const DDS_OP_TYPE_STR: u32 = 0x05 << 16;
+    pub const DDS_OP_TYPE_BST: u32 = 0x06 << 16;
+    pub const DDS_OP_TYPE_SEQ: u32 = 0x07 << 16;
+    pub const DDS_OP_TYPE_ARR: u32 = 0x08 << 16;
+    pub const DDS_OP_TYPE_UNI: u32 = 0x09 << 16;
+    pub const DDS_OP_TYPE_STU: u32 = 0x0a << 16;
+    pub const DDS_OP_TYPE_BOO: u32 = DDS_OP_TYPE_1BY;
+    pub const DDS_OP_SUBTYPE_1BY: u32 = 0x01 << 8;
+    pub const DDS_OP_SUBTYPE_2BY: u32 = 0x02 << 8;
+    pub const DDS_OP_SUBTYPE_4BY: u32 = 0x03 << 8;
+    pub const DDS_OP_SUBTYPE_8BY: u32 = 0x04 << 8;
+    pub const DDS_OP_SUBTYPE_STR: u32 = 0x05 << 8;
+    pub const DDS_OP_SUBTYPE_BST: u32 = 0x06 << 8;
+    pub const DDS_OP_SUBTYPE_SEQ: u32 = 0x07 << 8;
+    pub const DDS_OP_SUBTYPE_ARR: u32 = 0x08 << 8;
+    pub const DDS_OP_SUBTYPE_UNI: u32 = 0x09 << 8;
+    pub const DDS_OP_SUBTYPE_STU: u32 = 0x0a << 8;
+    pub const DDS_OP_SUBTYPE_BOO: u32 = DDS_OP_SUBTYPE_1BY;
+    pub const DDS_OP_FLAG_KEY: u32 = 0x01;
+    pub const DDS_OP_FLAG_DEF: u32 = 0x02;
+    pub const DDS_OP_FLAG_FP: u32 = 0x02;
+    pub const DDS_OP_FLAG_SGN: u32 = 0x04;
+}
+";
@@ -0,0 +1,398 @@
+// Copyright (C) 2020  Sojan James
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0>
+
+/* The IR as a JSON document, for tools outside Rust.
+
+ The document is versioned, JSON_VERSION changes when a name is removed or
+ changes its meaning, names added to the objects leave it as is:
+
+    { "version": 1, "included": [..], "diagnostics": [..], "root": <module> }
+
+ module         name (null for the root), scoped_name, types, modules, constants
+ type           name, scoped_name, kind, source, annotations, and by kind
+                  typedef     type
+                  struct      base, members, topic (null or the topic)
+                  union       discriminator, cases
+                  enum        enumerators (name, ordinal)
+                  bitmask     bit_bound, flags (name, position)
+                  bitset      fields (name, null for padding, width, type)
+                  interface   operations (name, return_type, params, raises)
+ member         name, type, key, source, annotations
+ case           name, type, labels (values), default
+ topic          keys, key_size (null if unbounded), flags (the flagset of the
+                descriptor, e.g. DDS_TOPIC_FIXED_KEY)
+ key            path (the member names), type
+ annotation     name, params (name, null for the single value of @name(value),
+                value, null for names the annotation defines, and text, the
+                value in IDL syntax)
+ constant       name, scoped_name, type, value, source
+ source         file (the included file as written, null for the main file), line
+
+ Scoped names are absolute and written A::B. A type spec is an object of its
+ kind, one of boolean, octet, char, wchar, int8, int16, int32, int64, uint8,
+ uint16, uint32, uint64, float32, float64, long_double, fixed (digits,
+ scale), string and wstring (bound), sequence (element, bound), array
+ (element, dims), map (key, value, bound, entry, the struct of the entries
+ or null) and named (name); bound is null if unbounded. A value is an object of its kind, integer, float, boolean, char
+ and string with its value, fixed with the decimal value as a string and
+ enumerator with enumeration, name and ordinal.
+
+ The annotations of structs, unions, enums and members are listed in their
+ objects. @key and #pragma keylist are also the key flag of members and the
+ keys of topics, @bit_bound and @position bit_bound and the positions of
+ bitmask flags.
+*/
+
+use super::*;
+
+/// The version of the JSON document
+pub const JSON_VERSION: u32 = 1;
+
+enum Json {
+    Null,
+    Bool(bool),
+    Integer(i128),
+    Float(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(&'static str, Json)>),
+}
+
+impl Specification {
+    /// The JSON document of the model, see JSON_VERSION
+    pub fn to_json(&self) -> String {
+        let diagnostics = self.diagnostics.iter().map(|diagnostic| {
+            let (severity, message) = match diagnostic {
+                Diagnostic::Error(message) => ("error", message),
+                Diagnostic::Warning(message) => ("warning", message),
+            };
+            Json::Object(vec![("severity", string(severity)), ("message", string(message))])
+        });
+        let document = Json::Object(vec![
+            ("version", Json::Integer(JSON_VERSION as i128)),
+            ("included", Json::Array(self.included.iter().map(|f| string(f)).collect())),
+            ("diagnostics", Json::Array(diagnostics.collect())),
+            ("root", module(&self.root)),
+        ]);
+        let mut out = String::new();
+        document.write(&mut out, 0);
+        out.push('\n');
+        out
+    }
+}
+
+impl Json {
+    fn write(&self, out: &mut String, level: usize) {
+        let indent = |out: &mut String, level: usize| out.push_str(&" ".repeat(2 * level));
+        match self {
+            Json::Null => out.push_str("null"),
+            Json::Bool(b) => out.push_str(&b.to_string()),
+            Json::Integer(n) => out.push_str(&n.to_string()),
+            Json::Float(f) if f.is_finite() => out.push_str(&format!("{:?}", f)),
+            // JSON has no infinities
+            Json::Float(_) => out.push_str("null"),
+            Json::String(s) => escape(out, s),
+            Json::Array(items) if items.is_empty() => out.push_str("[]"),
+            Json::Array(items) => {
+                out.push_str("[\n");
+                for (i, item) in items.iter().enumerate() {
+                    indent(out, level + 1);
+                    item.write(out, level + 1);
+                    out.push_str(if i + 1 < items.len() { ",\n" } else { "\n" });
+                }
+                indent(out, level);
+                out.push(']');
+            }
+            Json::Object(fields) => {
+                out.push_str("{\n");
+                for (i, (name, value)) in fields.iter().enumerate() {
+                    indent(out, level + 1);
+                    escape(out, name);
+                    out.push_str(": ");
+                    value.write(out, level + 1);
+                    out.push_str(if i + 1 < fields.len() { ",\n" } else { "\n" });
+                }
+                indent(out, level);
+                out.push('}');
+            }
+        }
+    }
+}
+
+fn escape(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn string(s: &str) -> Json {
+    Json::String(String::from(s))
+}
+
+fn scoped_name(path: &[String]) -> Json {
+    Json::String(path.join("::"))
+}
+
+fn list<T, F: Fn(&T) -> Json>(items: &[T], f: F) -> Json {
+    Json::Array(items.iter().map(f).collect())
+}
+
+fn source(source: &Option<String>, line: usize) -> Json {
+    Json::Object(vec![
+        ("file", source.as_deref().map(string).unwrap_or(Json::Null)),
+        ("line", Json::Integer(line as i128)),
+    ])
+}
+
+fn module(module: &Module) -> Json {
+    Json::Object(vec![
+        ("name", module.id.as_deref().map(string).unwrap_or(Json::Null)),
+        ("scoped_name", scoped_name(&module.path)),
+        ("types", list(&module.types, type_decl)),
+        ("modules", list(&module.modules, self::module)),
+        ("constants", list(&module.constants, constant)),
+    ])
+}
+
+fn constant(constant: &Constant) -> Json {
+    Json::Object(vec![
+        ("name", string(&constant.id)),
+        ("scoped_name", scoped_name(&constant.path)),
+        ("type", type_spec(&constant.type_spec)),
+        ("value", value(&constant.value)),
+        ("source", source(&constant.source, constant.line)),
+    ])
+}
+
+fn named(id: &str, spec: &TypeSpec) -> Vec<(&'static str, Json)> {
+    vec![("name", string(id)), ("type", type_spec(spec))]
+}
+
+fn type_decl(decl: &TypeDecl) -> Json {
+    let kind = match decl.kind {
+        TypeKind::Typedef(_) => "typedef",
+        TypeKind::Struct(_) => "struct",
+        TypeKind::Union(_) => "union",
+        TypeKind::Enum(_) => "enum",
+        TypeKind::Bitmask(_) => "bitmask",
+        TypeKind::Bitset(_) => "bitset",
+        TypeKind::Forward => "forward",
+        TypeKind::Interface(_) => "interface",
+    };
+    let mut fields = vec![
+        ("name", string(&decl.id)),
+        ("scoped_name", scoped_name(&decl.path)),
+        ("kind", string(kind)),
+        ("source", source(&decl.source, decl.line)),
+        ("annotations", list(&decl.annotations, annotation)),
+    ];
+    match decl.kind {
+        TypeKind::Typedef(ref spec) => fields.push(("type", type_spec(spec))),
+        TypeKind::Struct(ref s) => {
+            fields.push(("base", s.base.as_deref().map(scoped_name).unwrap_or(Json::Null)));
+            fields.push((
+                "members",
+                list(&s.members, |member| {
+                    let mut fields = named(&member.id, &member.type_spec);
+                    fields.push(("key", Json::Bool(member.is_key)));
+                    fields.push(("source", source(&decl.source, member.line)));
+                    fields.push(("annotations", list(&member.annotations, annotation)));
+                    Json::Object(fields)
+                }),
+            ));
+            let topic = s.topic.as_ref().map(|topic| {
+                Json::Object(vec![
+                    (
+                        "keys",
+                        list(&topic.keys, |key| {
+                            Json::Object(vec![
+                                ("path", list(&key.path, |id| string(id))),
+                                ("type", type_spec(&key.type_spec)),
+                            ])
+                        }),
+                    ),
+                    (
+                        "key_size",
+                        topic.key_size.map(|size| Json::Integer(size as i128)).unwrap_or(Json::Null),
+                    ),
+                    ("flags", list(&topic.flags, |flag| string(flag))),
+                ])
+            });
+            fields.push(("topic", topic.unwrap_or(Json::Null)));
+        }
+        TypeKind::Union(ref u) => {
+            fields.push(("discriminator", type_spec(&u.discriminator)));
+            fields.push((
+                "cases",
+                list(&u.cases, |case| {
+                    let mut fields = named(&case.id, &case.type_spec);
+                    let labels = case.labels.iter().filter_map(|label| match label {
+                        CaseLabel::Value(v) => Some(value(v)),
+                        CaseLabel::Default => None,
+                    });
+                    fields.push(("labels", Json::Array(labels.collect())));
+                    fields.push(("default", Json::Bool(case.labels.contains(&CaseLabel::Default))));
+                    Json::Object(fields)
+                }),
+            ));
+        }
+        TypeKind::Enum(ref enumerators) => {
+            let enumerators = enumerators.iter().enumerate().map(|(ordinal, id)| {
+                Json::Object(vec![("name", string(id)), ("ordinal", Json::Integer(ordinal as i128))])
+            });
+            fields.push(("enumerators", Json::Array(enumerators.collect())));
+        }
+        TypeKind::Bitmask(ref bitmask) => {
+            fields.push(("bit_bound", Json::Integer(bitmask.bit_bound as i128)));
+            fields.push((
+                "flags",
+                list(&bitmask.flags, |flag| {
+                    Json::Object(vec![
+                        ("name", string(&flag.id)),
+                        ("position", Json::Integer(flag.position as i128)),
+                    ])
+                }),
+            ));
+        }
+        TypeKind::Bitset(ref bitfields) => fields.push((
+            "fields",
+            list(bitfields, |field| {
+                Json::Object(vec![
+                    ("name", field.id.as_deref().map(string).unwrap_or(Json::Null)),
+                    ("width", Json::Integer(field.width as i128)),
+                    ("type", type_spec(&field.type_spec)),
+                ])
+            }),
+        )),
+        TypeKind::Interface(ref operations) => fields.push((
+            "operations",
+            list(operations, |op| {
+                Json::Object(vec![
+                    ("name", string(&op.id)),
+                    ("return_type", op.return_type.as_ref().map(type_spec).unwrap_or(Json::Null)),
+                    (
+                        "params",
+                        list(&op.params, |param| {
+                            let mut fields = named(&param.id, &param.type_spec);
+                            let direction = match param.direction {
+                                Direction::In => "in",
+                                Direction::Out => "out",
+                                Direction::InOut => "inout",
+                            };
+                            fields.push(("direction", string(direction)));
+                            Json::Object(fields)
+                        }),
+                    ),
+                    ("raises", list(&op.raises, |name| scoped_name(name))),
+                ])
+            }),
+        )),
+        TypeKind::Forward => {}
+    }
+    Json::Object(fields)
+}
+
+fn annotation(annotation: &Annotation) -> Json {
+    Json::Object(vec![
+        ("name", string(&annotation.name)),
+        (
+            "params",
+            list(&annotation.params, |param| {
+                Json::Object(vec![
+                    ("name", param.name.as_deref().map(string).unwrap_or(Json::Null)),
+                    ("value", param.value.as_ref().map(value).unwrap_or(Json::Null)),
+                    ("text", string(&param.text)),
+                ])
+            }),
+        ),
+    ])
+}
+
+fn bound(bound: &Option<u64>) -> Json {
+    bound.map(|b| Json::Integer(b as i128)).unwrap_or(Json::Null)
+}
+
+fn type_spec(spec: &TypeSpec) -> Json {
+    let kind = |kind: &str, mut fields: Vec<(&'static str, Json)>| {
+        fields.insert(0, ("kind", string(kind)));
+        Json::Object(fields)
+    };
+    match spec {
+        TypeSpec::Boolean => kind("boolean", vec![]),
+        TypeSpec::Octet => kind("octet", vec![]),
+        TypeSpec::Char => kind("char", vec![]),
+        TypeSpec::WideChar => kind("wchar", vec![]),
+        TypeSpec::Int8 => kind("int8", vec![]),
+        TypeSpec::Int16 => kind("int16", vec![]),
+        TypeSpec::Int32 => kind("int32", vec![]),
+        TypeSpec::Int64 => kind("int64", vec![]),
+        TypeSpec::UInt8 => kind("uint8", vec![]),
+        TypeSpec::UInt16 => kind("uint16", vec![]),
+        TypeSpec::UInt32 => kind("uint32", vec![]),
+        TypeSpec::UInt64 => kind("uint64", vec![]),
+        TypeSpec::Float32 => kind("float32", vec![]),
+        TypeSpec::Float64 => kind("float64", vec![]),
+        TypeSpec::LongDouble => kind("long_double", vec![]),
+        TypeSpec::Fixed { digits, scale } => kind(
+            "fixed",
+            vec![
+                ("digits", Json::Integer(*digits as i128)),
+                ("scale", Json::Integer(*scale as i128)),
+            ],
+        ),
+        TypeSpec::String(b) => kind("string", vec![("bound", bound(b))]),
+        TypeSpec::WideString(b) => kind("wstring", vec![("bound", bound(b))]),
+        TypeSpec::Sequence(elem, b) => kind("sequence", vec![("element", type_spec(elem)), ("bound", bound(b))]),
+        TypeSpec::Array(elem, dims) => kind(
+            "array",
+            vec![
+                ("element", type_spec(elem)),
+                ("dims", list(dims, |d| Json::Integer(*d as i128))),
+            ],
+        ),
+        TypeSpec::Map(key, value, b, entry) => kind(
+            "map",
+            vec![
+                ("key", type_spec(key)),
+                ("value", type_spec(value)),
+                ("bound", bound(b)),
+                ("entry", entry.as_deref().map(scoped_name).unwrap_or(Json::Null)),
+            ],
+        ),
+        TypeSpec::Named(name) => kind("named", vec![("name", scoped_name(name))]),
+    }
+}
+
+fn value(value: &ConstValue) -> Json {
+    let kind = |kind: &str, value: Json| Json::Object(vec![("kind", string(kind)), ("value", value)]);
+    match value {
+        ConstValue::Integer(n) => kind("integer", Json::Integer(*n as i128)),
+        ConstValue::Float(f) => kind("float", Json::Float(*f)),
+        ConstValue::Fixed { scaled, scale } => kind("fixed", Json::String(fixed_decimal(*scaled, *scale))),
+        ConstValue::Boolean(b) => kind("boolean", Json::Bool(*b)),
+        ConstValue::Char(c) => kind("char", Json::String(c.to_string())),
+        ConstValue::String(s) => kind("string", string(s)),
+        ConstValue::Enumerator {
+            enumeration,
+            id,
+            ordinal,
+        } => Json::Object(vec![
+            ("kind", string("enumerator")),
+            ("enumeration", scoped_name(enumeration)),
+            ("name", string(id)),
+            ("ordinal", Json::Integer(*ordinal as i128)),
+        ]),
+    }
+}
@@ -0,0 +1,1154 @@
+// Copyright (C) 2020  Sojan James
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0>
+
+/* The resolved model of an IDL file, for generators and checks outside this
+ crate.
+
+ The IR is built once the file and its includes are parsed: scoped names are
+ absolute, constants, bounds and union labels are folded to their values and
+ the keys of topics are resolved to member paths. @key and #pragma keylist are
+ Member::is_key and Struct::topic, @bit_bound and @position the bit positions
+ of bitmasks. The annotations of structs, unions, enums and struct members are
+ kept in TypeDecl::annotations and Member::annotations, with the values of
+ their literal parameters.
+
+ The declarations of included files are part of the model, `source` names the
+ file declaring them. Interfaces and maps add the structs of their C
+ representation, see cdds::rpc.
+
+    let spec = cyclonedds_idlc::parse_ir(&mut loader, &config, &idl)?;
+    checker.visit_module(&spec.root);
+*/
+
+pub mod json;
+pub mod visit;
+
+use crate::ast::{
+    IdlAnnotation, IdlModule, IdlParamAttribute, IdlStructMember, IdlSwitchLabel, IdlTypeDclKind,
+    IdlTypeSpec, IdlValueExpr,
+};
+use crate::cdds::descriptor::topic_flags;
+use crate::cdds::key_hash::get_key_size;
+use crate::cdds::{struct_members, Model};
+use crate::{Diagnostic, IdlError, WideCharSize};
+
+/// The names of a path, outermost first
+pub type ScopedName = Vec<String>;
+
+/// A parsed IDL file and the files it includes
+#[derive(Debug, Clone)]
+pub struct Specification {
+    /// The unnamed root module
+    pub root: Module,
+    /// The resolved paths of the included files
+    pub included: Vec<String>,
+    pub diagnostics: Vec<Diagnostic>,
+    // the resolved paths and names as written of the files included by the
+    // main file, for the built-in backends
+    pub(crate) main_includes: Vec<(String, String)>,
+}
+
+impl Specification {
+    /// Whether a definition had errors, it is missing from the model
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(|d| matches!(d, Diagnostic::Error(_)))
+    }
+}
+
+/// An IDL type
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeSpec {
+    Boolean,
+    Octet,
+    Char,
+    WideChar,
+    Int8,
+    Int16,
+    Int32,
+    Int64,
+    UInt8,
+    UInt16,
+    UInt32,
+    UInt64,
+    Float32,
+    Float64,
+    LongDouble,
+    Fixed { digits: u32, scale: u32 },
+    /// A string, with its bound if bounded
+    String(Option<u64>),
+    WideString(Option<u64>),
+    Sequence(Box<TypeSpec>, Option<u64>),
+    /// An array and its dimensions, outermost first
+    Array(Box<TypeSpec>, Vec<u64>),
+    /// A map of keys to values, and the struct of its entries if the map is
+    /// a struct member or typedef, its C representation is a sequence of them
+    Map(Box<TypeSpec>, Box<TypeSpec>, Option<u64>, Option<ScopedName>),
+    /// A declared type, see Module::get_type
+    Named(ScopedName),
+}
+
+impl TypeSpec {
+    /// The type behind typedefs, `root` is the root module
+    pub fn resolve<'a>(&'a self, root: &'a Module) -> &'a TypeSpec {
+        match self {
+            TypeSpec::Named(name) => match root.get_type(name).map(|t| &t.kind) {
+                Some(TypeKind::Typedef(spec)) => spec.resolve(root),
+                _ => self,
+            },
+            _ => self,
+        }
+    }
+}
+
+/// The value of a constant or a union label
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConstValue {
+    Integer(i64),
+    Float(f64),
+    /// A fixed point value, `scaled` / 10^`scale`
+    Fixed { scaled: i128, scale: u32 },
+    Boolean(bool),
+    Char(char),
+    String(String),
+    /// The enumerator `id` of the enum `enumeration`, at position `ordinal`
+    Enumerator {
+        enumeration: ScopedName,
+        id: String,
+        ordinal: u32,
+    },
+}
+
+/// The decimal notation of the fixed point value `scaled` / 10^`scale`
+pub fn fixed_decimal(scaled: i128, scale: u32) -> String {
+    let digits = format!("{:0width$}", scaled.unsigned_abs(), width = scale as usize + 1);
+    let (integral, fraction) = digits.split_at(digits.len() - scale as usize);
+    let sign = if scaled < 0 { "-" } else { "" };
+    match fraction {
+        "" => format!("{}{}", sign, integral),
+        _ => format!("{}{}.{}", sign, integral, fraction),
+    }
+}
+
+/// A module, the root module has no name
+#[derive(Debug, Clone)]
+pub struct Module {
+    pub id: Option<String>,
+    pub path: ScopedName,
+    /// The type declarations, in declaration order
+    pub types: Vec<TypeDecl>,
+    pub modules: Vec<Module>,
+    pub constants: Vec<Constant>,
+}
+
+/// A type declaration
+#[derive(Debug, Clone)]
+pub struct TypeDecl {
+    pub id: String,
+    /// The absolute name of the type
+    pub path: ScopedName,
+    pub kind: TypeKind,
+    /// The included file declaring the type as written, None for the main file
+    pub source: Option<String>,
+    /// The line of the declaration in its file
+    pub line: usize,
+    /// The annotations of structs, unions and enums
+    pub annotations: Vec<Annotation>,
+}
+
+/// An annotation as applied, e.g. @range(min = 1, max = 5)
+#[derive(Debug, Clone)]
+pub struct Annotation {
+    /// The name as written, without the @
+    pub name: String,
+    pub params: Vec<AnnotationParam>,
+}
+
+/// A parameter of an annotation, the single value of @name(value) has no name
+#[derive(Debug, Clone)]
+pub struct AnnotationParam {
+    pub name: Option<String>,
+    /// The value of literals and constants, None for names the annotation
+    /// defines, e.g. APPENDABLE
+    pub value: Option<ConstValue>,
+    /// The parameter in IDL syntax
+    pub text: String,
+}
+
+/// The kinds of type declarations
+#[derive(Debug, Clone)]
+pub enum TypeKind {
+    Typedef(TypeSpec),
+    Struct(Struct),
+    Union(Union),
+    /// The enumerators in order
+    Enum(Vec<String>),
+    Bitmask(Bitmask),
+    Bitset(Vec<BitField>),
+    /// A struct or union declared ahead of its definition
+    Forward,
+    /// An interface, served by the DDS-RPC request and reply topics
+    Interface(Vec<Operation>),
+}
+
+/// A struct, a topic if `topic` is set
+#[derive(Debug, Clone)]
+pub struct Struct {
+    pub base: Option<ScopedName>,
+    /// The members, without those of the base
+    pub members: Vec<Member>,
+    pub topic: Option<Topic>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Member {
+    pub id: String,
+    pub type_spec: TypeSpec,
+    pub is_key: bool,
+    pub annotations: Vec<Annotation>,
+    /// The line of the declaration in the file of its struct
+    pub line: usize,
+}
+
+/// The keys of a topic, an empty list for keyless topics
+#[derive(Debug, Clone)]
+pub struct Topic {
+    pub keys: Vec<Key>,
+    /// The maximum size of the serialized key, None if it is unbounded
+    pub key_size: Option<u32>,
+    /// The flagset of the topic descriptor, e.g. DDS_TOPIC_FIXED_KEY
+    pub flags: Vec<String>,
+}
+
+/// A key field of a topic
+#[derive(Debug, Clone)]
+pub struct Key {
+    /// The member path from the topic to the key field
+    pub path: Vec<String>,
+    pub type_spec: TypeSpec,
+}
+
+#[derive(Debug, Clone)]
+pub struct Union {
+    pub discriminator: TypeSpec,
+    pub cases: Vec<UnionCase>,
+}
+
+#[derive(Debug, Clone)]
+pub struct UnionCase {
+    pub labels: Vec<CaseLabel>,
+    pub id: String,
+    pub type_spec: TypeSpec,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CaseLabel {
+    Value(ConstValue),
+    Default,
+}
+
+#[derive(Debug, Clone)]
+pub struct Bitmask {
+    pub bit_bound: u32,
+    pub flags: Vec<BitFlag>,
+}
+
+/// A flag of a bitmask and its bit position
+#[derive(Debug, Clone)]
+pub struct BitFlag {
+    pub id: String,
+    pub position: u32,
+}
+
+/// A field of a bitset, anonymous fields are padding
+#[derive(Debug, Clone)]
+pub struct BitField {
+    pub id: Option<String>,
+    pub width: u32,
+    pub type_spec: TypeSpec,
+}
+
+#[derive(Debug, Clone)]
+pub struct Operation {
+    pub id: String,
+    /// None for void operations
+    pub return_type: Option<TypeSpec>,
+    pub params: Vec<Parameter>,
+    /// The exceptions raised, structs
+    pub raises: Vec<ScopedName>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    In,
+    Out,
+    InOut,
+}
+
+#[derive(Debug, Clone)]
+pub struct Parameter {
+    pub id: String,
+    pub direction: Direction,
+    pub type_spec: TypeSpec,
+}
+
+#[derive(Debug, Clone)]
+pub struct Constant {
+    pub id: String,
+    /// The absolute name of the constant
+    pub path: ScopedName,
+    pub type_spec: TypeSpec,
+    pub value: ConstValue,
+    /// The included file declaring the constant as written, None for the main file
+    pub source: Option<String>,
+    /// The line of the declaration in its file
+    pub line: usize,
+}
+
+impl Module {
+    /// The module `path`, relative to this module
+    pub fn get_module(&self, path: &[String]) -> Option<&Module> {
+        match path.split_first() {
+            Some((id, rest)) => self
+                .modules
+                .iter()
+                .find(|m| m.id.as_deref() == Some(id.as_str()))
+                .and_then(|m| m.get_module(rest)),
+            None => Some(self),
+        }
+    }
+
+    /// The type declaration `name`, relative to this module
+    pub fn get_type(&self, name: &[String]) -> Option<&TypeDecl> {
+        let (id, path) = name.split_last()?;
+        self.get_module(path)?.types.iter().find(|t| &t.id == id)
+    }
+
+    /// The constant `name`, relative to this module
+    pub fn get_constant(&self, name: &[String]) -> Option<&Constant> {
+        let (id, path) = name.split_last()?;
+        self.get_module(path)?.constants.iter().find(|c| &c.id == id)
+    }
+
+    /// The declarations of the main file, after the declarations they use.
+    /// C and C++ need complete types where the IR lists the types of a
+    /// module before its nested modules.
+    pub fn main_file_declarations(&self) -> Vec<Declaration<'_>> {
+        let mut items = Vec::new();
+        main_file_items(self, &mut items);
+        ordered(&items)
+    }
+
+    /// Build the IR of the parsed `root` module, the declarations that can't
+    /// be resolved are left out with an error. `wchar_size` is the size of
+    /// the wide chars of the generated types, the key sizes of the topics
+    /// depend on it.
+    pub(crate) fn from_ast(root: &IdlModule, wchar_size: WideCharSize, diagnostics: &mut Vec<Diagnostic>) -> Module {
+        let mut module = build_module(root, Vec::new(), root, diagnostics);
+        let mut sizes = Vec::new();
+        topic_sizes(&module, Model { root: &module, wchar_size }, &mut sizes);
+        set_topic_sizes(&mut module, &sizes);
+        module
+    }
+}
+
+/// A type or constant declaration
+#[derive(Clone, Copy)]
+pub enum Declaration<'a> {
+    Type(&'a TypeDecl),
+    Constant(&'a Constant),
+}
+
+impl<'a> Declaration<'a> {
+    /// The absolute name of the declaration
+    pub fn path(&self) -> &'a ScopedName {
+        match self {
+            Declaration::Type(decl) => &decl.path,
+            Declaration::Constant(constant) => &constant.path,
+        }
+    }
+
+    // the declared types used by the declaration
+    fn dependencies(&self, names: &mut Vec<ScopedName>) {
+        match self {
+            Declaration::Constant(constant) => {
+                named_types(&constant.type_spec, names);
+                if let ConstValue::Enumerator { ref enumeration, .. } = constant.value {
+                    names.push(enumeration.clone());
+                }
+            }
+            Declaration::Type(decl) => match decl.kind {
+                TypeKind::Typedef(ref spec) => named_types(spec, names),
+                TypeKind::Struct(ref s) => {
+                    names.extend(s.base.iter().cloned());
+                    s.members.iter().for_each(|m| named_types(&m.type_spec, names));
+                }
+                TypeKind::Union(ref u) => {
+                    named_types(&u.discriminator, names);
+                    u.cases.iter().for_each(|c| named_types(&c.type_spec, names));
+                }
+                TypeKind::Bitset(ref fields) => fields.iter().for_each(|f| named_types(&f.type_spec, names)),
+                TypeKind::Enum(_) | TypeKind::Bitmask(_) | TypeKind::Forward | TypeKind::Interface(_) => {}
+            },
+        }
+    }
+}
+
+fn named_types(spec: &TypeSpec, names: &mut Vec<ScopedName>) {
+    match spec {
+        TypeSpec::Named(path) => names.push(path.clone()),
+        TypeSpec::Sequence(elem, _) | TypeSpec::Array(elem, _) => named_types(elem, names),
+        TypeSpec::Map(key, value, _, _) => {
+            named_types(key, names);
+            named_types(value, names);
+        }
+        _ => {}
+    }
+}
+
+// the declarations of the main file in `module`, in declaration order
+fn main_file_items<'a>(module: &'a Module, items: &mut Vec<Declaration<'a>>) {
+    items.extend(module.types.iter().filter(|t| t.source.is_none()).map(Declaration::Type));
+    items.extend(module.constants.iter().filter(|c| c.source.is_none()).map(Declaration::Constant));
+    for module in module.modules.iter() {
+        main_file_items(module, items);
+    }
+}
+
+// the declarations after the declarations they use, the IR lists the types
+// of a module before its nested modules
+fn ordered<'a>(items: &[Declaration<'a>]) -> Vec<Declaration<'a>> {
+    fn visit<'a>(idx: usize, items: &[Declaration<'a>], visited: &mut Vec<bool>, ordered: &mut Vec<Declaration<'a>>) {
+        if visited[idx] {
+            return;
+        }
+        visited[idx] = true;
+        let mut names = Vec::new();
+        items[idx].dependencies(&mut names);
+        for name in names.iter() {
+            for dep in 0..items.len() {
+                if items[dep].path() == name {
+                    visit(dep, items, visited, ordered);
+                }
+            }
+        }
+        ordered.push(items[idx]);
+    }
+
+    let mut visited = vec![false; items.len()];
+    let mut ordered = Vec::new();
+    for idx in 0..items.len() {
+        visit(idx, items, &mut visited, &mut ordered);
+    }
+    ordered
+}
+
+fn build_module(
+    module: &IdlModule,
+    path: ScopedName,
+    root: &IdlModule,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Module {
+    let scoped = |id: &str| {
+        let mut name = path.clone();
+        name.push(String::from(id));
+        name
+    };
+    let source = |id: &str| module.sources.get(id).cloned();
+    let line = |id: &str| module.lines.get(id).cloned().unwrap_or_default();
+    let left_out = |name: &str, e: IdlError| {
+        Diagnostic::Error(format!("{} is left out of the model: {:?}", scoped(name).join("::"), e))
+    };
+
+    let mut types = Vec::new();
+    for (name, typ) in module.types.iter() {
+        match build_type_kind(&typ.0, module, root) {
+            Ok(kind) => types.push(TypeDecl {
+                id: String::from(typ.0.id()),
+                path: scoped(name),
+                kind,
+                source: source(name),
+                line: line(name),
+                annotations: module
+                    .annotations
+                    .get(name)
+                    .map(|annotations| build_annotations(annotations, root))
+                    .unwrap_or_default(),
+            }),
+            Err(e) => diagnostics.push(left_out(name, e)),
+        }
+    }
+    let mut modules = Vec::new();
+    for (name, submodule) in module.modules.iter() {
+        modules.push(build_module(submodule, scoped(name), root, diagnostics));
+    }
+    let mut constants = Vec::new();
+    for (name, cnst) in module.constants.iter() {
+        let value = fold(&cnst.value, &cnst.typedcl, root)
+            .ok_or_else(|| IdlError::ErrorMesg(String::from("Unable to evaluate the value")));
+        match value.and_then(|value| Ok((value, build_type_spec(&cnst.typedcl, root)?))) {
+            Ok((value, type_spec)) => constants.push(Constant {
+                id: cnst.id.clone(),
+                path: scoped(name),
+                type_spec,
+                value,
+                source: source(name),
+                line: line(name),
+            }),
+            Err(e) => diagnostics.push(left_out(name, e)),
+        }
+    }
+    Module {
+        id: module.id.clone(),
+        path,
+        types,
+        modules,
+        constants,
+    }
+}
+
+fn build_type_kind(
+    kind: &IdlTypeDclKind,
+    module: &IdlModule,
+    root: &IdlModule,
+) -> Result<TypeKind, IdlError> {
+    match kind {
+        IdlTypeDclKind::TypeDcl(_, spec) => Ok(TypeKind::Typedef(build_type_spec(spec, root)?)),
+        IdlTypeDclKind::StructDcl(id, members, is_topic) => {
+            let base = match members.first().filter(|m| m.is_base).map(|m| m.type_spec.as_ref()) {
+                Some(IdlTypeSpec::ScopedName(name)) => Some(name.0.clone()),
+                _ => None,
+            };
+            let topic = match is_topic {
+                true => Some(build_topic(id, members, module.get_keys(id), root)?),
+                false => None,
+            };
+            Ok(TypeKind::Struct(Struct {
+                base,
+                members: members
+                    .iter()
+                    .filter(|m| !m.is_base)
+                    .map(|m| {
+                        Ok(Member {
+                            id: m.id.clone(),
+                            type_spec: build_type_spec(&m.type_spec, root)?,
+                            is_key: m.is_key,
+                            annotations: build_annotations(&m.annotations, root),
+                            line: m.line,
+                        })
+                    })
+                    .collect::<Result<_, IdlError>>()?,
+                topic,
+            }))
+        }
+        IdlTypeDclKind::UnionDcl(id, switch_type, switch_cases) => {
+            let mut cases = Vec::new();
+            for case in switch_cases {
+                let mut labels = Vec::new();
+                for label in case.labels.iter() {
+                    labels.push(match label {
+                        IdlSwitchLabel::Label(expr) => CaseLabel::Value(
+                            fold(expr, switch_type, root).ok_or_else(|| {
+                                IdlError::ErrorMesg(format!(
+                                    "Unable to evaluate a label of {} in union {}",
+                                    case.elem_spec.id, id
+                                ))
+                            })?,
+                        ),
+                        IdlSwitchLabel::Default => CaseLabel::Default,
+                    });
+                }
+                cases.push(UnionCase {
+                    labels,
+                    id: case.elem_spec.id.clone(),
+                    type_spec: build_type_spec(&case.elem_spec.type_spec, root)?,
+                });
+            }
+            Ok(TypeKind::Union(Union {
+                discriminator: build_type_spec(switch_type, root)?,
+                cases,
+            }))
+        }
+        IdlTypeDclKind::EnumDcl(_, enumerators) => Ok(TypeKind::Enum(enumerators.clone())),
+        IdlTypeDclKind::BitmaskDcl(_, bit_bound, values) => Ok(TypeKind::Bitmask(Bitmask {
+            bit_bound: *bit_bound,
+            flags: values
+                .iter()
+                .map(|v| BitFlag {
+                    id: v.id.clone(),
+                    position: v.position,
+                })
+                .collect(),
+        })),
+        IdlTypeDclKind::BitsetDcl(_, fields) => Ok(TypeKind::Bitset(
+            fields
+                .iter()
+                .map(|f| {
+                    Ok(BitField {
+                        id: f.id.clone(),
+                        width: f.width,
+                        type_spec: build_type_spec(&f.type_spec, root)?,
+                    })
+                })
+                .collect::<Result<_, IdlError>>()?,
+        )),
+        IdlTypeDclKind::ForwardDcl(_) => Ok(TypeKind::Forward),
+        IdlTypeDclKind::InterfaceDcl(_, operations) => {
+            let mut ops = Vec::new();
+            for op in operations {
+                let return_type = match op.return_type {
+                    Some(ref spec) => Some(build_type_spec(spec, root)?),
+                    None => None,
+                };
+                let mut params = Vec::new();
+                for param in op.params.iter() {
+                    params.push(Parameter {
+                        id: param.id.clone(),
+                        direction: match param.attribute {
+                            IdlParamAttribute::In => Direction::In,
+                            IdlParamAttribute::Out => Direction::Out,
+                            IdlParamAttribute::InOut => Direction::InOut,
+                        },
+                        type_spec: build_type_spec(&param.type_spec, root)?,
+                    });
+                }
+                ops.push(Operation {
+                    id: op.id.clone(),
+                    return_type,
+                    params,
+                    raises: op.raises.iter().map(|name| name.0.clone()).collect(),
+                });
+            }
+            Ok(TypeKind::Interface(ops))
+        }
+        IdlTypeDclKind::None => Err(IdlError::InternalError),
+    }
+}
+
+fn build_topic(
+    id: &str,
+    members: &[Box<IdlStructMember>],
+    keys: Option<&Vec<String>>,
+    root: &IdlModule,
+) -> Result<Topic, IdlError> {
+    let keys = keys.map(Vec::as_slice).unwrap_or_default();
+    Ok(Topic {
+        keys: keys
+            .iter()
+            .map(|key| {
+                let (path, spec) = resolve_key(members, key, root)
+                    .ok_or_else(|| IdlError::ErrorMesg(format!("Key {} not found in {}", key, id)))?;
+                Ok(Key {
+                    path,
+                    type_spec: build_type_spec(spec, root)?,
+                })
+            })
+            .collect::<Result<_, IdlError>>()?,
+        // the size of a wide char is known with the model, see topic_sizes
+        key_size: None,
+        flags: Vec::new(),
+    })
+}
+
+// The member path and the type of a (possibly dotted) key of a struct with
+// `members`, e.g. "header.id"
+fn resolve_key<'a>(
+    members: &'a [Box<IdlStructMember>],
+    key: &str,
+    root: &'a IdlModule,
+) -> Option<(Vec<String>, &'a IdlTypeSpec)> {
+    let mut members = members;
+    let mut path = Vec::new();
+    let mut components = key.split('.').peekable();
+    while let Some(component) = components.next() {
+        let member = members.iter().find(|m| m.id == component)?;
+        path.push(member.id.clone());
+        if components.peek().is_none() {
+            return Some((path, &member.type_spec));
+        }
+        members = get_struct_members(&member.type_spec, root)?;
+    }
+    None
+}
+
+// Members of the struct type referenced by `spec`, following typedefs
+fn get_struct_members<'a>(spec: &'a IdlTypeSpec, root: &'a IdlModule) -> Option<&'a [Box<IdlStructMember>]> {
+    match spec {
+        IdlTypeSpec::ScopedName(name) => match root.get_type_decl(name).map(|t| &t.0) {
+            Some(IdlTypeDclKind::StructDcl(_, members, _)) => Some(members),
+            Some(IdlTypeDclKind::TypeDcl(_, typespec)) => get_struct_members(typespec, root),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// The key size and the flags of the topics of `module`, by the absolute name
+// of the topic
+fn topic_sizes(module: &Module, model: Model, sizes: &mut Vec<(ScopedName, Option<u32>, Vec<String>)>) {
+    for decl in module.types.iter() {
+        if let TypeKind::Struct(ref s) = decl.kind {
+            if let Some(topic) = s.topic.as_ref() {
+                let key_size = Some(get_key_size(&topic.keys, model))
+                    .filter(|size| *size >= 0)
+                    .map(|size| size as u32);
+                let flags = topic_flags(&struct_members(s), &topic.keys, model);
+                sizes.push((decl.path.clone(), key_size, flags.into_iter().map(String::from).collect()));
+            }
+        }
+    }
+    for module in module.modules.iter() {
+        topic_sizes(module, model, sizes);
+    }
+}
+
+fn set_topic_sizes(module: &mut Module, sizes: &[(ScopedName, Option<u32>, Vec<String>)]) {
+    for decl in module.types.iter_mut() {
+        let size = sizes.iter().find(|(path, _, _)| *path == decl.path);
+        if let (TypeKind::Struct(Struct { topic: Some(ref mut topic), .. }), Some((_, key_size, flags))) =
+            (&mut decl.kind, size)
+        {
+            topic.key_size = *key_size;
+            topic.flags = flags.clone();
+        }
+    }
+    for module in module.modules.iter_mut() {
+        set_topic_sizes(module, sizes);
+    }
+}
+
+fn build_annotations(annotations: &[IdlAnnotation], root: &IdlModule) -> Vec<Annotation> {
+    annotations
+        .iter()
+        .map(|annotation| Annotation {
+            name: annotation.name.clone(),
+            params: annotation
+                .params
+                .iter()
+                .map(|(name, expr)| {
+                    let mut text = Vec::new();
+                    let _ = expr.write(&mut text);
+                    AnnotationParam {
+                        name: name.clone(),
+                        value: fold_literal(expr, root),
+                        text: String::from_utf8_lossy(&text).into_owned(),
+                    }
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+fn build_type_spec(spec: &IdlTypeSpec, root: &IdlModule) -> Result<TypeSpec, IdlError> {
+    let bound = |bound: &Option<Box<IdlValueExpr>>| match bound {
+        Some(expr) => fold_bound(expr, root).map(Some),
+        None => Ok(None),
+    };
+    Ok(match spec {
+        IdlTypeSpec::BooleanType => TypeSpec::Boolean,
+        IdlTypeSpec::OctetType => TypeSpec::Octet,
+        IdlTypeSpec::CharType => TypeSpec::Char,
+        IdlTypeSpec::WideCharType => TypeSpec::WideChar,
+        IdlTypeSpec::I8Type => TypeSpec::Int8,
+        IdlTypeSpec::I16Type => TypeSpec::Int16,
+        IdlTypeSpec::I32Type => TypeSpec::Int32,
+        IdlTypeSpec::I64Type => TypeSpec::Int64,
+        IdlTypeSpec::U8Type => TypeSpec::UInt8,
+        IdlTypeSpec::U16Type => TypeSpec::UInt16,
+        IdlTypeSpec::U32Type => TypeSpec::UInt32,
+        IdlTypeSpec::U64Type => TypeSpec::UInt64,
+        IdlTypeSpec::F32Type => TypeSpec::Float32,
+        IdlTypeSpec::F64Type => TypeSpec::Float64,
+        IdlTypeSpec::F128Type => TypeSpec::LongDouble,
+        IdlTypeSpec::FixedPtType(digits, scale) => TypeSpec::Fixed {
+            digits: *digits,
+            scale: *scale,
+        },
+        IdlTypeSpec::StringType(bnd) => TypeSpec::String(bound(bnd)?),
+        IdlTypeSpec::WideStringType(bnd) => TypeSpec::WideString(bound(bnd)?),
+        IdlTypeSpec::SequenceType(elem, bnd) => {
+            TypeSpec::Sequence(Box::new(build_type_spec(elem, root)?), bound(bnd)?)
+        }
+        IdlTypeSpec::ArrayType(elem, dims) => TypeSpec::Array(
+            Box::new(build_type_spec(elem, root)?),
+            dims.iter()
+                .map(|dim| fold_bound(dim, root))
+                .collect::<Result<_, IdlError>>()?,
+        ),
+        IdlTypeSpec::MapType(key, value, bnd, entry) => TypeSpec::Map(
+            Box::new(build_type_spec(key, root)?),
+            Box::new(build_type_spec(value, root)?),
+            bound(bnd)?,
+            entry.as_ref().map(|name| name.0.clone()),
+        ),
+        IdlTypeSpec::ScopedName(name) => TypeSpec::Named(name.0.clone()),
+        IdlTypeSpec::None => return Err(IdlError::InternalError),
+    })
+}
+
+// A bound or an array dimension
+fn fold_bound(expr: &IdlValueExpr, root: &IdlModule) -> Result<u64, IdlError> {
+    match expr.eval_integer(root) {
+        Some(value) if value >= 0 => Ok(value as u64),
+        _ => {
+            let mut text = Vec::new();
+            let _ = expr.write(&mut text);
+            Err(IdlError::ErrorMesg(format!(
+                "Unable to evaluate the bound {}",
+                String::from_utf8_lossy(&text)
+            )))
+        }
+    }
+}
+
+// The type behind typedefs
+fn underlying<'a>(spec: &'a IdlTypeSpec, root: &'a IdlModule) -> &'a IdlTypeSpec {
+    match spec {
+        IdlTypeSpec::ScopedName(name) => match root.get_type_decl(name).map(|t| &t.0) {
+            Some(IdlTypeDclKind::TypeDcl(_, typespec)) => underlying(typespec, root),
+            _ => spec,
+        },
+        _ => spec,
+    }
+}
+
+// The value of `expr` as a value of the type `spec`
+fn fold(expr: &IdlValueExpr, spec: &IdlTypeSpec, root: &IdlModule) -> Option<ConstValue> {
+    match underlying(spec, root) {
+        IdlTypeSpec::F32Type | IdlTypeSpec::F64Type | IdlTypeSpec::F128Type => {
+            expr.eval_float(root).map(ConstValue::Float)
+        }
+        IdlTypeSpec::FixedPtType(_, _) => expr.eval_fixed(root).map(|v| ConstValue::Fixed {
+            scaled: v.scaled,
+            scale: v.scale,
+        }),
+        IdlTypeSpec::BooleanType => expr.eval_integer(root).map(|v| ConstValue::Boolean(v != 0)),
+        IdlTypeSpec::CharType | IdlTypeSpec::WideCharType => literal(expr, root)
+            .and_then(|text| text.chars().next())
+            .map(ConstValue::Char),
+        IdlTypeSpec::StringType(_) | IdlTypeSpec::WideStringType(_) => {
+            literal(expr, root).map(ConstValue::String)
+        }
+        IdlTypeSpec::ScopedName(enumeration) => match root.get_type_decl(enumeration).map(|t| &t.0) {
+            Some(IdlTypeDclKind::EnumDcl(_, enumerators)) => {
+                let name = match expr {
+                    IdlValueExpr::ScopedName(name) => name,
+                    _ => return None,
+                };
+                if let Some(cnst) = root.get_const_decl(name) {
+                    return fold(&cnst.value, spec, root);
+                }
+                let id = name.0.last()?;
+                let ordinal = enumerators.iter().position(|e| e == id)?;
+                Some(ConstValue::Enumerator {
+                    enumeration: enumeration.0.clone(),
+                    id: id.clone(),
+                    ordinal: ordinal as u32,
+                })
+            }
+            _ => None,
+        },
+        _ => expr.eval_integer(root).map(ConstValue::Integer),
+    }
+}
+
+// The value of `expr` without a type, the type of its literals or constants
+fn fold_literal(expr: &IdlValueExpr, root: &IdlModule) -> Option<ConstValue> {
+    match expr {
+        IdlValueExpr::CharLiteral(_) | IdlValueExpr::WideCharLiteral(_) => literal(expr, root)
+            .and_then(|text| text.chars().next())
+            .map(ConstValue::Char),
+        IdlValueExpr::StringLiteral(_) | IdlValueExpr::WideStringLiteral(_) => {
+            literal(expr, root).map(ConstValue::String)
+        }
+        IdlValueExpr::BooleanLiteral(value) => Some(ConstValue::Boolean(*value)),
+        IdlValueExpr::Brace(expr) => fold_literal(expr, root),
+        IdlValueExpr::ScopedName(name) => root
+            .get_const_decl(name)
+            .and_then(|cnst| fold(&cnst.value, &cnst.typedcl, root)),
+        _ => expr
+            .eval_integer(root)
+            .map(ConstValue::Integer)
+            .or_else(|| expr.eval_float(root).map(ConstValue::Float)),
+    }
+}
+
+// The text of a character or string literal, following constant names
+fn literal(expr: &IdlValueExpr, root: &IdlModule) -> Option<String> {
+    match expr {
+        IdlValueExpr::CharLiteral(text)
+        | IdlValueExpr::WideCharLiteral(text)
+        | IdlValueExpr::StringLiteral(text)
+        | IdlValueExpr::WideStringLiteral(text) => Some(unescape(text)),
+        IdlValueExpr::Brace(expr) => literal(expr, root),
+        IdlValueExpr::ScopedName(name) => root
+            .get_const_decl(name)
+            .and_then(|c| literal(&c.value, root)),
+        _ => None,
+    }
+}
+
+// The characters of a literal as written, e.g. L"a\tb"
+fn unescape(literal: &str) -> String {
+    let quoted = literal.trim_start_matches('L');
+    let text = quoted.get(1..quoted.len().saturating_sub(1)).unwrap_or_default();
+    let mut value = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+        let escaped = match chars.next() {
+            Some('n') => Some('\n'),
+            Some('t') => Some('\t'),
+            Some('v') => Some('\u{b}'),
+            Some('b') => Some('\u{8}'),
+            Some('r') => Some('\r'),
+            Some('f') => Some('\u{c}'),
+            Some('a') => Some('\u{7}'),
+            Some('x') => escaped_number(&mut chars, 16, String::new(), 2),
+            Some('u') => escaped_number(&mut chars, 16, String::new(), 4),
+            Some(c) if c.is_digit(8) => escaped_number(&mut chars, 8, c.to_string(), 3),
+            other => other,
+        };
+        value.extend(escaped);
+    }
+    value
+}
+
+// The character of an escape sequence number of at most `max` digits
+fn escaped_number(
+    chars: &mut std::iter::Peekable<std::str::Chars>,
+    radix: u32,
+    mut digits: String,
+    max: usize,
+) -> Option<char> {
+    while digits.len() < max && chars.peek().is_some_and(|c| c.is_digit(radix)) {
+        digits.extend(chars.next());
+    }
+    u32::from_str_radix(&digits, radix).ok().and_then(char::from_u32)
+}
+
+#[cfg(test)]
+mod test {
+    use super::visit::{walk_type_decl, Visitor};
+    use super::*;
+    use crate::{parse_ir, Configuration, MemoryLoader};
+
+    fn ir(idl: &str) -> Specification {
+        let config = Configuration::default();
+        parse_ir(&mut MemoryLoader::new(), &config, idl).unwrap()
+    }
+
+    #[test]
+    fn resolved_model() {
+        let spec = ir(r#"
+            module M {
+                const long N = 2 * 3 + 1;
+                const double HALF = 1 / 2.0;
+                const string<8> NAME = "a\tb";
+                const char C = '\x41';
+                enum Color { RED, GREEN };
+                const Color FAVOURITE = GREEN;
+                typedef long Row[N];
+                struct Header { long id; };
+                struct Msg {
+                    Header header;
+                    sequence<Row, N> rows;
+                    string<N> name;
+                };
+                #pragma keylist Msg header.id
+                union U switch (Color) { case RED: long r; case GREEN: default: short g; };
+            };
+        "#);
+        assert!(spec.diagnostics.is_empty());
+        let value = |name: &str| spec.root.get_constant(&[String::from("M"), String::from(name)]).unwrap().value.clone();
+        assert_eq!(value("N"), ConstValue::Integer(7));
+        assert_eq!(value("HALF"), ConstValue::Float(0.5));
+        assert_eq!(value("NAME"), ConstValue::String(String::from("a\tb")));
+        assert_eq!(value("C"), ConstValue::Char('A'));
+        let green = ConstValue::Enumerator {
+            enumeration: vec![String::from("M"), String::from("Color")],
+            id: String::from("GREEN"),
+            ordinal: 1,
+        };
+        assert_eq!(value("FAVOURITE"), green);
+
+        let path = |name: &str| vec![String::from("M"), String::from(name)];
+        assert_eq!(spec.root.get_type(&path("Msg")).unwrap().line, 11);
+        assert_eq!(spec.root.get_constant(&path("NAME")).unwrap().line, 5);
+        let row = spec.root.get_type(&path("Row")).unwrap();
+        assert!(matches!(row.kind, TypeKind::Typedef(TypeSpec::Array(_, ref dims)) if dims == &[7]));
+        let msg = match spec.root.get_type(&path("Msg")).unwrap().kind {
+            TypeKind::Struct(ref msg) => msg.clone(),
+            _ => panic!("Msg is not a struct"),
+        };
+        assert_eq!(msg.members[1].type_spec, TypeSpec::Sequence(Box::new(TypeSpec::Named(path("Row"))), Some(7)));
+        assert_eq!(msg.members[2].type_spec, TypeSpec::String(Some(7)));
+        let keys = &msg.topic.unwrap().keys;
+        assert_eq!(keys[0].path, vec!["header", "id"]);
+        assert_eq!(keys[0].type_spec, TypeSpec::Int32);
+        assert!(TypeSpec::Named(path("Row")).resolve(&spec.root) != &TypeSpec::Named(path("Row")));
+
+        match spec.root.get_type(&path("U")).unwrap().kind {
+            TypeKind::Union(ref u) => {
+                assert_eq!(u.cases[1].labels, vec![CaseLabel::Value(green), CaseLabel::Default]);
+            }
+            _ => panic!("U is not a union"),
+        }
+    }
+
+    #[test]
+    fn visitor() {
+        // the names of the members of each struct
+        struct Members(Vec<String>, String);
+        impl Visitor for Members {
+            fn visit_type_decl(&mut self, decl: &TypeDecl) {
+                self.1 = decl.id.clone();
+                walk_type_decl(self, decl);
+            }
+            fn visit_member(&mut self, member: &Member) {
+                self.0.push(format!("{}.{}", self.1, member.id));
+            }
+        }
+        let spec = ir("module A { struct S { long a; }; module B { struct T { S s; long b; }; }; };");
+        let mut members = Members(Vec::new(), String::new());
+        members.visit_module(&spec.root);
+        assert_eq!(members.0, vec!["S.a", "T.s", "T.b"]);
+    }
+
+    #[test]
+    fn annotations() {
+        let spec = ir(r#"
+            module M {
+                const long MAX = 5;
+                @final struct S {
+                    @key long id;
+                    @key(FALSE) long b;
+                    @range(min = 1, max = MAX) long r;
+                    @unit("m") double d;
+                };
+                @extensibility(APPENDABLE) enum E { A };
+            };
+        "#);
+        assert!(spec.diagnostics.is_empty());
+        let path = |name: &str| vec![String::from("M"), String::from(name)];
+        let decl = spec.root.get_type(&path("S")).unwrap();
+        assert_eq!(decl.annotations[0].name, "final");
+        let s = match decl.kind {
+            TypeKind::Struct(ref s) => s,
+            _ => panic!("S is not a struct"),
+        };
+        assert_eq!(s.members.iter().map(|m| m.is_key).collect::<Vec<_>>(), vec![true, false, false, false]);
+        assert_eq!(s.topic.as_ref().unwrap().keys[0].path, vec!["id"]);
+        let range = &s.members[2].annotations[0];
+        assert_eq!(range.name, "range");
+        assert_eq!(range.params[1].name.as_deref(), Some("max"));
+        assert_eq!(range.params[1].value, Some(ConstValue::Integer(5)));
+        let unit = &s.members[3].annotations[0].params[0];
+        assert_eq!(unit.name, None);
+        assert_eq!(unit.value, Some(ConstValue::String(String::from("m"))));
+
+        let extensibility = &spec.root.get_type(&path("E")).unwrap().annotations[0];
+        assert_eq!(extensibility.params[0].value, None);
+        assert_eq!(extensibility.params[0].text, "APPENDABLE");
+    }
+
+    #[test]
+    fn json() {
+        let spec = ir("module M {\n  const fixed F = 1.50d;\n  const string T = \"a\\x01\\t\\\"\u{e9}\\\\\";\n  struct S {\n    long id;\n    @unit(\"m\") sequence<string<4>, 2> tags;\n  };\n  #pragma keylist S id\n};\n");
+        let json = spec.to_json();
+        assert!(json.starts_with("{\n  \"version\": 1,\n  \"included\": [],\n  \"diagnostics\": [],\n"));
+        let s = r#"
+            {
+              "name": "S",
+              "scoped_name": "M::S",
+              "kind": "struct",
+              "source": {
+                "file": null,
+                "line": 4
+              },
+              "annotations": [],
+              "base": null,
+              "members": [
+                {
+                  "name": "id",
+                  "type": {
+                    "kind": "int32"
+                  },
+                  "key": true,
+                  "source": {
+                    "file": null,
+                    "line": 5
+                  },
+                  "annotations": []
+                },
+                {
+                  "name": "tags",
+                  "type": {
+                    "kind": "sequence",
+                    "element": {
+                      "kind": "string",
+                      "bound": 4
+                    },
+                    "bound": 2
+                  },
+                  "key": false,
+                  "source": {
+                    "file": null,
+                    "line": 6
+                  },
+                  "annotations": [
+                    {
+                      "name": "unit",
+                      "params": [
+                        {
+                          "name": null,
+                          "value": {
+                            "kind": "string",
+                            "value": "m"
+                          },
+                          "text": "\"m\""
+                        }
+                      ]
+                    }
+                  ]
+                }
+              ],
+              "topic": {
+                "keys": [
+                  {
+                    "path": [
+                      "id"
+                    ],
+                    "type": {
+                      "kind": "int32"
+                    }
+                  }
+                ],
+                "key_size": 4,
+                "flags": [
+                  "DDS_TOPIC_NO_OPTIMIZE",
+                  "DDS_TOPIC_FIXED_KEY"
+                ]
+              }
+            }"#;
+        // the struct, regardless of the indentation of the nested objects
+        let flat = |s: &str| s.lines().map(str::trim).collect::<Vec<_>>().join("\n");
+        assert!(flat(&json).contains(&flat(s)[1..]), "{}", json);
+        assert!(flat(&json).contains("\"value\": {\n\"kind\": \"fixed\",\n\"value\": \"1.5\"\n}"));
+        // control characters are escaped, other characters are UTF-8
+        assert!(flat(&json).contains("\"value\": \"a\\u0001\\t\\\"\u{e9}\\\\\"\n"), "{}", json);
+    }
+
+    #[test]
+    fn unknown_types_are_errors() {
+        let spec = ir("struct A { long x; };\nstruct B {\n  A a;\n  Unknown y;\n};");
+        assert!(spec.has_errors());
+        assert!(spec.diagnostics[0].to_string().contains("Unknown type Unknown at line 4"), "{:?}", spec.diagnostics);
+        // only the forward declaration of the skipped struct remains
+        assert!(matches!(spec.root.get_type(&[String::from("B")]).map(|t| &t.kind), Some(TypeKind::Forward)));
+    }
+}
@@ -0,0 +1,125 @@
+// Copyright (C) 2020  Sojan James
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0>
+
+/* A visitor walking the IR.
+
+ Each visit method walks the children with the walk function of the same
+ name by default, an implementation overriding a method calls the walk
+ function to go on into the children. Modules are walked in the order of the
+ generated code: types, nested modules, constants.
+*/
+
+use super::*;
+
+pub trait Visitor {
+    fn visit_module(&mut self, module: &Module) {
+        walk_module(self, module)
+    }
+
+    fn visit_type_decl(&mut self, decl: &TypeDecl) {
+        walk_type_decl(self, decl)
+    }
+
+    fn visit_member(&mut self, member: &Member) {
+        walk_member(self, member)
+    }
+
+    fn visit_union_case(&mut self, case: &UnionCase) {
+        walk_union_case(self, case)
+    }
+
+    fn visit_bit_field(&mut self, field: &BitField) {
+        walk_bit_field(self, field)
+    }
+
+    fn visit_operation(&mut self, operation: &Operation) {
+        walk_operation(self, operation)
+    }
+
+    fn visit_constant(&mut self, constant: &Constant) {
+        walk_constant(self, constant)
+    }
+
+    fn visit_type_spec(&mut self, spec: &TypeSpec) {
+        walk_type_spec(self, spec)
+    }
+}
+
+pub fn walk_module<V: Visitor + ?Sized>(visitor: &mut V, module: &Module) {
+    for decl in module.types.iter() {
+        visitor.visit_type_decl(decl);
+    }
+    for submodule in module.modules.iter() {
+        visitor.visit_module(submodule);
+    }
+    for constant in module.constants.iter() {
+        visitor.visit_constant(constant);
+    }
+}
+
+pub fn walk_type_decl<V: Visitor + ?Sized>(visitor: &mut V, decl: &TypeDecl) {
+    match decl.kind {
+        TypeKind::Typedef(ref spec) => visitor.visit_type_spec(spec),
+        TypeKind::Struct(ref s) => {
+            for member in s.members.iter() {
+                visitor.visit_member(member);
+            }
+        }
+        TypeKind::Union(ref u) => {
+            visitor.visit_type_spec(&u.discriminator);
+            for case in u.cases.iter() {
+                visitor.visit_union_case(case);
+            }
+        }
+        TypeKind::Bitset(ref fields) => {
+            for field in fields.iter() {
+                visitor.visit_bit_field(field);
+            }
+        }
+        TypeKind::Interface(ref operations) => {
+            for operation in operations.iter() {
+                visitor.visit_operation(operation);
+            }
+        }
+        TypeKind::Enum(_) | TypeKind::Bitmask(_) | TypeKind::Forward => {}
+    }
+}
+
+pub fn walk_member<V: Visitor + ?Sized>(visitor: &mut V, member: &Member) {
+    visitor.visit_type_spec(&member.type_spec)
+}
+
+pub fn walk_union_case<V: Visitor + ?Sized>(visitor: &mut V, case: &UnionCase) {
+    visitor.visit_type_spec(&case.type_spec)
+}
+
+pub fn walk_bit_field<V: Visitor + ?Sized>(visitor: &mut V, field: &BitField) {
+    visitor.visit_type_spec(&field.type_spec)
+}
+
+pub fn walk_operation<V: Visitor + ?Sized>(visitor: &mut V, operation: &Operation) {
+    if let Some(ref spec) = operation.return_type {
+        visitor.visit_type_spec(spec);
+    }
+    for param in operation.params.iter() {
+        visitor.visit_type_spec(&param.type_spec);
+    }
+}
+
+pub fn walk_constant<V: Visitor + ?Sized>(visitor: &mut V, constant: &Constant) {
+    visitor.visit_type_spec(&constant.type_spec)
+}
+
+/// Walks the element, key and value types of template types
+pub fn walk_type_spec<V: Visitor + ?Sized>(visitor: &mut V, spec: &TypeSpec) {
+    match spec {
+        TypeSpec::Sequence(elem, _) | TypeSpec::Array(elem, _) => visitor.visit_type_spec(elem),
+        TypeSpec::Map(key, value, _, _) => {
+            visitor.visit_type_spec(key);
+            visitor.visit_type_spec(value);
+        }
+        _ => {}
+    }
+}
@@ -6,22 +6,30 @@
 extern crate linked_hash_map;
 
 mod ast;
+mod backend;
+mod builder;
 mod cdds;
+mod cpp;
+#[cfg(test)]
+mod fixture;
+mod grammar;
+pub mod ir;
+mod template;
 
 use pest::iterators::{Pair, Pairs};
 use pest::Parser;
-use rtps_idl_grammar::{IdlParser, Rule};
+use crate::grammar::IdlParser;
+pub use crate::grammar::Rule;
 use std::collections::HashMap;
-use std::fs::File;
 use std::io::{Error, ErrorKind};
-use std::io::{Read, Write};
-use std::include;
+use std::io::Write;
 use std::path::PathBuf;
 
 use crate::ast::*;
-
-const MODULE_PRELUDE: &[u8] = b"#[allow(unused_imports)]
-";
+pub use crate::backend::{Backend, Backends, CBackend, RustBackend};
+pub use crate::builder::Builder;
+pub use crate::cpp::CppBackend;
+pub use crate::template::TemplateBackend;
 
 ///
 #[derive(Debug)]
@@ -32,21 +40,123 @@ pub enum IdlError {
     ErrorMesg(String),
     FileNotFound(String),
     KeyNotFound(String),
+    // a type holds a forward declared type by value, it would contain itself;
+    // unlike the errors in definitions it stops the generation
+    IncompleteType(String),
 }
 
 ///
 pub trait IdlLoader {
+    /// The path of the file named by `#include "filename"` (`quoted`) or
+    /// `#include <filename>` in the file `includer`. Each path is processed
+    /// once, by default the path is the name as written.
+    fn resolve(&self, filename: &str, includer: &str, quoted: bool) -> Result<String, Error> {
+        let _ = (includer, quoted);
+        Ok(String::from(filename))
+    }
+
     fn load(&self, filename: &str) -> Result<String, Error>;
 }
 
+/// Size of the IDL wchar in memory, it must match wchar_t of the target
+/// platform. On the wire a wchar is always 16 bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WideCharSize {
+    // Windows
+    Bits16,
+    // Linux and most other platforms
+    Bits32,
+}
+
+impl std::str::FromStr for WideCharSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "16" => Ok(WideCharSize::Bits16),
+            "32" => Ok(WideCharSize::Bits32),
+            _ => Err(format!("Invalid wchar size {}, expected 16 or 32", s)),
+        }
+    }
+}
+
+/// Cyclone DDS release the topic descriptors are generated for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CycloneVersion {
+    V0_7,
+}
+
+impl CycloneVersion {
+    /// Whether the serializer of the release has ops for wide strings
+    pub fn has_wide_string_ops(&self) -> bool {
+        match self {
+            CycloneVersion::V0_7 => false,
+        }
+    }
+}
+
+impl std::fmt::Display for CycloneVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CycloneVersion::V0_7 => write!(f, "0.7"),
+        }
+    }
+}
+
+impl std::str::FromStr for CycloneVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "0.7" => Ok(CycloneVersion::V0_7),
+            _ => Err(format!("Unsupported Cyclone DDS version {}, expected 0.7", s)),
+        }
+    }
+}
+
+/// Representation of the IDL long double, Rust has no equivalent type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LongDoubleRepr {
+    // 16 bytes aligned like the C long double of x86_64 and aarch64, the value is opaque
+    Opaque,
+    // generated as double, for platforms where long double is a double
+    Double,
+}
+
+impl std::str::FromStr for LongDoubleRepr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "opaque" => Ok(LongDoubleRepr::Opaque),
+            "double" => Ok(LongDoubleRepr::Double),
+            _ => Err(format!("Invalid long double representation {}, expected opaque or double", s)),
+        }
+    }
+}
+
 ///
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Configuration {
     pub definition: HashMap<String, String>,
     pub verbose: bool,
     pub generate_c: bool,
     pub idl_name: PathBuf,
-    pub generate_descriptors : bool, // generate the descriptors in Rust
+    // generate the topic descriptors: in Rust, or the C file if generate_c is set
+    pub generate_descriptors : bool,
+    // generate the layout checks: a Rust test module, or the C tables if generate_c is set
+    pub layout_checks: bool,
+    // path of the runtime crate the generated Rust imports from
+    pub runtime_crate: String,
+    // Cyclone DDS release of the topic descriptors
+    pub cyclone_version: CycloneVersion,
+    // platform convention for wchar
+    pub wchar_size: WideCharSize,
+    // platform convention for long double
+    pub long_double: LongDoubleRepr,
+    // Rust path of the modules generated from the included files, the module
+    // of `#include "a.idl"` is <include_path>::a
+    pub include_path: String,
 }
 
 ///
@@ -57,6 +167,8 @@ impl Configuration {
         gen_c: bool,
         idl_name: PathBuf,
         generate_descriptors : bool,
+        layout_checks: bool,
+        runtime_crate: String,
     ) -> Configuration {
         Configuration {
             definition: defs,
@@ -64,6 +176,12 @@ impl Configuration {
             generate_c: gen_c,
             idl_name,
             generate_descriptors,
+            layout_checks,
+            runtime_crate,
+            cyclone_version: CycloneVersion::V0_7,
+            wchar_size: WideCharSize::Bits32,
+            long_double: LongDoubleRepr::Opaque,
+            include_path: String::from("crate"),
         }
     }
 }
@@ -77,6 +195,12 @@ impl Default for Configuration {
             generate_c: false,
             idl_name: PathBuf::default(),
             generate_descriptors : false,
+            layout_checks: false,
+            runtime_crate: String::from("cyclonedds_sys"),
+            cyclone_version: CycloneVersion::V0_7,
+            wchar_size: WideCharSize::Bits32,
+            long_double: LongDoubleRepr::Opaque,
+            include_path: String::from("crate"),
         }
     }
 }
@@ -89,20 +213,49 @@ pub fn load_idl(_config: &Configuration, _path: &str) -> Result<String, Error> {
 ///
 type Scope = Vec<String>;
 
+// Record the include file declaring `name`, None for the main file, and the
+// line of the declaration
+fn set_source(module: &mut IdlModule, name: &str, source: Option<String>, line: usize) {
+    module.lines.insert(String::from(name), line);
+    match source {
+        Some(file) => {
+            module.sources.insert(String::from(name), file);
+        }
+        None => {
+            module.sources.remove(name);
+        }
+    }
+}
+
 ///
 #[derive(Debug, Clone)]
 struct Context<'i> {
     config: &'i Configuration,
-    definitions: HashMap<String, String>,
     root_module: Box<IdlModule>,
+    // the included file being processed, None for the main file
+    source: Option<String>,
+    // the line of the definition being processed
+    line: usize,
+    // the resolved paths of the files being processed, the main file first
+    include_stack: Vec<String>,
+    // the resolved paths of the files processed so far
+    processed: Vec<String>,
+    // the resolved paths and names as written of the files included by the main file
+    main_includes: Vec<(String, String)>,
+    diagnostics: Vec<Diagnostic>,
 }
 
 impl<'i> Context<'i> {
-    pub fn new(config: &'i Configuration) -> Context {
+    pub fn new(config: &'i Configuration) -> Context<'i> {
         Context {
             config: &config,
-            definitions: HashMap::default(),
-            root_module: Box::new(IdlModule::new(None, 0)),
+            root_module: Box::new(IdlModule::new(None)),
+            source: None,
+            line: 0,
+            include_stack: Vec::new(),
+            processed: Vec::new(),
+            main_includes: Vec::new(),
+            diagnostics: Vec::new(),
         }
     }
 
@@ -110,7 +263,6 @@ impl<'i> Context<'i> {
     fn lookup_module(&mut self, scope: &Scope) -> &mut Box<IdlModule> {
         // Starting from Root traverse the scope-path
         let mut current_module = &mut self.root_module;
-        let level = scope.len();
         let mut submodule_scope = Vec::new();
         //println!("Lookup module: scope:{:?}",&scope);
         for name in scope {
@@ -119,7 +271,7 @@ impl<'i> Context<'i> {
             let submodule = current_module
                 .modules
                 .entry(name.to_owned())
-                .or_insert(Box::new(IdlModule::new(Some(name.to_owned()),level)));
+                .or_insert(Box::new(IdlModule::new(Some(name.to_owned()))));
             current_module = submodule;
         }
 
@@ -133,12 +285,312 @@ impl<'i> Context<'i> {
         key: String,
         type_dcl: Box<IdlTypeDcl>,
     ) -> Result<(), IdlError> {
+        let (source, line) = (self.source.clone(), self.line);
         let current_module = self.lookup_module(scope);
-        current_module.types.entry(key).or_insert(type_dcl);
+        // the definition replaces a forward declaration and takes its place
+        // in declaration order, after the types it depends on
+        let is_forward = |t: &IdlTypeDcl| matches!(t.0, IdlTypeDclKind::ForwardDcl(_));
+        if !is_forward(&type_dcl) && current_module.types.get(&key).is_some_and(|t| is_forward(t)) {
+            current_module.types.remove(&key);
+        }
+        if !current_module.types.contains_key(&key) {
+            set_source(current_module, &key, source, line);
+            current_module.types.insert(key, type_dcl);
+        }
 
         Ok(())
     }
 
+    // The annotations of the type declaration `key`
+    fn add_annotations(&mut self, scope: &mut Scope, key: &str, annotations: Vec<IdlAnnotation>) {
+        if !annotations.is_empty() {
+            self.lookup_module(scope).annotations.insert(String::from(key), annotations);
+        }
+    }
+
+    // True if `spec` is a forward declared type used by value, following
+    // arrays and typedefs
+    fn is_incomplete(&self, spec: &IdlTypeSpec) -> bool {
+        match spec {
+            IdlTypeSpec::ArrayType(typespec, _) => self.is_incomplete(typespec),
+            IdlTypeSpec::ScopedName(name) => match self.root_module.get_type_decl(name).map(|t| &t.0) {
+                Some(IdlTypeDclKind::ForwardDcl(_)) => true,
+                Some(IdlTypeDclKind::TypeDcl(_, typespec)) => self.is_incomplete(typespec),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    // The file and line of the definition being processed
+    fn location(&self) -> String {
+        format!("{}:{}", self.include_stack.last().map(|f| f.as_str()).unwrap_or(""), self.line)
+    }
+
+    // The `members` of the `kind` `id` must not hold a forward declared type
+    // by value
+    fn check_complete<'a>(
+        &self,
+        kind: &str,
+        id: &str,
+        mut members: impl Iterator<Item = (&'a String, &'a IdlTypeSpec)>,
+    ) -> Result<(), IdlError> {
+        match members.find(|(_, spec)| self.is_incomplete(spec)) {
+            Some((member, _)) => Err(IdlError::IncompleteType(format!(
+                "{}: Member {} of {} {} has an incomplete type, forward declared types are only allowed as sequence elements",
+                self.location(),
+                member,
+                kind,
+                id
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    // op_dcl = { op_type_spec ~ identifier ~ "(" ~ parameter_dcls? ~ ")" ~ raises_expr? }
+    fn read_operation(&mut self, scope: &mut Scope, pair: &Pair<Rule>) -> Result<IdlOperation, IdlError> {
+        let mut iter = pair.clone().into_inner();
+        // op_type_spec = { type_spec | "void" }, the grammar reads void as a scoped name
+        let op_type = iter.next().unwrap();
+        let return_type = match op_type.as_str().trim() {
+            "void" => None,
+            _ => Some(self.read_type_spec(scope, &op_type)?),
+        };
+        let id = self.read_identifier(scope, &iter.next().unwrap())?;
+        let mut params = Vec::new();
+        let mut raises = Vec::new();
+        for p in iter {
+            match p.as_rule() {
+                // param_dcl = { param_attribute ~ type_spec ~ simple_declarator }
+                Rule::parameter_dcls => {
+                    for param in p.into_inner() {
+                        let mut param_iter = param.into_inner();
+                        let attribute = match param_iter.next().unwrap().as_str() {
+                            "in" => IdlParamAttribute::In,
+                            "out" => IdlParamAttribute::Out,
+                            _ => IdlParamAttribute::InOut,
+                        };
+                        let type_spec = self.read_type_spec(scope, &param_iter.next().unwrap())?;
+                        let declarator = param_iter.next().unwrap();
+                        let param_id =
+                            self.read_identifier(scope, &declarator.into_inner().next().unwrap())?;
+                        params.push(IdlParameter {
+                            id: param_id,
+                            attribute,
+                            type_spec,
+                        });
+                    }
+                }
+                // raises_expr = { "raises" ~ "(" ~ scoped_name ~ ("," ~ scoped_name)* ~ ")" }
+                _ => {
+                    for name in p.into_inner() {
+                        raises.push(self.read_scoped_name(scope, &name)?);
+                    }
+                }
+            }
+        }
+        Ok(IdlOperation {
+            id,
+            return_type,
+            params,
+            raises,
+        })
+    }
+
+    // Adds the DDS-RPC request and reply topics of the interface `id`, see cdds::rpc
+    fn add_interface(
+        &mut self,
+        scope: &mut Scope,
+        id: String,
+        operations: Vec<IdlOperation>,
+    ) -> Result<(), IdlError> {
+        let names = operations
+            .iter()
+            .map(|op| &op.id)
+            .chain(operations.iter().flat_map(|op| op.params.iter().map(|p| &p.id)));
+        for name in names {
+            if crate::cdds::rpc::RPC_RESERVED.contains(&name.as_str()) {
+                return Err(IdlError::ErrorMesg(format!(
+                    "{} of interface {} is reserved for the DDS-RPC types",
+                    name, id
+                )));
+            }
+        }
+        let raises = operations.iter().flat_map(|op| op.raises.iter().map(|name| &name.0));
+        let exceptions = crate::cdds::rpc::exceptions(raises);
+        for (idx, name) in exceptions.iter().enumerate() {
+            if !matches!(
+                self.root_module.get_type_decl(&IdlScopedName(name.to_vec(), true)).map(|t| &t.0),
+                Some(IdlTypeDclKind::StructDcl(_, _, _))
+            ) {
+                return Err(IdlError::ErrorMesg(format!(
+                    "{} raised by interface {} is not an exception",
+                    name.join("::"),
+                    id
+                )));
+            }
+            if exceptions[..idx].iter().any(|e| e.last() == name.last()) {
+                return Err(IdlError::ErrorMesg(format!(
+                    "Interface {} raises two exceptions named {}",
+                    id,
+                    name.last().unwrap()
+                )));
+            }
+        }
+
+        let line = self.line;
+        let member = |id: &str, type_spec: Box<IdlTypeSpec>| {
+            Box::new(IdlStructMember {
+                id: String::from(id),
+                type_spec,
+                is_key: false,
+                is_base: false,
+                annotations: Vec::new(),
+                line,
+            })
+        };
+        let header = vec![
+            member("client_id", Box::new(IdlTypeSpec::U64Type)),
+            member("call_id", Box::new(IdlTypeSpec::I64Type)),
+            member("operation", Box::new(IdlTypeSpec::U32Type)),
+        ];
+        let mut request = header.clone();
+        let mut reply = header;
+        reply.push(member("remote_ex", Box::new(IdlTypeSpec::I32Type)));
+        reply.push(member("exception", Box::new(IdlTypeSpec::U32Type)));
+
+        for op in &operations {
+            let mut params = Vec::new();
+            let mut results = Vec::new();
+            if let Some(return_type) = op.return_type.as_ref() {
+                results.push(member(crate::cdds::rpc::RPC_RETURN_MEMBER, return_type.clone()));
+            }
+            for param in &op.params {
+                if param.attribute != IdlParamAttribute::Out {
+                    params.push(member(&param.id, param.type_spec.clone()));
+                }
+                if param.attribute != IdlParamAttribute::In {
+                    results.push(member(&param.id, param.type_spec.clone()));
+                }
+            }
+            for (name, mut members, topic) in [
+                (crate::cdds::rpc::in_name(&id, &op.id), params, &mut request),
+                (crate::cdds::rpc::out_name(&id, &op.id), results, &mut reply),
+            ] {
+                if members.is_empty() {
+                    continue;
+                }
+                for m in members.iter_mut() {
+                    let prefix = format!("{}_{}", name, m.id);
+                    self.add_map_entries(scope, &prefix, &mut m.type_spec)?;
+                }
+                let mut path = scope.clone();
+                path.push(name.clone());
+                topic.push(member(
+                    &op.id,
+                    Box::new(IdlTypeSpec::ScopedName(IdlScopedName(path, true))),
+                ));
+                let typedcl = Box::new(IdlTypeDcl(IdlTypeDclKind::StructDcl(name.clone(), members, false)));
+                self.add_type_dcl(scope, name, typedcl)?;
+            }
+        }
+        for name in &exceptions {
+            reply.push(member(
+                &crate::cdds::rpc::exception_member(name),
+                Box::new(IdlTypeSpec::ScopedName(IdlScopedName(name.to_vec(), true))),
+            ));
+        }
+
+        for (name, members) in [
+            (crate::cdds::rpc::request_name(&id), request),
+            (crate::cdds::rpc::reply_name(&id), reply),
+        ] {
+            let typedcl = Box::new(IdlTypeDcl(IdlTypeDclKind::StructDcl(name.clone(), members, false)));
+            self.add_type_dcl(scope, name.clone(), typedcl)?;
+            // keyless topics, the requests and replies correlate by the client and call id
+            self.lookup_module(scope).set_topic_and_key_flags(&name, &Vec::new())?;
+        }
+        let key = id.clone();
+        self.add_type_dcl(scope, key, Box::new(IdlTypeDcl(IdlTypeDclKind::InterfaceDcl(id, operations))))
+    }
+
+    // Map keys are integers or strings as in XTypes, these are ordered and
+    // hashable in Rust as well
+    fn is_map_key(&self, spec: &IdlTypeSpec) -> bool {
+        match spec {
+            IdlTypeSpec::I8Type
+            | IdlTypeSpec::I16Type
+            | IdlTypeSpec::I32Type
+            | IdlTypeSpec::I64Type
+            | IdlTypeSpec::U8Type
+            | IdlTypeSpec::U16Type
+            | IdlTypeSpec::U32Type
+            | IdlTypeSpec::U64Type
+            | IdlTypeSpec::StringType(_)
+            | IdlTypeSpec::WideStringType(_) => true,
+            IdlTypeSpec::ScopedName(name) => match self.root_module.get_type_decl(name).map(|t| &t.0) {
+                Some(IdlTypeDclKind::TypeDcl(_, typespec)) => self.is_map_key(typespec),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    // Adds the entry struct of the maps in `spec`, named after `prefix`. The
+    // C representation of a map is a sequence of its entries.
+    fn add_map_entries(
+        &mut self,
+        scope: &mut Scope,
+        prefix: &str,
+        spec: &mut IdlTypeSpec,
+    ) -> Result<(), IdlError> {
+        match spec {
+            IdlTypeSpec::MapType(key, value, _, entry @ None) => {
+                let id = format!("{}_entry", prefix);
+                if self.is_incomplete(value) {
+                    return Err(IdlError::IncompleteType(format!(
+                        "{}: Map {} has an incomplete value type, forward declared types are only allowed as sequence elements",
+                        self.location(),
+                        prefix
+                    )));
+                }
+                self.add_map_entries(scope, &format!("{}_value", id), value)?;
+                let members = vec![
+                    Box::new(IdlStructMember {
+                        id: String::from("key"),
+                        type_spec: key.clone(),
+                        is_key: false,
+                        is_base: false,
+                        annotations: Vec::new(),
+                        line: self.line,
+                    }),
+                    Box::new(IdlStructMember {
+                        id: String::from("value"),
+                        type_spec: value.clone(),
+                        is_key: false,
+                        is_base: false,
+                        annotations: Vec::new(),
+                        line: self.line,
+                    }),
+                ];
+                let type_dcl = Box::new(IdlTypeDcl(IdlTypeDclKind::StructDcl(
+                    id.clone(),
+                    members,
+                    false,
+                )));
+                self.add_type_dcl(scope, id.clone(), type_dcl)?;
+                let mut path = scope.clone();
+                path.push(id);
+                *entry = Some(IdlScopedName(path, true));
+                Ok(())
+            }
+            IdlTypeSpec::ArrayType(typespec, _) | IdlTypeSpec::SequenceType(typespec, _) => {
+                self.add_map_entries(scope, prefix, typespec)
+            }
+            _ => Ok(()),
+        }
+    }
+
     ///
     fn add_const_dcl(
         &mut self,
@@ -146,8 +598,12 @@ impl<'i> Context<'i> {
         key: String,
         const_dcl: Box<IdlConstDcl>,
     ) -> Result<(), IdlError> {
+        let (source, line) = (self.source.clone(), self.line);
         let current_module = self.lookup_module(scope);
-        current_module.constants.entry(key).or_insert(const_dcl);
+        if !current_module.constants.contains_key(&key) {
+            set_source(current_module, &key, source, line);
+            current_module.constants.insert(key, const_dcl);
+        }
 
         Ok(())
     }
@@ -161,7 +617,7 @@ impl<'i> Context<'i> {
             let mut keys: Vec<String> = Vec::new();
             while let Some(key) = directive.next() {
                 //println!("Key: {}", key);
-                keys.push(key.into());
+                keys.push(self.resolve_inherited_key(scope, struct_name, key));
             }
             let current_module = self.lookup_module(scope);
             current_module.set_topic_and_key_flags(struct_name, &keys)?;
@@ -172,6 +628,77 @@ impl<'i> Context<'i> {
         Ok(())
     }
 
+    // The member embedding the base struct `base` of struct `id`
+    fn read_struct_base(
+        &self,
+        id: &str,
+        base: IdlScopedName,
+    ) -> Result<Box<IdlStructMember>, IdlError> {
+        match self.root_module.get_type_decl(&base).map(|t| &t.0) {
+            Some(IdlTypeDclKind::StructDcl(_, _, _)) => Ok(Box::new(IdlStructMember {
+                id: String::from(STRUCT_BASE_MEMBER),
+                type_spec: Box::new(IdlTypeSpec::ScopedName(base)),
+                is_key: false,
+                is_base: true,
+                annotations: Vec::new(),
+                line: self.line,
+            })),
+            _ => Err(IdlError::ErrorMesg(format!(
+                "The base {} of struct {} is not a struct",
+                base.0.join("::"),
+                id
+            ))),
+        }
+    }
+
+    // Keylist of the struct `name`, None if it is not a topic
+    fn get_struct_keys(&self, name: &IdlScopedName) -> Option<Vec<String>> {
+        let (struct_name, module) = name.0.split_last()?;
+        self.root_module
+            .get_module(module)
+            .and_then(|m| m.get_keys(struct_name))
+            .cloned()
+    }
+
+    // Path of `key` from `members`, keys of a base struct are reached
+    // through the embedded base member
+    fn get_inherited_key_path(
+        &self,
+        members: &[Box<IdlStructMember>],
+        key: &str,
+    ) -> Option<String> {
+        let member_id = key.split('.').next().unwrap_or(key);
+        if members.iter().any(|m| m.id == member_id) {
+            return Some(String::from(key));
+        }
+        let base = members.first().filter(|m| m.is_base)?;
+        let base_members = match base.type_spec.as_ref() {
+            IdlTypeSpec::ScopedName(name) => match self.root_module.get_type_decl(name).map(|t| &t.0) {
+                Some(IdlTypeDclKind::StructDcl(_, members, _)) => members,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        self.get_inherited_key_path(base_members, key)
+            .map(|path| format!("{}.{}", base.id, path))
+    }
+
+    // The keylist entry `key` of struct `struct_name`, keys declared by the
+    // base struct become paths through the base member
+    fn resolve_inherited_key(&self, scope: &Scope, struct_name: &str, key: &str) -> String {
+        let members = self
+            .root_module
+            .get_module(scope)
+            .and_then(|m| m.types.get(struct_name))
+            .and_then(|decl| match &decl.0 {
+                IdlTypeDclKind::StructDcl(_, members, _) => Some(members),
+                _ => None,
+            });
+        members
+            .and_then(|members| self.get_inherited_key_path(members, key))
+            .unwrap_or_else(|| String::from(key))
+    }
+
     // type_spec = { template_type_spec | simple_type_spec }
     pub fn read_type_spec(
         &mut self,
@@ -186,10 +713,24 @@ impl<'i> Context<'i> {
         match pair.as_rule() {
             Rule::float => Ok(Box::new(IdlTypeSpec::F32Type)),
             Rule::double => Ok(Box::new(IdlTypeSpec::F64Type)),
-            Rule::long_double => Ok(Box::new(IdlTypeSpec::F128Type)),
+            Rule::long_double => match self.config.long_double {
+                LongDoubleRepr::Opaque => {
+                    self.diagnostics.push(Diagnostic::Warning(String::from(
+                        "long double is generated as an opaque 16 byte value",
+                    )));
+                    Ok(Box::new(IdlTypeSpec::F128Type))
+                }
+                LongDoubleRepr::Double => {
+                    self.diagnostics
+                        .push(Diagnostic::Warning(String::from("long double is generated as double")));
+                    Ok(Box::new(IdlTypeSpec::F64Type))
+                }
+            },
             Rule::unsigned_short_int => Ok(Box::new(IdlTypeSpec::U16Type)),
             Rule::unsigned_longlong_int => Ok(Box::new(IdlTypeSpec::U64Type)),
             Rule::unsigned_long_int => Ok(Box::new(IdlTypeSpec::U32Type)),
+            Rule::unsigned_tiny_int => Ok(Box::new(IdlTypeSpec::U8Type)),
+            Rule::signed_tiny_int => Ok(Box::new(IdlTypeSpec::I8Type)),
             Rule::signed_short_int => Ok(Box::new(IdlTypeSpec::I16Type)),
             Rule::signed_longlong_int => Ok(Box::new(IdlTypeSpec::I64Type)),
             Rule::signed_long_int => Ok(Box::new(IdlTypeSpec::I32Type)),
@@ -204,6 +745,25 @@ impl<'i> Context<'i> {
                     Ok(Box::new(IdlTypeSpec::StringType(Some(pos_int_const))))
                 }
             },
+            Rule::fixed_pt_type => {
+                let mut read_int = |p: Option<Pair<Rule>>| -> Result<u32, IdlError> {
+                    let p = p.ok_or(IdlError::ExpectedItem(Rule::positive_int_const))?;
+                    let expr = self.read_const_expr(scope, &p)?;
+                    expr.eval_integer(&self.root_module)
+                        .filter(|v| *v >= 0)
+                        .map(|v| v as u32)
+                        .ok_or_else(|| IdlError::ErrorMesg(format!("Invalid fixed point size {}", p.as_str())))
+                };
+                let digits = read_int(iter.next())?;
+                let scale = read_int(iter.next())?;
+                if digits == 0 || digits > FIXED_MAX_DIGITS || scale > digits {
+                    return Err(IdlError::ErrorMesg(format!(
+                        "Invalid fixed<{}, {}>, the digits must be 1 to {} and the scale at most the digits",
+                        digits, scale, FIXED_MAX_DIGITS
+                    )));
+                }
+                Ok(Box::new(IdlTypeSpec::FixedPtType(digits, scale)))
+            }
             Rule::wide_string_type => match iter.next() {
                 None => Ok(Box::new(IdlTypeSpec::WideStringType(None))),
                 Some(ref p) => {
@@ -226,10 +786,34 @@ impl<'i> Context<'i> {
                 }
                 _ => panic!(),
             },
+            // map_type = { "map" ~ "<" ~ type_spec ~ "," ~ type_spec ~ ("," ~ positive_int_const)? ~ ">" }
+            Rule::map_type => {
+                let key = self.read_type_spec(scope, &iter.next().unwrap())?;
+                let value = self.read_type_spec(scope, &iter.next().unwrap())?;
+                let bound = match iter.next() {
+                    Some(ref p) => Some(self.read_const_expr(scope, p)?),
+                    None => None,
+                };
+                if !self.is_map_key(&key) {
+                    return Err(IdlError::ErrorMesg(format!(
+                        "Invalid map key type {}, map keys must be integers or strings",
+                        pair.as_str()
+                    )));
+                }
+                Ok(Box::new(IdlTypeSpec::MapType(key, value, bound, None)))
+            }
 
             //  scoped_name = { "::"? ~ identifier ~ ("::" ~ identifier)* }
             Rule::scoped_name => {
                 let name = self.read_scoped_name(scope, pair)?;
+                if self.root_module.get_type_decl(&name).is_none() {
+                    let (line, _) = pair.clone().into_span().start_pos().line_col();
+                    return Err(IdlError::ErrorMesg(format!(
+                        "Unknown type {} at line {}",
+                        pair.as_str().trim_end(),
+                        line
+                    )));
+                }
                 Ok(Box::new(IdlTypeSpec::ScopedName(name)))
             }
 
@@ -265,6 +849,9 @@ impl<'i> Context<'i> {
                     id: id,
                     type_spec: type_spec.clone(),
                     is_key: false,
+                    is_base: false,
+                    annotations: Vec::new(),
+                    line: self.line,
                 });
 
                 Ok(member_dcl)
@@ -287,6 +874,9 @@ impl<'i> Context<'i> {
                     id: id,
                     type_spec: array_type_spec,
                     is_key: false,
+                    is_base: false,
+                    annotations: Vec::new(),
+                    line: self.line,
                 });
 
                 Ok(member_dcl)
@@ -296,7 +886,7 @@ impl<'i> Context<'i> {
         }
     }
 
-    // member = { type_spec ~ declarators ~ ";" }
+    // member = { annotation_appl* ~ type_spec ~ declarators ~ ";" }
     // declarators = { declarator ~ ("," ~ declarator )* }
     // declarator = { array_declarator | simple_declarator }
     fn read_struct_member(
@@ -309,6 +899,8 @@ impl<'i> Context<'i> {
             print!("{:indent$}", "", indent = 3 * scope.len());
             println!("{:?}", pair.as_rule());
         }
+        let (line, _) = pair.clone().into_span().start_pos().line_col();
+        let annotations = self.read_annotations(scope, &mut iter)?;
         let type_spec: Box<IdlTypeSpec> = self.read_type_spec(scope, &iter.next().unwrap())?;
 
         // skip rule 'declarators' and parse sibblings `declarator'
@@ -318,7 +910,144 @@ impl<'i> Context<'i> {
             .map(|declarator| self.read_struct_member_declarator(scope, &declarator, &type_spec))
             .collect();
 
+        let mut members = members?;
+        for member in members.iter_mut() {
+            member.annotations = annotations.clone();
+            member.line = line;
+        }
+        Ok(members)
+    }
+
+    /// The annotations leading `iter`, annotation_appl*
+    fn read_annotations(&mut self, scope: &mut Scope, iter: &mut Pairs<Rule>) -> Result<Vec<IdlAnnotation>, IdlError> {
+        let mut annotations = Vec::new();
+        while let Some(p) = iter.clone().next().filter(|p| p.as_rule() == Rule::annotation_appl) {
+            iter.next();
+            annotations.push(self.read_annotation(scope, &p)?);
+        }
+        Ok(annotations)
+    }
+
+    /// annotation_appl = { "@" ~ scoped_name ~ ("(" ~ annotation_appl_params ~ ")")? }
+    /// annotation_appl_params = { const_expr | annotation_appl_param ~ ("," ~ annotation_appl_param)* }
+    /// annotation_appl_param = { identifier ~ "=" ~ const_expr }
+    fn read_annotation(&mut self, scope: &mut Scope, pair: &Pair<Rule>) -> Result<IdlAnnotation, IdlError> {
+        let mut iter = pair.clone().into_inner();
+        // the scoped_name ends with the whitespace ahead of the parameters
+        let name = iter.next().unwrap().as_str().trim_end().to_owned();
+        let mut params = Vec::new();
+        for p in iter.next().map(|params| params.into_inner()).into_iter().flatten() {
+            match p.as_rule() {
+                Rule::const_expr => params.push((None, self.read_const_expr(scope, &p)?)),
+                _ => {
+                    let mut param = p.into_inner();
+                    let id = self.read_identifier(scope, &param.next().unwrap())?;
+                    params.push((Some(id), self.read_const_expr(scope, &param.next().unwrap())?));
+                }
+            }
+        }
+        Ok(IdlAnnotation { name, params })
+    }
+
+    // The members of `members` annotated @key, @key(FALSE) is not a key
+    fn annotated_keys(&self, members: &[Box<IdlStructMember>]) -> Vec<String> {
         members
+            .iter()
+            .filter(|m| {
+                m.annotations.iter().any(|a| {
+                    a.name == "key"
+                        && a.params
+                            .first()
+                            .is_none_or(|(_, value)| value.eval_integer(&self.root_module) != Some(0))
+                })
+            })
+            .map(|m| m.id.clone())
+            .collect()
+    }
+
+    /// annotation_appl = { "@" ~ scoped_name ~ ("(" ~ annotation_appl_params ~ ")")? }
+    /// The value of the annotation `name` with a single integer parameter,
+    /// None for other annotations
+    fn read_integer_annotation(
+        &mut self,
+        scope: &mut Scope,
+        pair: &Pair<Rule>,
+        name: &str,
+    ) -> Result<Option<u32>, IdlError> {
+        let mut iter = pair.clone().into_inner();
+        if self.config.verbose {
+            print!("{:indent$}", "", indent = 3 * scope.len());
+            println!("{:?} '{}'", pair.as_rule(), pair.as_str());
+        }
+        if iter.next().map(|p| p.as_str().trim_end() == name) != Some(true) {
+            return Ok(None);
+        }
+        let param = iter.next().and_then(|params| params.into_inner().next());
+        let value = match param {
+            Some(ref p) if p.as_rule() == Rule::const_expr => self
+                .read_const_expr(scope, p)?
+                .eval_integer(&self.root_module)
+                .filter(|v| *v >= 0 && *v <= u32::MAX as i64),
+            _ => None,
+        };
+        match value {
+            Some(value) => Ok(Some(value as u32)),
+            None => Err(IdlError::ErrorMesg(format!(
+                "Invalid annotation {}, expected a positive integer",
+                pair.as_str()
+            ))),
+        }
+    }
+
+    /// bitfield = { bitfield_spec ~ identifier* ~ ";" }
+    /// bitfield_spec = { "bitfield" ~ "<" ~ positive_int_const ~ ("," ~ destination_type)? ~ ">" }
+    fn read_bitfield(
+        &mut self,
+        scope: &mut Scope,
+        pair: &Pair<Rule>,
+    ) -> Result<Vec<IdlBitField>, IdlError> {
+        let mut iter = pair.clone().into_inner();
+        if self.config.verbose {
+            print!("{:indent$}", "", indent = 3 * scope.len());
+            println!("{:?}", pair.as_rule());
+        }
+        let spec = iter.next().ok_or(IdlError::ExpectedItem(Rule::bitfield_spec))?;
+        let mut spec_iter = spec.clone().into_inner();
+        let width = match spec_iter.next() {
+            Some(ref p) => self.read_const_expr(scope, p)?.eval_integer(&self.root_module),
+            None => None,
+        }
+        .filter(|w| *w >= 1 && *w <= BITS_MAX as i64)
+        .ok_or_else(|| IdlError::ErrorMesg(format!("Invalid bitfield width in {}", spec.as_str())))?
+            as u32;
+        let type_spec = match spec_iter.next() {
+            Some(ref p) => self.read_type_spec(scope, p)?,
+            None => Box::new(bitfield_default_type(width)),
+        };
+        if bitfield_type_bits(&type_spec).is_none_or(|bits| bits < width) {
+            return Err(IdlError::ErrorMesg(format!(
+                "The type of {} has less than {} bits",
+                spec.as_str(),
+                width
+            )));
+        }
+
+        let ids: Vec<String> = iter.map(|p| p.as_str().to_owned()).collect();
+        if ids.is_empty() {
+            return Ok(vec![IdlBitField {
+                id: None,
+                width,
+                type_spec,
+            }]);
+        }
+        Ok(ids
+            .into_iter()
+            .map(|id| IdlBitField {
+                id: Some(id),
+                width,
+                type_spec: type_spec.clone(),
+            })
+            .collect())
     }
 
     //
@@ -354,7 +1083,27 @@ impl<'i> Context<'i> {
             .map(|p| self.read_identifier(scope, &p).unwrap().to_owned())
             .collect::<Vec<String>>();
 
-        Ok(IdlScopedName(scoped_name, is_absolute_name))
+        Ok(self.resolve_scoped_name(scope, IdlScopedName(scoped_name, is_absolute_name)))
+    }
+
+    /// Resolve a relative name against the enclosing scopes, innermost first.
+    /// Names of types and constants declared so far become absolute, anything
+    /// else is returned unchanged.
+    fn resolve_scoped_name(&self, scope: &Scope, name: IdlScopedName) -> IdlScopedName {
+        if name.1 {
+            return name;
+        }
+        for level in (0..=scope.len()).rev() {
+            let mut candidate = scope[..level].to_vec();
+            candidate.extend(name.0.iter().cloned());
+            let candidate = IdlScopedName(candidate, true);
+            if self.root_module.get_type_decl(&candidate).is_some()
+                || self.root_module.get_const_decl(&candidate).is_some()
+            {
+                return candidate;
+            }
+        }
+        name
     }
 
     /// const_expr = { unary_expr ~ (or_expr | xor_expr | and_expr | shift_expr | add_expr | mult_expr)? }
@@ -408,7 +1157,9 @@ impl<'i> Context<'i> {
                     let name = self.read_scoped_name(scope, p)?;
                     Ok(Box::new(IdlValueExpr::ScopedName(name)))
                 }
-                Some(ref p) if p.as_rule() == Rule::literal => self.read_const_expr(scope, p),
+                Some(ref p) if p.as_rule() == Rule::literal || p.as_rule() == Rule::boolean_literal => {
+                    self.read_const_expr(scope, p)
+                }
                 Some(ref p) if p.as_rule() == Rule::const_expr => {
                     let expr = self.read_const_expr(scope, p)?;
                     Ok(Box::new(IdlValueExpr::Brace(expr)))
@@ -507,10 +1258,12 @@ impl<'i> Context<'i> {
             // simple_declarator = { identifier }
             Rule::simple_declarator => {
                 let id = self.read_identifier(scope, &iter.next().unwrap())?;
+                let mut type_spec = type_spec.clone();
+                self.add_map_entries(scope, &id, &mut type_spec)?;
 
                 let type_dcl = Box::new(IdlTypeDcl(IdlTypeDclKind::TypeDcl(
                     id.clone(),
-                    type_spec.clone(),
+                    type_spec,
                 )));
                 self.add_type_dcl(scope, id, type_dcl)
             }
@@ -519,6 +1272,8 @@ impl<'i> Context<'i> {
             Rule::array_declarator => {
                 let id = self.read_identifier(scope, &iter.next().unwrap())?;
                 let key = id.clone();
+                let mut type_spec = type_spec.clone();
+                self.add_map_entries(scope, &id, &mut type_spec)?;
 
                 let array_sizes: Result<Vec<_>, IdlError> = iter
                     .map(|p|
@@ -528,7 +1283,7 @@ impl<'i> Context<'i> {
                                 &p.clone().into_inner().next().unwrap()))
                     .collect();
                 let array_type_spec =
-                    Box::new(IdlTypeSpec::ArrayType(type_spec.clone(), array_sizes?));
+                    Box::new(IdlTypeSpec::ArrayType(type_spec, array_sizes?));
                 let type_dcl = Box::new(IdlTypeDcl(IdlTypeDclKind::TypeDcl(id, array_type_spec)));
                 self.add_type_dcl(scope, key, type_dcl)
             }
@@ -550,6 +1305,11 @@ impl<'i> Context<'i> {
             print!("{:indent$}", "", indent = 3 * scope.len());
             println!("{:?}", pair.as_rule());
         }
+        // the annotations of a type declaration precede it
+        let annotations = match pair.as_rule() {
+            Rule::struct_def | Rule::union_def | Rule::enum_dcl => self.read_annotations(scope, &mut iter)?,
+            _ => Vec::new(),
+        };
         match pair.as_rule() {
             // module_dcl = { "module" ~ identifier ~ "{" ~ definition* ~ "}" }
             Rule::module_dcl => {
@@ -560,7 +1320,7 @@ impl<'i> Context<'i> {
                 let _ = self.lookup_module(scope);
 
                 for p in iter {
-                    let _ = self.process::<L>(scope, loader, &p);
+                    self.process::<L>(scope, loader, &p)?;
                 }
 
                 let _ = scope.pop();
@@ -568,37 +1328,167 @@ impl<'i> Context<'i> {
                 Ok(())
             }
 
-            // struct_def = { "struct" ~ identifier ~ (":" ~ scoped_name)? ~ "{" ~ member* ~ "}" }
+            // struct_def = { annotation_appl* ~ "struct" ~ identifier ~ (":" ~ scoped_name)? ~ "{" ~ member* ~ "}" }
             Rule::struct_def => {
                 let id = iter.next().unwrap().as_str().to_owned();
                 let key = id.clone();
+                // the struct may refer to itself through sequences
+                let forward_dcl = Box::new(IdlTypeDcl(IdlTypeDclKind::ForwardDcl(id.clone())));
+                self.add_type_dcl(scope, key.clone(), forward_dcl)?;
+                let mut base = None;
                 let m1: Result<Vec<Vec<Box<IdlStructMember>>>, _> = iter
-                    .map(|p| {
+                    .map(|p| match p.as_rule() {
+                        // the base struct is embedded as the first member
+                        Rule::scoped_name => {
+                            let base_name = self.read_scoped_name(scope, &p)?;
+                            let base_member = self.read_struct_base(&id, base_name.clone())?;
+                            base = Some(base_name);
+                            Ok(vec![base_member])
+                        }
                         // skip hte member-node and read sibbling directly
-                        self.read_struct_member(scope, &p)
+                        _ => self.read_struct_member(scope, &p),
                     })
                     .collect();
 
                 let m2 = m1?;
-                let members = m2.into_iter().flatten().collect::<Vec<_>>();
+                let mut members = m2.into_iter().flatten().collect::<Vec<_>>();
+                if base.is_some() && members.iter().skip(1).any(|m| m.id == STRUCT_BASE_MEMBER) {
+                    return Err(IdlError::ErrorMesg(format!(
+                        "Member {} of struct {} is reserved for its base",
+                        STRUCT_BASE_MEMBER, id
+                    )));
+                }
+                // a struct can't contain itself, recursion needs a sequence
+                self.check_complete("struct", &id, members.iter().map(|m| (&m.id, &*m.type_spec)))?;
+                for m in members.iter_mut() {
+                    let prefix = format!("{}_{}", id, m.id);
+                    self.add_map_entries(scope, &prefix, &mut m.type_spec)?;
+                }
 
+                let annotated_keys = self.annotated_keys(&members);
                 let typedcl = Box::new(IdlTypeDcl(IdlTypeDclKind::StructDcl(id, members, false)));
-                self.add_type_dcl(scope, key, typedcl)
+                self.add_type_dcl(scope, key.clone(), typedcl)?;
+                self.add_annotations(scope, &key, annotations);
+
+                // a derived struct of a topic is a topic with the keys of its base
+                let base_keys = base.and_then(|base| self.get_struct_keys(&base));
+                if let Some(keys) = base_keys {
+                    let keys = keys
+                        .iter()
+                        .map(|k| format!("{}.{}", STRUCT_BASE_MEMBER, k))
+                        .collect();
+                    self.lookup_module(scope).set_topic_and_key_flags(&key, &keys)?;
+                }
+                // the members annotated @key are keys, like a keylist pragma
+                if annotated_keys.is_empty() {
+                    Ok(())
+                } else {
+                    self.lookup_module(scope).set_topic_and_key_flags(&key, &annotated_keys)
+                }
             }
 
-            // union_def = { "union" ~ identifier ~ "switch" ~ "(" ~ switch_type_spec ~ ")" ~ "{" ~ switch_body ~ "}" }
+            // struct_forward_dcl = { "struct" ~ identifier }
+            // union_forward_dcl = { "union" ~ identifier }
+            Rule::struct_forward_dcl | Rule::union_forward_dcl => {
+                let id = self.read_identifier(scope, &iter.next().unwrap())?;
+                let key = id.clone();
+                self.add_type_dcl(scope, key, Box::new(IdlTypeDcl(IdlTypeDclKind::ForwardDcl(id))))
+            }
+
+            // union_def = { annotation_appl* ~ "union" ~ identifier ~ "switch" ~ "(" ~ switch_type_spec ~ ")" ~ "{" ~ switch_body ~ "}" }
             Rule::union_def => {
                 let id = self.read_identifier(scope, &iter.next().unwrap())?;
                 let key = id.to_owned();
+                // the union may refer to itself through sequences
+                let forward_dcl = Box::new(IdlTypeDcl(IdlTypeDclKind::ForwardDcl(id.clone())));
+                self.add_type_dcl(scope, key.clone(), forward_dcl)?;
                 let switch_type_spec = self.read_switch_type_spec(scope, &iter.next().unwrap())?;
                 let switch_body = self.read_switch_body(scope, &iter.next().unwrap())?;
+                self.check_complete(
+                    "union",
+                    &id,
+                    switch_body.iter().map(|case| (&case.elem_spec.id, &*case.elem_spec.type_spec)),
+                )?;
                 let union_def = Box::new(IdlTypeDcl(IdlTypeDclKind::UnionDcl(
                     id,
                     switch_type_spec,
                     switch_body,
                 )));
 
-                self.add_type_dcl(scope, key, union_def)
+                self.add_type_dcl(scope, key.clone(), union_def)?;
+                self.add_annotations(scope, &key, annotations);
+                Ok(())
+            }
+
+            // except_dcl = { "exception" ~ identifier ~ "{" ~ member* ~ "}" }
+            Rule::except_dcl => {
+                let id = self.read_identifier(scope, &iter.next().unwrap())?;
+                let key = id.clone();
+                let mut members = Vec::new();
+                for p in iter {
+                    members.append(&mut self.read_struct_member(scope, &p)?);
+                }
+                self.check_complete("exception", &id, members.iter().map(|m| (&m.id, &*m.type_spec)))?;
+                for m in members.iter_mut() {
+                    let prefix = format!("{}_{}", id, m.id);
+                    self.add_map_entries(scope, &prefix, &mut m.type_spec)?;
+                }
+                let typedcl = Box::new(IdlTypeDcl(IdlTypeDclKind::StructDcl(id, members, false)));
+                self.add_type_dcl(scope, key, typedcl)
+            }
+
+            // interface_def = { interface_header ~ "{" ~ interface_body ~ "}" }
+            // interface_header = { interface_kind ~ identifier ~ interface_inheritance_spec? }
+            Rule::interface_def => {
+                let mut header = iter.next().unwrap().into_inner();
+                let body = iter.next().unwrap();
+                let _kind = header.next();
+                let id = self.read_identifier(scope, &header.next().unwrap())?;
+                let mut operations: Vec<IdlOperation> = Vec::new();
+                // the operations of the base interfaces come first
+                if let Some(bases) = header.next() {
+                    for base in bases.into_inner() {
+                        let name = self.read_scoped_name(scope, &base.into_inner().next().unwrap())?;
+                        match self.root_module.get_type_decl(&name).map(|t| &t.0) {
+                            Some(IdlTypeDclKind::InterfaceDcl(_, base_operations)) => {
+                                for op in base_operations {
+                                    if !operations.iter().any(|o| o.id == op.id) {
+                                        operations.push(op.clone());
+                                    }
+                                }
+                            }
+                            _ => {
+                                return Err(IdlError::ErrorMesg(format!(
+                                    "The base {} of interface {} is not an interface",
+                                    name.0.join("::"),
+                                    id
+                                )))
+                            }
+                        }
+                    }
+                }
+                // declarations of the interface are scoped by it
+                scope.push(id.clone());
+                let result = body.into_inner().try_for_each(|export| {
+                    let p = export.into_inner().next().unwrap();
+                    match p.as_rule() {
+                        Rule::op_dcl => {
+                            operations.push(self.read_operation(scope, &p)?);
+                            Ok(())
+                        }
+                        Rule::attr_dcl | Rule::op_oneway_dcl | Rule::op_with_context => {
+                            Err(IdlError::ErrorMesg(format!(
+                                "Unsupported declaration {} of interface {}",
+                                p.as_str(),
+                                id
+                            )))
+                        }
+                        _ => self.process::<L>(scope, loader, &p),
+                    }
+                });
+                let _ = scope.pop();
+                result?;
+                self.add_interface(scope, id, operations)
             }
 
             // type_declarator = { (template_type_spec | constr_type_dcl | simple_type_spec) ~ any_declarators }
@@ -613,7 +1503,7 @@ impl<'i> Context<'i> {
                 Ok(())
             }
 
-            // enum_dcl = { "enum" ~ identifier ~ "{" ~ enumerator ~ ("," ~ enumerator)* ~ ","? ~ "}" }
+            // enum_dcl = { annotation_appl* ~ "enum" ~ identifier ~ "{" ~ enumerator ~ ("," ~ enumerator)* ~ ","? ~ "}" }
             // enumerator = { identifier }
             Rule::enum_dcl => {
                 let id = iter.next().unwrap().as_str().to_owned();
@@ -622,14 +1512,115 @@ impl<'i> Context<'i> {
                     iter.map(|p| self.read_identifier(scope, &p)).collect();
 
                 let typedcl = Box::new(IdlTypeDcl(IdlTypeDclKind::EnumDcl(id, enums?)));
+                self.add_type_dcl(scope, key.clone(), typedcl)?;
+                self.add_annotations(scope, &key, annotations);
+                Ok(())
+            }
+            // bitmask_dcl = { annotation_appl* ~ "bitmask" ~ identifier ~ "{" ~ bit_value ~ ("," ~ bit_value)* ~ "}" }
+            // bit_value = { annotation_appl* ~ identifier }
+            Rule::bitmask_dcl => {
+                let mut id = String::new();
+                let mut bit_bound = BITMASK_DEFAULT_BIT_BOUND;
+                let mut values: Vec<IdlBitValue> = Vec::new();
+                for p in iter {
+                    match p.as_rule() {
+                        Rule::annotation_appl => {
+                            if let Some(value) = self.read_integer_annotation(scope, &p, "bit_bound")? {
+                                bit_bound = value;
+                            }
+                        }
+                        Rule::identifier => id = p.as_str().to_owned(),
+                        _ => {
+                            // without @position a flag takes the bit after the previous flag
+                            let mut position = values.last().map_or(0, |v| v.position + 1);
+                            let mut flag = String::new();
+                            for q in p.into_inner() {
+                                match q.as_rule() {
+                                    Rule::annotation_appl => {
+                                        if let Some(value) = self.read_integer_annotation(scope, &q, "position")? {
+                                            position = value;
+                                        }
+                                    }
+                                    _ => flag = self.read_identifier(scope, &q)?,
+                                }
+                            }
+                            values.push(IdlBitValue { id: flag, position });
+                        }
+                    }
+                }
+                if bit_bound == 0 || bit_bound > BITS_MAX {
+                    return Err(IdlError::ErrorMesg(format!(
+                        "Invalid @bit_bound({}) of bitmask {}, it must be 1 to {}",
+                        bit_bound, id, BITS_MAX
+                    )));
+                }
+                for (idx, value) in values.iter().enumerate() {
+                    if value.position >= bit_bound
+                        || values[..idx].iter().any(|v| v.position == value.position)
+                    {
+                        return Err(IdlError::ErrorMesg(format!(
+                            "Invalid position {} of {}::{}, the bit is out of bounds or in use",
+                            value.position, id, value.id
+                        )));
+                    }
+                }
+                let key = id.clone();
+                let typedcl = Box::new(IdlTypeDcl(IdlTypeDclKind::BitmaskDcl(id, bit_bound, values)));
+                self.add_type_dcl(scope, key, typedcl)
+            }
+            // bitset_dcl = { "bitset" ~ identifier ~ (":" ~ scoped_name)? ~ "{" ~ bitfield* ~ "}" }
+            Rule::bitset_dcl => {
+                let id = self.read_identifier(scope, &iter.next().unwrap())?;
+                let mut fields: Vec<IdlBitField> = Vec::new();
+                for p in iter {
+                    match p.as_rule() {
+                        // the bits of the base bitset come first
+                        Rule::scoped_name => {
+                            let base = self.read_scoped_name(scope, &p)?;
+                            match self.root_module.get_type_decl(&base).map(|t| &t.0) {
+                                Some(IdlTypeDclKind::BitsetDcl(_, base_fields)) => {
+                                    fields.extend(base_fields.iter().cloned())
+                                }
+                                _ => {
+                                    return Err(IdlError::ErrorMesg(format!(
+                                        "The base {} of bitset {} is not a bitset",
+                                        p.as_str(),
+                                        id
+                                    )))
+                                }
+                            }
+                        }
+                        _ => fields.append(&mut self.read_bitfield(scope, &p)?),
+                    }
+                }
+                let bits: u32 = fields.iter().map(|f| f.width).sum();
+                if bits > BITS_MAX {
+                    return Err(IdlError::ErrorMesg(format!(
+                        "Bitset {} has {} bits, at most {} are supported",
+                        id, bits, BITS_MAX
+                    )));
+                }
+                let key = id.clone();
+                let typedcl = Box::new(IdlTypeDcl(IdlTypeDclKind::BitsetDcl(id, fields)));
                 self.add_type_dcl(scope, key, typedcl)
             }
             // const_dcl = { "const" ~ const_type ~ identifier ~ "=" ~ const_expr }
             Rule::const_dcl => {
-                let type_spec = self.read_type_spec(scope, &iter.next().unwrap())?;
+                let type_pair = iter.next().unwrap();
+                let is_fixed = type_pair.clone().into_inner().next().map(|p| p.as_rule())
+                    == Some(Rule::fixed_pt_const_type);
                 let id = self.read_identifier(scope, &iter.next().unwrap())?;
                 let key = id.clone();
                 let const_expr = self.read_const_expr(scope, &iter.next().unwrap())?;
+                // the digits and scale of a fixed point constant follow from its value
+                let type_spec = if is_fixed {
+                    let value = const_expr.eval_fixed(&self.root_module).ok_or_else(|| {
+                        IdlError::ErrorMesg(format!("Unable to evaluate fixed point constant {}", id))
+                    })?;
+                    Box::new(IdlTypeSpec::FixedPtType(value.digits(), value.scale))
+                } else {
+                    self.read_type_spec(scope, &type_pair)?
+                };
                 let const_dcl = Box::new(IdlConstDcl {
                     id: id,
                     typedcl: type_spec,
@@ -643,16 +1634,45 @@ impl<'i> Context<'i> {
                 match pair.clone().into_inner().nth(0) {
                     Some(ref p) => {
                         let fname = p.as_str();
+                        // the delimiter in front of the path
+                        let offset = p.clone().into_span().start() - pair.clone().into_span().start();
+                        let delimiter = pair.as_str().as_bytes()[offset - 1];
+                        let includer = self.include_stack.last().cloned().unwrap_or_default();
+                        let path = loader
+                            .resolve(fname, &includer, delimiter == b'"')
+                            .map_err(|_| IdlError::FileNotFound(fname.to_owned()))?;
+                        if self.include_stack.len() == 1 && !self.main_includes.iter().any(|(p, _)| *p == path) {
+                            self.main_includes.push((path.clone(), fname.to_owned()));
+                        }
+                        if self.include_stack.contains(&path) {
+                            let mut chain = self.include_stack.clone();
+                            chain.push(path);
+                            return Err(IdlError::ErrorMesg(format!(
+                                "Include cycle: {}",
+                                chain.join(" -> ")
+                            )));
+                        }
+                        // every file is processed once, as with #pragma once or include guards
+                        if self.processed.contains(&path) {
+                            return Ok(());
+                        }
+                        self.processed.push(path.clone());
                         let data = loader
-                            .load(fname)
+                            .load(&path)
                             .map_err(|_| IdlError::FileNotFound(fname.to_owned()))?;
 
                         let idl: Pairs<Rule> = IdlParser::parse(Rule::specification, &data)
                             .map_err(|e| IdlError::ErrorMesg(e.to_string()))?;
 
-                        for p in idl {
-                            self.process::<L>(scope, loader, &p)?;
-                        }
+                        // the declarations of the file are generated with its own output
+                        let outer = self.source.replace(fname.to_owned());
+                        self.include_stack.push(path);
+                        let result = idl
+                            .into_iter()
+                            .try_for_each(|p| self.process::<L>(scope, loader, &p));
+                        self.include_stack.pop();
+                        self.source = outer;
+                        result?;
                     }
                     _ => {}
                 }
@@ -663,8 +1683,21 @@ impl<'i> Context<'i> {
 
             // anything else
             _ => {
+                // an invalid definition is skipped, the others are still generated
                 for p in iter {
-                    let _ = self.process::<L>(scope, loader, &p);
+                    let (line, _) = p.clone().into_span().start_pos().line_col();
+                    self.line = line;
+                    match self.process::<L>(scope, loader, &p) {
+                        Ok(()) => {}
+                        Err(err @ IdlError::IncompleteType(_)) => return Err(err),
+                        Err(err) => self.diagnostics.push(Diagnostic::Error(format!(
+                            "{}:{}: {}: {:?}",
+                            self.include_stack.last().map(|f| f.as_str()).unwrap_or(""),
+                            line,
+                            p.as_str().lines().next().unwrap_or(""),
+                            err
+                        ))),
+                    }
                 }
                 Ok(())
             }
@@ -833,15 +1866,60 @@ impl<'i> Context<'i> {
     // enumerator = { identifier }
 }
 
-///
-///
-pub fn generate_with_loader<W: Write, L: IdlLoader>(
-    out: &mut W,
+/// The outputs generated from an IDL file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputKind {
+    Rust,
+    CHeader,
+    CSource,
+    // the C layout tables of the Rust layout checks
+    CLayout,
+    // an output of another backend, named by the backend
+    Other,
+}
+
+impl OutputKind {
+    /// The name of the output of the IDL file `idl_name`
+    pub fn file_name(&self, idl_name: &std::path::Path) -> String {
+        let stem = idl_name.file_stem().unwrap_or_default().to_string_lossy();
+        match self {
+            OutputKind::Rust => format!("{}.rs", stem),
+            OutputKind::CHeader => format!("{}.h", stem),
+            OutputKind::CSource => format!("{}.c", stem),
+            OutputKind::CLayout => format!("{}_layout.c", stem),
+            OutputKind::Other => stem.into_owned(),
+        }
+    }
+}
+
+/// A message of the generation. The parsing goes on after errors in
+/// definitions to report all of them, the generation then fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Diagnostic {
+    Error(String),
+    Warning(String),
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Diagnostic::Error(mesg) => write!(f, "Error! : {}", mesg),
+            Diagnostic::Warning(mesg) => write!(f, "Warning! : {}", mesg),
+        }
+    }
+}
+
+// Parse the main file `idldecl` and the files it includes
+fn parse<'i, L: IdlLoader>(
     loader: &mut L,
-    config: &Configuration,
+    config: &'i Configuration,
     idldecl: &str,
-) -> Result<(), IdlError> {
+) -> Result<Context<'i>, IdlError> {
     let mut ctx = Context::new(config);
+    // quoted includes of the main file are relative to its directory
+    let main_file = config.idl_name.canonicalize().unwrap_or_else(|_| config.idl_name.clone());
+    ctx.include_stack.push(main_file.to_string_lossy().into_owned());
+    ctx.processed = ctx.include_stack.clone();
 
     let idl: Pairs<Rule> = IdlParser::parse(Rule::specification, &idldecl)
         .map_err(|e| IdlError::ErrorMesg(e.to_string()))?;
@@ -849,64 +1927,129 @@ pub fn generate_with_loader<W: Write, L: IdlLoader>(
     let mut scope = Scope::new();
 
     for p in idl {
-        let _ = ctx.process::<L>(&mut scope, loader, &p);
+        ctx.process::<L>(&mut scope, loader, &p)?;
     }
+    Ok(ctx)
+}
 
-    if config.generate_c {
-        let idlnamestem = String::from(config.idl_name.file_stem().unwrap().to_str().unwrap());
-        let idlname = config.idl_name.file_name().unwrap().to_str().unwrap();
-
-        let mut header_name = idlnamestem.clone();
-        header_name.push_str(".h");
-
-        let file_header = std::include_str!("cdds/templates/file_header.txt")
-            .replace("<FILENAME>", &header_name)
-            .replace("<IDLNAME>", idlname)
-            .replace(
-                "<HEADERDEFINE>",
-                &crate::cdds::header_macro_name(&idlnamestem),
-            );
-        let _ = out.write(file_header.as_bytes());
-
-        let hfile_footer = std::include_str!("cdds/templates/h_file_footer.txt").replace(
-            "<HEADERDEFINE>",
-            &crate::cdds::header_macro_name(&idlnamestem),
-        );
-
-        let scope = Vec::new();
-
-        ctx.root_module
-            .write_h(out, &scope, &ctx.root_module)
-            .map_err(|_| IdlError::InternalError)?;
+/// Generate the main output of `backend`, see Backend::generate. The
+/// diagnostics of the definitions are returned, nothing is written when one
+/// of them is an error.
+pub fn generate_with_backend<W: Write, L: IdlLoader>(
+    out: &mut W,
+    loader: &mut L,
+    backend: &dyn Backend,
+    config: &Configuration,
+    idldecl: &str,
+) -> Result<Vec<Diagnostic>, IdlError> {
+    let spec = parse_ir(loader, config, idldecl)?;
+    // the output would miss the definitions with errors
+    if spec.has_errors() {
+        return Ok(spec.diagnostics);
+    }
+    if let Some(output) = backend.generate(&spec, config)?.last() {
+        out.write_all(output.contents.as_bytes())
+            .map_err(|e| IdlError::ErrorMesg(e.to_string()))?;
+    }
+    Ok(spec.diagnostics)
+}
 
-        out.write(hfile_footer.as_bytes())
-            .map(|_| ())
-            .map_err(|e| IdlError::ErrorMesg(e.to_string()))
+/// Generate the Rust file, or the C output selected by the configuration
+/// with `generate_c`: the layout tables, the descriptors or the header. The
+/// errors in definitions fail the generation, the warnings are dropped.
+pub fn generate_with_loader<W: Write, L: IdlLoader>(
+    out: &mut W,
+    loader: &mut L,
+    config: &Configuration,
+    idldecl: &str,
+) -> Result<(), IdlError> {
+    let backends = Backends::new();
+    let backend = backends
+        .get(if config.generate_c { "c" } else { "rust" })
+        .expect("the built-in backends are registered");
+    let errors: Vec<String> = generate_with_backend(out, loader, backend, config, idldecl)?
+        .iter()
+        .filter(|d| matches!(d, Diagnostic::Error(_)))
+        .map(|d| d.to_string())
+        .collect();
+    if errors.is_empty() {
+        Ok(())
     } else {
-        let _ = out.write(MODULE_PRELUDE);
-        let USE_CYCLONEDDS_SYS = include_str!("templates/use_cyclonedds.txt");
-        let _ = out.write(USE_CYCLONEDDS_SYS.as_bytes());
-        ctx.root_module
-            .write(out, 0, &ctx.root_module,&Vec::new(), config.generate_descriptors)
-            .map_err(|_| IdlError::InternalError)
+        Err(IdlError::ErrorMesg(errors.join("\n")))
     }
 }
 
+/// An IDL file given to generate_files
+#[derive(Debug, Clone)]
+pub enum IdlSource {
+    /// A file on disk
+    File(PathBuf),
+    /// The text of a file named like one on disk, the name locates its quoted includes
+    Memory(PathBuf, String),
+}
+
+/// A file generated by generate_files
+#[derive(Debug, Clone)]
+pub struct OutputFile {
+    pub name: String,
+    pub kind: OutputKind,
+    pub contents: String,
+}
+
+/// The result of generate_files
 #[derive(Debug, Clone, Default)]
-struct Loader {
-    search_path: Vec<String>,
+pub struct Generated {
+    pub outputs: Vec<OutputFile>,
+    // the resolved paths of the files read through include directives
+    pub included: Vec<String>,
+    pub diagnostics: Vec<Diagnostic>,
 }
 
-///
-fn load_from(prefix: &std::path::Path, filename: &str) -> Result<String, Error> {
-    let fullname = prefix.join(filename);
+impl Generated {
+    /// Whether a source or a definition had errors, the outputs are then
+    /// incomplete and must not be used
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(|d| matches!(d, Diagnostic::Error(_)))
+    }
+}
 
-    let mut file = File::open(fullname)?;
-    let mut data = String::new();
+/// Generate the outputs of every source from a single parse: the Rust file,
+/// and with `generate_c` the C header, the C file of the descriptors with
+/// `generate_descriptors` and the C layout tables with `layout_checks`. The
+/// `idl_name` of the configuration is replaced by the name of each source.
+pub fn generate_files<L: IdlLoader>(
+    loader: &mut L,
+    config: &Configuration,
+    sources: &[IdlSource],
+) -> Generated {
+    let mut langs = vec!["rust"];
+    if config.generate_c {
+        langs.push("c");
+    }
+    Backends::new().generate_files(&langs, loader, config, sources)
+}
 
-    file.read_to_string(&mut data)?;
+/// Parse an IDL file and the files it includes into the resolved model, see ir.
+/// Errors in definitions are diagnostics of the model and skip the
+/// definitions, see Specification::has_errors.
+pub fn parse_ir<L: IdlLoader>(
+    loader: &mut L,
+    config: &Configuration,
+    idldecl: &str,
+) -> Result<ir::Specification, IdlError> {
+    let mut ctx = parse(loader, config, idldecl)?;
+    Ok(ir::Specification {
+        root: ir::Module::from_ast(&ctx.root_module, config.wchar_size, &mut ctx.diagnostics),
+        included: ctx.processed.iter().skip(1).cloned().collect(),
+        diagnostics: ctx.diagnostics,
+        main_includes: ctx.main_includes,
+    })
+}
 
-    return Ok(data);
+/// Loads the included files from the file system
+#[derive(Debug, Clone, Default)]
+pub struct Loader {
+    search_path: Vec<String>,
 }
 
 ///
@@ -920,18 +2063,100 @@ impl Loader {
 
 ///
 impl IdlLoader for Loader {
+    // A quoted name is searched next to the includer first, then in the
+    // search path like a name in angle brackets
+    fn resolve(&self, filename: &str, includer: &str, quoted: bool) -> Result<String, Error> {
+        let includer_dir = std::path::Path::new(includer)
+            .parent()
+            .map(|dir| dir.to_path_buf())
+            .filter(|_| quoted);
+        let candidates = includer_dir
+            .into_iter()
+            .chain(self.search_path.iter().map(PathBuf::from))
+            .map(|dir| dir.join(filename));
+        for candidate in candidates {
+            if candidate.is_file() {
+                return Ok(candidate.canonicalize()?.to_string_lossy().into_owned());
+            }
+        }
+        Err(Error::from(ErrorKind::NotFound))
+    }
+
     fn load(&self, filename: &str) -> Result<String, Error> {
+        if std::path::Path::new(filename).is_absolute() {
+            return std::fs::read_to_string(filename);
+        }
         for prefix in &self.search_path {
-            let prefix_path = std::path::Path::new(&prefix);
-            match load_from(&prefix_path, filename) {
-                Ok(data) => return Ok(data),
-                _ => continue,
+            let path = std::path::Path::new(prefix).join(filename);
+            if let Ok(data) = std::fs::read_to_string(path) {
+                return Ok(data);
             }
         }
         Err(Error::from(ErrorKind::NotFound))
     }
 }
 
+/// Loads the included files from memory, e.g. for tests. A quoted name is
+/// looked up next to the includer first, then as written.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryLoader {
+    files: HashMap<String, String>,
+}
+
+impl MemoryLoader {
+    pub fn new() -> MemoryLoader {
+        MemoryLoader::default()
+    }
+
+    /// Add the file `name` holding `data`
+    pub fn add_file(&mut self, name: &str, data: &str) -> &mut MemoryLoader {
+        self.files.insert(normalize_path(std::path::Path::new(name)), String::from(data));
+        self
+    }
+}
+
+// The path without "." and ".." components, as far as they can be removed
+fn normalize_path(path: &std::path::Path) -> String {
+    use std::path::Component;
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    normalized.push("..");
+                }
+            }
+            _ => normalized.push(component),
+        }
+    }
+    normalized.to_string_lossy().into_owned()
+}
+
+impl IdlLoader for MemoryLoader {
+    fn resolve(&self, filename: &str, includer: &str, quoted: bool) -> Result<String, Error> {
+        let includer_dir = std::path::Path::new(includer).parent().filter(|_| quoted);
+        let candidates = includer_dir
+            .map(|dir| dir.join(filename))
+            .into_iter()
+            .chain(std::iter::once(PathBuf::from(filename)));
+        for candidate in candidates {
+            let name = normalize_path(&candidate);
+            if self.files.contains_key(&name) {
+                return Ok(name);
+            }
+        }
+        Err(Error::from(ErrorKind::NotFound))
+    }
+
+    fn load(&self, filename: &str) -> Result<String, Error> {
+        self.files
+            .get(&normalize_path(std::path::Path::new(filename)))
+            .cloned()
+            .ok_or_else(|| Error::from(ErrorKind::NotFound))
+    }
+}
+
 ///
 pub fn generate_with_search_path<W: Write>(
     out: &mut W,
@@ -943,3 +2168,288 @@ pub fn generate_with_search_path<W: Write>(
 
     generate_with_loader(out, &mut loader, config, data)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixture::{c_config, generate, generate_error, generate_with};
+
+    #[test]
+    fn diagnostics_are_returned() {
+        let idl = "struct A { long x; };\n#pragma keylist A y\nstruct B { long z; };";
+        let mut out = Vec::new();
+        let diagnostics = generate_with_backend(
+            &mut out,
+            &mut Loader::new(Vec::new()),
+            &RustBackend,
+            &Configuration::default(),
+            idl,
+        )
+        .unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert!(matches!(&diagnostics[0], Diagnostic::Error(_)));
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn keyword_prefixed_names() {
+        let generated = generate(
+            &Configuration::default(),
+            "struct S { long long_a; unsigned short short_b; long double_c; unsigned long long unsigned_d; };",
+        );
+        assert!(generated.contains("pub long_a: i32,"), "{}", generated);
+        assert!(generated.contains("pub short_b: u16,"));
+        assert!(generated.contains("pub double_c: i32,"));
+        assert!(generated.contains("pub unsigned_d: u64,"));
+    }
+
+    #[test]
+    fn includes() {
+        let mut files = MemoryLoader::new();
+        files.add_file(
+            "common/point.idl",
+            "module Common { struct Point { long x; long y; };
+                             struct Tagged { long id; Point p; };
+                             #pragma keylist Tagged id
+             };",
+        );
+        let idl = "#include \"common/point.idl\"
+                   module Common { struct Line { Point a; Point b; }; };
+                   module App { struct Shape { long id; Common::Line l; };
+                   #pragma keylist Shape id
+};";
+
+        // the included declarations are generated with the included file
+        let mut config = Configuration {
+            generate_c: true,
+            idl_name: std::path::PathBuf::from("shape.idl"),
+            ..Default::default()
+        };
+        let generated = generate_with(&mut files, &config, idl);
+        assert!(generated.contains("#include \"common/point.h\"\n"));
+        assert!(generated.contains("} Common_Line;"));
+        assert!(!generated.contains("} Common_Point;"));
+        config.generate_descriptors = true;
+        let generated = generate_with(&mut files, &config, idl);
+        assert!(generated.contains("App_Shape_desc ="));
+        assert!(!generated.contains("Common_Tagged_desc"));
+
+        // and used from its Rust module
+        let config = Configuration {
+            include_path: String::from("crate::idl"),
+            ..Default::default()
+        };
+        let generated = generate_with(&mut files, &config, idl);
+        assert!(generated.contains(
+            "    pub use crate::idl::point::Common::Point;\n\
+             \x20   pub use crate::idl::point::Common::Tagged;\n\
+             \x20   pub use crate::idl::point::Common::TaggedKey;\n"
+        ));
+        assert!(generated.contains("pub struct Line {"));
+        assert!(!generated.contains("pub struct Point {"));
+    }
+
+    #[test]
+    fn include_once_and_cycles() {
+        let config = Configuration::default();
+        let mut files = MemoryLoader::new();
+        files
+            .add_file("point.idl", "#pragma once\nmodule P { struct Point { long x; }; };")
+            .add_file("line.idl", "#include \"point.idl\"\nmodule L { struct Line { P::Point a; }; };")
+            .add_file("a.idl", "#include \"b.idl\"\nmodule A { struct SA { long x; }; };")
+            .add_file("b.idl", "#include \"a.idl\"\nmodule B { struct SB { long x; }; };");
+        // point.idl is processed once
+        let idl = "#include \"point.idl\"\n#include \"line.idl\"\nmodule M { struct S { L::Line l; }; };";
+        let generated = generate_with(&mut files, &config, idl);
+        assert_eq!(generated.matches("pub use crate::point::P::Point;").count(), 1);
+
+        // a cycle is an error of the include closing it, the files are processed once
+        let sources = [IdlSource::Memory(std::path::PathBuf::from("main.idl"), String::from("#include \"a.idl\""))];
+        let generated = Backends::new().generate_files(&["rust"], &mut files, &config, &sources);
+        assert!(generated.has_errors());
+        assert!(generated.diagnostics[0].to_string().contains("Include cycle"));
+        let generated = &generated.outputs[0].contents;
+        assert_eq!(generated.matches("pub use crate::b::B::SB;").count(), 1);
+        assert_eq!(generated.matches("pub use crate::a::A::SA;").count(), 1);
+    }
+
+    #[test]
+    fn include_resolution() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/include");
+        let loader = Loader::new(vec![dir.join("sys").to_string_lossy().into_owned()]);
+        let includer = dir.join("main.idl");
+        let includer = includer.to_str().unwrap();
+        let local = dir.join("types.idl").canonicalize().unwrap();
+        let system = dir.join("sys/types.idl").canonicalize().unwrap();
+
+        // quoted names are searched next to the includer first
+        let path = loader.resolve("types.idl", includer, true).unwrap();
+        assert_eq!(std::path::Path::new(&path), local);
+        let path = loader.resolve("./sys/../types.idl", includer, true).unwrap();
+        assert_eq!(std::path::Path::new(&path), local);
+        // names in angle brackets only in the search path
+        let path = loader.resolve("types.idl", includer, false).unwrap();
+        assert_eq!(std::path::Path::new(&path), system);
+        assert!(loader.resolve("missing.idl", includer, true).is_err());
+        assert!(loader.load(&path).unwrap().contains("module System"));
+    }
+
+    #[test]
+    fn generated_files() {
+        let mut files = MemoryLoader::new();
+        files
+            .add_file("idl/common/point.idl", "module P { struct Point { long x; }; };")
+            .add_file("idl/shape.idl", "#include \"common/point.idl\"\nmodule S { struct Shape { long id; P::Point p; long double d; };\n#pragma keylist Shape id\n};")
+            .add_file("idl/bad.idl", "#include \"missing.idl\"\nmodule B { struct Bad { long id; };\n#pragma keylist Bad key\n};");
+        let config = Configuration {
+            generate_c: true,
+            generate_descriptors: true,
+            ..Default::default()
+        };
+        let sources = vec![
+            IdlSource::Memory(
+                std::path::PathBuf::from("idl/shape.idl"),
+                files.load("idl/shape.idl").unwrap(),
+            ),
+            IdlSource::Memory(std::path::PathBuf::from("idl/bad.idl"), files.load("idl/bad.idl").unwrap()),
+        ];
+        let generated = generate_files(&mut files, &config, &sources);
+
+        // one parse gives every output
+        let names: Vec<(&str, OutputKind)> =
+            generated.outputs.iter().map(|o| (o.name.as_str(), o.kind)).collect();
+        assert_eq!(
+            names[..3],
+            [
+                ("shape.rs", OutputKind::Rust),
+                ("shape.h", OutputKind::CHeader),
+                ("shape.c", OutputKind::CSource)
+            ]
+        );
+        assert!(generated.outputs[0].contents.contains("pub use crate::point::P::Point;"));
+        assert!(generated.outputs[1].contents.contains("#include \"common/point.h\""));
+        assert!(generated.outputs[2].contents.contains("S_Shape_desc ="));
+        assert_eq!(generated.included, vec![String::from("idl/common/point.idl")]);
+
+        // the invalid definitions are reported, the others still generated
+        assert!(generated.has_errors());
+        assert!(generated.diagnostics.contains(&Diagnostic::Warning(String::from(
+            "long double is generated as an opaque 16 byte value"
+        ))));
+        assert!(generated.diagnostics.iter().any(|d| match d {
+            Diagnostic::Error(mesg) => mesg.contains("FileNotFound(\"missing.idl\")"),
+            _ => false,
+        }));
+        assert!(generated.outputs[3].contents.contains("pub struct Bad {"));
+    }
+
+    #[test]
+    fn explicit_width_integers() {
+        let idl = "module M { typedef long int8_t; struct I { int8 a; uint8 b; int16 c; uint64 d; int8_t e; };\n#pragma keylist I a\n};";
+        let generated = generate(&c_config(), idl);
+        assert!(generated.contains(
+            "DDS_OP_ADR | DDS_OP_TYPE_1BY | DDS_OP_FLAG_SGN | DDS_OP_FLAG_KEY, offsetof (M_I, a),"
+        ));
+        assert!(generated.contains("DDS_OP_ADR | DDS_OP_TYPE_1BY, offsetof (M_I, b),"));
+        assert!(generated.contains("DDS_OP_ADR | DDS_OP_TYPE_2BY | DDS_OP_FLAG_SGN, offsetof (M_I, c),"));
+        assert!(generated.contains("DDS_OP_ADR | DDS_OP_TYPE_8BY, offsetof (M_I, d),"));
+        assert!(generated.contains("DDS_OP_ADR | DDS_OP_TYPE_4BY | DDS_OP_FLAG_SGN, offsetof (M_I, e),"));
+        assert!(generated.contains("<Member name=\\\"a\\\"><Int8/></Member><Member name=\\\"b\\\"><UInt8/></Member>"));
+    }
+
+    #[test]
+    fn struct_inheritance() {
+        let idl = "module M { struct Base { long id; string name; };\n#pragma keylist Base id\n\
+                   struct Mid : Base { double x; }; struct D : M::Mid { long seq; };\n#pragma keylist D seq\n};";
+        let generated = generate(&c_config(), idl);
+        // the base is embedded first and its keys are inherited
+        assert!(generated.contains("{ \"parent.id\", 0 }"));
+        assert!(generated.contains("{ \"parent.parent.id\", 0 },\n  { \"seq\", 6 }"));
+        assert!(generated.contains(
+            "DDS_OP_ADR | DDS_OP_TYPE_4BY | DDS_OP_FLAG_SGN | DDS_OP_FLAG_KEY, offsetof (M_D, parent.parent.id),"
+        ));
+        assert!(generated.contains("DDS_OP_ADR | DDS_OP_TYPE_8BY | DDS_OP_FLAG_FP, offsetof (M_D, parent.x),"));
+
+        let generated = generate(&Configuration::default(), idl);
+        assert!(generated.contains("pub parent: crate::M::Mid,"));
+        assert!(generated.contains("impl ::std::ops::Deref for D {"));
+        assert!(generated.contains("parent_parent_id: self.parent.parent.id,"));
+
+        // a base key named by the derived keylist, and a member shadowing the base
+        let generated = generate(&c_config(), "module M { struct B { long id; }; struct D : B { long v; };\n#pragma keylist D id v\n};");
+        assert!(generated.contains("{ \"parent.id\", 0 },\n  { \"v\", 2 }"));
+        let errors = generate_error(&c_config(), "module M { struct B { long id; }; struct E : B { long parent; }; };");
+        assert!(errors.contains("Member parent of struct E is reserved for its base"), "{}", errors);
+    }
+
+    #[test]
+    fn recursive_types() {
+        let idl = "module M { struct Node; struct Node { long id; sequence<Node> children; };\n#pragma keylist Node id\n\
+                   struct Tree { long key; Node root; };\n#pragma keylist Tree key\n\
+                   struct A; struct B { sequence<A> a; }; struct A { long x; sequence<B> b; };\n#pragma keylist A x\n};";
+        let generated = generate(&c_config(), idl);
+        // the element ops jump back to the ops of the topic
+        assert!(generated.contains(
+            "  DDS_OP_ADR | DDS_OP_TYPE_SEQ | DDS_OP_SUBTYPE_STU, offsetof (M_Node, children), sizeof (M_Node), (6u << 16u) + 4u,\n\
+             \x20   DDS_OP_JSR | (65536 - 6),\n    DDS_OP_RTS,\n  DDS_OP_RTS\n"
+        ));
+        // a nested struct is expanded once as element, then jumps back to it
+        assert!(generated.contains(
+            "offsetof (M_Tree, root.children), sizeof (M_Node), (13u << 16u) + 4u,\n\
+             \x20   DDS_OP_ADR | DDS_OP_TYPE_4BY | DDS_OP_FLAG_SGN, offsetof (M_Node, id),\n\
+             \x20   DDS_OP_ADR | DDS_OP_TYPE_SEQ | DDS_OP_SUBTYPE_STU, offsetof (M_Node, children), sizeof (M_Node), (6u << 16u) + 4u,\n\
+             \x20     DDS_OP_JSR | (65536 - 6),\n"
+        ));
+        assert!(generated.contains("      DDS_OP_JSR | (65536 - 10),\n"));
+
+        // a type can only contain itself through a sequence, otherwise the
+        // generation stops
+        for idl in &[
+            "module M { struct N; struct P { N n[2]; }; };",
+            "module M { union U switch (long) { case 1: U u; }; };",
+            "module M { union U; struct S { map<long, U> m; }; };",
+            "module M { module A { struct N; exception E { N n; }; }; struct Q { long q; }; };",
+        ] {
+            let mesg = generate_error(&Configuration::default(), idl);
+            assert!(mesg.starts_with(":1: "), "{}", mesg);
+        }
+        let idl = "module M { union U switch (long) { case 1: sequence<U> u; }; };";
+        assert!(generate(&Configuration::default(), idl).contains("pub enum U {"));
+
+        // the element type of a topic must be defined
+        let idl = "module M { struct N; struct Q { long k; sequence<N> n; };\n#pragma keylist Q k\n};";
+        let mesg = generate_error(&c_config(), idl);
+        assert!(mesg.contains("N"), "{}", mesg);
+    }
+
+    #[test]
+    fn maps() {
+        let idl = "module M { struct Leaf { long a; };
+                   struct S { long id; map<string, Leaf, 4> leaves; map<int16, map<uint32, string>> deep; };
+                   #pragma keylist S id
+};";
+        let generated = generate(&c_config(), idl);
+        // a map is a sequence of key/value entries
+        assert!(generated.contains(
+            "  DDS_OP_ADR | DDS_OP_TYPE_SEQ | DDS_OP_SUBTYPE_STU, offsetof (M_S, leaves), sizeof (M_S_leaves_entry), (9u << 16u) + 4u,\n\
+             \x20   DDS_OP_ADR | DDS_OP_TYPE_STR, offsetof (M_S_leaves_entry, key),\n\
+             \x20   DDS_OP_ADR | DDS_OP_TYPE_4BY | DDS_OP_FLAG_SGN, offsetof (M_S_leaves_entry, value.a),\n\
+             \x20   DDS_OP_RTS,\n"
+        ));
+        assert!(generated.contains(
+            "offsetof (M_S_deep_entry, value), sizeof (M_S_deep_entry_value_entry), (9u << 16u) + 4u,\n"
+        ));
+        assert!(generated.contains(
+            "<Member name=\\\"leaves\\\"><Sequence size=\\\"4\\\"><Type name=\\\"S_leaves_entry\\\"/></Sequence></Member>"
+        ));
+
+        // the Rust map is the sequence of the entries of the C header
+        let generated = generate(&Configuration::default(), idl);
+        assert!(generated.contains("pub leaves: dds_sequence_t,"), "{}", generated);
+        assert!(generated.contains("pub struct S_leaves_entry {\n        pub key: *mut ::std::os::raw::c_char,\n        pub value: crate::M::Leaf,\n    }"));
+
+        // keys must be integers or strings
+        let errors = generate_error(&c_config(), "module M { struct F { long k; map<double, long> m; };\n#pragma keylist F k\n};");
+        assert!(errors.contains("map keys must be integers or strings"), "{}", errors);
+    }
+}
@@ -0,0 +1,144 @@
+// Copyright (C) 2020  Sojan James
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0>
+
+/* The filters of templates.
+
+    lower, upper            the case of a string
+    snake_case, screaming_snake_case, camel_case, lower_camel_case
+                            a name in a naming convention, the words of the
+                            name are split at _ and at case changes
+    replace(from, to)       the string with each from replaced
+    join(sep)               the items of a list joined by sep, "" by default
+    map(attribute)          the attribute of each item of a list
+    length, first, last     of a list, length of a string as well
+    default(value)          value if none or ""
+    c_type, rust_type       the type of the generated C and Rust code
+    c_dims                  the dimensions following the name of a C member,
+                            [2][3] of arrays and the BCD bytes of fixed
+*/
+
+use super::Value;
+use crate::ast::fixed_size;
+use crate::cdds::{c_type_name, rust_type};
+use crate::ir::TypeSpec;
+use crate::Configuration;
+
+pub(crate) fn apply(name: &str, value: Value, args: &[Value], config: &Configuration) -> Result<Value, String> {
+    let expects = |what: &str, value: &Value| format!("{} expects {}, found {}", name, what, value.describe());
+    let text = |value: Value| match value {
+        Value::String(s) => Ok(s),
+        value => Err(expects("a string", &value)),
+    };
+    let arg = |index: usize| match args.get(index) {
+        Some(Value::String(s)) => Ok(s.as_str()),
+        Some(value) => Err(format!("{} expects a string argument, found {}", name, value.describe())),
+        None => Err(format!("{} expects {} arguments", name, index + 1)),
+    };
+
+    Ok(match name {
+        "lower" => Value::String(text(value)?.to_lowercase()),
+        "upper" => Value::String(text(value)?.to_uppercase()),
+        "snake_case" => Value::String(words(&text(value)?).join("_").to_lowercase()),
+        "screaming_snake_case" => Value::String(words(&text(value)?).join("_").to_uppercase()),
+        "camel_case" => Value::String(words(&text(value)?).iter().map(|w| capitalize(w)).collect()),
+        "lower_camel_case" => {
+            let words = words(&text(value)?);
+            let mut name = words.first().map(|w| w.to_lowercase()).unwrap_or_default();
+            name.extend(words.iter().skip(1).map(|w| capitalize(w)));
+            Value::String(name)
+        }
+        "replace" => Value::String(text(value)?.replace(arg(0)?, arg(1)?)),
+        "join" => match value {
+            Value::List(items) => {
+                let sep = if args.is_empty() { "" } else { arg(0)? };
+                let items = items.iter().map(|item| item.to_text()).collect::<Result<Vec<_>, _>>()?;
+                Value::String(items.join(sep))
+            }
+            value => return Err(expects("a list", &value)),
+        },
+        "length" => match value {
+            Value::List(items) => Value::Integer(items.len() as i64),
+            Value::String(s) => Value::Integer(s.chars().count() as i64),
+            value => return Err(expects("a list", &value)),
+        },
+        "first" | "last" => match value {
+            Value::List(mut items) if name == "first" => items.drain(..).next().unwrap_or(Value::Null),
+            Value::List(mut items) => items.pop().unwrap_or(Value::Null),
+            value => return Err(expects("a list", &value)),
+        },
+        "default" => match value {
+            Value::Null => args.first().cloned().unwrap_or(Value::Null),
+            Value::String(ref s) if s.is_empty() => args.first().cloned().unwrap_or(Value::Null),
+            value => value,
+        },
+        "c_type" | "rust_type" | "c_dims" => match value {
+            Value::Type(ref spec) if name == "c_type" => Value::String(c_type_name(spec)),
+            Value::Type(ref spec) if name == "rust_type" => Value::String(rust_type(spec, config.wchar_size)),
+            Value::Type(ref spec) => Value::String(c_dims(spec)),
+            value => return Err(expects("a type", &value)),
+        },
+        _ => return Err(format!("unknown filter {}", name)),
+    })
+}
+
+// HTTPServer2Id is HTTP, Server2, Id
+fn words(name: &str) -> Vec<String> {
+    let chars = name.chars().collect::<Vec<_>>();
+    let mut words = Vec::new();
+    let mut word = String::new();
+    for (i, c) in chars.iter().enumerate() {
+        if *c == '_' || *c == '-' || c.is_whitespace() {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            continue;
+        }
+        if c.is_uppercase() && !word.is_empty() {
+            let prev = chars[i - 1];
+            let next_lower = chars.get(i + 1).is_some_and(|next| next.is_lowercase());
+            if prev.is_lowercase() || prev.is_ascii_digit() || (prev.is_uppercase() && next_lower) {
+                words.push(std::mem::take(&mut word));
+            }
+        }
+        word.push(*c);
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars.flat_map(|c| c.to_lowercase())).collect(),
+        None => String::new(),
+    }
+}
+
+fn c_dims(spec: &TypeSpec) -> String {
+    match spec {
+        TypeSpec::Array(elem, dims) => {
+            let dims = dims.iter().map(|dim| format!("[{}]", dim)).collect::<String>();
+            dims + &c_dims(elem)
+        }
+        TypeSpec::Fixed { digits, .. } => format!("[{}]", fixed_size(*digits)),
+        TypeSpec::String(Some(bound)) => format!("[{}]", bound + 1),
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::words;
+
+    #[test]
+    fn naming_words() {
+        assert_eq!(words("shape_id"), vec!["shape", "id"]);
+        assert_eq!(words("ShapeId"), vec!["Shape", "Id"]);
+        assert_eq!(words("HTTPServer2Id"), vec!["HTTP", "Server2", "Id"]);
+        assert_eq!(words("MAX_SIZE"), vec!["MAX", "SIZE"]);
+    }
+}
@@ -0,0 +1,841 @@
+// Copyright (C) 2020  Sojan James
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0>
+
+/* A backend rendering user templates against the IR.
+
+ Each file of a template directory is a template of one output, the file name
+ is rendered as well, so `{{ idl }}_topics.h` of shape.idl writes
+ shape_topics.h. The syntax is a small subset of Jinja:
+
+    {{ expr }}                      the value of expr
+    {% for x in expr %}..{% endfor %}
+    {% if expr %}..{% elif expr %}..{% else %}..{% endif %}
+    {# comment #}
+
+ Expressions are names and attributes (`type.members`), string and integer
+ literals, true, false and none, the comparisons == != < <= > >=, and, or,
+ not, and filters (`type.name | snake_case`, `path | join("::")`). Inside a
+ loop `loop.index`, `loop.index0`, `loop.first`, `loop.last` and
+ `loop.length` describe the iteration. A line holding only a {% %} or {# #}
+ tag produces no output.
+
+ The names of the model are described in model, the filters in filters.
+*/
+
+mod filters;
+mod model;
+
+use crate::backend::Backend;
+use crate::ir::{Module, Specification, TypeSpec};
+use crate::{Configuration, IdlError, OutputFile, OutputKind};
+
+use std::path::Path;
+
+/// Renders the templates of a directory, an output per template
+pub struct TemplateBackend {
+    name: String,
+    templates: Vec<Template>,
+}
+
+struct Template {
+    file_name: String,
+    // the name of the output and its contents
+    name: Vec<Node>,
+    body: Vec<Node>,
+}
+
+impl TemplateBackend {
+    /// A backend without templates, selected by `name`
+    pub fn new(name: &str) -> TemplateBackend {
+        TemplateBackend {
+            name: String::from(name),
+            templates: Vec::new(),
+        }
+    }
+
+    /// The templates of the files of `dir` in the order of their names, the
+    /// last one is the main output
+    pub fn from_dir<P: AsRef<Path>>(name: &str, dir: P) -> Result<TemplateBackend, IdlError> {
+        let dir = dir.as_ref();
+        let entries = std::fs::read_dir(dir)
+            .map_err(|e| IdlError::ErrorMesg(format!("{}: {}", dir.display(), e)))?;
+        let mut files = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file())
+            .filter(|path| !path.file_name().unwrap().to_string_lossy().starts_with('.'))
+            .collect::<Vec<_>>();
+        files.sort();
+
+        let mut backend = TemplateBackend::new(name);
+        for path in files {
+            let source = std::fs::read_to_string(&path)
+                .map_err(|e| IdlError::ErrorMesg(format!("{}: {}", path.display(), e)))?;
+            backend.add(&path.file_name().unwrap().to_string_lossy(), &source)?;
+        }
+        Ok(backend)
+    }
+
+    /// Add the template `source` of the output `file_name`, after the
+    /// templates added before
+    pub fn add(&mut self, file_name: &str, source: &str) -> Result<(), IdlError> {
+        let error = |e: TemplateError| IdlError::ErrorMesg(format!("{}:{}: {}", file_name, e.line, e.message));
+        self.templates.push(Template {
+            file_name: String::from(file_name),
+            name: parse(file_name).map_err(error)?,
+            body: parse(source).map_err(error)?,
+        });
+        Ok(())
+    }
+}
+
+impl Backend for TemplateBackend {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn generate(&self, spec: &Specification, config: &Configuration) -> Result<Vec<OutputFile>, IdlError> {
+        let context = model::context(spec, config);
+        let mut outputs = Vec::new();
+        for template in self.templates.iter() {
+            let error =
+                |e: TemplateError| IdlError::ErrorMesg(format!("{}:{}: {}", template.file_name, e.line, e.message));
+            let mut renderer = Renderer::new(&spec.root, config, context.clone());
+            let name = renderer.render(&template.name).map_err(error)?;
+            let contents = renderer.render(&template.body).map_err(error)?;
+            outputs.push(OutputFile {
+                name,
+                kind: OutputKind::Other,
+                contents,
+            });
+        }
+        Ok(outputs)
+    }
+}
+
+/// A value of the model
+#[derive(Debug, Clone)]
+pub(crate) enum Value {
+    Null,
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+    List(Vec<Value>),
+    Object(Vec<(&'static str, Value)>),
+    /// A type, its attributes are computed on access, see model::type_attribute
+    Type(TypeSpec),
+}
+
+impl Value {
+    fn is_true(&self) -> bool {
+        match self {
+            Value::Null => false,
+            Value::Bool(b) => *b,
+            Value::Integer(n) => *n != 0,
+            Value::Float(f) => *f != 0.0,
+            Value::String(s) => !s.is_empty(),
+            Value::List(items) => !items.is_empty(),
+            Value::Object(_) | Value::Type(_) => true,
+        }
+    }
+
+    fn to_text(&self) -> Result<String, String> {
+        Ok(match self {
+            Value::Null => String::new(),
+            Value::Bool(b) => b.to_string(),
+            Value::Integer(n) => n.to_string(),
+            Value::Float(f) => f.to_string(),
+            Value::String(s) => s.clone(),
+            Value::List(items) => items
+                .iter()
+                .map(|item| item.to_text())
+                .collect::<Result<Vec<_>, _>>()?
+                .join(", "),
+            Value::Object(_) => return Err(String::from("can't print an object")),
+            Value::Type(spec) => model::idl_type(spec),
+        })
+    }
+
+    fn equals(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Null, Value::Null) => true,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Integer(a), Value::Integer(b)) => a == b,
+            (Value::Float(a), Value::Float(b)) => a == b,
+            (Value::Integer(a), Value::Float(b)) | (Value::Float(b), Value::Integer(a)) => *a as f64 == *b,
+            (Value::String(a), Value::String(b)) => a == b,
+            // a type equals its IDL spelling
+            (Value::Type(a), Value::Type(b)) => a == b,
+            (Value::Type(spec), Value::String(s)) | (Value::String(s), Value::Type(spec)) => {
+                &model::idl_type(spec) == s
+            }
+            (Value::List(a), Value::List(b)) => {
+                a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| a.equals(b))
+            }
+            _ => false,
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            Value::Null => "none",
+            Value::Bool(_) => "a boolean",
+            Value::Integer(_) => "an integer",
+            Value::Float(_) => "a float",
+            Value::String(_) => "a string",
+            Value::List(_) => "a list",
+            Value::Object(_) => "an object",
+            Value::Type(_) => "a type",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TemplateError {
+    line: usize,
+    message: String,
+}
+
+fn error<T>(line: usize, message: String) -> Result<T, TemplateError> {
+    Err(TemplateError { line, message })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Compare {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug)]
+enum Expr {
+    Literal(Value),
+    /// A name and its attributes
+    Path(Vec<String>),
+    Filter(Box<Expr>, String, Vec<Expr>),
+    Compare(Box<Expr>, Compare, Box<Expr>),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug)]
+enum Node {
+    Text(String),
+    Print(Expr, usize),
+    /// The conditions with their line and branch, and the else branch
+    If(Vec<(Expr, usize, Vec<Node>)>, Vec<Node>),
+    For(String, Expr, usize, Vec<Node>),
+}
+
+// The pieces of a template between tags
+enum Piece {
+    Text(String),
+    Print(String, usize),
+    Tag(String, usize),
+}
+
+fn split(source: &str) -> Result<Vec<Piece>, TemplateError> {
+    let mut pieces = Vec::new();
+    let mut text = String::new();
+    let mut rest = source;
+    let mut line = 1;
+    // nothing but blanks precede rest on its line
+    let mut line_start = true;
+    while let Some(start) = rest.find('{') {
+        let before = &rest[..start];
+        let close = match &rest[start..] {
+            s if s.starts_with("{{") => "}}",
+            s if s.starts_with("{%") => "%}",
+            s if s.starts_with("{#") => "#}",
+            _ => {
+                text.push_str(&rest[..=start]);
+                line += before.matches('\n').count();
+                line_start = false;
+                rest = &rest[start + 1..];
+                continue;
+            }
+        };
+        line += before.matches('\n').count();
+        let end = match rest[start + 2..].find(close) {
+            Some(end) => start + 2 + end,
+            None => return error(line, format!("unclosed {}", &rest[start..start + 2])),
+        };
+        let inner = &rest[start + 2..end];
+        let mut after = &rest[end + 2..];
+
+        let tag_line = line;
+        let indent = before.rfind('\n').map(|i| &before[i + 1..]).unwrap_or(before);
+        let alone = (line_start || before.contains('\n')) && indent.chars().all(|c| c == ' ' || c == '\t');
+        line_start = false;
+        if close != "}}" && alone {
+            // a block tag alone on its line leaves no line behind
+            text.push_str(&before[..before.len() - indent.len()]);
+            if after.starts_with('\n') || after.starts_with("\r\n") {
+                after = &after[after.find('\n').unwrap() + 1..];
+                line += 1;
+                line_start = true;
+            }
+        } else {
+            text.push_str(before);
+        }
+        if !text.is_empty() {
+            pieces.push(Piece::Text(std::mem::take(&mut text)));
+        }
+        match close {
+            "}}" => pieces.push(Piece::Print(String::from(inner.trim()), tag_line)),
+            "%}" => pieces.push(Piece::Tag(String::from(inner.trim()), tag_line)),
+            _ => {}
+        }
+        line += inner.matches('\n').count();
+        rest = after;
+    }
+    text.push_str(rest);
+    if !text.is_empty() {
+        pieces.push(Piece::Text(text));
+    }
+    Ok(pieces)
+}
+
+fn parse(source: &str) -> Result<Vec<Node>, TemplateError> {
+    let mut pieces = split(source)?.into_iter();
+    let (nodes, end) = parse_block(&mut pieces)?;
+    match end {
+        Some((tag, line)) => error(line, format!("unexpected {{% {} %}}", tag)),
+        None => Ok(nodes),
+    }
+}
+
+// A tag and its line
+type Tag = (String, usize);
+
+// The nodes up to the next elif, else, endif or endfor tag, and that tag
+fn parse_block<I: Iterator<Item = Piece>>(pieces: &mut I) -> Result<(Vec<Node>, Option<Tag>), TemplateError> {
+    let mut nodes = Vec::new();
+    while let Some(piece) = pieces.next() {
+        match piece {
+            Piece::Text(text) => nodes.push(Node::Text(text)),
+            Piece::Print(expr, line) => nodes.push(Node::Print(parse_expr(&expr, line)?, line)),
+            Piece::Tag(tag, line) => {
+                let (keyword, args) = match tag.find(char::is_whitespace) {
+                    Some(i) => (&tag[..i], tag[i..].trim()),
+                    None => (tag.as_str(), ""),
+                };
+                match keyword {
+                    "if" => nodes.push(parse_if(pieces, args, line)?),
+                    "for" => nodes.push(parse_for(pieces, args, line)?),
+                    "elif" | "else" | "endif" | "endfor" => return Ok((nodes, Some((tag, line)))),
+                    _ => return error(line, format!("unknown tag {{% {} %}}", tag)),
+                }
+            }
+        }
+    }
+    Ok((nodes, None))
+}
+
+fn parse_if<I: Iterator<Item = Piece>>(pieces: &mut I, cond: &str, line: usize) -> Result<Node, TemplateError> {
+    let mut branches = Vec::new();
+    let mut cond = (parse_expr(cond, line)?, line);
+    loop {
+        let (nodes, end) = parse_block(pieces)?;
+        branches.push((cond.0, cond.1, nodes));
+        match end {
+            Some((ref tag, line)) if tag.starts_with("elif") => {
+                cond = (parse_expr(tag["elif".len()..].trim(), line)?, line);
+            }
+            Some((ref tag, line)) if tag == "else" => {
+                let (nodes, end) = parse_block(pieces)?;
+                return match end {
+                    Some((ref tag, _)) if tag == "endif" => Ok(Node::If(branches, nodes)),
+                    Some((tag, line)) => error(line, format!("expected {{% endif %}}, found {{% {} %}}", tag)),
+                    None => error(line, String::from("missing {% endif %}")),
+                };
+            }
+            Some((ref tag, _)) if tag == "endif" => return Ok(Node::If(branches, Vec::new())),
+            Some((tag, line)) => return error(line, format!("expected {{% endif %}}, found {{% {} %}}", tag)),
+            None => return error(line, String::from("missing {% endif %}")),
+        }
+    }
+}
+
+fn parse_for<I: Iterator<Item = Piece>>(pieces: &mut I, args: &str, line: usize) -> Result<Node, TemplateError> {
+    let mut parts = args.splitn(3, char::is_whitespace);
+    let (var, expr) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(var), Some("in"), Some(expr)) if is_identifier(var) => (var, expr),
+        _ => return error(line, format!("expected {{% for <name> in <expr> %}}, found {{% for {} %}}", args)),
+    };
+    let expr = parse_expr(expr, line)?;
+    let (body, end) = parse_block(pieces)?;
+    match end {
+        Some((ref tag, _)) if tag == "endfor" => Ok(Node::For(String::from(var), expr, line, body)),
+        Some((tag, line)) => error(line, format!("expected {{% endfor %}}, found {{% {} %}}", tag)),
+        None => error(line, String::from("missing {% endfor %}")),
+    }
+}
+
+fn is_identifier(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Int(i64),
+    Sym(&'static str),
+}
+
+const SYMBOLS: [&str; 11] = ["==", "!=", "<=", ">=", "<", ">", "|", "(", ")", ",", "."];
+
+fn tokenize(expr: &str, line: usize) -> Result<Vec<Token>, TemplateError> {
+    let mut tokens = Vec::new();
+    let mut rest = expr.trim_start();
+    while !rest.is_empty() {
+        let c = rest.chars().next().unwrap();
+        if c == '"' || c == '\'' {
+            let end = match rest[1..].find(c) {
+                Some(end) => end + 1,
+                None => return error(line, format!("unclosed string in {}", expr)),
+            };
+            tokens.push(Token::Str(String::from(&rest[1..end])));
+            rest = &rest[end + 1..];
+        } else if c.is_ascii_digit() {
+            let end = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+            match rest[..end].parse() {
+                Ok(n) => tokens.push(Token::Int(n)),
+                Err(_) => return error(line, format!("integer out of range in {}", expr)),
+            }
+            rest = &rest[end..];
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Ident(String::from(&rest[..end])));
+            rest = &rest[end..];
+        } else {
+            match SYMBOLS.iter().find(|s| rest.starts_with(*s)) {
+                Some(s) => {
+                    tokens.push(Token::Sym(s));
+                    rest = &rest[s.len()..];
+                }
+                None => return error(line, format!("unexpected {} in {}", c, expr)),
+            }
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+fn parse_expr(expr: &str, line: usize) -> Result<Expr, TemplateError> {
+    if expr.trim().is_empty() {
+        return error(line, String::from("missing expression"));
+    }
+    let mut parser = ExprParser {
+        tokens: tokenize(expr, line)?,
+        pos: 0,
+        source: expr,
+        line,
+    };
+    let parsed = parser.or()?;
+    match parser.tokens.get(parser.pos) {
+        Some(token) => parser.unexpected(token.clone()),
+        None => Ok(parsed),
+    }
+}
+
+struct ExprParser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    source: &'a str,
+    line: usize,
+}
+
+impl<'a> ExprParser<'a> {
+    fn peek_is(&self, token: &Token) -> bool {
+        self.tokens.get(self.pos) == Some(token)
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let found = self.peek_is(&Token::Ident(String::from(keyword)));
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn symbol(&mut self, symbol: &'static str) -> bool {
+        let found = self.peek_is(&Token::Sym(symbol));
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn next(&mut self) -> Result<Token, TemplateError> {
+        match self.tokens.get(self.pos) {
+            Some(token) => {
+                self.pos += 1;
+                Ok(token.clone())
+            }
+            None => error(self.line, format!("unexpected end of {}", self.source)),
+        }
+    }
+
+    fn unexpected<T>(&self, token: Token) -> Result<T, TemplateError> {
+        let token = match token {
+            Token::Ident(s) | Token::Str(s) => s,
+            Token::Int(n) => n.to_string(),
+            Token::Sym(s) => String::from(s),
+        };
+        error(self.line, format!("unexpected {} in {}", token, self.source))
+    }
+
+    fn identifier(&mut self) -> Result<String, TemplateError> {
+        match self.next()? {
+            Token::Ident(id) => Ok(id),
+            token => self.unexpected(token),
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, TemplateError> {
+        let mut expr = self.and()?;
+        while self.keyword("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, TemplateError> {
+        let mut expr = self.not()?;
+        while self.keyword("and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, TemplateError> {
+        if self.keyword("not") {
+            Ok(Expr::Not(Box::new(self.not()?)))
+        } else {
+            self.compare()
+        }
+    }
+
+    fn compare(&mut self) -> Result<Expr, TemplateError> {
+        let lhs = self.filtered()?;
+        let op = match self.tokens.get(self.pos) {
+            Some(Token::Sym("==")) => Compare::Eq,
+            Some(Token::Sym("!=")) => Compare::Ne,
+            Some(Token::Sym("<")) => Compare::Lt,
+            Some(Token::Sym("<=")) => Compare::Le,
+            Some(Token::Sym(">")) => Compare::Gt,
+            Some(Token::Sym(">=")) => Compare::Ge,
+            _ => return Ok(lhs),
+        };
+        self.pos += 1;
+        Ok(Expr::Compare(Box::new(lhs), op, Box::new(self.filtered()?)))
+    }
+
+    fn filtered(&mut self) -> Result<Expr, TemplateError> {
+        let mut expr = self.primary()?;
+        while self.symbol("|") {
+            let name = self.identifier()?;
+            let mut args = Vec::new();
+            if self.symbol("(") && !self.symbol(")") {
+                loop {
+                    args.push(self.or()?);
+                    if self.symbol(")") {
+                        break;
+                    }
+                    if !self.symbol(",") {
+                        let token = self.next()?;
+                        return self.unexpected(token);
+                    }
+                }
+            }
+            expr = Expr::Filter(Box::new(expr), name, args);
+        }
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> Result<Expr, TemplateError> {
+        match self.next()? {
+            Token::Str(s) => Ok(Expr::Literal(Value::String(s))),
+            Token::Int(n) => Ok(Expr::Literal(Value::Integer(n))),
+            Token::Sym("(") => {
+                let expr = self.or()?;
+                if self.symbol(")") {
+                    Ok(expr)
+                } else {
+                    let token = self.next()?;
+                    self.unexpected(token)
+                }
+            }
+            Token::Ident(ref id) if id == "true" => Ok(Expr::Literal(Value::Bool(true))),
+            Token::Ident(ref id) if id == "false" => Ok(Expr::Literal(Value::Bool(false))),
+            Token::Ident(ref id) if id == "none" => Ok(Expr::Literal(Value::Null)),
+            Token::Ident(id) => {
+                let mut path = vec![id];
+                while self.symbol(".") {
+                    path.push(self.identifier()?);
+                }
+                Ok(Expr::Path(path))
+            }
+            token => self.unexpected(token),
+        }
+    }
+}
+
+struct Renderer<'a> {
+    root: &'a Module,
+    config: &'a Configuration,
+    // the variables, the names of loops shadow the names outside
+    scopes: Vec<(String, Value)>,
+}
+
+impl<'a> Renderer<'a> {
+    fn new(root: &'a Module, config: &'a Configuration, context: Vec<(&'static str, Value)>) -> Renderer<'a> {
+        Renderer {
+            root,
+            config,
+            scopes: context.into_iter().map(|(name, value)| (String::from(name), value)).collect(),
+        }
+    }
+
+    fn render(&mut self, nodes: &[Node]) -> Result<String, TemplateError> {
+        let mut out = String::new();
+        self.render_nodes(nodes, &mut out)?;
+        Ok(out)
+    }
+
+    fn render_nodes(&mut self, nodes: &[Node], out: &mut String) -> Result<(), TemplateError> {
+        for node in nodes {
+            match node {
+                Node::Text(text) => out.push_str(text),
+                Node::Print(expr, line) => {
+                    let value = self.eval(expr, *line)?;
+                    out.push_str(&value.to_text().or_else(|e| error(*line, e))?);
+                }
+                Node::If(branches, otherwise) => {
+                    let mut taken = None;
+                    for (cond, line, nodes) in branches.iter() {
+                        if self.eval(cond, *line)?.is_true() {
+                            taken = Some(nodes);
+                            break;
+                        }
+                    }
+                    self.render_nodes(taken.unwrap_or(otherwise), out)?;
+                }
+                Node::For(var, expr, line, body) => {
+                    let items = match self.eval(expr, *line)? {
+                        Value::List(items) => items,
+                        Value::Null => Vec::new(),
+                        value => return error(*line, format!("can't loop over {}", value.describe())),
+                    };
+                    let length = items.len();
+                    for (index, item) in items.into_iter().enumerate() {
+                        let state = Value::Object(vec![
+                            ("index", Value::Integer(index as i64 + 1)),
+                            ("index0", Value::Integer(index as i64)),
+                            ("first", Value::Bool(index == 0)),
+                            ("last", Value::Bool(index + 1 == length)),
+                            ("length", Value::Integer(length as i64)),
+                        ]);
+                        self.scopes.push((String::from("loop"), state));
+                        self.scopes.push((var.clone(), item));
+                        let rendered = self.render_nodes(body, out);
+                        self.scopes.truncate(self.scopes.len() - 2);
+                        rendered?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn eval(&self, expr: &Expr, line: usize) -> Result<Value, TemplateError> {
+        Ok(match expr {
+            Expr::Literal(value) => value.clone(),
+            Expr::Path(path) => {
+                let mut value = match self.scopes.iter().rev().find(|(name, _)| name == &path[0]) {
+                    Some((_, value)) => value.clone(),
+                    None => return error(line, format!("unknown name {}", path[0])),
+                };
+                for attribute in path[1..].iter() {
+                    value = self.attribute(&value, attribute);
+                }
+                value
+            }
+            Expr::Filter(expr, name, args) => {
+                let value = self.eval(expr, line)?;
+                let args = args
+                    .iter()
+                    .map(|arg| self.eval(arg, line))
+                    .collect::<Result<Vec<_>, _>>()?;
+                match (name.as_str(), value, args.as_slice()) {
+                    // the attribute of each item
+                    ("map", Value::List(items), [Value::String(attribute)]) => {
+                        Value::List(items.iter().map(|item| self.attribute(item, attribute)).collect())
+                    }
+                    (_, value, args) => filters::apply(name, value, args, self.config).or_else(|e| error(line, e))?,
+                }
+            }
+            Expr::Compare(lhs, op, rhs) => {
+                let (lhs, rhs) = (self.eval(lhs, line)?, self.eval(rhs, line)?);
+                Value::Bool(match op {
+                    Compare::Eq => lhs.equals(&rhs),
+                    Compare::Ne => !lhs.equals(&rhs),
+                    _ => {
+                        let ordering = match (&lhs, &rhs) {
+                            (Value::Integer(a), Value::Integer(b)) => a.cmp(b),
+                            (Value::String(a), Value::String(b)) => a.cmp(b),
+                            _ => {
+                                return error(
+                                    line,
+                                    format!("can't compare {} with {}", lhs.describe(), rhs.describe()),
+                                )
+                            }
+                        };
+                        match op {
+                            Compare::Lt => ordering.is_lt(),
+                            Compare::Le => ordering.is_le(),
+                            Compare::Gt => ordering.is_gt(),
+                            _ => ordering.is_ge(),
+                        }
+                    }
+                })
+            }
+            Expr::Not(expr) => Value::Bool(!self.eval(expr, line)?.is_true()),
+            Expr::And(lhs, rhs) => Value::Bool(self.eval(lhs, line)?.is_true() && self.eval(rhs, line)?.is_true()),
+            Expr::Or(lhs, rhs) => Value::Bool(self.eval(lhs, line)?.is_true() || self.eval(rhs, line)?.is_true()),
+        })
+    }
+
+    // A missing attribute is none, so the attributes of one kind of
+    // declaration can be tested on the others
+    fn attribute(&self, value: &Value, name: &str) -> Value {
+        match value {
+            Value::Object(fields) => fields
+                .iter()
+                .find(|(field, _)| *field == name)
+                .map(|(_, value)| value.clone())
+                .unwrap_or(Value::Null),
+            Value::Type(spec) => model::type_attribute(spec, name, self.root),
+            Value::List(items) if name == "length" => Value::Integer(items.len() as i64),
+            _ => Value::Null,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TemplateBackend;
+    use crate::backend::Backend;
+    use crate::{parse_ir, Configuration, IdlError, MemoryLoader};
+
+    fn render(template: &str, idl: &str) -> Result<String, IdlError> {
+        let mut backend = TemplateBackend::new("test");
+        backend.add("{{ idl }}.txt", template)?;
+        let config = Configuration {
+            idl_name: std::path::PathBuf::from("idl/shape.idl"),
+            ..Configuration::default()
+        };
+        let spec = parse_ir(&mut MemoryLoader::new(), &config, idl)?;
+        let mut outputs = backend.generate(&spec, &config)?;
+        let output = outputs.pop().unwrap();
+        assert_eq!(output.name, "shape.txt");
+        Ok(output.contents)
+    }
+
+    #[test]
+    fn templates() {
+        let idl = r#"
+            module M {
+                const long SIZE = 3;
+                enum Color { RED, GREEN };
+                typedef sequence<long, 8> Values;
+                struct Shape {
+                    long shape_id;
+                    string<16> label;
+                    Color color;
+                    float points[SIZE][2];
+                    Values values;
+                };
+                #pragma keylist Shape shape_id
+                union U switch (long) { case 1: case 2: long a; default: Color c; };
+            };
+        "#;
+
+        let template = r#"// {{ file }}
+{% for type in types %}
+{% if type.kind == "struct" %}
+struct {{ type.c_name }} /* {{ type.name | snake_case }}{% if type.is_topic %}, topic{% endif %} */
+{
+{% for member in type.members %}
+    {{ member.type | c_type }} {{ member.name }}{{ member.type | c_dims }};{% if member.is_key %} // key{% endif %}
+{% endfor %}
+};
+{% for member in type.members %}
+{% if member.type.bound %}
+#define {{ type.name | screaming_snake_case }}_{{ member.name | upper }}_MAX {{ member.type.bound }}
+{% endif %}
+{% endfor %}
+pub struct {{ type.name | camel_case }} { {% for member in type.members %}{{ member.name }}: {{ member.type | rust_type }}{% if not loop.last %}, {% endif %}{% endfor %} }
+keys: {{ type.keys | map("name") | join(", ") }}
+{% elif type.kind == "enum" %}
+enum {{ type.scoped_name }} { {{ type.enumerators | map("name") | join(", ") }} }
+{% elif type.kind == "typedef" %}
+typedef {{ type.type }} {{ type.name }} ({{ type.type.kind }} of {{ type.type.element }}, bound {{ type.type.bound }})
+{% else %}
+{{ type.kind }} {{ type.name }} switch ({{ type.discriminator }}){% for case in type.members %} {{ case.labels | join("/") | default("default") }}:{{ case.name }}{% endfor %}
+{% endif %}
+{% endfor %}
+{# constants #}
+{% for constant in constants %}
+{{ constant.scoped_name }} = {{ constant.value }}
+{% endfor %}
+"#;
+
+        assert_eq!(
+            render(template, idl).unwrap(),
+            r#"// shape.idl
+enum M::Color { RED, GREEN }
+typedef sequence<long, 8> Values (sequence of long, bound 8)
+struct M_Shape /* shape, topic */
+{
+    int32_t shape_id; // key
+    char label[17];
+    M_Color color;
+    float points[3][2];
+    M_Values values;
+};
+#define SHAPE_LABEL_MAX 16
+pub struct Shape { shape_id: i32, label: [::std::os::raw::c_char;17], color: crate::M::Color, points: [[f32;3];2], values: crate::M::Values }
+keys: shape_id
+union U switch (long) 1/2:a default:c
+M::SIZE = 3
+"#
+        );
+    }
+
+    #[test]
+    fn template_errors() {
+        let err = |template: &str| match render(template, "struct S { long a; };") {
+            Err(IdlError::ErrorMesg(msg)) => msg,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(err("a\n{% if x %}"), "{{ idl }}.txt:2: missing {% endif %}");
+        assert_eq!(err("a\n{% end %}"), "{{ idl }}.txt:2: unknown tag {% end %}");
+        assert_eq!(err("{% if %}\n{% endif %}"), "{{ idl }}.txt:1: missing expression");
+        assert_eq!(err("{{ a b }}"), "{{ idl }}.txt:1: unexpected b in a b");
+        assert_eq!(err("{{ types | shout }}"), "{{ idl }}.txt:1: unknown filter shout");
+        assert_eq!(err("{# a\nb #}\n\n{% for t in types %}{{ t }}{% endfor %}"), "{{ idl }}.txt:4: can't print an object");
+        assert_eq!(err("{% if type %}{% endif %}"), "{{ idl }}.txt:1: unknown name type");
+    }
+}
@@ -0,0 +1,391 @@
+// Copyright (C) 2020  Sojan James
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0>
+
+/* The names a template is rendered with.
+
+    idl             the file name of the IDL file without extension
+    file            the file name of the IDL file
+    included        the resolved paths of the included files
+    module          the root module
+    modules         the modules, nested modules after their parent
+    types           the types declared by the IDL file, of all modules
+    constants       the constants declared by the IDL file, of all modules
+    topics          the topic structs of types
+
+ A module has name, path, scoped_name, c_name, types, modules and constants,
+ the root module is named "". A type declaration has name, path (the list of
+ names), scoped_name (A::B), c_name (A_B), kind (typedef, struct, union, enum,
+ bitmask, bitset, forward or interface), source (the included file declaring
+ it, none for the IDL file), line and is_topic, and by kind:
+
+    typedef         type
+    struct          base, members (name, type, is_key), keys (name, path, type)
+    union           discriminator, members (name, type, labels, is_default)
+    enum            enumerators (name, ordinal)
+    bitmask         bit_bound, flags (name, position)
+    bitset          members (name, none for padding, width, type)
+    interface       operations (name, return_type, raises,
+                    params (name, direction, type))
+
+ A constant has name, path, scoped_name, c_name, type, value, source and
+ line. A type prints as its IDL spelling, its attributes are below in
+ type_attribute.
+*/
+
+use super::Value;
+use crate::ir::{
+    fixed_decimal, CaseLabel, ConstValue, Constant, Direction, Module, ScopedName, Specification, Struct,
+    TypeDecl, TypeKind, TypeSpec,
+};
+use crate::Configuration;
+
+/// The names of the root scope
+pub(crate) fn context(spec: &Specification, config: &Configuration) -> Vec<(&'static str, Value)> {
+    // the declarations of the IDL file, in the order of the walk
+    struct Declared<'a> {
+        types: Vec<&'a TypeDecl>,
+        constants: Vec<&'a Constant>,
+    }
+    impl<'a> Declared<'a> {
+        fn module(&mut self, module: &'a Module) {
+            self.types.extend(module.types.iter().filter(|t| t.source.is_none()));
+            for submodule in module.modules.iter() {
+                self.module(submodule);
+            }
+            self.constants.extend(module.constants.iter().filter(|c| c.source.is_none()));
+        }
+    }
+    let mut declared = Declared {
+        types: Vec::new(),
+        constants: Vec::new(),
+    };
+    declared.module(&spec.root);
+
+    let mut modules = Vec::new();
+    flatten_modules(&spec.root, &mut modules);
+
+    let file_name = |name: Option<&std::ffi::OsStr>| {
+        Value::String(name.map(|n| n.to_string_lossy().into_owned()).unwrap_or_default())
+    };
+    vec![
+        ("idl", file_name(config.idl_name.file_stem())),
+        ("file", file_name(config.idl_name.file_name())),
+        (
+            "included",
+            Value::List(spec.included.iter().map(|f| Value::String(f.clone())).collect()),
+        ),
+        ("module", module(&spec.root)),
+        ("modules", Value::List(modules)),
+        (
+            "types",
+            Value::List(declared.types.iter().map(|t| type_decl(t)).collect()),
+        ),
+        (
+            "constants",
+            Value::List(declared.constants.iter().map(|c| constant(c)).collect()),
+        ),
+        (
+            "topics",
+            Value::List(
+                declared
+                    .types
+                    .iter()
+                    .filter(|t| is_topic(t))
+                    .map(|t| type_decl(t))
+                    .collect(),
+            ),
+        ),
+    ]
+}
+
+fn flatten_modules(module: &Module, modules: &mut Vec<Value>) {
+    for submodule in module.modules.iter() {
+        modules.push(self::module(submodule));
+        flatten_modules(submodule, modules);
+    }
+}
+
+fn names(path: &[String]) -> Vec<(&'static str, Value)> {
+    vec![
+        ("path", Value::List(path.iter().map(|id| Value::String(id.clone())).collect())),
+        ("scoped_name", Value::String(path.join("::"))),
+        ("c_name", Value::String(path.join("_"))),
+    ]
+}
+
+fn source(source: &Option<String>) -> Value {
+    source.clone().map(Value::String).unwrap_or(Value::Null)
+}
+
+fn module(module: &Module) -> Value {
+    let mut fields = vec![("name", Value::String(module.id.clone().unwrap_or_default()))];
+    fields.extend(names(&module.path));
+    fields.push(("types", Value::List(module.types.iter().map(type_decl).collect())));
+    fields.push(("modules", Value::List(module.modules.iter().map(self::module).collect())));
+    fields.push(("constants", Value::List(module.constants.iter().map(constant).collect())));
+    Value::Object(fields)
+}
+
+fn constant(constant: &Constant) -> Value {
+    let mut fields = vec![("name", Value::String(constant.id.clone()))];
+    fields.extend(names(&constant.path));
+    fields.push(("type", Value::Type(constant.type_spec.clone())));
+    fields.push(("value", const_value(&constant.value)));
+    fields.push(("source", source(&constant.source)));
+    fields.push(("line", Value::Integer(constant.line as i64)));
+    Value::Object(fields)
+}
+
+fn is_topic(decl: &TypeDecl) -> bool {
+    matches!(decl.kind, TypeKind::Struct(Struct { topic: Some(_), .. }))
+}
+
+fn list<T, F: Fn(&T) -> Value>(items: &[T], f: F) -> Value {
+    Value::List(items.iter().map(f).collect())
+}
+
+fn member(id: &str, spec: &TypeSpec) -> Vec<(&'static str, Value)> {
+    vec![
+        ("name", Value::String(String::from(id))),
+        ("type", Value::Type(spec.clone())),
+    ]
+}
+
+fn type_decl(decl: &TypeDecl) -> Value {
+    let kind = match decl.kind {
+        TypeKind::Typedef(_) => "typedef",
+        TypeKind::Struct(_) => "struct",
+        TypeKind::Union(_) => "union",
+        TypeKind::Enum(_) => "enum",
+        TypeKind::Bitmask(_) => "bitmask",
+        TypeKind::Bitset(_) => "bitset",
+        TypeKind::Forward => "forward",
+        TypeKind::Interface(_) => "interface",
+    };
+    let mut fields = vec![("name", Value::String(decl.id.clone()))];
+    fields.extend(names(&decl.path));
+    fields.push(("kind", Value::String(String::from(kind))));
+    fields.push(("source", source(&decl.source)));
+    fields.push(("line", Value::Integer(decl.line as i64)));
+    fields.push(("is_topic", Value::Bool(is_topic(decl))));
+
+    match decl.kind {
+        TypeKind::Typedef(ref spec) => fields.push(("type", Value::Type(spec.clone()))),
+        TypeKind::Struct(ref s) => {
+            let base = s.base.clone().map(|base| Value::Type(TypeSpec::Named(base)));
+            fields.push(("base", base.unwrap_or(Value::Null)));
+            fields.push((
+                "members",
+                list(&s.members, |m| {
+                    let mut fields = member(&m.id, &m.type_spec);
+                    fields.push(("is_key", Value::Bool(m.is_key)));
+                    Value::Object(fields)
+                }),
+            ));
+            let keys = s.topic.as_ref().map(|topic| topic.keys.as_slice()).unwrap_or(&[]);
+            fields.push((
+                "keys",
+                list(keys, |key| {
+                    let mut fields = member(&key.path.join("."), &key.type_spec);
+                    fields.push((
+                        "path",
+                        list(&key.path, |id| Value::String(id.clone())),
+                    ));
+                    Value::Object(fields)
+                }),
+            ));
+        }
+        TypeKind::Union(ref u) => {
+            fields.push(("discriminator", Value::Type(u.discriminator.clone())));
+            fields.push((
+                "members",
+                list(&u.cases, |case| {
+                    let mut fields = member(&case.id, &case.type_spec);
+                    let labels = case.labels.iter().filter_map(|label| match label {
+                        CaseLabel::Value(value) => Some(const_value(value)),
+                        CaseLabel::Default => None,
+                    });
+                    fields.push(("labels", Value::List(labels.collect())));
+                    fields.push(("is_default", Value::Bool(case.labels.contains(&CaseLabel::Default))));
+                    Value::Object(fields)
+                }),
+            ));
+        }
+        TypeKind::Enum(ref enumerators) => {
+            let enumerators = enumerators.iter().enumerate().map(|(ordinal, id)| {
+                Value::Object(vec![
+                    ("name", Value::String(id.clone())),
+                    ("ordinal", Value::Integer(ordinal as i64)),
+                ])
+            });
+            fields.push(("enumerators", Value::List(enumerators.collect())));
+        }
+        TypeKind::Bitmask(ref bitmask) => {
+            fields.push(("bit_bound", Value::Integer(bitmask.bit_bound as i64)));
+            fields.push((
+                "flags",
+                list(&bitmask.flags, |flag| {
+                    Value::Object(vec![
+                        ("name", Value::String(flag.id.clone())),
+                        ("position", Value::Integer(flag.position as i64)),
+                    ])
+                }),
+            ));
+        }
+        TypeKind::Bitset(ref bitfields) => fields.push((
+            "members",
+            list(bitfields, |field| {
+                Value::Object(vec![
+                    ("name", field.id.clone().map(Value::String).unwrap_or(Value::Null)),
+                    ("type", Value::Type(field.type_spec.clone())),
+                    ("width", Value::Integer(field.width as i64)),
+                ])
+            }),
+        )),
+        TypeKind::Interface(ref operations) => fields.push((
+            "operations",
+            list(operations, |op| {
+                Value::Object(vec![
+                    ("name", Value::String(op.id.clone())),
+                    (
+                        "return_type",
+                        op.return_type.clone().map(Value::Type).unwrap_or(Value::Null),
+                    ),
+                    (
+                        "params",
+                        list(&op.params, |param| {
+                            let mut fields = member(&param.id, &param.type_spec);
+                            let direction = match param.direction {
+                                Direction::In => "in",
+                                Direction::Out => "out",
+                                Direction::InOut => "inout",
+                            };
+                            fields.push(("direction", Value::String(String::from(direction))));
+                            Value::Object(fields)
+                        }),
+                    ),
+                    ("raises", list(&op.raises, |name| Value::String(name.join("::")))),
+                ])
+            }),
+        )),
+        TypeKind::Forward => {}
+    }
+    Value::Object(fields)
+}
+
+fn const_value(value: &ConstValue) -> Value {
+    match value {
+        ConstValue::Integer(n) => Value::Integer(*n),
+        ConstValue::Float(f) => Value::Float(*f),
+        ConstValue::Fixed { scaled, scale } => Value::String(fixed_decimal(*scaled, *scale)),
+        ConstValue::Boolean(b) => Value::Bool(*b),
+        ConstValue::Char(c) => Value::String(c.to_string()),
+        ConstValue::String(s) => Value::String(s.clone()),
+        ConstValue::Enumerator { id, .. } => Value::String(id.clone()),
+    }
+}
+
+/// The IDL spelling of `spec`
+pub(crate) fn idl_type(spec: &TypeSpec) -> String {
+    let bounded = |name: &str, bound: &Option<u64>| match bound {
+        Some(bound) => format!("{}<{}>", name, bound),
+        None => String::from(name),
+    };
+    match spec {
+        TypeSpec::Boolean => String::from("boolean"),
+        TypeSpec::Octet => String::from("octet"),
+        TypeSpec::Char => String::from("char"),
+        TypeSpec::WideChar => String::from("wchar"),
+        TypeSpec::Int8 => String::from("int8"),
+        TypeSpec::Int16 => String::from("short"),
+        TypeSpec::Int32 => String::from("long"),
+        TypeSpec::Int64 => String::from("long long"),
+        TypeSpec::UInt8 => String::from("uint8"),
+        TypeSpec::UInt16 => String::from("unsigned short"),
+        TypeSpec::UInt32 => String::from("unsigned long"),
+        TypeSpec::UInt64 => String::from("unsigned long long"),
+        TypeSpec::Float32 => String::from("float"),
+        TypeSpec::Float64 => String::from("double"),
+        TypeSpec::LongDouble => String::from("long double"),
+        TypeSpec::Fixed { digits, scale } => format!("fixed<{}, {}>", digits, scale),
+        TypeSpec::String(bound) => bounded("string", bound),
+        TypeSpec::WideString(bound) => bounded("wstring", bound),
+        TypeSpec::Sequence(elem, None) => format!("sequence<{}>", idl_type(elem)),
+        TypeSpec::Sequence(elem, Some(bound)) => format!("sequence<{}, {}>", idl_type(elem), bound),
+        TypeSpec::Array(elem, dims) => {
+            let dims = dims.iter().map(|dim| format!("[{}]", dim)).collect::<String>();
+            format!("{}{}", idl_type(elem), dims)
+        }
+        TypeSpec::Map(key, value, None, _) => format!("map<{}, {}>", idl_type(key), idl_type(value)),
+        TypeSpec::Map(key, value, Some(bound), _) => {
+            format!("map<{}, {}, {}>", idl_type(key), idl_type(value), bound)
+        }
+        TypeSpec::Named(name) => name.join("::"),
+    }
+}
+
+/* The attributes of a type:
+
+    kind            the IDL spelling of primitive types, or fixed, string,
+                    wstring, sequence, array, map or named
+    idl             the IDL spelling
+    is_primitive    a boolean, char, integer or floating point type
+    bound           of strings, sequences and maps, none if unbounded
+    element         of sequences and arrays
+    dims            of arrays, outermost first
+    key, value      of maps
+    digits, scale   of fixed
+    name, path, c_name      of named types, the scoped name as in declarations
+    decl            the declaration of named types
+    resolved        the type behind typedefs
+*/
+pub(crate) fn type_attribute(spec: &TypeSpec, name: &str, root: &Module) -> Value {
+    let named = |path: &ScopedName| match name {
+        "name" => Value::String(path.join("::")),
+        "path" => Value::List(path.iter().map(|id| Value::String(id.clone())).collect()),
+        "c_name" => Value::String(path.join("_")),
+        "decl" => root.get_type(path).map(type_decl).unwrap_or(Value::Null),
+        _ => Value::Null,
+    };
+    let bound = |bound: &Option<u64>| bound.map(|b| Value::Integer(b as i64)).unwrap_or(Value::Null);
+    match (name, spec) {
+        ("idl", _) => Value::String(idl_type(spec)),
+        ("resolved", _) => Value::Type(spec.resolve(root).clone()),
+        ("kind", _) => Value::String(String::from(match spec {
+            TypeSpec::Fixed { .. } => "fixed",
+            TypeSpec::String(_) => "string",
+            TypeSpec::WideString(_) => "wstring",
+            TypeSpec::Sequence(_, _) => "sequence",
+            TypeSpec::Array(_, _) => "array",
+            TypeSpec::Map(_, _, _, _) => "map",
+            TypeSpec::Named(_) => "named",
+            _ => return Value::String(idl_type(spec)),
+        })),
+        ("is_primitive", _) => Value::Bool(!matches!(
+            spec,
+            TypeSpec::Fixed { .. }
+                | TypeSpec::String(_)
+                | TypeSpec::WideString(_)
+                | TypeSpec::Sequence(_, _)
+                | TypeSpec::Array(_, _)
+                | TypeSpec::Map(_, _, _, _)
+                | TypeSpec::Named(_)
+        )),
+        ("bound", TypeSpec::String(b))
+        | ("bound", TypeSpec::WideString(b))
+        | ("bound", TypeSpec::Sequence(_, b))
+        | ("bound", TypeSpec::Map(_, _, b, _)) => bound(b),
+        ("element", TypeSpec::Sequence(elem, _)) | ("element", TypeSpec::Array(elem, _)) => {
+            Value::Type((**elem).clone())
+        }
+        ("dims", TypeSpec::Array(_, dims)) => Value::List(dims.iter().map(|d| Value::Integer(*d as i64)).collect()),
+        ("key", TypeSpec::Map(key, _, _, _)) => Value::Type((**key).clone()),
+        ("value", TypeSpec::Map(_, value, _, _)) => Value::Type((**value).clone()),
+        ("digits", TypeSpec::Fixed { digits, .. }) => Value::Integer(*digits as i64),
+        ("scale", TypeSpec::Fixed { scale, .. }) => Value::Integer(*scale as i64),
+        (_, TypeSpec::Named(path)) => named(path),
+        _ => Value::Null,
+    }
+}